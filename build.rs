@@ -1,3 +1,5 @@
 fn main() {
+    // Rebuild when a migration is added, since sqlx::migrate! embeds them
+    println!("cargo:rerun-if-changed=migrations");
     tauri_build::build()
 }
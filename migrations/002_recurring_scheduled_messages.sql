@@ -0,0 +1,17 @@
+-- Recurring scheduled messages: next fire time and stop conditions
+ALTER TABLE scheduled_messages ADD COLUMN next_run_at DATETIME; -- next fire time for cron schedules
+ALTER TABLE scheduled_messages ADD COLUMN end_at DATETIME; -- no runs after this time
+ALTER TABLE scheduled_messages ADD COLUMN max_occurrences INTEGER; -- NULL for unlimited
+ALTER TABLE scheduled_messages ADD COLUMN run_count INTEGER NOT NULL DEFAULT 0;
+
+-- Scheduled message runs table: One row per delivery of a scheduled message
+CREATE TABLE IF NOT EXISTS scheduled_message_runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    scheduled_message_id INTEGER NOT NULL,
+    status TEXT NOT NULL, -- sent, failed
+    error_message TEXT,
+    run_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (scheduled_message_id) REFERENCES scheduled_messages(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_scheduled_message_runs_message_id ON scheduled_message_runs(scheduled_message_id);
@@ -0,0 +1,4 @@
+-- X-Line-Retry-Key for the delivery in progress, kept across scheduler ticks
+-- so a retried send is never delivered twice
+ALTER TABLE scheduled_messages ADD COLUMN retry_key TEXT;
+ALTER TABLE scheduled_messages ADD COLUMN retry_started_at DATETIME;
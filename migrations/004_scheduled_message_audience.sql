@@ -0,0 +1,3 @@
+-- Multicast / narrowcast targets for scheduled messages
+ALTER TABLE scheduled_messages ADD COLUMN audience TEXT; -- JSON audience, NULL for push/broadcast
+ALTER TABLE scheduled_message_runs ADD COLUMN line_request_id TEXT; -- narrowcast request ID for progress polling
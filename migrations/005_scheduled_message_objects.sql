@@ -0,0 +1,3 @@
+-- Rich scheduled messages: JSON array of LINE message objects (up to 5).
+-- message_text keeps a readable summary; NULL messages means plain text.
+ALTER TABLE scheduled_messages ADD COLUMN messages TEXT;
@@ -0,0 +1,20 @@
+-- Rich menus created through the app, with the definition sent to LINE
+CREATE TABLE IF NOT EXISTS rich_menus (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    definition TEXT NOT NULL, -- JSON rich menu object
+    line_rich_menu_id TEXT NOT NULL UNIQUE,
+    image_path TEXT, -- local file uploaded as the menu image, NULL until uploaded
+    is_default BOOLEAN NOT NULL DEFAULT 0,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Rich menu aliases used by richmenuswitch actions
+CREATE TABLE IF NOT EXISTS rich_menu_aliases (
+    alias_id TEXT PRIMARY KEY,
+    rich_menu_id INTEGER NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (rich_menu_id) REFERENCES rich_menus(id)
+);
@@ -0,0 +1,24 @@
+-- Every webhook event as received, for event types without a table of their own
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event_type TEXT NOT NULL, -- message, postback, join, unsend, beacon, ...
+    webhook_event_id TEXT UNIQUE, -- used to skip redelivered events
+    source_type TEXT, -- user, group, room
+    line_user_id TEXT,
+    source_id TEXT, -- group or room ID
+    payload TEXT NOT NULL, -- raw event JSON
+    timestamp DATETIME NOT NULL, -- event time reported by LINE
+    is_redelivery BOOLEAN NOT NULL DEFAULT 0,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_events_type ON events(event_type, timestamp);
+CREATE INDEX IF NOT EXISTS idx_events_user ON events(line_user_id, timestamp);
+
+-- Link stored messages to LINE so unsend events can retract them
+ALTER TABLE messages ADD COLUMN line_message_id TEXT;
+ALTER TABLE messages ADD COLUMN source_type TEXT NOT NULL DEFAULT 'user'; -- user, group, room
+ALTER TABLE messages ADD COLUMN source_id TEXT; -- group or room ID, NULL for 1:1 chats
+ALTER TABLE messages ADD COLUMN retracted_at DATETIME; -- set when the user unsends the message
+
+CREATE INDEX IF NOT EXISTS idx_messages_line_message_id ON messages(line_message_id);
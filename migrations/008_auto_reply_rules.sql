@@ -0,0 +1,29 @@
+-- Auto-reply rules: inbound text matching a rule is answered with the reply token
+CREATE TABLE IF NOT EXISTS auto_reply_rules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    match_type TEXT NOT NULL, -- exact, contains, regex, any
+    pattern TEXT, -- keyword or regular expression, NULL for "any"
+    case_sensitive BOOLEAN NOT NULL DEFAULT 0,
+    start_time TEXT, -- HH:MM local time; with end_time, the rule only applies in this window
+    end_time TEXT, -- HH:MM; a window ending before it starts wraps past midnight
+    messages TEXT NOT NULL, -- JSON array of LINE message objects (up to 5)
+    priority INTEGER NOT NULL DEFAULT 0, -- higher priority rules are evaluated first
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Every rule match and whether the reply went out
+CREATE TABLE IF NOT EXISTS auto_reply_logs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    rule_id INTEGER NOT NULL,
+    line_user_id TEXT NOT NULL,
+    message_text TEXT NOT NULL,
+    status TEXT NOT NULL, -- sent, failed
+    error_message TEXT,
+    matched_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (rule_id) REFERENCES auto_reply_rules(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_auto_reply_logs_rule ON auto_reply_logs(rule_id, matched_at);
@@ -0,0 +1,5 @@
+-- When the user's LINE profile was last fetched, so the refresh job can
+-- work through the oldest profiles first
+ALTER TABLE users ADD COLUMN profile_refreshed_at DATETIME;
+
+CREATE INDEX IF NOT EXISTS idx_users_profile_refreshed_at ON users(profile_refreshed_at);
@@ -0,0 +1,27 @@
+-- Follow lifecycle: whether the user still follows the account and when
+-- they first followed, last unfollowed (blocked) and last re-followed
+ALTER TABLE users ADD COLUMN follow_status TEXT NOT NULL DEFAULT 'following'; -- following, unfollowed
+ALTER TABLE users ADD COLUMN followed_at DATETIME;
+ALTER TABLE users ADD COLUMN unfollowed_at DATETIME;
+ALTER TABLE users ADD COLUMN refollowed_at DATETIME;
+
+-- Users recorded before this migration are assumed to have followed when first seen
+UPDATE users SET followed_at = created_at WHERE followed_at IS NULL;
+
+CREATE INDEX IF NOT EXISTS idx_users_follow_status ON users(follow_status);
+
+-- Every follow and unfollow event, oldest first
+CREATE TABLE IF NOT EXISTS follow_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    line_user_id TEXT NOT NULL,
+    event_type TEXT NOT NULL, -- follow, unfollow
+    is_unblocked BOOLEAN NOT NULL DEFAULT FALSE, -- follow after the user had blocked the account
+    occurred_at DATETIME NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (line_user_id) REFERENCES users(line_user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_follow_history_line_user_id ON follow_history(line_user_id);
+
+-- Reminders that were deliberately not sent, e.g. because the user blocked the account
+ALTER TABLE calendars ADD COLUMN reminder_skipped_reason TEXT;
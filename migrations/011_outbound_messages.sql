@@ -0,0 +1,39 @@
+-- Outbound messages are stored alongside inbound ones, so a user's history
+-- reads as one conversation. The table is rebuilt because line_user_id
+-- becomes nullable: broadcasts and narrowcasts have no single recipient.
+CREATE TABLE messages_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    line_user_id TEXT, -- NULL for broadcasts and narrowcasts
+    message_type TEXT NOT NULL, -- text, image, video, audio, location, sticker, ...
+    message_text TEXT,
+    message_data TEXT, -- inbound: JSON data for non-text messages; outbound: the message objects sent
+    timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+    line_message_id TEXT,
+    source_type TEXT NOT NULL DEFAULT 'user', -- user, group, room
+    source_id TEXT, -- group or room ID, NULL for 1:1 chats
+    retracted_at DATETIME, -- set when the user unsends the message
+    direction TEXT NOT NULL DEFAULT 'inbound', -- inbound, outbound
+    sender TEXT, -- outbound only: admin, bot, scheduler, rule
+    send_method TEXT, -- outbound only: push, reply, multicast, broadcast, narrowcast
+    line_request_id TEXT, -- x-line-request-id of the send, for LINE support inquiries
+    delivery_status TEXT, -- outbound only: sent, failed
+    error_message TEXT,
+    FOREIGN KEY (line_user_id) REFERENCES users(line_user_id)
+);
+
+INSERT INTO messages_new (
+    id, line_user_id, message_type, message_text, message_data, timestamp,
+    line_message_id, source_type, source_id, retracted_at
+)
+SELECT
+    id, line_user_id, message_type, message_text, message_data, timestamp,
+    line_message_id, source_type, source_id, retracted_at
+FROM messages;
+
+DROP TABLE messages;
+ALTER TABLE messages_new RENAME TO messages;
+
+CREATE INDEX IF NOT EXISTS idx_messages_line_user_id ON messages(line_user_id);
+CREATE INDEX IF NOT EXISTS idx_messages_timestamp ON messages(timestamp);
+CREATE INDEX IF NOT EXISTS idx_messages_line_message_id ON messages(line_message_id);
+CREATE INDEX IF NOT EXISTS idx_messages_direction ON messages(direction);
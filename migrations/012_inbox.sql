@@ -0,0 +1,38 @@
+-- One conversation per user for the inbox: 1:1 chat state on top of the
+-- messages table (unread count, status, assignment).
+CREATE TABLE IF NOT EXISTS conversations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    line_user_id TEXT NOT NULL UNIQUE,
+    status TEXT NOT NULL DEFAULT 'open', -- open, pending (waiting on the user), resolved
+    assigned_to TEXT, -- admin handling the conversation, free text
+    unread_count INTEGER NOT NULL DEFAULT 0,
+    last_inbound_at DATETIME,
+    last_outbound_at DATETIME,
+    last_read_at DATETIME,
+    last_message_preview TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (line_user_id) REFERENCES users(line_user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_conversations_status ON conversations(status);
+CREATE INDEX IF NOT EXISTS idx_conversations_assigned_to ON conversations(assigned_to);
+
+-- Existing 1:1 chats start out read and open
+INSERT INTO conversations (line_user_id, last_inbound_at, last_outbound_at)
+SELECT
+    line_user_id,
+    MAX(CASE WHEN direction = 'inbound' THEN timestamp END),
+    MAX(CASE WHEN direction = 'outbound' AND delivery_status = 'sent' THEN timestamp END)
+FROM messages
+WHERE line_user_id IS NOT NULL AND source_type = 'user'
+GROUP BY line_user_id
+HAVING MAX(CASE WHEN direction = 'inbound' THEN 1 ELSE 0 END) = 1;
+
+UPDATE conversations SET last_message_preview = (
+    SELECT COALESCE(m.message_text, '[' || m.message_type || ']')
+    FROM messages m
+    WHERE m.line_user_id = conversations.line_user_id AND m.source_type = 'user'
+    ORDER BY m.timestamp DESC, m.id DESC
+    LIMIT 1
+);
@@ -0,0 +1,10 @@
+-- Only one process (desktop app or headless server) may run the scheduler at
+-- a time. The owner renews its lease periodically; once it expires, another
+-- process can take over.
+CREATE TABLE IF NOT EXISTS scheduler_lease (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    owner TEXT NOT NULL, -- unique per process
+    description TEXT, -- e.g. "headless (pid 1234)", for logs
+    acquired_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    expires_at DATETIME NOT NULL
+);
@@ -0,0 +1,12 @@
+-- Keys for the REST admin API (/api/v1). Only a SHA-256 hash of each key is
+-- stored; the key itself is shown once when it is created.
+CREATE TABLE IF NOT EXISTS api_keys (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    key_prefix TEXT NOT NULL, -- first characters of the key, to tell keys apart
+    key_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT NOT NULL, -- space-separated, e.g. "users:read analytics:read"
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    last_used_at DATETIME,
+    revoked_at DATETIME
+);
@@ -0,0 +1,10 @@
+-- The master key that encrypts secret settings (see src/secrets). The key
+-- itself is never stored: only an id to recognize it, and the salt it is
+-- derived with when it comes from a passphrase.
+CREATE TABLE IF NOT EXISTS secrets_master_key (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    key_id TEXT NOT NULL,
+    kdf_salt TEXT, -- base64, NULL for a key file
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    rotated_at DATETIME
+);
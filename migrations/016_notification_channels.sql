@@ -0,0 +1,16 @@
+-- Where admin notifications go. Each row is one channel (a Slack webhook,
+-- an email address list, ...) of the kind handled by src/notification/<kind>.
+-- `config` is the channel's JSON settings, encrypted with the master key
+-- since it holds webhook URLs and passwords.
+--
+-- The old fixed `slack_webhook_url` / `line_notify_token` settings are
+-- moved into channels on startup, where they can be decrypted.
+CREATE TABLE IF NOT EXISTS notification_channels (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    kind TEXT NOT NULL, -- slack, discord, teams, email, webhook, line_push, line_notify
+    config TEXT NOT NULL,
+    enabled INTEGER NOT NULL DEFAULT 1,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,37 @@
+-- Routing of notifications by event kind. An event goes to the channel of
+-- every enabled rule for its kind; kinds without any rule go to every
+-- enabled channel, as before rules existed.
+CREATE TABLE IF NOT EXISTS notification_rules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    event_kind TEXT NOT NULL, -- new_message, new_follower, unfollow, bot_membership, scheduled_send_failed, reminder_failed
+    channel_id INTEGER NOT NULL,
+    keywords TEXT, -- JSON array; only events whose text contains one of them (case-insensitive)
+    quiet_start TEXT, -- HH:MM local time; with quiet_end, events are held for a digest in this window
+    quiet_end TEXT, -- HH:MM; a window ending before it starts wraps past midnight
+    rate_limit INTEGER, -- at most this many notifications per user per rate_window_minutes, the rest are held
+    rate_window_minutes INTEGER,
+    digest_minutes INTEGER, -- when set, every event is held and sent as a digest at most this often
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    last_digest_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (channel_id) REFERENCES notification_channels(id) ON DELETE CASCADE
+);
+
+-- Events matched by a rule: sent right away, or held until the rule's next
+-- digest. Sent events count towards the rule's rate limit.
+CREATE TABLE IF NOT EXISTS notification_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    rule_id INTEGER NOT NULL,
+    event_kind TEXT NOT NULL,
+    line_user_id TEXT,
+    title TEXT NOT NULL,
+    text TEXT NOT NULL,
+    status TEXT NOT NULL, -- sent, held, digested
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (rule_id) REFERENCES notification_rules(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_notification_events_rule ON notification_events(rule_id, status, created_at);
+CREATE INDEX IF NOT EXISTS idx_notification_events_user ON notification_events(rule_id, line_user_id, created_at);
@@ -0,0 +1,18 @@
+-- notification_logs becomes the outbox: each row is one notification for one
+-- channel, written before it is sent and retried with backoff until it is
+-- delivered or given up on.
+ALTER TABLE notification_logs RENAME COLUMN sent_at TO created_at;
+ALTER TABLE notification_logs ADD COLUMN channel_id INTEGER; -- NULL for rows logged before the outbox
+ALTER TABLE notification_logs ADD COLUMN event_kind TEXT; -- new_message, ...; NULL for tests and mixed digests
+ALTER TABLE notification_logs ADD COLUMN title TEXT;
+ALTER TABLE notification_logs ADD COLUMN text TEXT;
+ALTER TABLE notification_logs ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE notification_logs ADD COLUMN next_attempt_at DATETIME; -- when a pending row is (re)tried
+ALTER TABLE notification_logs ADD COLUMN delivered_at DATETIME;
+
+-- Statuses are now pending, sent and dead (out of attempts)
+UPDATE notification_logs SET status = 'sent', attempts = 1, delivered_at = created_at WHERE status = 'success';
+UPDATE notification_logs SET status = 'dead', attempts = 1 WHERE status = 'failed';
+
+CREATE INDEX IF NOT EXISTS idx_notification_logs_due ON notification_logs(status, next_attempt_at);
+CREATE INDEX IF NOT EXISTS idx_notification_logs_created ON notification_logs(created_at);
@@ -0,0 +1,7 @@
+-- Slack bridge: each LINE conversation is mirrored to a thread in the
+-- bridge's Slack channel, and replies in the thread are pushed to the user
+-- (stored as outbound messages with sender 'slack').
+ALTER TABLE conversations ADD COLUMN slack_channel TEXT;
+ALTER TABLE conversations ADD COLUMN slack_thread_ts TEXT; -- ts of the thread's first message
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_conversations_slack_thread ON conversations(slack_channel, slack_thread_ts);
@@ -0,0 +1,36 @@
+-- Message templates: text with {{placeholders}} rendered for each recipient
+-- at send time (see src/templates/).
+CREATE TABLE IF NOT EXISTS message_templates (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    body TEXT NOT NULL,
+    description TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Calendar reminders are rendered from the template named calendar_reminder;
+-- this is the text they were sent with before
+INSERT OR IGNORE INTO message_templates (name, body, description) VALUES (
+    'calendar_reminder',
+    '📅 イベントリマインダー
+
+「{{event.title}}」が{{event.starts_in}}に開始されます。
+
+{{event.description | default:"詳細なし"}}',
+    'カレンダーイベントのリマインダー'
+);
+
+-- Custom attributes of a user, available to templates as {{attr.<name>}}
+CREATE TABLE IF NOT EXISTS user_attributes (
+    line_user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (line_user_id, name),
+    FOREIGN KEY (line_user_id) REFERENCES users(line_user_id) ON DELETE CASCADE
+);
+
+-- A scheduled message can send a stored template, rendered when it is due;
+-- message_text keeps the template's text from when it was scheduled
+ALTER TABLE scheduled_messages ADD COLUMN template_id INTEGER REFERENCES message_templates(id) ON DELETE SET NULL;
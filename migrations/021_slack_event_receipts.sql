@@ -0,0 +1,6 @@
+-- Slack Events API deliveries the bridge has acted on. Slack retries a
+-- delivery it thinks failed with the same event_id, which is skipped here.
+CREATE TABLE IF NOT EXISTS slack_event_receipts (
+    event_id TEXT PRIMARY KEY,
+    received_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,18 @@
+-- Inbound LINE messages to post to the Slack bridge. A row is written when
+-- the message is stored, so a Slack outage delays posts instead of losing
+-- them; failed posts are retried with backoff like notification_logs.
+CREATE TABLE IF NOT EXISTS slack_bridge_posts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    message_id INTEGER NOT NULL UNIQUE,
+    line_user_id TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending', -- pending, sent or dead (out of attempts)
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    error_message TEXT, -- error of the last attempt
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    posted_at DATETIME,
+    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_slack_bridge_posts_due ON slack_bridge_posts(status, next_attempt_at);
+CREATE INDEX IF NOT EXISTS idx_slack_bridge_posts_user ON slack_bridge_posts(line_user_id, status);
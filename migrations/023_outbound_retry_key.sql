@@ -0,0 +1,6 @@
+-- The X-Line-Retry-Key an outbound message was sent with. Retrying a send
+-- reuses its key, and updates the message stored for the earlier attempt
+-- instead of storing another one.
+ALTER TABLE messages ADD COLUMN retry_key TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_messages_retry_key ON messages(retry_key);
@@ -0,0 +1,3 @@
+-- The LINE user a queued notification is about, so a retry is sent with
+-- the same notification that was queued
+ALTER TABLE notification_logs ADD COLUMN line_user_id TEXT;
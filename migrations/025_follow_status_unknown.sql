@@ -0,0 +1,37 @@
+-- 010 marked every existing user as following. Take the status from what
+-- we actually know instead, and use 'unknown' for users we have no follow
+-- for, e.g. only seen in groups or through postbacks.
+
+-- Users followed or unfollowed before follow_history existed: the latest
+-- follow or unfollow event (stored since 007) decides
+UPDATE users SET
+    follow_status = CASE (
+        SELECT event_type FROM events e
+        WHERE e.line_user_id = users.line_user_id AND e.event_type IN ('follow', 'unfollow')
+        ORDER BY e.timestamp DESC, e.id DESC LIMIT 1
+    ) WHEN 'unfollow' THEN 'unfollowed' ELSE 'following' END,
+    followed_at = COALESCE((
+        SELECT MIN(timestamp) FROM events e
+        WHERE e.line_user_id = users.line_user_id AND e.event_type = 'follow'
+    ), followed_at),
+    unfollowed_at = (
+        SELECT MAX(timestamp) FROM events e
+        WHERE e.line_user_id = users.line_user_id AND e.event_type = 'unfollow'
+    )
+WHERE NOT EXISTS (SELECT 1 FROM follow_history h WHERE h.line_user_id = users.line_user_id)
+  AND EXISTS (
+      SELECT 1 FROM events e
+      WHERE e.line_user_id = users.line_user_id AND e.event_type IN ('follow', 'unfollow')
+  );
+
+-- Without any follow event, a 1:1 chat still shows the user follows
+UPDATE users SET follow_status = 'unknown', followed_at = NULL
+WHERE NOT EXISTS (SELECT 1 FROM follow_history h WHERE h.line_user_id = users.line_user_id)
+  AND NOT EXISTS (
+      SELECT 1 FROM events e
+      WHERE e.line_user_id = users.line_user_id AND e.event_type IN ('follow', 'unfollow')
+  )
+  AND NOT EXISTS (
+      SELECT 1 FROM messages m
+      WHERE m.line_user_id = users.line_user_id AND m.source_type = 'user'
+  );
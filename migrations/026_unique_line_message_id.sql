@@ -0,0 +1,8 @@
+-- A LINE message is stored once. Storing it again (e.g. when LINE redelivers
+-- an event whose handling failed) finds the stored row instead.
+DELETE FROM messages
+WHERE line_message_id IS NOT NULL
+  AND id > (SELECT MIN(id) FROM messages first WHERE first.line_message_id = messages.line_message_id);
+
+DROP INDEX IF EXISTS idx_messages_line_message_id;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_messages_line_message_id ON messages(line_message_id);
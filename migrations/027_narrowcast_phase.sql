@@ -0,0 +1,2 @@
+-- Final outcome of the narrowcast a scheduled message run started
+ALTER TABLE scheduled_message_runs ADD COLUMN narrowcast_phase TEXT; -- waiting, sending, succeeded or failed; NULL for other sends
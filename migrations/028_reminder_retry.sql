@@ -0,0 +1,4 @@
+-- Calendar reminders that fail transiently are retried with one retry key,
+-- so LINE drops duplicates, until the event starts
+ALTER TABLE calendars ADD COLUMN reminder_retry_key TEXT;
+ALTER TABLE calendars ADD COLUMN reminder_error TEXT; -- last error while retrying
@@ -0,0 +1,3 @@
+-- Retry key reused by every attempt at a queued notification, so LINE drops
+-- a push it already accepted
+ALTER TABLE notification_logs ADD COLUMN retry_key TEXT;
@@ -1,9 +1,14 @@
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
+use utoipa::ToSchema;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct DashboardStats {
+    /// Users who currently follow the account
     pub total_users: i64,
+    /// Users who have unfollowed (blocked) the account
+    pub unfollowed_users: i64,
+    /// Messages received; what we send is not counted
     pub total_messages: i64,
     pub messages_today: i64,
     pub new_users_this_week: i64,
@@ -13,19 +18,19 @@ pub struct DashboardStats {
     pub hourly_activity: Vec<HourlyActivity>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct MessageTypeCount {
     pub message_type: String,
     pub count: i64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct HourlyActivity {
     pub hour: i32,
     pub count: i64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct UserStats {
     pub user_id: String,
     pub display_name: Option<String>,
@@ -37,25 +42,32 @@ pub struct UserStats {
 
 pub async fn get_dashboard_stats(db: &SqlitePool) -> Result<DashboardStats, sqlx::Error> {
     // Total users
-    let total_users: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users")
+    let total_users: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users WHERE follow_status = 'following'")
+        .fetch_one(db)
+        .await?;
+
+    // Users who blocked the account
+    let unfollowed_users: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users WHERE follow_status = 'unfollowed'")
         .fetch_one(db)
         .await?;
 
     // Total messages
-    let total_messages: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM messages")
+    let total_messages: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM messages WHERE direction = 'inbound'")
         .fetch_one(db)
         .await?;
 
     // Messages today
     let messages_today: (i64,) = sqlx::query_as(
-        "SELECT COUNT(*) FROM messages WHERE DATE(timestamp) = DATE('now')"
+        "SELECT COUNT(*) FROM messages WHERE direction = 'inbound' AND DATE(timestamp) = DATE('now')"
     )
     .fetch_one(db)
     .await?;
 
     // New users this week
     let new_users_this_week: (i64,) = sqlx::query_as(
-        "SELECT COUNT(*) FROM users WHERE created_at >= datetime('now', '-7 days')"
+        "SELECT COUNT(*) FROM users
+         WHERE follow_status = 'following'
+         AND created_at >= datetime('now', '-7 days')"
     )
     .fetch_one(db)
     .await?;
@@ -72,7 +84,8 @@ pub async fn get_dashboard_stats(db: &SqlitePool) -> Result<DashboardStats, sqlx
         "SELECT COUNT(*) FROM calendars
          WHERE event_time >= datetime('now')
          AND event_time <= datetime('now', '+7 days')
-         AND reminder_sent = 0"
+         AND reminder_sent = 0
+         AND line_user_id NOT IN (SELECT line_user_id FROM users WHERE follow_status != 'following')"
     )
     .fetch_one(db)
     .await?;
@@ -81,6 +94,7 @@ pub async fn get_dashboard_stats(db: &SqlitePool) -> Result<DashboardStats, sqlx
     let message_types = sqlx::query_as::<_, (String, i64)>(
         "SELECT message_type, COUNT(*) as count
          FROM messages
+         WHERE direction = 'inbound'
          GROUP BY message_type
          ORDER BY count DESC"
     )
@@ -94,7 +108,7 @@ pub async fn get_dashboard_stats(db: &SqlitePool) -> Result<DashboardStats, sqlx
     let hourly_activity = sqlx::query_as::<_, (i32, i64)>(
         "SELECT CAST(strftime('%H', timestamp) AS INTEGER) as hour, COUNT(*) as count
          FROM messages
-         WHERE DATE(timestamp) >= DATE('now', '-7 days')
+         WHERE direction = 'inbound' AND DATE(timestamp) >= DATE('now', '-7 days')
          GROUP BY hour
          ORDER BY hour"
     )
@@ -106,6 +120,7 @@ pub async fn get_dashboard_stats(db: &SqlitePool) -> Result<DashboardStats, sqlx
 
     Ok(DashboardStats {
         total_users: total_users.0,
+        unfollowed_users: unfollowed_users.0,
         total_messages: total_messages.0,
         messages_today: messages_today.0,
         new_users_this_week: new_users_this_week.0,
@@ -118,21 +133,21 @@ pub async fn get_dashboard_stats(db: &SqlitePool) -> Result<DashboardStats, sqlx
 
 pub async fn get_user_stats(db: &SqlitePool, user_id: &str) -> Result<UserStats, sqlx::Error> {
     let message_count: (i64,) = sqlx::query_as(
-        "SELECT COUNT(*) FROM messages WHERE line_user_id = ?"
+        "SELECT COUNT(*) FROM messages WHERE line_user_id = ? AND direction = 'inbound'"
     )
     .bind(user_id)
     .fetch_one(db)
     .await?;
 
     let first_message: Option<(String,)> = sqlx::query_as(
-        "SELECT timestamp FROM messages WHERE line_user_id = ? ORDER BY timestamp ASC LIMIT 1"
+        "SELECT timestamp FROM messages WHERE line_user_id = ? AND direction = 'inbound' ORDER BY timestamp ASC LIMIT 1"
     )
     .bind(user_id)
     .fetch_optional(db)
     .await?;
 
     let last_message: Option<(String,)> = sqlx::query_as(
-        "SELECT timestamp FROM messages WHERE line_user_id = ? ORDER BY timestamp DESC LIMIT 1"
+        "SELECT timestamp FROM messages WHERE line_user_id = ? AND direction = 'inbound' ORDER BY timestamp DESC LIMIT 1"
     )
     .bind(user_id)
     .fetch_optional(db)
@@ -140,7 +155,7 @@ pub async fn get_user_stats(db: &SqlitePool, user_id: &str) -> Result<UserStats,
 
     let most_used_type: Option<(String,)> = sqlx::query_as(
         "SELECT message_type FROM messages
-         WHERE line_user_id = ?
+         WHERE line_user_id = ? AND direction = 'inbound'
          GROUP BY message_type
          ORDER BY COUNT(*) DESC LIMIT 1"
     )
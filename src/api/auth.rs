@@ -0,0 +1,159 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::sync::Arc;
+
+use crate::api::AppState;
+use crate::db::models::ApiKey;
+
+/// What an API key may do. Each `/api/v1` route needs one of these.
+pub const SCOPES: &[&str] = &[
+    "users:read",
+    "users:write",
+    "messages:read",
+    "messages:send",
+    "scheduled:read",
+    "scheduled:write",
+    "calendar:read",
+    "calendar:write",
+    "settings:read",
+    "settings:write",
+    "analytics:read",
+];
+
+/// Prefix of every key, so leaked keys are easy to recognize
+const KEY_PREFIX: &str = "lak_";
+/// How much of a key is stored in the clear to tell keys apart
+const DISPLAY_PREFIX_LEN: usize = 12;
+
+/// A newly created key. `key` is only available here; just its hash is stored.
+#[derive(Debug, serde::Serialize)]
+pub struct CreatedApiKey {
+    pub id: i64,
+    pub key: String,
+}
+
+/// Create a key with the given scopes
+pub async fn create_api_key(db: &SqlitePool, name: &str, scopes: &[String]) -> Result<CreatedApiKey, anyhow::Error> {
+    if name.trim().is_empty() {
+        anyhow::bail!("An API key needs a name");
+    }
+    if scopes.is_empty() {
+        anyhow::bail!("An API key needs at least one scope");
+    }
+    if let Some(unknown) = scopes.iter().find(|s| !SCOPES.contains(&s.as_str())) {
+        anyhow::bail!("Unknown scope '{}' (expected any of {:?})", unknown, SCOPES);
+    }
+
+    // Two v4 UUIDs give 244 random bits
+    let key = format!(
+        "{}{}{}",
+        KEY_PREFIX,
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    );
+    let id = ApiKey::create(db, name.trim(), &key[..DISPLAY_PREFIX_LEN], &hash_key(&key), &scopes.join(" ")).await?;
+
+    Ok(CreatedApiKey { id, key })
+}
+
+fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The key a request was authenticated with, available to handlers as an
+/// extension
+#[derive(Debug, Clone)]
+pub struct Authenticated {
+    pub key_id: i64,
+    scopes: Vec<String>,
+}
+
+impl Authenticated {
+    /// Fail with 403 unless the key has `scope`
+    pub fn require(&self, scope: &str) -> Result<(), AuthError> {
+        if self.scopes.iter().any(|s| s == scope) {
+            Ok(())
+        } else {
+            Err(AuthError::MissingScope(scope.to_string()))
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Missing API key (send it as 'Authorization: Bearer <key>' or 'X-API-Key: <key>')")]
+    MissingKey,
+    #[error("Invalid or revoked API key")]
+    InvalidKey,
+    #[error("API key lacks the '{0}' scope")]
+    MissingScope(String),
+    #[error("Failed to check API key: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            AuthError::MissingKey | AuthError::InvalidKey => StatusCode::UNAUTHORIZED,
+            AuthError::MissingScope(_) => StatusCode::FORBIDDEN,
+            AuthError::Database(e) => {
+                tracing::error!("Failed to check API key: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+
+        let mut response = (status, Json(serde_json::json!({ "error": self.to_string() }))).into_response();
+        if status == StatusCode::UNAUTHORIZED {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, header::HeaderValue::from_static("Bearer"));
+        }
+        response
+    }
+}
+
+/// Middleware for `/api/v1`: look up the request's API key and hand it to
+/// the handlers, which check its scopes
+pub async fn require_api_key(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AuthError> {
+    let key = presented_key(request.headers()).ok_or(AuthError::MissingKey)?;
+    let api_key = ApiKey::find_active_by_hash(&state.db, &hash_key(key))
+        .await?
+        .ok_or(AuthError::InvalidKey)?;
+
+    if let Err(e) = ApiKey::touch(&state.db, api_key.id).await {
+        tracing::warn!("Failed to record use of API key {}: {}", api_key.id, e);
+    }
+
+    request.extensions_mut().insert(Authenticated {
+        key_id: api_key.id,
+        scopes: api_key.scopes.split_whitespace().map(str::to_string).collect(),
+    });
+
+    Ok(next.run(request).await)
+}
+
+fn presented_key(headers: &HeaderMap) -> Option<&str> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    bearer
+        .or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok()))
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+}
@@ -1,5 +1,11 @@
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
 use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::time::Duration;
+
+use crate::settings;
+
+pub use crate::api::line_message::Message;
 
 #[derive(Debug, Serialize)]
 pub struct PushMessage {
@@ -19,41 +25,290 @@ pub struct BroadcastMessage {
     messages: Vec<Message>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize)]
+pub struct MulticastMessage {
+    to: Vec<String>,
+    messages: Vec<Message>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NarrowcastMessage {
+    messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recipient: Option<Recipient>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filter: Option<NarrowcastFilter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<NarrowcastLimit>,
+}
+
+/// Maximum number of user IDs per multicast request
+pub const MULTICAST_MAX_RECIPIENTS: usize = 500;
+
+/// Recipient object for narrowcast: audiences, redelivery of an earlier
+/// request, or a logical combination of them
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
-pub enum Message {
-    #[serde(rename = "text")]
-    Text { text: String },
-    #[serde(rename = "image")]
-    Image {
-        #[serde(rename = "originalContentUrl")]
-        original_content_url: String,
-        #[serde(rename = "previewImageUrl")]
-        preview_image_url: String,
+pub enum Recipient {
+    #[serde(rename = "audience")]
+    Audience {
+        #[serde(rename = "audienceGroupId")]
+        audience_group_id: i64,
     },
-    #[serde(rename = "video")]
-    Video {
-        #[serde(rename = "originalContentUrl")]
-        original_content_url: String,
-        #[serde(rename = "previewImageUrl")]
-        preview_image_url: String,
+    #[serde(rename = "redelivery")]
+    Redelivery {
+        #[serde(rename = "requestId")]
+        request_id: String,
     },
-    #[serde(rename = "flex")]
-    Flex {
-        #[serde(rename = "altText")]
-        alt_text: String,
-        contents: serde_json::Value,
+    #[serde(rename = "operator")]
+    Operator {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        and: Option<Vec<Recipient>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        or: Option<Vec<Recipient>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        not: Option<Box<Recipient>>,
     },
 }
 
-#[derive(Debug, Deserialize)]
-pub struct LineApiResponse {
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NarrowcastFilter {
+    pub demographic: DemographicFilter,
+}
+
+/// Demographic filter object for narrowcast. Values use LINE's identifiers,
+/// e.g. `age_20`, `jp_13`, `day_30`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum DemographicFilter {
+    #[serde(rename = "gender")]
+    Gender {
+        #[serde(rename = "oneOf")]
+        one_of: Vec<String>,
+    },
+    #[serde(rename = "age")]
+    Age {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        gte: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lt: Option<String>,
+    },
+    #[serde(rename = "appType")]
+    AppType {
+        #[serde(rename = "oneOf")]
+        one_of: Vec<String>,
+    },
+    #[serde(rename = "area")]
+    Area {
+        #[serde(rename = "oneOf")]
+        one_of: Vec<String>,
+    },
+    #[serde(rename = "subscriptionPeriod")]
+    SubscriptionPeriod {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        gte: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lt: Option<String>,
+    },
+    #[serde(rename = "operator")]
+    Operator {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        and: Option<Vec<DemographicFilter>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        or: Option<Vec<DemographicFilter>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        not: Option<Box<DemographicFilter>>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NarrowcastLimit {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<i64>,
+    #[serde(rename = "upToRemainingQuota", default, skip_serializing_if = "Option::is_none")]
+    pub up_to_remaining_quota: Option<bool>,
+}
+
+/// Progress of a narrowcast, from `/v2/bot/message/progress/narrowcast`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NarrowcastProgress {
+    /// waiting, sending, succeeded or failed
+    pub phase: String,
+    pub success_count: Option<i64>,
+    pub failure_count: Option<i64>,
+    pub target_count: Option<i64>,
+    pub failed_description: Option<String>,
+    pub error_code: Option<i64>,
+    pub accepted_time: Option<String>,
+    pub completed_time: Option<String>,
+}
+
+impl NarrowcastProgress {
+    pub fn is_finished(&self) -> bool {
+        self.phase == "succeeded" || self.phase == "failed"
+    }
+}
+
+/// Group of users a message is sent to, beyond a single push or a broadcast.
+/// Stored as JSON on scheduled messages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Audience {
+    Multicast {
+        user_ids: Vec<String>,
+    },
+    Narrowcast {
+        #[serde(default)]
+        recipient: Option<Recipient>,
+        #[serde(default)]
+        filter: Option<NarrowcastFilter>,
+        #[serde(default)]
+        limit: Option<NarrowcastLimit>,
+    },
+}
+
+/// Error body returned by the LINE API
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LineErrorResponse {
+    pub message: Option<String>,
+    #[serde(default)]
+    pub details: Vec<LineErrorDetail>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LineErrorDetail {
     pub message: Option<String>,
+    pub property: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LineApiError {
+    /// The API answered with a non-success status
+    #[error("LINE API error ({status}): {}{}", .body.message.as_deref().unwrap_or("no message"), format_details(&.body.details))]
+    Api {
+        status: StatusCode,
+        body: LineErrorResponse,
+        /// `x-line-request-id` header, for support inquiries
+        request_id: Option<String>,
+        /// Parsed `Retry-After` header
+        retry_after: Option<Duration>,
+    },
+    /// The request never got a response (connection, timeout, decoding)
+    #[error("LINE API request failed: {0}")]
+    Http(#[from] reqwest::Error),
 }
 
+fn format_details(details: &[LineErrorDetail]) -> String {
+    if details.is_empty() {
+        return String::new();
+    }
+
+    let parts: Vec<String> = details
+        .iter()
+        .map(|d| match (&d.property, &d.message) {
+            (Some(property), Some(message)) => format!("{}: {}", property, message),
+            (None, Some(message)) => message.clone(),
+            (Some(property), None) => property.clone(),
+            (None, None) => "unknown".to_string(),
+        })
+        .collect();
+
+    format!(" [{}]", parts.join("; "))
+}
+
+impl LineApiError {
+    async fn from_response(response: Response) -> Self {
+        let status = response.status();
+        let request_id = response
+            .headers()
+            .get("x-line-request-id")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+
+        let text = response.text().await.unwrap_or_default();
+        let body = serde_json::from_str::<LineErrorResponse>(&text).unwrap_or_else(|_| LineErrorResponse {
+            message: (!text.is_empty()).then_some(text),
+            details: Vec::new(),
+        });
+
+        LineApiError::Api { status, body, request_id, retry_after }
+    }
+
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            LineApiError::Api { status, .. } => Some(*status),
+            LineApiError::Http(e) => e.status(),
+        }
+    }
+
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            LineApiError::Api { request_id, .. } => request_id.as_deref(),
+            LineApiError::Http(_) => None,
+        }
+    }
+
+    /// Rate limits, server errors and network failures are worth retrying;
+    /// anything else (bad payload, expired token, ...) will fail again
+    pub fn is_transient(&self) -> bool {
+        match self {
+            LineApiError::Api { status, .. } => {
+                *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            }
+            LineApiError::Http(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+        }
+    }
+}
+
+/// How `LineClient` retries transient failures
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff, unless the server told us how long to wait
+    fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_delay);
+        }
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay)
+    }
+}
+
+/// Default host for the LINE Messaging API
+pub const DEFAULT_API_BASE_URL: &str = "https://api.line.me";
+
+/// Default host for content endpoints (rich menu images, message content)
+pub const DEFAULT_DATA_API_BASE_URL: &str = "https://api-data.line.me";
+
 pub struct LineClient {
-    client: Client,
+    pub(crate) client: Client,
     access_token: String,
+    base_url: String,
+    data_base_url: String,
+    retry_policy: RetryPolicy,
 }
 
 impl LineClient {
@@ -61,98 +316,349 @@ impl LineClient {
         Self {
             client: Client::new(),
             access_token,
+            base_url: DEFAULT_API_BASE_URL.to_string(),
+            data_base_url: DEFAULT_DATA_API_BASE_URL.to_string(),
+            retry_policy: RetryPolicy::default(),
         }
     }
 
-    /// Push message to a specific user
-    pub async fn push_message(&self, user_id: &str, messages: Vec<Message>) -> Result<(), anyhow::Error> {
-        let payload = PushMessage {
-            to: user_id.to_string(),
-            messages,
-        };
+    /// Create a client that talks to a different API host (e.g. the local mock
+    /// server). Content endpoints go to the same host.
+    pub fn with_base_url(access_token: String, base_url: String) -> Self {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        Self {
+            client: Client::new(),
+            access_token,
+            data_base_url: base_url.clone(),
+            base_url,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
 
-        let response = self
-            .client
-            .post("https://api.line.me/v2/bot/message/push")
-            .header("Authorization", format!("Bearer {}", self.access_token))
-            .header("Content-Type", "application/json")
-            .json(&payload)
-            .send()
-            .await?;
+    /// Create a client from the `line_channel_access_token` setting. The API host
+    /// comes from the `line_api_base_url` setting (or `LINE_API_BASE_URL`), and
+    /// defaults to the real LINE API.
+    pub async fn from_settings(db: &SqlitePool) -> Result<Self, anyhow::Error> {
+        let access_token = settings::get(db, settings::LINE_CHANNEL_ACCESS_TOKEN)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("LINE channel access token not configured"))?;
+        let base_url = settings::get(db, settings::LINE_API_BASE_URL).await?;
+
+        Ok(match base_url {
+            Some(base_url) => Self::with_base_url(access_token, base_url),
+            None => Self::new(access_token),
+        })
+    }
+
+    pub(crate) fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    pub(crate) fn data_url(&self, path: &str) -> String {
+        format!("{}{}", self.data_base_url, path)
+    }
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow::anyhow!("LINE API error: {}", error_text));
+    /// Send a request, retrying transient failures with backoff. `build` is called
+    /// once per attempt. With a `retry_key`, a 409 means LINE already accepted an
+    /// earlier attempt, so it counts as success.
+    pub(crate) async fn execute<F>(&self, build: F, retry_key: Option<&str>) -> Result<Response, LineApiError>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let mut attempt = 0;
+
+        loop {
+            let mut request = build().header("Authorization", format!("Bearer {}", self.access_token));
+            if let Some(key) = retry_key {
+                request = request.header("X-Line-Retry-Key", key);
+            }
+
+            let error = match request.send().await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) if response.status() == StatusCode::CONFLICT && retry_key.is_some() => {
+                    tracing::info!("LINE already accepted request with retry key {:?}", retry_key);
+                    return Ok(response);
+                }
+                Ok(response) => LineApiError::from_response(response).await,
+                Err(e) => LineApiError::Http(e),
+            };
+
+            if !error.is_transient() || attempt >= self.retry_policy.max_retries {
+                return Err(error);
+            }
+
+            let retry_after = match &error {
+                LineApiError::Api { retry_after, .. } => *retry_after,
+                LineApiError::Http(_) => None,
+            };
+            let delay = self.retry_policy.delay(attempt, retry_after);
+            tracing::warn!("{}; retrying in {:?} (attempt {})", error, delay, attempt + 1);
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
         }
+    }
 
+    pub(crate) async fn post_json<T: Serialize>(
+        &self,
+        path: &str,
+        payload: &T,
+        retry_key: Option<&str>,
+    ) -> Result<(), LineApiError> {
+        let url = self.url(path);
+        self.execute(|| self.client.post(&url).json(payload), retry_key).await?;
         Ok(())
     }
 
-    /// Reply to a message
-    pub async fn reply_message(&self, reply_token: &str, messages: Vec<Message>) -> Result<(), anyhow::Error> {
+    /// POST a message send and return its `x-line-request-id`, which is what
+    /// LINE support asks for when a message went missing
+    async fn send_json<T: Serialize>(
+        &self,
+        path: &str,
+        payload: &T,
+        retry_key: Option<&str>,
+    ) -> Result<Option<String>, LineApiError> {
+        let url = self.url(path);
+        let response = self.execute(|| self.client.post(&url).json(payload), retry_key).await?;
+
+        Ok(response
+            .headers()
+            .get("x-line-request-id")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string()))
+    }
+
+    /// Push message to a specific user with a caller-supplied `X-Line-Retry-Key`
+    /// (a UUID), so the caller can safely resend it later without double
+    /// delivery. Returns the LINE request ID.
+    pub async fn push_message_with_retry_key(
+        &self,
+        user_id: &str,
+        messages: Vec<Message>,
+        retry_key: &str,
+    ) -> Result<Option<String>, LineApiError> {
+        let payload = PushMessage {
+            to: user_id.to_string(),
+            messages,
+        };
+
+        self.send_json("/v2/bot/message/push", &payload, Some(retry_key)).await
+    }
+
+    /// Reply to a message. Returns the LINE request ID.
+    pub async fn reply_message(&self, reply_token: &str, messages: Vec<Message>) -> Result<Option<String>, LineApiError> {
         let payload = ReplyMessage {
             reply_token: reply_token.to_string(),
             messages,
         };
 
-        let response = self
-            .client
-            .post("https://api.line.me/v2/bot/message/reply")
-            .header("Authorization", format!("Bearer {}", self.access_token))
-            .header("Content-Type", "application/json")
-            .json(&payload)
-            .send()
-            .await?;
+        // Reply tokens are single-use, so replies take no retry key
+        self.send_json("/v2/bot/message/reply", &payload, None).await
+    }
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow::anyhow!("LINE API error: {}", error_text));
+    /// Broadcast message to all users with a caller-supplied `X-Line-Retry-Key`.
+    /// Returns the LINE request ID.
+    pub async fn broadcast_message_with_retry_key(
+        &self,
+        messages: Vec<Message>,
+        retry_key: &str,
+    ) -> Result<Option<String>, LineApiError> {
+        let payload = BroadcastMessage { messages };
+
+        self.send_json("/v2/bot/message/broadcast", &payload, Some(retry_key)).await
+    }
+
+    /// Send the same messages to many users, split into requests of at most
+    /// `MULTICAST_MAX_RECIPIENTS` user IDs, with a caller-supplied base retry
+    /// key. Each chunk gets its own key derived from it, so a resend only
+    /// delivers the chunks that failed.
+    /// Every chunk is sent even if an earlier one fails; the result of each
+    /// (its request ID or error) is returned in order.
+    pub async fn multicast_message_with_retry_key(
+        &self,
+        user_ids: &[String],
+        messages: Vec<Message>,
+        retry_key: &str,
+    ) -> Vec<Result<Option<String>, LineApiError>> {
+        let mut results = Vec::new();
+
+        for (index, chunk) in user_ids.chunks(MULTICAST_MAX_RECIPIENTS).enumerate() {
+            let payload = MulticastMessage {
+                to: chunk.to_vec(),
+                messages: messages.clone(),
+            };
+
+            let chunk_key = chunk_retry_key(retry_key, index);
+            results.push(self.send_json("/v2/bot/message/multicast", &payload, Some(&chunk_key)).await);
         }
 
-        Ok(())
+        results
     }
 
-    /// Broadcast message to all users
-    pub async fn broadcast_message(&self, messages: Vec<Message>) -> Result<(), anyhow::Error> {
-        let payload = BroadcastMessage { messages };
+    /// Start a narrowcast to an audience and/or demographic filter, with a
+    /// caller-supplied `X-Line-Retry-Key`. Returns the request ID used to poll
+    /// `get_narrowcast_progress`.
+    pub async fn narrowcast_message_with_retry_key(
+        &self,
+        messages: Vec<Message>,
+        recipient: Option<Recipient>,
+        filter: Option<NarrowcastFilter>,
+        limit: Option<NarrowcastLimit>,
+        retry_key: &str,
+    ) -> Result<String, LineApiError> {
+        let payload = NarrowcastMessage {
+            messages,
+            recipient,
+            filter,
+            limit,
+        };
 
+        let url = self.url("/v2/bot/message/narrowcast");
         let response = self
-            .client
-            .post("https://api.line.me/v2/bot/message/broadcast")
-            .header("Authorization", format!("Bearer {}", self.access_token))
-            .header("Content-Type", "application/json")
-            .json(&payload)
-            .send()
+            .execute(|| self.client.post(&url).json(&payload), Some(retry_key))
             .await?;
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow::anyhow!("LINE API error: {}", error_text));
-        }
+        // A 409 for an already accepted retry key carries the original request ID
+        let request_id = response
+            .headers()
+            .get("x-line-accepted-request-id")
+            .or_else(|| response.headers().get("x-line-request-id"))
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
 
-        Ok(())
+        Ok(request_id)
     }
 
-    /// Get user profile
-    pub async fn get_profile(&self, user_id: &str) -> Result<UserProfile, anyhow::Error> {
+    /// Get the progress of a narrowcast started with `narrowcast_message_with_retry_key`
+    pub async fn get_narrowcast_progress(&self, request_id: &str) -> Result<NarrowcastProgress, LineApiError> {
+        let url = self.url("/v2/bot/message/progress/narrowcast");
         let response = self
-            .client
-            .get(&format!("https://api.line.me/v2/bot/profile/{}", user_id))
-            .header("Authorization", format!("Bearer {}", self.access_token))
-            .send()
+            .execute(|| self.client.get(&url).query(&[("requestId", request_id)]), None)
             .await?;
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow::anyhow!("LINE API error: {}", error_text));
+        let progress = response.json::<NarrowcastProgress>().await?;
+        Ok(progress)
+    }
+
+    /// Poll narrowcast progress until it succeeds or fails, or `timeout` passes
+    pub async fn wait_for_narrowcast(
+        &self,
+        request_id: &str,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<NarrowcastProgress, LineApiError> {
+        let deadline = tokio::time::Instant::now() + timeout;
+
+        loop {
+            let progress = self.get_narrowcast_progress(request_id).await?;
+            if progress.is_finished() || tokio::time::Instant::now() + poll_interval > deadline {
+                return Ok(progress);
+            }
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+
+    /// Download the content of an image, video, audio or file message a user
+    /// sent. Returns `None` while LINE is still preparing it (202 Accepted).
+    pub async fn get_message_content(&self, message_id: &str) -> Result<Option<MessageContent>, LineApiError> {
+        let url = self.data_url(&format!("/v2/bot/message/{}/content", message_id));
+        let response = self.execute(|| self.client.get(&url), None).await?;
+
+        if response.status() == StatusCode::ACCEPTED {
+            return Ok(None);
         }
 
+        let content_type = response
+            .headers()
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.split(';').next().unwrap_or(v).trim().to_string());
+        let bytes = response.bytes().await?.to_vec();
+
+        Ok(Some(MessageContent { bytes, content_type }))
+    }
+
+    /// Whether a video or audio message is ready to download: "processing",
+    /// "succeeded" or "failed"
+    pub async fn get_content_transcoding_status(&self, message_id: &str) -> Result<String, LineApiError> {
+        let url = self.data_url(&format!("/v2/bot/message/{}/content/transcoding", message_id));
+        let response = self.execute(|| self.client.get(&url), None).await?;
+
+        let transcoding = response.json::<TranscodingStatus>().await?;
+        Ok(transcoding.status)
+    }
+
+    /// Get user profile
+    pub async fn get_profile(&self, user_id: &str) -> Result<UserProfile, LineApiError> {
+        let url = self.url(&format!("/v2/bot/profile/{}", user_id));
+        let response = self.execute(|| self.client.get(&url), None).await?;
+
         let profile = response.json::<UserProfile>().await?;
         Ok(profile)
     }
+
+    /// Get the bot's own account info. Cheap, so it doubles as a check of
+    /// the access token.
+    pub async fn get_bot_info(&self) -> Result<BotInfo, LineApiError> {
+        let url = self.url("/v2/bot/info");
+        let response = self.execute(|| self.client.get(&url), None).await?;
+
+        Ok(response.json::<BotInfo>().await?)
+    }
+}
+
+/// Generate a fresh `X-Line-Retry-Key`
+pub fn new_retry_key() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Derive a stable retry key for one chunk of a split request. Only the low
+/// bits change, so the result is still a valid version 4 UUID.
+fn chunk_retry_key(retry_key: &str, index: usize) -> String {
+    match uuid::Uuid::parse_str(retry_key) {
+        Ok(base) if index > 0 => uuid::Uuid::from_u128(base.as_u128() ^ index as u128).to_string(),
+        Ok(base) => base.to_string(),
+        Err(_) => new_retry_key(),
+    }
+}
+
+/// Derive a stable retry key for one of several requests that make up a
+/// send, e.g. a multicast split by personalized text. The bits changed don't
+/// overlap with `chunk_retry_key`'s, so chunks of each part stay distinct.
+pub fn part_retry_key(retry_key: &str, part: usize) -> String {
+    match uuid::Uuid::parse_str(retry_key) {
+        Ok(base) if part > 0 => uuid::Uuid::from_u128(base.as_u128() ^ ((part as u128) << 32)).to_string(),
+        Ok(base) => base.to_string(),
+        Err(_) => new_retry_key(),
+    }
+}
+
+/// Binary content of a message sent by a user
+#[derive(Debug)]
+pub struct MessageContent {
+    pub bytes: Vec<u8>,
+    pub content_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TranscodingStatus {
+    status: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BotInfo {
+    pub user_id: String,
+    pub basic_id: String,
+    pub premium_id: Option<String>,
+    pub display_name: String,
+    pub picture_url: Option<String>,
+    pub chat_mode: String,
+    pub mark_as_read_mode: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct UserProfile {
     #[serde(rename = "userId")]
     pub user_id: String,
@@ -0,0 +1,521 @@
+//! LINE message objects: everything that can go in the `messages` array of a
+//! push, reply, multicast, narrowcast or broadcast request.
+
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of message objects in one send request
+pub const MAX_MESSAGES_PER_REQUEST: usize = 5;
+
+const MAX_TEXT_LENGTH: usize = 5000;
+const MAX_ALT_TEXT_LENGTH: usize = 1500;
+const MAX_ACTION_LABEL_LENGTH: usize = 20;
+const MAX_QUICK_REPLY_ITEMS: usize = 13;
+const MAX_CAROUSEL_COLUMNS: usize = 10;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum Message {
+    #[serde(rename = "text")]
+    Text {
+        text: String,
+        #[serde(flatten)]
+        options: MessageOptions,
+    },
+    #[serde(rename = "sticker")]
+    Sticker {
+        #[serde(rename = "packageId")]
+        package_id: String,
+        #[serde(rename = "stickerId")]
+        sticker_id: String,
+        #[serde(flatten)]
+        options: MessageOptions,
+    },
+    #[serde(rename = "image")]
+    Image {
+        #[serde(rename = "originalContentUrl")]
+        original_content_url: String,
+        #[serde(rename = "previewImageUrl")]
+        preview_image_url: String,
+        #[serde(flatten)]
+        options: MessageOptions,
+    },
+    #[serde(rename = "video")]
+    Video {
+        #[serde(rename = "originalContentUrl")]
+        original_content_url: String,
+        #[serde(rename = "previewImageUrl")]
+        preview_image_url: String,
+        #[serde(rename = "trackingId", default, skip_serializing_if = "Option::is_none")]
+        tracking_id: Option<String>,
+        #[serde(flatten)]
+        options: MessageOptions,
+    },
+    #[serde(rename = "audio")]
+    Audio {
+        #[serde(rename = "originalContentUrl")]
+        original_content_url: String,
+        /// Length of the audio in milliseconds
+        duration: i64,
+        #[serde(flatten)]
+        options: MessageOptions,
+    },
+    #[serde(rename = "location")]
+    Location {
+        title: String,
+        address: String,
+        latitude: f64,
+        longitude: f64,
+        #[serde(flatten)]
+        options: MessageOptions,
+    },
+    #[serde(rename = "imagemap")]
+    Imagemap {
+        #[serde(rename = "baseUrl")]
+        base_url: String,
+        #[serde(rename = "altText")]
+        alt_text: String,
+        #[serde(rename = "baseSize")]
+        base_size: ImagemapBaseSize,
+        actions: Vec<ImagemapAction>,
+        #[serde(flatten)]
+        options: MessageOptions,
+    },
+    #[serde(rename = "template")]
+    Template {
+        #[serde(rename = "altText")]
+        alt_text: String,
+        template: Box<Template>,
+        #[serde(flatten)]
+        options: MessageOptions,
+    },
+    #[serde(rename = "flex")]
+    Flex {
+        #[serde(rename = "altText")]
+        alt_text: String,
+        contents: serde_json::Value,
+        #[serde(flatten)]
+        options: MessageOptions,
+    },
+}
+
+/// Properties shared by every message type
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct MessageOptions {
+    #[serde(rename = "quickReply", default, skip_serializing_if = "Option::is_none")]
+    pub quick_reply: Option<QuickReply>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<Sender>,
+}
+
+/// Overrides the bot's display name and icon for one message
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Sender {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(rename = "iconUrl", default, skip_serializing_if = "Option::is_none")]
+    pub icon_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuickReply {
+    pub items: Vec<QuickReplyItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuickReplyItem {
+    /// Always `action`
+    #[serde(rename = "type", default = "quick_reply_item_type")]
+    pub item_type: String,
+    #[serde(rename = "imageUrl", default, skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    pub action: Action,
+}
+
+fn quick_reply_item_type() -> String {
+    "action".to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum Action {
+    #[serde(rename = "postback")]
+    Postback {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        label: Option<String>,
+        data: String,
+        #[serde(rename = "displayText", default, skip_serializing_if = "Option::is_none")]
+        display_text: Option<String>,
+        #[serde(rename = "inputOption", default, skip_serializing_if = "Option::is_none")]
+        input_option: Option<String>,
+        #[serde(rename = "fillInText", default, skip_serializing_if = "Option::is_none")]
+        fill_in_text: Option<String>,
+    },
+    #[serde(rename = "message")]
+    Message {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        label: Option<String>,
+        text: String,
+    },
+    #[serde(rename = "uri")]
+    Uri {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        label: Option<String>,
+        uri: String,
+    },
+    #[serde(rename = "datetimepicker")]
+    DatetimePicker {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        label: Option<String>,
+        data: String,
+        /// date, time or datetime
+        mode: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        initial: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min: Option<String>,
+    },
+    #[serde(rename = "camera")]
+    Camera { label: String },
+    #[serde(rename = "cameraRoll")]
+    CameraRoll { label: String },
+    #[serde(rename = "location")]
+    Location { label: String },
+    #[serde(rename = "richmenuswitch")]
+    RichMenuSwitch {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        label: Option<String>,
+        #[serde(rename = "richMenuAliasId")]
+        rich_menu_alias_id: String,
+        data: String,
+    },
+    #[serde(rename = "clipboard")]
+    Clipboard {
+        label: String,
+        #[serde(rename = "clipboardText")]
+        clipboard_text: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImagemapBaseSize {
+    pub width: i64,
+    pub height: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImagemapArea {
+    pub x: i64,
+    pub y: i64,
+    pub width: i64,
+    pub height: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum ImagemapAction {
+    #[serde(rename = "uri")]
+    Uri {
+        #[serde(rename = "linkUri")]
+        link_uri: String,
+        area: ImagemapArea,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        label: Option<String>,
+    },
+    #[serde(rename = "message")]
+    Message {
+        text: String,
+        area: ImagemapArea,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        label: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum Template {
+    #[serde(rename = "buttons")]
+    Buttons {
+        #[serde(rename = "thumbnailImageUrl", default, skip_serializing_if = "Option::is_none")]
+        thumbnail_image_url: Option<String>,
+        #[serde(rename = "imageAspectRatio", default, skip_serializing_if = "Option::is_none")]
+        image_aspect_ratio: Option<String>,
+        #[serde(rename = "imageSize", default, skip_serializing_if = "Option::is_none")]
+        image_size: Option<String>,
+        #[serde(rename = "imageBackgroundColor", default, skip_serializing_if = "Option::is_none")]
+        image_background_color: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        text: String,
+        #[serde(rename = "defaultAction", default, skip_serializing_if = "Option::is_none")]
+        default_action: Option<Box<Action>>,
+        actions: Vec<Action>,
+    },
+    #[serde(rename = "confirm")]
+    Confirm {
+        text: String,
+        actions: Vec<Action>,
+    },
+    #[serde(rename = "carousel")]
+    Carousel {
+        columns: Vec<CarouselColumn>,
+        #[serde(rename = "imageAspectRatio", default, skip_serializing_if = "Option::is_none")]
+        image_aspect_ratio: Option<String>,
+        #[serde(rename = "imageSize", default, skip_serializing_if = "Option::is_none")]
+        image_size: Option<String>,
+    },
+    #[serde(rename = "image_carousel")]
+    ImageCarousel {
+        columns: Vec<ImageCarouselColumn>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CarouselColumn {
+    #[serde(rename = "thumbnailImageUrl", default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail_image_url: Option<String>,
+    #[serde(rename = "imageBackgroundColor", default, skip_serializing_if = "Option::is_none")]
+    pub image_background_color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub text: String,
+    #[serde(rename = "defaultAction", default, skip_serializing_if = "Option::is_none")]
+    pub default_action: Option<Action>,
+    pub actions: Vec<Action>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImageCarouselColumn {
+    #[serde(rename = "imageUrl")]
+    pub image_url: String,
+    pub action: Action,
+}
+
+impl Message {
+    /// Plain text message
+    pub fn text(text: impl Into<String>) -> Self {
+        Message::Text {
+            text: text.into(),
+            options: MessageOptions::default(),
+        }
+    }
+
+    fn options(&self) -> &MessageOptions {
+        match self {
+            Message::Text { options, .. }
+            | Message::Sticker { options, .. }
+            | Message::Image { options, .. }
+            | Message::Video { options, .. }
+            | Message::Audio { options, .. }
+            | Message::Location { options, .. }
+            | Message::Imagemap { options, .. }
+            | Message::Template { options, .. }
+            | Message::Flex { options, .. } => options,
+        }
+    }
+
+    /// Short human-readable summary, used as the list text of a scheduled message
+    pub fn preview_text(&self) -> String {
+        match self {
+            Message::Text { text, .. } => text.clone(),
+            Message::Sticker { .. } => "[スタンプ]".to_string(),
+            Message::Image { .. } => "[画像]".to_string(),
+            Message::Video { .. } => "[動画]".to_string(),
+            Message::Audio { .. } => "[音声]".to_string(),
+            Message::Location { title, .. } => format!("[位置情報] {}", title),
+            Message::Imagemap { alt_text, .. }
+            | Message::Template { alt_text, .. }
+            | Message::Flex { alt_text, .. } => alt_text.clone(),
+        }
+    }
+
+    /// Check the message against LINE's documented limits
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Message::Text { text, .. } => {
+                check_not_empty("text", text)?;
+                check_length("text", text, MAX_TEXT_LENGTH)?;
+            }
+            Message::Sticker { package_id, sticker_id, .. } => {
+                check_not_empty("packageId", package_id)?;
+                check_not_empty("stickerId", sticker_id)?;
+            }
+            Message::Image { original_content_url, preview_image_url, .. }
+            | Message::Video { original_content_url, preview_image_url, .. } => {
+                check_https("originalContentUrl", original_content_url)?;
+                check_https("previewImageUrl", preview_image_url)?;
+            }
+            Message::Audio { original_content_url, duration, .. } => {
+                check_https("originalContentUrl", original_content_url)?;
+                if *duration <= 0 {
+                    return Err("audio duration must be positive".to_string());
+                }
+            }
+            Message::Location { title, address, latitude, longitude, .. } => {
+                check_not_empty("title", title)?;
+                check_not_empty("address", address)?;
+                if !(-90.0..=90.0).contains(latitude) || !(-180.0..=180.0).contains(longitude) {
+                    return Err("location coordinates out of range".to_string());
+                }
+            }
+            Message::Imagemap { base_url, alt_text, actions, .. } => {
+                check_https("baseUrl", base_url)?;
+                check_alt_text(alt_text)?;
+                if actions.is_empty() {
+                    return Err("imagemap needs at least one action".to_string());
+                }
+            }
+            Message::Template { alt_text, template, .. } => {
+                check_alt_text(alt_text)?;
+                template.validate()?;
+            }
+            Message::Flex { alt_text, contents, .. } => {
+                check_alt_text(alt_text)?;
+                if !contents.is_object() {
+                    return Err("flex contents must be a bubble or carousel object".to_string());
+                }
+            }
+        }
+
+        let options = self.options();
+        if let Some(quick_reply) = &options.quick_reply {
+            if quick_reply.items.is_empty() || quick_reply.items.len() > MAX_QUICK_REPLY_ITEMS {
+                return Err(format!("quick reply needs 1 to {} items", MAX_QUICK_REPLY_ITEMS));
+            }
+            for item in &quick_reply.items {
+                item.action.validate()?;
+            }
+        }
+        if let Some(sender) = &options.sender {
+            if let Some(name) = &sender.name {
+                check_length("sender name", name, 20)?;
+            }
+            if let Some(icon_url) = &sender.icon_url {
+                check_https("sender iconUrl", icon_url)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Template {
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            Template::Buttons { text, actions, .. } => {
+                check_not_empty("buttons text", text)?;
+                check_action_count("buttons", actions, 1, 4)?;
+            }
+            Template::Confirm { text, actions } => {
+                check_not_empty("confirm text", text)?;
+                check_action_count("confirm", actions, 2, 2)?;
+            }
+            Template::Carousel { columns, .. } => {
+                if columns.is_empty() || columns.len() > MAX_CAROUSEL_COLUMNS {
+                    return Err(format!("carousel needs 1 to {} columns", MAX_CAROUSEL_COLUMNS));
+                }
+                for column in columns {
+                    check_not_empty("carousel column text", &column.text)?;
+                    check_action_count("carousel column", &column.actions, 1, 3)?;
+                }
+                // LINE rejects carousels whose columns have different numbers of actions
+                if columns.iter().any(|c| c.actions.len() != columns[0].actions.len()) {
+                    return Err("all carousel columns must have the same number of actions".to_string());
+                }
+            }
+            Template::ImageCarousel { columns } => {
+                if columns.is_empty() || columns.len() > MAX_CAROUSEL_COLUMNS {
+                    return Err(format!("image carousel needs 1 to {} columns", MAX_CAROUSEL_COLUMNS));
+                }
+                for column in columns {
+                    check_https("imageUrl", &column.image_url)?;
+                    column.action.validate()?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Action {
+    fn label(&self) -> Option<&str> {
+        match self {
+            Action::Postback { label, .. }
+            | Action::Message { label, .. }
+            | Action::Uri { label, .. }
+            | Action::DatetimePicker { label, .. }
+            | Action::RichMenuSwitch { label, .. } => label.as_deref(),
+            Action::Camera { label }
+            | Action::CameraRoll { label }
+            | Action::Location { label }
+            | Action::Clipboard { label, .. } => Some(label),
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if let Some(label) = self.label() {
+            check_length("action label", label, MAX_ACTION_LABEL_LENGTH)?;
+        }
+        if let Action::DatetimePicker { mode, .. } = self {
+            if !matches!(mode.as_str(), "date" | "time" | "datetime") {
+                return Err(format!("invalid datetimepicker mode '{}'", mode));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_action_count(what: &str, actions: &[Action], min: usize, max: usize) -> Result<(), String> {
+    if actions.len() < min || actions.len() > max {
+        let expected = if min == max { format!("exactly {}", min) } else { format!("{} to {}", min, max) };
+        return Err(format!("{} needs {} actions, got {}", what, expected, actions.len()));
+    }
+    actions.iter().try_for_each(Action::validate)
+}
+
+fn check_not_empty(field: &str, value: &str) -> Result<(), String> {
+    if value.trim().is_empty() {
+        return Err(format!("{} must not be empty", field));
+    }
+    Ok(())
+}
+
+fn check_length(field: &str, value: &str, max: usize) -> Result<(), String> {
+    if value.chars().count() > max {
+        return Err(format!("{} is longer than {} characters", field, max));
+    }
+    Ok(())
+}
+
+fn check_alt_text(alt_text: &str) -> Result<(), String> {
+    check_not_empty("altText", alt_text)?;
+    check_length("altText", alt_text, MAX_ALT_TEXT_LENGTH)
+}
+
+fn check_https(field: &str, url: &str) -> Result<(), String> {
+    if !url.starts_with("https://") {
+        return Err(format!("{} must be an HTTPS URL", field));
+    }
+    Ok(())
+}
+
+/// Validate a whole send request: 1 to 5 messages, each within LINE's limits
+pub fn validate_messages(messages: &[Message]) -> Result<(), String> {
+    if messages.is_empty() || messages.len() > MAX_MESSAGES_PER_REQUEST {
+        return Err(format!(
+            "A request needs 1 to {} messages, got {}",
+            MAX_MESSAGES_PER_REQUEST,
+            messages.len()
+        ));
+    }
+
+    for (index, message) in messages.iter().enumerate() {
+        message
+            .validate()
+            .map_err(|e| format!("Message {}: {}", index + 1, e))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,810 @@
+//! Local stand-in for the LINE Messaging API (enabled with the `line-mock` feature).
+//!
+//! Records every message request it receives and serves profiles, so the
+//! scheduler, reminders and commands can run end-to-end without outbound network.
+//! Point `LINE_API_BASE_URL` (or the `line_api_base_url` setting) at it.
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::api::line_client::UserProfile;
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RecordedRequests {
+    pub pushes: Vec<serde_json::Value>,
+    pub replies: Vec<serde_json::Value>,
+    pub broadcasts: Vec<serde_json::Value>,
+    pub multicasts: Vec<serde_json::Value>,
+    pub narrowcasts: Vec<serde_json::Value>,
+}
+
+#[derive(Clone, Default)]
+pub struct MockLineServer {
+    recorded: Arc<Mutex<RecordedRequests>>,
+    profiles: Arc<Mutex<HashMap<String, UserProfile>>>,
+    request_counter: Arc<AtomicU64>,
+    /// Retry keys already accepted, answered with 409 like the real API
+    retry_keys: Arc<Mutex<HashSet<String>>>,
+    /// Statuses to answer the next message requests with (to exercise retries)
+    failures: Arc<Mutex<VecDeque<StatusCode>>>,
+    /// Narrowcast request IDs and their recipient counts
+    narrowcast_ids: Arc<Mutex<HashMap<String, i64>>>,
+    rich_menus: Arc<Mutex<RichMenuState>>,
+    /// Message content served by the content endpoint
+    contents: Arc<Mutex<HashMap<String, MockContent>>>,
+}
+
+#[derive(Debug, Clone)]
+struct MockContent {
+    content_type: String,
+    bytes: Vec<u8>,
+    /// Transcoding status checks that answer "processing" before "succeeded"
+    processing_polls: u32,
+}
+
+/// Served for message IDs without registered content: a 1x1 PNG
+const DEFAULT_CONTENT: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x01\0\0\0\x01\x08\x06\0\0\0\x1f\x15\xc4\x89\0\0\0\rIDATx\x9cc\xf8\x0f\0\0\x01\x01\x01\0\x18\xdd\x8d\xb0\0\0\0\0IEND\xaeB`\x82";
+
+/// Rich menus, images, links and aliases held by the mock
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RichMenuState {
+    pub menus: HashMap<String, serde_json::Value>,
+    /// Rich menu IDs that have an uploaded image, with its content type
+    pub images: HashMap<String, String>,
+    pub default_menu: Option<String>,
+    /// User ID -> linked rich menu ID
+    pub user_links: HashMap<String, String>,
+    /// Alias ID -> rich menu ID
+    pub aliases: HashMap<String, String>,
+}
+
+impl MockLineServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/v2/bot/message/push", post(push))
+            .route("/v2/bot/message/reply", post(reply))
+            .route("/v2/bot/message/broadcast", post(broadcast))
+            .route("/v2/bot/message/multicast", post(multicast))
+            .route("/v2/bot/message/narrowcast", post(narrowcast))
+            .route("/v2/bot/message/progress/narrowcast", get(narrowcast_progress))
+            .route("/v2/bot/profile/:user_id", get(profile))
+            .route("/v2/bot/info", get(bot_info))
+            .route("/v2/bot/richmenu", post(create_rich_menu))
+            .route("/v2/bot/richmenu/list", get(list_rich_menus))
+            .route("/v2/bot/richmenu/:rich_menu_id", get(get_rich_menu).delete(delete_rich_menu))
+            .route("/v2/bot/richmenu/:rich_menu_id/content", post(upload_rich_menu_image))
+            .route("/v2/bot/richmenu/bulk/link", post(bulk_link_rich_menu))
+            .route("/v2/bot/richmenu/bulk/unlink", post(bulk_unlink_rich_menu))
+            .route("/v2/bot/richmenu/alias", post(create_rich_menu_alias))
+            .route("/v2/bot/richmenu/alias/list", get(list_rich_menu_aliases))
+            .route(
+                "/v2/bot/richmenu/alias/:alias_id",
+                get(get_rich_menu_alias).post(update_rich_menu_alias).delete(delete_rich_menu_alias),
+            )
+            .route(
+                "/v2/bot/user/all/richmenu",
+                get(get_default_rich_menu).delete(clear_default_rich_menu),
+            )
+            .route("/v2/bot/user/all/richmenu/:rich_menu_id", post(set_default_rich_menu))
+            .route(
+                "/v2/bot/user/:user_id/richmenu",
+                get(get_user_rich_menu).delete(unlink_user_rich_menu),
+            )
+            .route("/v2/bot/user/:user_id/richmenu/:rich_menu_id", post(link_user_rich_menu))
+            .route("/mock/richmenus", get(rich_menu_state))
+            .route("/v2/bot/message/:message_id/content", get(message_content))
+            .route("/v2/bot/message/:message_id/content/transcoding", get(content_transcoding))
+            .route("/mock/content/:message_id", post(register_content))
+            .route("/mock/requests", get(list_requests).delete(clear_requests))
+            .route("/mock/fail/:status", post(inject_failure))
+            .with_state(self.clone())
+    }
+
+    /// Bind and serve in the background, returning the bound address
+    /// (pass port 0 to pick a free port)
+    pub async fn spawn(self, addr: SocketAddr) -> Result<SocketAddr, std::io::Error> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let app = self.router();
+
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                tracing::error!("Mock LINE server stopped: {}", e);
+            }
+        });
+
+        tracing::info!("Mock LINE API listening on http://{}", local_addr);
+        Ok(local_addr)
+    }
+
+    /// Snapshot of everything received so far
+    pub fn recorded(&self) -> RecordedRequests {
+        self.recorded.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        *self.recorded.lock().unwrap() = RecordedRequests::default();
+    }
+
+    /// Serve a fixed profile for a user instead of a generated one
+    pub fn add_profile(&self, profile: UserProfile) {
+        self.profiles
+            .lock()
+            .unwrap()
+            .insert(profile.user_id.clone(), profile);
+    }
+
+    /// Snapshot of the rich menus, links and aliases
+    pub fn rich_menu_state(&self) -> RichMenuState {
+        self.rich_menus.lock().unwrap().clone()
+    }
+
+    /// Serve `bytes` as the content of a message. With `processing_polls`,
+    /// that many transcoding checks answer "processing" first.
+    pub fn add_content(&self, message_id: &str, content_type: &str, bytes: Vec<u8>, processing_polls: u32) {
+        self.contents.lock().unwrap().insert(
+            message_id.to_string(),
+            MockContent {
+                content_type: content_type.to_string(),
+                bytes,
+                processing_polls,
+            },
+        );
+    }
+
+    /// Answer the next message request with `status` instead of accepting it
+    pub fn fail_next(&self, status: StatusCode) {
+        self.failures.lock().unwrap().push_back(status);
+    }
+
+    fn next_request_id(&self) -> String {
+        format!("mock-{:016x}", self.request_counter.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Build a response carrying an `x-line-request-id` like the real API
+    fn respond(&self, status: StatusCode, body: serde_json::Value) -> Response {
+        (status, [("x-line-request-id", self.next_request_id())], Json(body)).into_response()
+    }
+}
+
+fn is_authorized(headers: &HeaderMap) -> bool {
+    headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with("Bearer ") && v.len() > "Bearer ".len())
+        .unwrap_or(false)
+}
+
+fn unauthorized(server: &MockLineServer) -> Response {
+    server.respond(
+        StatusCode::UNAUTHORIZED,
+        serde_json::json!({ "message": "Authentication failed. Confirm that the access token in the authorization header is valid." }),
+    )
+}
+
+/// Common checks for message endpoints: auth, injected failures and retry keys.
+/// Returns a response to send instead of accepting the request.
+fn reject_message_request(server: &MockLineServer, headers: &HeaderMap) -> Option<Response> {
+    if !is_authorized(headers) {
+        return Some(unauthorized(server));
+    }
+
+    if let Some(status) = server.failures.lock().unwrap().pop_front() {
+        let mut response = server.respond(status, serde_json::json!({ "message": "Injected failure" }));
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            response.headers_mut().insert("retry-after", "1".parse().unwrap());
+        }
+        return Some(response);
+    }
+
+    let retry_key = headers.get("x-line-retry-key").and_then(|v| v.to_str().ok());
+    if let Some(key) = retry_key {
+        if !server.retry_keys.lock().unwrap().insert(key.to_string()) {
+            return Some(server.respond(
+                StatusCode::CONFLICT,
+                serde_json::json!({ "message": "The retry key is already accepted" }),
+            ));
+        }
+    }
+
+    None
+}
+
+async fn push(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> Response {
+    if let Some(response) = reject_message_request(&server, &headers) {
+        return response;
+    }
+    server.recorded.lock().unwrap().pushes.push(body);
+    server.respond(StatusCode::OK, serde_json::json!({}))
+}
+
+async fn reply(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> Response {
+    if let Some(response) = reject_message_request(&server, &headers) {
+        return response;
+    }
+    server.recorded.lock().unwrap().replies.push(body);
+    server.respond(StatusCode::OK, serde_json::json!({}))
+}
+
+async fn broadcast(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> Response {
+    if let Some(response) = reject_message_request(&server, &headers) {
+        return response;
+    }
+    server.recorded.lock().unwrap().broadcasts.push(body);
+    server.respond(StatusCode::OK, serde_json::json!({}))
+}
+
+async fn multicast(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> Response {
+    if let Some(response) = reject_message_request(&server, &headers) {
+        return response;
+    }
+    server.recorded.lock().unwrap().multicasts.push(body);
+    server.respond(StatusCode::OK, serde_json::json!({}))
+}
+
+async fn narrowcast(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> Response {
+    if let Some(response) = reject_message_request(&server, &headers) {
+        return response;
+    }
+    server.recorded.lock().unwrap().narrowcasts.push(body);
+
+    let response = server.respond(StatusCode::ACCEPTED, serde_json::json!({}));
+    if let Some(request_id) = response.headers().get("x-line-request-id").and_then(|v| v.to_str().ok()) {
+        server.narrowcast_ids.lock().unwrap().insert(request_id.to_string(), 0);
+    }
+    response
+}
+
+#[derive(serde::Deserialize)]
+struct ProgressQuery {
+    #[serde(rename = "requestId")]
+    request_id: String,
+}
+
+/// Every narrowcast finishes immediately in the mock
+async fn narrowcast_progress(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Query(query): Query<ProgressQuery>,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    let target_count = server.narrowcast_ids.lock().unwrap().get(&query.request_id).copied();
+    match target_count {
+        Some(count) => server.respond(
+            StatusCode::OK,
+            serde_json::json!({
+                "phase": "succeeded",
+                "successCount": count,
+                "failureCount": 0,
+                "targetCount": count,
+            }),
+        ),
+        None => server.respond(StatusCode::NOT_FOUND, serde_json::json!({ "message": "Not found" })),
+    }
+}
+
+async fn bot_info(State(server): State<MockLineServer>, headers: HeaderMap) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    server.respond(
+        StatusCode::OK,
+        serde_json::json!({
+            "userId": "Umockbot",
+            "basicId": "@mockbot",
+            "displayName": "Mock Bot",
+            "chatMode": "bot",
+            "markAsReadMode": "auto",
+        }),
+    )
+}
+
+async fn profile(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Path(user_id): Path<String>,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    let profile = server
+        .profiles
+        .lock()
+        .unwrap()
+        .get(&user_id)
+        .cloned()
+        .unwrap_or_else(|| UserProfile {
+            display_name: format!(
+                "Mock User {}",
+                user_id.chars().skip(user_id.chars().count().saturating_sub(4)).collect::<String>()
+            ),
+            user_id: user_id.clone(),
+            picture_url: None,
+            status_message: None,
+        });
+
+    server.respond(StatusCode::OK, serde_json::to_value(profile).unwrap_or_default())
+}
+
+fn not_found(server: &MockLineServer) -> Response {
+    server.respond(StatusCode::NOT_FOUND, serde_json::json!({ "message": "Not found" }))
+}
+
+fn bad_request(server: &MockLineServer, message: &str) -> Response {
+    server.respond(StatusCode::BAD_REQUEST, serde_json::json!({ "message": message }))
+}
+
+/// Like the real API, a menu can only be shown once it has an image
+fn check_displayable(server: &MockLineServer, state: &RichMenuState, rich_menu_id: &str) -> Option<Response> {
+    if !state.menus.contains_key(rich_menu_id) {
+        return Some(not_found(server));
+    }
+    if !state.images.contains_key(rich_menu_id) {
+        return Some(bad_request(server, "must upload richmenu image before applying it to user"));
+    }
+    None
+}
+
+async fn create_rich_menu(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Json(mut body): Json<serde_json::Value>,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    let rich_menu_id = format!("richmenu-{:032x}", server.request_counter.fetch_add(1, Ordering::Relaxed));
+    body["richMenuId"] = serde_json::Value::String(rich_menu_id.clone());
+    server.rich_menus.lock().unwrap().menus.insert(rich_menu_id.clone(), body);
+
+    server.respond(StatusCode::OK, serde_json::json!({ "richMenuId": rich_menu_id }))
+}
+
+async fn list_rich_menus(State(server): State<MockLineServer>, headers: HeaderMap) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    let menus: Vec<serde_json::Value> = server.rich_menus.lock().unwrap().menus.values().cloned().collect();
+    server.respond(StatusCode::OK, serde_json::json!({ "richmenus": menus }))
+}
+
+async fn get_rich_menu(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Path(rich_menu_id): Path<String>,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    let menu = server.rich_menus.lock().unwrap().menus.get(&rich_menu_id).cloned();
+    match menu {
+        Some(menu) => server.respond(StatusCode::OK, menu),
+        None => not_found(&server),
+    }
+}
+
+async fn delete_rich_menu(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Path(rich_menu_id): Path<String>,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    let mut state = server.rich_menus.lock().unwrap();
+    if state.menus.remove(&rich_menu_id).is_none() {
+        return not_found(&server);
+    }
+    state.images.remove(&rich_menu_id);
+    state.user_links.retain(|_, id| *id != rich_menu_id);
+    state.aliases.retain(|_, id| *id != rich_menu_id);
+    if state.default_menu.as_deref() == Some(rich_menu_id.as_str()) {
+        state.default_menu = None;
+    }
+
+    server.respond(StatusCode::OK, serde_json::json!({}))
+}
+
+async fn upload_rich_menu_image(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Path(rich_menu_id): Path<String>,
+    body: Bytes,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    let content_type = headers
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    if !matches!(content_type.as_str(), "image/jpeg" | "image/png") || body.is_empty() {
+        return bad_request(&server, "Image must be a JPEG or PNG file");
+    }
+
+    let mut state = server.rich_menus.lock().unwrap();
+    if !state.menus.contains_key(&rich_menu_id) {
+        return not_found(&server);
+    }
+    if state.images.contains_key(&rich_menu_id) {
+        return bad_request(&server, "An image has already been uploaded to the richmenu");
+    }
+    state.images.insert(rich_menu_id, content_type);
+
+    server.respond(StatusCode::OK, serde_json::json!({}))
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BulkLinkBody {
+    rich_menu_id: Option<String>,
+    user_ids: Vec<String>,
+}
+
+async fn bulk_link_rich_menu(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Json(body): Json<BulkLinkBody>,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+    let Some(rich_menu_id) = body.rich_menu_id else {
+        return bad_request(&server, "richMenuId is required");
+    };
+
+    let mut state = server.rich_menus.lock().unwrap();
+    if let Some(response) = check_displayable(&server, &state, &rich_menu_id) {
+        return response;
+    }
+    for user_id in body.user_ids {
+        state.user_links.insert(user_id, rich_menu_id.clone());
+    }
+
+    server.respond(StatusCode::ACCEPTED, serde_json::json!({}))
+}
+
+async fn bulk_unlink_rich_menu(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Json(body): Json<BulkLinkBody>,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    let mut state = server.rich_menus.lock().unwrap();
+    for user_id in &body.user_ids {
+        state.user_links.remove(user_id);
+    }
+
+    server.respond(StatusCode::ACCEPTED, serde_json::json!({}))
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AliasBody {
+    rich_menu_alias_id: Option<String>,
+    rich_menu_id: String,
+}
+
+async fn create_rich_menu_alias(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Json(body): Json<AliasBody>,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+    let Some(alias_id) = body.rich_menu_alias_id else {
+        return bad_request(&server, "richMenuAliasId is required");
+    };
+
+    let mut state = server.rich_menus.lock().unwrap();
+    if let Some(response) = check_displayable(&server, &state, &body.rich_menu_id) {
+        return response;
+    }
+    if state.aliases.contains_key(&alias_id) {
+        return bad_request(&server, "conflict richmenu alias id");
+    }
+    state.aliases.insert(alias_id, body.rich_menu_id);
+
+    server.respond(StatusCode::OK, serde_json::json!({}))
+}
+
+async fn list_rich_menu_aliases(State(server): State<MockLineServer>, headers: HeaderMap) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    let aliases: Vec<serde_json::Value> = server
+        .rich_menus
+        .lock()
+        .unwrap()
+        .aliases
+        .iter()
+        .map(|(alias_id, rich_menu_id)| serde_json::json!({ "richMenuAliasId": alias_id, "richMenuId": rich_menu_id }))
+        .collect();
+    server.respond(StatusCode::OK, serde_json::json!({ "aliases": aliases }))
+}
+
+async fn get_rich_menu_alias(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Path(alias_id): Path<String>,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    let rich_menu_id = server.rich_menus.lock().unwrap().aliases.get(&alias_id).cloned();
+    match rich_menu_id {
+        Some(rich_menu_id) => server.respond(
+            StatusCode::OK,
+            serde_json::json!({ "richMenuAliasId": alias_id, "richMenuId": rich_menu_id }),
+        ),
+        None => not_found(&server),
+    }
+}
+
+async fn update_rich_menu_alias(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Path(alias_id): Path<String>,
+    Json(body): Json<AliasBody>,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    let mut state = server.rich_menus.lock().unwrap();
+    if !state.aliases.contains_key(&alias_id) {
+        return not_found(&server);
+    }
+    if let Some(response) = check_displayable(&server, &state, &body.rich_menu_id) {
+        return response;
+    }
+    state.aliases.insert(alias_id, body.rich_menu_id);
+
+    server.respond(StatusCode::OK, serde_json::json!({}))
+}
+
+async fn delete_rich_menu_alias(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Path(alias_id): Path<String>,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    match server.rich_menus.lock().unwrap().aliases.remove(&alias_id) {
+        Some(_) => server.respond(StatusCode::OK, serde_json::json!({})),
+        None => not_found(&server),
+    }
+}
+
+async fn get_default_rich_menu(State(server): State<MockLineServer>, headers: HeaderMap) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    let default_menu = server.rich_menus.lock().unwrap().default_menu.clone();
+    match default_menu {
+        Some(rich_menu_id) => server.respond(StatusCode::OK, serde_json::json!({ "richMenuId": rich_menu_id })),
+        None => not_found(&server),
+    }
+}
+
+async fn set_default_rich_menu(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Path(rich_menu_id): Path<String>,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    let mut state = server.rich_menus.lock().unwrap();
+    if let Some(response) = check_displayable(&server, &state, &rich_menu_id) {
+        return response;
+    }
+    state.default_menu = Some(rich_menu_id);
+
+    server.respond(StatusCode::OK, serde_json::json!({}))
+}
+
+async fn clear_default_rich_menu(State(server): State<MockLineServer>, headers: HeaderMap) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    server.rich_menus.lock().unwrap().default_menu = None;
+    server.respond(StatusCode::OK, serde_json::json!({}))
+}
+
+async fn get_user_rich_menu(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Path(user_id): Path<String>,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    let rich_menu_id = server.rich_menus.lock().unwrap().user_links.get(&user_id).cloned();
+    match rich_menu_id {
+        Some(rich_menu_id) => server.respond(StatusCode::OK, serde_json::json!({ "richMenuId": rich_menu_id })),
+        None => not_found(&server),
+    }
+}
+
+async fn link_user_rich_menu(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Path((user_id, rich_menu_id)): Path<(String, String)>,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    let mut state = server.rich_menus.lock().unwrap();
+    if let Some(response) = check_displayable(&server, &state, &rich_menu_id) {
+        return response;
+    }
+    state.user_links.insert(user_id, rich_menu_id);
+
+    server.respond(StatusCode::OK, serde_json::json!({}))
+}
+
+async fn unlink_user_rich_menu(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Path(user_id): Path<String>,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    server.rich_menus.lock().unwrap().user_links.remove(&user_id);
+    server.respond(StatusCode::OK, serde_json::json!({}))
+}
+
+async fn rich_menu_state(State(server): State<MockLineServer>) -> impl IntoResponse {
+    Json(server.rich_menu_state())
+}
+
+async fn message_content(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Path(message_id): Path<String>,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    let content = match server.contents.lock().unwrap().get_mut(&message_id) {
+        Some(content) if content.processing_polls > 0 => {
+            content.processing_polls -= 1;
+            None
+        }
+        Some(content) => Some((content.content_type.clone(), content.bytes.clone())),
+        None => Some(("image/png".to_string(), DEFAULT_CONTENT.to_vec())),
+    };
+    let Some((content_type, bytes)) = content else {
+        // Still being prepared
+        return server.respond(StatusCode::ACCEPTED, serde_json::json!({}));
+    };
+
+    (
+        StatusCode::OK,
+        [("content-type", content_type), ("x-line-request-id", server.next_request_id())],
+        bytes,
+    )
+        .into_response()
+}
+
+async fn content_transcoding(
+    State(server): State<MockLineServer>,
+    headers: HeaderMap,
+    Path(message_id): Path<String>,
+) -> Response {
+    if !is_authorized(&headers) {
+        return unauthorized(&server);
+    }
+
+    let status = match server.contents.lock().unwrap().get_mut(&message_id) {
+        Some(content) if content.processing_polls > 0 => {
+            content.processing_polls -= 1;
+            "processing"
+        }
+        _ => "succeeded",
+    };
+    server.respond(StatusCode::OK, serde_json::json!({ "status": status }))
+}
+
+#[derive(serde::Deserialize)]
+struct RegisterContentQuery {
+    #[serde(default)]
+    processing: u32,
+}
+
+async fn register_content(
+    State(server): State<MockLineServer>,
+    Path(message_id): Path<String>,
+    Query(query): Query<RegisterContentQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> impl IntoResponse {
+    let content_type = headers
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("application/octet-stream");
+    server.add_content(&message_id, content_type, body.to_vec(), query.processing);
+    StatusCode::NO_CONTENT
+}
+
+async fn list_requests(State(server): State<MockLineServer>) -> impl IntoResponse {
+    Json(server.recorded())
+}
+
+async fn clear_requests(State(server): State<MockLineServer>) -> impl IntoResponse {
+    server.clear();
+    StatusCode::NO_CONTENT
+}
+
+async fn inject_failure(State(server): State<MockLineServer>, Path(status): Path<u16>) -> impl IntoResponse {
+    match StatusCode::from_u16(status) {
+        Ok(status) => {
+            server.fail_next(status);
+            StatusCode::NO_CONTENT
+        }
+        Err(_) => StatusCode::BAD_REQUEST,
+    }
+}
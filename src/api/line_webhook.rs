@@ -1,59 +1,204 @@
 use axum::{
-    extract::{State, Json},
+    body::Bytes,
+    extract::State,
     http::{HeaderMap, StatusCode},
     response::IntoResponse,
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use utoipa::ToSchema;
 use hmac::{Hmac, Mac};
 use sha2::Sha256;
 use base64::prelude::*;
 
 use crate::api::AppState;
-use crate::db::models::{User, Message};
-use crate::notification;
+use crate::db::models::{Event, User, Message};
+use crate::auto_reply;
+use crate::followers;
+use crate::inbox;
+use crate::media::{self, MediaStore};
+use crate::notification::{self, Notification, NotificationKind};
+use crate::settings;
 
 type HmacSha256 = Hmac<Sha256>;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct LineWebhook {
+    /// User ID of the bot that received the events
     destination: String,
-    events: Vec<LineEvent>,
+    /// Kept as raw JSON so every event can be stored verbatim, including
+    /// types we don't model yet
+    #[schema(value_type = Vec<Object>)]
+    events: Vec<serde_json::Value>,
+}
+
+/// Fields shared by every webhook event, with the type-specific part in `kind`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineEvent {
+    #[serde(default)]
+    pub webhook_event_id: Option<String>,
+    pub timestamp: i64,
+    /// Missing for some channel-level events
+    #[serde(default)]
+    pub source: Option<EventSource>,
+    #[serde(default)]
+    pub delivery_context: Option<DeliveryContext>,
+    /// "active", or "standby" while another channel handles the chat
+    #[serde(default)]
+    pub mode: Option<String>,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveryContext {
+    pub is_redelivery: bool,
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type")]
-pub enum LineEvent {
+pub enum EventKind {
     #[serde(rename = "message")]
     Message {
         #[serde(rename = "replyToken")]
         reply_token: String,
-        source: EventSource,
         message: LineMessage,
-        timestamp: i64,
     },
+    #[serde(rename = "unsend")]
+    Unsend { unsend: UnsendDetail },
     #[serde(rename = "follow")]
     Follow {
         #[serde(rename = "replyToken")]
         reply_token: String,
-        source: EventSource,
-        timestamp: i64,
+        #[serde(default)]
+        follow: Option<FollowDetail>,
     },
     #[serde(rename = "unfollow")]
-    Unfollow {
-        source: EventSource,
-        timestamp: i64,
+    Unfollow,
+    #[serde(rename = "join")]
+    Join,
+    #[serde(rename = "leave")]
+    Leave,
+    #[serde(rename = "memberJoined")]
+    MemberJoined { joined: Members },
+    #[serde(rename = "memberLeft")]
+    MemberLeft { left: Members },
+    #[serde(rename = "postback")]
+    Postback { postback: PostbackDetail },
+    #[serde(rename = "videoPlayComplete")]
+    VideoPlayComplete {
+        #[serde(rename = "videoPlayComplete")]
+        video_play_complete: VideoPlayCompleteDetail,
     },
+    #[serde(rename = "beacon")]
+    Beacon { beacon: BeaconDetail },
+    #[serde(rename = "accountLink")]
+    AccountLink { link: AccountLinkDetail },
     #[serde(other)]
     Other,
 }
 
+/// Where an event came from: a 1:1 chat, a group or a multi-person chat (room).
+/// `userId` is only present in groups and rooms when the user has consented.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum EventSource {
+    #[serde(rename = "user")]
+    User {
+        #[serde(rename = "userId")]
+        user_id: String,
+    },
+    #[serde(rename = "group")]
+    Group {
+        #[serde(rename = "groupId")]
+        group_id: String,
+        #[serde(rename = "userId", default)]
+        user_id: Option<String>,
+    },
+    #[serde(rename = "room")]
+    Room {
+        #[serde(rename = "roomId")]
+        room_id: String,
+        #[serde(rename = "userId", default)]
+        user_id: Option<String>,
+    },
+}
+
+impl EventSource {
+    pub fn source_type(&self) -> &'static str {
+        match self {
+            EventSource::User { .. } => "user",
+            EventSource::Group { .. } => "group",
+            EventSource::Room { .. } => "room",
+        }
+    }
+
+    pub fn user_id(&self) -> Option<&str> {
+        match self {
+            EventSource::User { user_id } => Some(user_id),
+            EventSource::Group { user_id, .. } | EventSource::Room { user_id, .. } => user_id.as_deref(),
+        }
+    }
+
+    /// Group or room ID; `None` for 1:1 chats
+    pub fn chat_id(&self) -> Option<&str> {
+        match self {
+            EventSource::User { .. } => None,
+            EventSource::Group { group_id, .. } => Some(group_id),
+            EventSource::Room { room_id, .. } => Some(room_id),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnsendDetail {
+    pub message_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FollowDetail {
+    /// True when a user who had blocked the account unblocks it
+    pub is_unblocked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Members {
+    pub members: Vec<EventSource>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PostbackDetail {
+    pub data: String,
+    /// Datetime picker and rich menu switch results
+    #[serde(default)]
+    pub params: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoPlayCompleteDetail {
+    pub tracking_id: String,
+}
+
 #[derive(Debug, Deserialize)]
-pub struct EventSource {
+pub struct BeaconDetail {
+    pub hwid: String,
+    /// enter, banner or stay
     #[serde(rename = "type")]
-    source_type: String,
-    #[serde(rename = "userId")]
-    user_id: String,
+    pub beacon_type: String,
+    #[serde(default)]
+    pub dm: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AccountLinkDetail {
+    /// ok or failed
+    pub result: String,
+    pub nonce: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -67,14 +212,34 @@ pub enum LineMessage {
     #[serde(rename = "image")]
     Image {
         id: String,
+        #[serde(rename = "contentProvider", default)]
+        content_provider: Option<ContentProvider>,
     },
     #[serde(rename = "video")]
     Video {
         id: String,
+        /// Length in milliseconds
+        #[serde(default)]
+        duration: Option<i64>,
+        #[serde(rename = "contentProvider", default)]
+        content_provider: Option<ContentProvider>,
     },
     #[serde(rename = "audio")]
     Audio {
         id: String,
+        /// Length in milliseconds
+        #[serde(default)]
+        duration: Option<i64>,
+        #[serde(rename = "contentProvider", default)]
+        content_provider: Option<ContentProvider>,
+    },
+    #[serde(rename = "file")]
+    File {
+        id: String,
+        #[serde(rename = "fileName")]
+        file_name: String,
+        #[serde(rename = "fileSize")]
+        file_size: i64,
     },
     #[serde(rename = "location")]
     Location {
@@ -96,53 +261,293 @@ pub enum LineMessage {
     Other,
 }
 
+/// Where the content of a media message lives: on LINE, or at URLs of the
+/// service that sent it ("external")
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ContentProvider {
+    #[serde(rename = "type")]
+    pub provider_type: String,
+    #[serde(rename = "originalContentUrl", default, skip_serializing_if = "Option::is_none")]
+    pub original_content_url: Option<String>,
+    #[serde(rename = "previewImageUrl", default, skip_serializing_if = "Option::is_none")]
+    pub preview_image_url: Option<String>,
+}
+
+/// Receive events from the LINE Platform
+#[utoipa::path(
+    post,
+    path = "/webhook/line",
+    tag = "webhook",
+    request_body = LineWebhook,
+    params(
+        ("x-line-signature" = String, Header, description = "base64(HMAC-SHA256(channel secret, body))"),
+    ),
+    responses(
+        (status = 200, description = "Events accepted"),
+        (status = 400, description = "Body is not a webhook payload"),
+        (status = 401, description = "Missing or invalid signature"),
+        (status = 500, description = "An event could not be handled; LINE's redelivery of it will be"),
+    ),
+)]
 pub async fn handle_webhook(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
-    Json(payload): Json<LineWebhook>,
+    body: Bytes,
 ) -> impl IntoResponse {
-    // Verify LINE signature
-    let signature = headers.get("x-line-signature");
-    if signature.is_none() {
-        tracing::warn!("Missing LINE signature header");
+    // Verify LINE signature against the raw request body
+    let signature = match headers.get("x-line-signature").and_then(|v| v.to_str().ok()) {
+        Some(sig) => sig,
+        None => {
+            tracing::warn!("Missing LINE signature header");
+            return StatusCode::UNAUTHORIZED;
+        }
+    };
+
+    let channel_secret = match settings::get(&state.db, settings::LINE_CHANNEL_SECRET).await {
+        Ok(Some(secret)) => secret,
+        Ok(None) => {
+            tracing::error!("LINE channel secret not configured, rejecting webhook");
+            return StatusCode::UNAUTHORIZED;
+        }
+        Err(e) => {
+            tracing::error!("Failed to load LINE channel secret: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    };
+
+    if !verify_signature(&channel_secret, &body, signature) {
+        tracing::warn!(
+            "Rejected LINE webhook with invalid signature ({} bytes, signature {:?})",
+            body.len(),
+            signature
+        );
         return StatusCode::UNAUTHORIZED;
     }
 
-    // TODO: Implement signature verification with channel secret
-    // For now, we'll skip verification in development
+    // Only parse the payload once the signature has been verified
+    let payload: LineWebhook = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(e) => {
+            tracing::warn!("Invalid LINE webhook payload: {}", e);
+            return StatusCode::BAD_REQUEST;
+        }
+    };
 
-    tracing::info!("Received LINE webhook with {} events", payload.events.len());
+    tracing::info!(
+        "Received LINE webhook for {} with {} events",
+        payload.destination,
+        payload.events.len()
+    );
 
-    for event in payload.events {
-        if let Err(e) = process_event(&state, event).await {
+    // Handle every event even if one fails. Failing the request asks LINE to
+    // redeliver it; the events that were handled are skipped then.
+    let mut status = StatusCode::OK;
+    for raw_event in payload.events {
+        if let Err(e) = process_event(&state, raw_event).await {
             tracing::error!("Failed to process event: {}", e);
+            status = StatusCode::INTERNAL_SERVER_ERROR;
         }
     }
 
-    StatusCode::OK
+    status
 }
 
-async fn process_event(state: &AppState, event: LineEvent) -> Result<(), anyhow::Error> {
-    match event {
-        LineEvent::Message { source, message, .. } => {
-            handle_message_event(state, &source.user_id, message).await?;
+/// Verify the `x-line-signature` header: base64(HMAC-SHA256(channel secret, body))
+pub fn verify_signature(channel_secret: &str, body: &[u8], signature: &str) -> bool {
+    let expected = match BASE64_STANDARD.decode(signature.trim()) {
+        Ok(bytes) => bytes,
+        Err(_) => return false,
+    };
+
+    let mut mac = match HmacSha256::new_from_slice(channel_secret.as_bytes()) {
+        Ok(mac) => mac,
+        Err(_) => return false,
+    };
+    mac.update(body);
+
+    // verify_slice compares in constant time
+    mac.verify_slice(&expected).is_ok()
+}
+
+/// LINE event timestamps are epoch milliseconds; store them like CURRENT_TIMESTAMP
+fn format_event_time(timestamp_ms: i64) -> String {
+    chrono::DateTime::from_timestamp_millis(timestamp_ms)
+        .unwrap_or_else(chrono::Utc::now)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+/// Store the raw event, then act on it. Redelivered events already stored
+/// (same `webhookEventId`) are skipped. An event that fails is removed again,
+/// so its redelivery is handled.
+async fn process_event(state: &AppState, raw_event: serde_json::Value) -> Result<(), anyhow::Error> {
+    let event_type = raw_event
+        .get("type")
+        .and_then(|t| t.as_str())
+        .unwrap_or("unknown")
+        .to_string();
+
+    let event = match LineEvent::deserialize(&raw_event) {
+        Ok(event) => event,
+        Err(e) => {
+            // Keep the payload so nothing is lost, even if we can't act on it
+            tracing::warn!("Could not parse {} event: {}", event_type, e);
+            let timestamp = raw_event.get("timestamp").and_then(|t| t.as_i64()).unwrap_or_default();
+            Event::create(
+                &state.db,
+                &event_type,
+                raw_event.get("webhookEventId").and_then(|id| id.as_str()),
+                None,
+                None,
+                None,
+                &raw_event.to_string(),
+                &format_event_time(timestamp),
+                false,
+            )
+            .await?;
+            return Ok(());
         }
-        LineEvent::Follow { source, .. } => {
-            tracing::info!("User followed: {}", source.user_id);
-            User::create(&state.db, &source.user_id, None).await?;
+    };
+
+    let source = event.source.as_ref();
+    let is_redelivery = event.delivery_context.as_ref().is_some_and(|c| c.is_redelivery);
+    let stored = Event::create(
+        &state.db,
+        &event_type,
+        event.webhook_event_id.as_deref(),
+        source.map(|s| s.source_type()),
+        source.and_then(|s| s.user_id()),
+        source.and_then(|s| s.chat_id()),
+        &raw_event.to_string(),
+        &format_event_time(event.timestamp),
+        is_redelivery,
+    )
+    .await?;
+
+    let Some(stored_id) = stored else {
+        tracing::info!(
+            "Skipping {} event {:?}, already processed",
+            event_type,
+            event.webhook_event_id
+        );
+        return Ok(());
+    };
+
+    if event.mode.as_deref() == Some("standby") {
+        tracing::debug!("Channel is in standby mode, not acting on {} event", event_type);
+        return Ok(());
+    }
+
+    if let Err(e) = handle_event(state, event).await {
+        Event::delete(&state.db, stored_id).await?;
+        return Err(e);
+    }
+    Ok(())
+}
+
+async fn handle_event(state: &AppState, event: LineEvent) -> Result<(), anyhow::Error> {
+    let occurred_at = format_event_time(event.timestamp);
+    let source = event.source;
+    let user_id = source.as_ref().and_then(|s| s.user_id()).map(str::to_string);
+
+    match event.kind {
+        EventKind::Message { reply_token, message } => match (&source, user_id) {
+            (Some(source), Some(user_id)) => {
+                handle_message_event(state, &user_id, source, &reply_token, message).await?;
+            }
+            _ => tracing::info!("Received message without a user ID, stored as event only"),
+        },
+        EventKind::Unsend { unsend } => {
+            let retracted = Message::mark_retracted(&state.db, &unsend.message_id).await?;
+            tracing::info!(
+                "Message {} was unsent by {:?} ({} stored message updated)",
+                unsend.message_id,
+                user_id,
+                retracted
+            );
+        }
+        EventKind::Follow { reply_token, follow } => {
+            let Some(user_id) = user_id else { return Ok(()) };
+            let unblocked = follow.is_some_and(|f| f.is_unblocked);
+            tracing::info!("User followed: {} (unblocked: {})", user_id, unblocked);
+
+            let followed = followers::handle_follow(&state.db, &user_id, &reply_token, unblocked, &occurred_at).await;
+            let display_name = match followed {
+                Ok(name) => name,
+                Err(e) => {
+                    tracing::error!("Failed to handle follow of {}: {}", user_id, e);
+                    None
+                }
+            };
 
             // Send notification to admin
-            let msg = format!("New follower: {}", source.user_id);
-            notification::send_notifications(&state.db, &msg).await;
+            let text = match display_name {
+                Some(name) => format!("{} ({})", name, user_id),
+                None => user_id.clone(),
+            };
+            notification::send_notifications(&state.db, &Notification::event(NotificationKind::NewFollower, "New follower", text).about_user(&user_id)).await;
         }
-        LineEvent::Unfollow { source, .. } => {
-            tracing::info!("User unfollowed: {}", source.user_id);
+        EventKind::Unfollow => {
+            let Some(user_id) = user_id else { return Ok(()) };
+            tracing::info!("User unfollowed: {}", user_id);
+            let display_name = followers::handle_unfollow(&state.db, &user_id, &occurred_at).await?;
 
             // Send notification to admin
-            let msg = format!("User unfollowed: {}", source.user_id);
-            notification::send_notifications(&state.db, &msg).await;
+            let text = match display_name {
+                Some(name) => format!("{} ({})", name, user_id),
+                None => user_id.clone(),
+            };
+            notification::send_notifications(&state.db, &Notification::event(NotificationKind::Unfollow, "User unfollowed", text).about_user(&user_id)).await;
+        }
+        EventKind::Join => {
+            let chat = describe_chat(source.as_ref());
+            tracing::info!("Bot joined {}", chat);
+            notification::send_notifications(&state.db, &Notification::event(NotificationKind::BotMembership, "Bot joined", chat)).await;
+        }
+        EventKind::Leave => {
+            let chat = describe_chat(source.as_ref());
+            tracing::info!("Bot left {}", chat);
+            notification::send_notifications(&state.db, &Notification::event(NotificationKind::BotMembership, "Bot left", chat)).await;
+        }
+        EventKind::MemberJoined { joined } => {
+            let members: Vec<&str> = joined.members.iter().filter_map(|m| m.user_id()).collect();
+            tracing::info!("Members joined {}: {:?}", describe_chat(source.as_ref()), members);
+        }
+        EventKind::MemberLeft { left } => {
+            let members: Vec<&str> = left.members.iter().filter_map(|m| m.user_id()).collect();
+            tracing::info!("Members left {}: {:?}", describe_chat(source.as_ref()), members);
         }
-        LineEvent::Other => {
+        EventKind::Postback { postback } => {
+            tracing::info!(
+                "Received postback from {:?}: {} (params: {:?})",
+                user_id,
+                postback.data,
+                postback.params
+            );
+            if let Some(user_id) = &user_id {
+                User::create(&state.db, user_id, None).await?;
+            }
+        }
+        EventKind::VideoPlayComplete { video_play_complete } => {
+            tracing::info!(
+                "User {:?} finished watching video {}",
+                user_id,
+                video_play_complete.tracking_id
+            );
+        }
+        EventKind::Beacon { beacon } => {
+            tracing::info!(
+                "Beacon {} from {:?}: {} (dm: {:?})",
+                beacon.hwid,
+                user_id,
+                beacon.beacon_type,
+                beacon.dm
+            );
+        }
+        EventKind::AccountLink { link } => {
+            tracing::info!("Account link for {:?}: {} (nonce {})", user_id, link.result, link.nonce);
+        }
+        EventKind::Other => {
             tracing::debug!("Received other event type");
         }
     }
@@ -150,38 +555,76 @@ async fn process_event(state: &AppState, event: LineEvent) -> Result<(), anyhow:
     Ok(())
 }
 
+fn describe_chat(source: Option<&EventSource>) -> String {
+    match source {
+        Some(EventSource::Group { group_id, .. }) => format!("group {}", group_id),
+        Some(EventSource::Room { room_id, .. }) => format!("room {}", room_id),
+        Some(EventSource::User { user_id }) => format!("chat with {}", user_id),
+        None => "unknown chat".to_string(),
+    }
+}
+
 async fn handle_message_event(
     state: &AppState,
     user_id: &str,
+    source: &EventSource,
+    reply_token: &str,
     message: LineMessage,
 ) -> Result<(), anyhow::Error> {
     // Ensure user exists in database
     User::create(&state.db, user_id, None).await?;
+    if let EventSource::User { .. } = source {
+        User::record_chat(&state.db, user_id).await?;
+    }
+
+    let source_type = source.source_type();
+    let chat_id = source.chat_id();
 
     match message {
-        LineMessage::Text { text, .. } => {
+        LineMessage::Text { id, text } => {
             tracing::info!("Received text message from {}: {}", user_id, text);
 
             // Store message in database
-            Message::create(&state.db, user_id, "text", Some(&text), None).await?;
+            let message_id =
+                Message::create(&state.db, user_id, "text", Some(&text), None, Some(&id), source_type, chat_id).await?;
+            inbox::record_inbound(&state.db, message_id).await?;
 
             // Send notification to admin
-            let notification_msg = format!("New message from {}: {}", user_id, text);
-            notification::send_notifications(&state.db, &notification_msg).await;
+            let notification =
+                Notification::event(NotificationKind::NewMessage, format!("New message from {}", user_id), text.clone())
+                    .about_user(user_id);
+            notification::send_notifications(&state.db, &notification).await;
+
+            // Answer with the first matching auto-reply rule
+            if let Err(e) = auto_reply::reply_to_text(&state.db, user_id, reply_token, &text).await {
+                tracing::error!("Auto-reply to {} failed: {}", user_id, e);
+            }
         }
-        LineMessage::Image { .. } => {
+        LineMessage::Image { id, content_provider } => {
             tracing::info!("Received image message from {}", user_id);
-            Message::create(&state.db, user_id, "image", None, None).await?;
+            let data = media_data(content_provider, None);
+            store_media_message(state, user_id, "image", data, &id, source_type, chat_id).await?;
         }
-        LineMessage::Video { .. } => {
+        LineMessage::Video { id, duration, content_provider } => {
             tracing::info!("Received video message from {}", user_id);
-            Message::create(&state.db, user_id, "video", None, None).await?;
+            let data = media_data(content_provider, duration);
+            store_media_message(state, user_id, "video", data, &id, source_type, chat_id).await?;
         }
-        LineMessage::Audio { .. } => {
+        LineMessage::Audio { id, duration, content_provider } => {
             tracing::info!("Received audio message from {}", user_id);
-            Message::create(&state.db, user_id, "audio", None, None).await?;
+            let data = media_data(content_provider, duration);
+            store_media_message(state, user_id, "audio", data, &id, source_type, chat_id).await?;
+        }
+        LineMessage::File { id, file_name, file_size } => {
+            tracing::info!("Received file {} from {}", file_name, user_id);
+            let data = serde_json::json!({
+                "status": "pending",
+                "fileName": file_name,
+                "fileSize": file_size,
+            });
+            store_media_message(state, user_id, "file", data, &id, source_type, chat_id).await?;
         }
-        LineMessage::Location { title, address, latitude, longitude, .. } => {
+        LineMessage::Location { id, title, address, latitude, longitude } => {
             tracing::info!("Received location message from {}", user_id);
             let data = serde_json::json!({
                 "title": title,
@@ -189,15 +632,37 @@ async fn handle_message_event(
                 "latitude": latitude,
                 "longitude": longitude,
             });
-            Message::create(&state.db, user_id, "location", None, Some(&data.to_string())).await?;
+            let message_id = Message::create(
+                &state.db,
+                user_id,
+                "location",
+                None,
+                Some(&data.to_string()),
+                Some(&id),
+                source_type,
+                chat_id,
+            )
+            .await?;
+            inbox::record_inbound(&state.db, message_id).await?;
         }
-        LineMessage::Sticker { package_id, sticker_id, .. } => {
+        LineMessage::Sticker { id, package_id, sticker_id } => {
             tracing::info!("Received sticker message from {}", user_id);
             let data = serde_json::json!({
                 "packageId": package_id,
                 "stickerId": sticker_id,
             });
-            Message::create(&state.db, user_id, "sticker", None, Some(&data.to_string())).await?;
+            let message_id = Message::create(
+                &state.db,
+                user_id,
+                "sticker",
+                None,
+                Some(&data.to_string()),
+                Some(&id),
+                source_type,
+                chat_id,
+            )
+            .await?;
+            inbox::record_inbound(&state.db, message_id).await?;
         }
         LineMessage::Other => {
             tracing::debug!("Received other message type from {}", user_id);
@@ -206,3 +671,49 @@ async fn handle_message_event(
 
     Ok(())
 }
+
+/// Initial `message_data` for an image/video/audio message; the download
+/// fills in the stored path, MIME type and size
+fn media_data(content_provider: Option<ContentProvider>, duration: Option<i64>) -> serde_json::Value {
+    let mut data = serde_json::json!({ "status": "pending" });
+    if let Some(provider) = content_provider {
+        data["contentProvider"] = serde_json::to_value(provider).unwrap_or_default();
+    }
+    if let Some(duration) = duration {
+        data["duration"] = duration.into();
+    }
+    data
+}
+
+/// Store a media message and download its content in the background, so the
+/// webhook can answer LINE right away
+async fn store_media_message(
+    state: &AppState,
+    user_id: &str,
+    message_type: &str,
+    data: serde_json::Value,
+    line_message_id: &str,
+    source_type: &str,
+    chat_id: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    let message_id = Message::create(
+        &state.db,
+        user_id,
+        message_type,
+        None,
+        Some(&data.to_string()),
+        Some(line_message_id),
+        source_type,
+        chat_id,
+    )
+    .await?;
+    inbox::record_inbound(&state.db, message_id).await?;
+
+    let db = state.db.clone();
+    tokio::spawn(async move {
+        // Errors are logged and recorded on the message by the download itself
+        let _ = media::download_message_content(&db, &MediaStore::from_env(), message_id).await;
+    });
+
+    Ok(())
+}
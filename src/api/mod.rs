@@ -1,13 +1,28 @@
+pub mod auth;
 pub mod line_webhook;
 pub mod line_client;
+pub mod line_message;
+pub mod openapi;
+pub mod rich_menu;
+pub mod slack_client;
+pub mod slack_events;
+pub mod v1;
+#[cfg(feature = "line-mock")]
+pub mod line_mock;
+#[cfg(feature = "slack-mock")]
+pub mod slack_mock;
 
 use axum::{
+    http::{header, HeaderName, HeaderValue, Method},
+    middleware,
     routing::{get, post},
     Router,
 };
 use sqlx::SqlitePool;
 use std::sync::Arc;
-use tower_http::cors::CorsLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
 
 #[derive(Clone)]
 pub struct AppState {
@@ -17,13 +32,44 @@ pub struct AppState {
 pub fn create_router(db: SqlitePool) -> Router {
     let state = Arc::new(AppState { db });
 
+    let api_v1 = v1::router().route_layer(middleware::from_fn_with_state(state.clone(), auth::require_api_key));
+
     Router::new()
         .route("/", get(health_check))
         .route("/webhook/line", post(line_webhook::handle_webhook))
-        .layer(CorsLayer::permissive())
+        .route("/webhook/slack", post(slack_events::handle_events))
+        .nest("/api/v1", api_v1)
+        // The API description and a docs page for it, bundled into the binary
+        .merge(SwaggerUi::new("/api/docs").url("/api/openapi.json", openapi::ApiDoc::openapi()))
+        .layer(cors_layer())
         .with_state(state)
 }
 
+/// Browsers may only call the API from the origins listed in
+/// `CORS_ALLOWED_ORIGINS` (comma-separated, e.g. `https://admin.example.com`).
+/// Without it no cross-origin requests are allowed; LINE and scripts aren't
+/// affected either way.
+fn cors_layer() -> CorsLayer {
+    let origins: Vec<HeaderValue> = std::env::var("CORS_ALLOWED_ORIGINS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .filter_map(|origin| match HeaderValue::from_str(origin) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                tracing::warn!("Ignoring invalid CORS origin '{}'", origin);
+                None
+            }
+        })
+        .collect();
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE, HeaderName::from_static("x-api-key")])
+}
+
 async fn health_check() -> &'static str {
     "OK"
 }
@@ -0,0 +1,158 @@
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use super::{line_webhook, slack_events, v1};
+
+/// The OpenAPI document of the HTTP API, served at `/api/openapi.json`.
+///
+/// It's derived from the handlers' `#[utoipa::path]` attributes and the
+/// request/response types themselves, so a changed type changes the
+/// document with it. New routes have to be listed in `paths` below.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "LINE Admin API",
+        description = "REST API of the LINE admin app. Every `/api/v1` route needs an API key with the scope listed in its security requirement."
+    ),
+    paths(
+        line_webhook::handle_webhook,
+        slack_events::handle_events,
+        v1::list_users,
+        v1::get_user,
+        v1::get_follow_history,
+        v1::refresh_profile,
+        v1::get_user_stats,
+        v1::list_messages,
+        v1::push_message,
+        v1::multicast_message,
+        v1::broadcast_message,
+        v1::list_scheduled_messages,
+        v1::create_scheduled_message,
+        v1::get_scheduled_message,
+        v1::get_scheduled_message_runs,
+        v1::cancel_scheduled_message,
+        v1::list_calendar_events,
+        v1::create_calendar_event,
+        v1::list_settings,
+        v1::get_setting,
+        v1::put_setting,
+        v1::get_dashboard_stats,
+    ),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "webhook", description = "Called by the LINE Platform and Slack"),
+        (name = "users", description = "Followers and their profiles"),
+        (name = "messages", description = "Message history and sending"),
+        (name = "scheduled", description = "Scheduled and recurring messages"),
+        (name = "calendar", description = "Calendar events"),
+        (name = "settings", description = "App settings"),
+        (name = "analytics", description = "Statistics"),
+    )
+)]
+pub struct ApiDoc;
+
+/// The two ways of presenting an API key (see `auth::require_api_key`)
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-API-Key"))),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+    use std::collections::BTreeSet;
+    use std::path::PathBuf;
+
+    /// The checked-in `openapi.json` at the crate root
+    fn snapshot_path() -> PathBuf {
+        let crate_root = std::path::Path::new(file!()).ancestors().nth(3).unwrap();
+        crate_root.join("openapi.json")
+    }
+
+    /// `(method, path)` of every `.route(...)` in the router sources, with
+    /// axum's `:param` written as OpenAPI's `{param}`
+    fn router_routes() -> BTreeSet<(String, String)> {
+        let route = Regex::new(r#"\.route\("([^"]*)",\s*([^\n]*)\)"#).unwrap();
+        let method = Regex::new(r"\b(get|post|put|delete|patch)\(").unwrap();
+        let param = Regex::new(r":(\w+)").unwrap();
+
+        // v1::router() is nested under /api/v1 by create_router()
+        let sources = [("", include_str!("mod.rs")), ("/api/v1", include_str!("v1.rs"))];
+        let mut routes = BTreeSet::new();
+        for (prefix, source) in sources {
+            for captures in route.captures_iter(source) {
+                let path = param.replace_all(&format!("{}{}", prefix, &captures[1]), "{$1}").to_string();
+                for handler in method.captures_iter(&captures[2]) {
+                    routes.insert((handler[1].to_string(), path.clone()));
+                }
+            }
+        }
+        routes
+    }
+
+    fn documented_routes() -> BTreeSet<(String, String)> {
+        let mut routes = BTreeSet::new();
+        for (path, item) in ApiDoc::openapi().paths.paths {
+            let operations = [
+                ("get", &item.get),
+                ("post", &item.post),
+                ("put", &item.put),
+                ("delete", &item.delete),
+                ("patch", &item.patch),
+            ];
+            for (method, operation) in operations {
+                if operation.is_some() {
+                    routes.insert((method.to_string(), path.clone()));
+                }
+            }
+        }
+        routes
+    }
+
+    #[test]
+    fn every_route_is_documented() {
+        let mut routes = router_routes();
+        // The health check isn't part of the API
+        assert!(routes.remove(&("get".to_string(), "/".to_string())));
+
+        let documented = documented_routes();
+        let undocumented: Vec<_> = routes.difference(&documented).collect();
+        let unrouted: Vec<_> = documented.difference(&routes).collect();
+        assert!(undocumented.is_empty(), "routes missing from ApiDoc paths: {:?}", undocumented);
+        assert!(unrouted.is_empty(), "documented routes that aren't routed: {:?}", unrouted);
+    }
+
+    /// Fails when a handler or one of its types changes without the
+    /// checked-in spec. Regenerate it with `UPDATE_OPENAPI=1 cargo test openapi`
+    /// and review the diff.
+    #[test]
+    fn spec_matches_snapshot() {
+        let spec = ApiDoc::openapi().to_pretty_json().unwrap() + "\n";
+        let path = snapshot_path();
+        if std::env::var_os("UPDATE_OPENAPI").is_some() {
+            std::fs::write(&path, &spec).unwrap();
+            return;
+        }
+
+        let snapshot = std::fs::read_to_string(&path).unwrap_or_default();
+        let current: serde_json::Value = serde_json::from_str(&spec).unwrap();
+        let checked_in: serde_json::Value = serde_json::from_str(&snapshot).unwrap_or_default();
+        assert!(
+            current == checked_in,
+            "{} is out of date with the handlers; run `UPDATE_OPENAPI=1 cargo test openapi` and commit the result",
+            path.display()
+        );
+    }
+}
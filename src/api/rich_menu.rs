@@ -0,0 +1,322 @@
+//! Rich menu management on top of `LineClient`: create menus from a JSON
+//! definition, upload their image, set the default menu, link menus to users
+//! (one by one or in bulk) and manage the aliases used for tab switching.
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::api::line_client::{LineApiError, LineClient};
+use crate::api::line_message::Action;
+
+pub const MAX_AREAS: usize = 20;
+pub const MAX_NAME_LENGTH: usize = 300;
+pub const MAX_CHAT_BAR_TEXT_LENGTH: usize = 14;
+pub const MAX_ALIAS_ID_LENGTH: usize = 32;
+
+/// Bulk link/unlink accepts at most this many user IDs per request
+pub const BULK_LINK_MAX_USERS: usize = 500;
+
+/// Rich menu images must be JPEG or PNG and at most 1 MB
+pub const MAX_IMAGE_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RichMenuSize {
+    pub width: i64,
+    pub height: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RichMenuBounds {
+    pub x: i64,
+    pub y: i64,
+    pub width: i64,
+    pub height: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RichMenuArea {
+    pub bounds: RichMenuBounds,
+    pub action: Action,
+}
+
+/// Rich menu object as sent to `POST /v2/bot/richmenu`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RichMenu {
+    pub size: RichMenuSize,
+    /// Whether the menu is shown expanded by default
+    #[serde(default)]
+    pub selected: bool,
+    pub name: String,
+    pub chat_bar_text: String,
+    pub areas: Vec<RichMenuArea>,
+}
+
+impl RichMenu {
+    /// Check the definition against LINE's limits before creating it
+    pub fn validate(&self) -> Result<(), String> {
+        let RichMenuSize { width, height } = self.size;
+        if !(800..=2500).contains(&width) || height < 250 {
+            return Err(format!(
+                "size must be 800 to 2500 pixels wide and at least 250 pixels high, got {}x{}",
+                width, height
+            ));
+        }
+        // LINE requires width / height >= 1.45
+        if width * 100 < height * 145 {
+            return Err(format!("size {}x{} is too tall (width / height must be at least 1.45)", width, height));
+        }
+
+        if self.name.trim().is_empty() {
+            return Err("name must not be empty".to_string());
+        }
+        if self.name.chars().count() > MAX_NAME_LENGTH {
+            return Err(format!("name is longer than {} characters", MAX_NAME_LENGTH));
+        }
+        if self.chat_bar_text.trim().is_empty() {
+            return Err("chatBarText must not be empty".to_string());
+        }
+        if self.chat_bar_text.chars().count() > MAX_CHAT_BAR_TEXT_LENGTH {
+            return Err(format!("chatBarText is longer than {} characters", MAX_CHAT_BAR_TEXT_LENGTH));
+        }
+
+        if self.areas.len() > MAX_AREAS {
+            return Err(format!("a rich menu has at most {} areas, got {}", MAX_AREAS, self.areas.len()));
+        }
+        for (index, area) in self.areas.iter().enumerate() {
+            let RichMenuBounds { x, y, width: w, height: h } = area.bounds;
+            if x < 0 || y < 0 || w <= 0 || h <= 0 || x + w > width || y + h > height {
+                return Err(format!("area {}: bounds fall outside the {}x{} menu", index + 1, width, height));
+            }
+            area.action
+                .validate()
+                .map_err(|e| format!("area {}: {}", index + 1, e))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Rich menu as returned by LINE, with its ID
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RichMenuResponse {
+    pub rich_menu_id: String,
+    #[serde(flatten)]
+    pub menu: RichMenu,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RichMenuAlias {
+    pub rich_menu_alias_id: String,
+    pub rich_menu_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RichMenuIdResponse {
+    rich_menu_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RichMenuListResponse {
+    richmenus: Vec<RichMenuResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RichMenuAliasListResponse {
+    aliases: Vec<RichMenuAlias>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BulkLinkRequest<'a> {
+    rich_menu_id: &'a str,
+    user_ids: &'a [String],
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BulkUnlinkRequest<'a> {
+    user_ids: &'a [String],
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateAliasRequest<'a> {
+    rich_menu_id: &'a str,
+}
+
+/// Validate an alias ID: 1 to 32 characters of `[A-Za-z0-9_-]`
+pub fn validate_alias_id(alias_id: &str) -> Result<(), String> {
+    let valid_chars = alias_id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if alias_id.is_empty() || alias_id.len() > MAX_ALIAS_ID_LENGTH || !valid_chars {
+        return Err(format!(
+            "Alias ID must be 1 to {} characters of letters, digits, '_' or '-'",
+            MAX_ALIAS_ID_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// Read a rich menu image from disk, returning its bytes and content type
+pub fn load_image(path: &Path) -> Result<(Vec<u8>, &'static str), String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    if bytes.len() > MAX_IMAGE_BYTES {
+        return Err(format!(
+            "Rich menu image is {} bytes, the limit is {} bytes",
+            bytes.len(),
+            MAX_IMAGE_BYTES
+        ));
+    }
+
+    let content_type = if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        "image/jpeg"
+    } else {
+        return Err("Rich menu image must be a JPEG or PNG file".to_string());
+    };
+
+    Ok((bytes, content_type))
+}
+
+impl LineClient {
+    /// Create a rich menu and return its ID
+    pub async fn create_rich_menu(&self, menu: &RichMenu) -> Result<String, LineApiError> {
+        let url = self.url("/v2/bot/richmenu");
+        let response = self.execute(|| self.client.post(&url).json(menu), None).await?;
+
+        let created = response.json::<RichMenuIdResponse>().await?;
+        Ok(created.rich_menu_id)
+    }
+
+    /// Upload the menu image. LINE accepts one image per menu; to change the
+    /// image, create a new menu.
+    pub async fn upload_rich_menu_image(
+        &self,
+        rich_menu_id: &str,
+        content_type: &str,
+        image: Vec<u8>,
+    ) -> Result<(), LineApiError> {
+        let url = self.data_url(&format!("/v2/bot/richmenu/{}/content", rich_menu_id));
+        self.execute(
+            || {
+                self.client
+                    .post(&url)
+                    .header("Content-Type", content_type)
+                    .body(image.clone())
+            },
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// List every rich menu on the channel, including ones not created here
+    pub async fn list_rich_menus(&self) -> Result<Vec<RichMenuResponse>, LineApiError> {
+        let url = self.url("/v2/bot/richmenu/list");
+        let response = self.execute(|| self.client.get(&url), None).await?;
+
+        let list = response.json::<RichMenuListResponse>().await?;
+        Ok(list.richmenus)
+    }
+
+    pub async fn delete_rich_menu(&self, rich_menu_id: &str) -> Result<(), LineApiError> {
+        let url = self.url(&format!("/v2/bot/richmenu/{}", rich_menu_id));
+        self.execute(|| self.client.delete(&url), None).await?;
+        Ok(())
+    }
+
+    /// Show this menu to every user without a per-user menu
+    pub async fn set_default_rich_menu(&self, rich_menu_id: &str) -> Result<(), LineApiError> {
+        let url = self.url(&format!("/v2/bot/user/all/richmenu/{}", rich_menu_id));
+        self.execute(|| self.client.post(&url), None).await?;
+        Ok(())
+    }
+
+    pub async fn clear_default_rich_menu(&self) -> Result<(), LineApiError> {
+        let url = self.url("/v2/bot/user/all/richmenu");
+        self.execute(|| self.client.delete(&url), None).await?;
+        Ok(())
+    }
+
+    /// Link a menu to one user; it takes precedence over the default menu
+    pub async fn link_rich_menu_to_user(&self, user_id: &str, rich_menu_id: &str) -> Result<(), LineApiError> {
+        let url = self.url(&format!("/v2/bot/user/{}/richmenu/{}", user_id, rich_menu_id));
+        self.execute(|| self.client.post(&url), None).await?;
+        Ok(())
+    }
+
+    pub async fn unlink_rich_menu_from_user(&self, user_id: &str) -> Result<(), LineApiError> {
+        let url = self.url(&format!("/v2/bot/user/{}/richmenu", user_id));
+        self.execute(|| self.client.delete(&url), None).await?;
+        Ok(())
+    }
+
+    /// Get the menu linked to a user, or `None` if the user has none
+    pub async fn get_user_rich_menu_id(&self, user_id: &str) -> Result<Option<String>, LineApiError> {
+        let url = self.url(&format!("/v2/bot/user/{}/richmenu", user_id));
+        match self.execute(|| self.client.get(&url), None).await {
+            Ok(response) => Ok(Some(response.json::<RichMenuIdResponse>().await?.rich_menu_id)),
+            Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Link a menu to many users, split into requests of at most
+    /// `BULK_LINK_MAX_USERS` user IDs. LINE applies bulk links asynchronously.
+    pub async fn link_rich_menu_to_users(&self, user_ids: &[String], rich_menu_id: &str) -> Result<(), LineApiError> {
+        for chunk in user_ids.chunks(BULK_LINK_MAX_USERS) {
+            let payload = BulkLinkRequest {
+                rich_menu_id,
+                user_ids: chunk,
+            };
+            self.post_json("/v2/bot/richmenu/bulk/link", &payload, None).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn unlink_rich_menu_from_users(&self, user_ids: &[String]) -> Result<(), LineApiError> {
+        for chunk in user_ids.chunks(BULK_LINK_MAX_USERS) {
+            let payload = BulkUnlinkRequest { user_ids: chunk };
+            self.post_json("/v2/bot/richmenu/bulk/unlink", &payload, None).await?;
+        }
+        Ok(())
+    }
+
+    /// Create an alias that `richmenuswitch` actions can point at
+    pub async fn create_rich_menu_alias(&self, alias_id: &str, rich_menu_id: &str) -> Result<(), LineApiError> {
+        let payload = RichMenuAlias {
+            rich_menu_alias_id: alias_id.to_string(),
+            rich_menu_id: rich_menu_id.to_string(),
+        };
+        self.post_json("/v2/bot/richmenu/alias", &payload, None).await
+    }
+
+    /// Point an existing alias at a different menu
+    pub async fn update_rich_menu_alias(&self, alias_id: &str, rich_menu_id: &str) -> Result<(), LineApiError> {
+        let payload = UpdateAliasRequest { rich_menu_id };
+        self.post_json(&format!("/v2/bot/richmenu/alias/{}", alias_id), &payload, None)
+            .await
+    }
+
+    pub async fn delete_rich_menu_alias(&self, alias_id: &str) -> Result<(), LineApiError> {
+        let url = self.url(&format!("/v2/bot/richmenu/alias/{}", alias_id));
+        self.execute(|| self.client.delete(&url), None).await?;
+        Ok(())
+    }
+
+    pub async fn list_rich_menu_aliases(&self) -> Result<Vec<RichMenuAlias>, LineApiError> {
+        let url = self.url("/v2/bot/richmenu/alias/list");
+        let response = self.execute(|| self.client.get(&url), None).await?;
+
+        let list = response.json::<RichMenuAliasListResponse>().await?;
+        Ok(list.aliases)
+    }
+}
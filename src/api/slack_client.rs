@@ -0,0 +1,136 @@
+use reqwest::{Client, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::settings;
+
+const SLACK_API_BASE: &str = "https://slack.com/api";
+
+/// Client for the few Slack Web API methods the Slack bridge uses,
+/// authenticated with a bot token
+#[derive(Clone)]
+pub struct SlackClient {
+    client: Client,
+    token: String,
+    base_url: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SlackApiError {
+    /// The API answered `"ok": false`, e.g. `channel_not_found`
+    #[error("Slack API error: {0}")]
+    Api(String),
+    #[error("Slack API rate limit hit, retry after {0} seconds")]
+    RateLimited(u64),
+    #[error("Slack API returned {0}")]
+    Status(StatusCode),
+    /// The request never got a response (connection, timeout, decoding)
+    #[error("Slack API request failed: {0}")]
+    Http(#[from] reqwest::Error),
+}
+
+/// A message posted with `chat.postMessage`
+#[derive(Debug, Clone, Deserialize)]
+pub struct PostedMessage {
+    pub channel: String,
+    /// The message's ID within the channel; a thread is identified by the ts
+    /// of its first message
+    pub ts: String,
+}
+
+/// Who the token belongs to, from `auth.test`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthInfo {
+    pub team: Option<String>,
+    pub user: Option<String>,
+    pub bot_id: Option<String>,
+}
+
+impl SlackClient {
+    pub fn new(token: String) -> Self {
+        Self::with_base_url(token, SLACK_API_BASE.to_string())
+    }
+
+    /// Use another API host, e.g. a local stand-in
+    pub fn with_base_url(token: String, base_url: String) -> Self {
+        Self {
+            client: Client::new(),
+            token,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Create a client from the `slack_bot_token` setting. The API host comes
+    /// from the `slack_api_base_url` setting (or `SLACK_API_BASE_URL`), and
+    /// defaults to the real Slack API.
+    pub async fn from_settings(db: &SqlitePool) -> Result<Self, anyhow::Error> {
+        let token = settings::get(db, settings::SLACK_BOT_TOKEN)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Slack bot token not configured"))?;
+        let base_url = settings::get(db, settings::SLACK_API_BASE_URL).await?;
+
+        Ok(match base_url {
+            Some(base_url) => Self::with_base_url(token, base_url),
+            None => Self::new(token),
+        })
+    }
+
+    /// Call a Web API method with a JSON body
+    async fn call<T: DeserializeOwned>(&self, method: &str, body: serde_json::Value) -> Result<T, SlackApiError> {
+        let response = self
+            .client
+            .post(format!("{}/{}", self.base_url, method))
+            .bearer_auth(&self.token)
+            .json(&body)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(60);
+            return Err(SlackApiError::RateLimited(retry_after));
+        }
+        if !status.is_success() {
+            return Err(SlackApiError::Status(status));
+        }
+
+        // Failures still answer 200, with `"ok": false` and an error code
+        let body: serde_json::Value = response.json().await?;
+        if body["ok"] != true {
+            let error = body["error"].as_str().unwrap_or("unknown_error");
+            return Err(SlackApiError::Api(error.to_string()));
+        }
+        serde_json::from_value(body).map_err(|e| SlackApiError::Api(format!("unexpected response: {}", e)))
+    }
+
+    /// Post a text message to a channel, or into a thread when `thread_ts` is given
+    pub async fn post_message(
+        &self,
+        channel: &str,
+        text: &str,
+        thread_ts: Option<&str>,
+    ) -> Result<PostedMessage, SlackApiError> {
+        let mut body = serde_json::json!({
+            "channel": channel,
+            "text": text,
+            "unfurl_links": false,
+            "unfurl_media": false,
+        });
+        if let Some(thread_ts) = thread_ts {
+            body["thread_ts"] = thread_ts.into();
+        }
+
+        self.call("chat.postMessage", body).await
+    }
+
+    /// Check the token
+    pub async fn auth_test(&self) -> Result<AuthInfo, SlackApiError> {
+        self.call("auth.test", serde_json::json!({})).await
+    }
+}
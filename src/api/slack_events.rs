@@ -0,0 +1,187 @@
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::sync::Arc;
+
+use crate::api::AppState;
+use crate::db::models::SlackEventReceipt;
+use crate::settings;
+use crate::slack_bridge;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Requests signed longer ago than this are rejected as possible replays
+const MAX_REQUEST_AGE_SECONDS: i64 = 5 * 60;
+
+/// The Events API payloads we act on
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SlackPayload {
+    /// Sent once when the Request URL is saved in the Slack app settings
+    UrlVerification { challenge: String },
+    EventCallback { event_id: String, event: SlackEvent },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct SlackEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    /// Set for edits, deletions, joins and other non-plain messages
+    #[serde(default)]
+    subtype: Option<String>,
+    /// Set for messages posted by bots, including the bridge itself
+    #[serde(default)]
+    bot_id: Option<String>,
+    #[serde(default)]
+    channel: Option<String>,
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(default)]
+    ts: Option<String>,
+    #[serde(default)]
+    thread_ts: Option<String>,
+}
+
+/// Slack Events API endpoint of the Slack bridge. Replies in a conversation's
+/// thread are pushed to the LINE user; everything else is acknowledged and
+/// ignored. Slack expects an answer within 3 seconds, so the push happens in
+/// the background.
+#[utoipa::path(
+    post,
+    path = "/webhook/slack",
+    tag = "webhook",
+    request_body(content = serde_json::Value, description = "Slack Events API payload (`url_verification` or `event_callback`)"),
+    params(
+        ("x-slack-request-timestamp" = String, Header, description = "Unix time the request was signed at"),
+        ("x-slack-signature" = String, Header, description = "v0= + hex(HMAC-SHA256(signing secret, \"v0:{timestamp}:{body}\"))"),
+    ),
+    responses(
+        (status = 200, description = "Event accepted; `{\"challenge\": ...}` for url_verification"),
+        (status = 400, description = "Body is not an Events API payload"),
+        (status = 401, description = "Missing, stale or invalid signature, or no signing secret configured"),
+        (status = 500, description = "Settings could not be loaded or the event not recorded"),
+    ),
+)]
+pub async fn handle_events(State(state): State<Arc<AppState>>, headers: HeaderMap, body: Bytes) -> Response {
+    let signing_secret = match settings::get(&state.db, settings::SLACK_SIGNING_SECRET).await {
+        Ok(Some(secret)) => secret,
+        Ok(None) => {
+            tracing::error!("Slack signing secret not configured, rejecting Slack event");
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+        Err(e) => {
+            tracing::error!("Failed to load Slack signing secret: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let timestamp = headers.get("x-slack-request-timestamp").and_then(|v| v.to_str().ok());
+    let signature = headers.get("x-slack-signature").and_then(|v| v.to_str().ok());
+    let (Some(timestamp), Some(signature)) = (timestamp, signature) else {
+        tracing::warn!("Missing Slack signature headers");
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
+    let fresh = timestamp
+        .parse::<i64>()
+        .is_ok_and(|ts| (chrono::Utc::now().timestamp() - ts).abs() <= MAX_REQUEST_AGE_SECONDS);
+    if !fresh {
+        tracing::warn!("Rejected Slack event with stale timestamp {:?}", timestamp);
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    if !verify_signature(&signing_secret, timestamp, &body, signature) {
+        tracing::warn!("Rejected Slack event with invalid signature ({} bytes)", body.len());
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let payload: SlackPayload = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(e) => {
+            tracing::warn!("Invalid Slack event payload: {}", e);
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+    };
+
+    match payload {
+        SlackPayload::UrlVerification { challenge } => Json(serde_json::json!({ "challenge": challenge })).into_response(),
+        SlackPayload::EventCallback { event_id, event } => {
+            let Some((channel, thread_ts, text)) = thread_reply(event) else {
+                return StatusCode::OK.into_response();
+            };
+
+            // Slack resends an event with the same ID when it doesn't see the
+            // first delivery succeed, whatever the reason
+            match SlackEventReceipt::record(&state.db, &event_id).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    tracing::debug!("Skipping Slack retry of {}", event_id);
+                    return StatusCode::OK.into_response();
+                }
+                Err(e) => {
+                    tracing::error!("Failed to record Slack event {}: {}", event_id, e);
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+            }
+
+            let db = state.db.clone();
+            tokio::spawn(async move {
+                if let Err(e) = slack_bridge::relay_reply(&db, &channel, &thread_ts, &text).await {
+                    tracing::error!("Failed to relay Slack event {}: {}", event_id, e);
+                    if let Err(e) = SlackEventReceipt::forget(&db, &event_id).await {
+                        tracing::error!("Failed to forget Slack event {}: {}", event_id, e);
+                    }
+                }
+            });
+            StatusCode::OK.into_response()
+        }
+        SlackPayload::Other => StatusCode::OK.into_response(),
+    }
+}
+
+/// The channel, thread and text of a person's reply in a thread. Thread
+/// parents, bot posts (the bridge's own included) and edits aren't replies.
+fn thread_reply(event: SlackEvent) -> Option<(String, String, String)> {
+    if event.kind != "message" || event.subtype.is_some() || event.bot_id.is_some() {
+        return None;
+    }
+    let thread_ts = event.thread_ts.filter(|thread_ts| event.ts.as_ref() != Some(thread_ts))?;
+
+    Some((event.channel?, thread_ts, event.text?))
+}
+
+/// Verify the `x-slack-signature` header:
+/// `v0=` + hex(HMAC-SHA256(signing secret, "v0:{timestamp}:{body}"))
+pub fn verify_signature(signing_secret: &str, timestamp: &str, body: &[u8], signature: &str) -> bool {
+    let Some(expected) = signature.trim().strip_prefix("v0=").and_then(decode_hex) else {
+        return false;
+    };
+
+    let mut mac = match HmacSha256::new_from_slice(signing_secret.as_bytes()) {
+        Ok(mac) => mac,
+        Err(_) => return false,
+    };
+    mac.update(format!("v0:{}:", timestamp).as_bytes());
+    mac.update(body);
+
+    // verify_slice compares in constant time
+    mac.verify_slice(&expected).is_ok()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect()
+}
@@ -0,0 +1,248 @@
+//! Local stand-in for the Slack Web and Events APIs (enabled with the `slack-mock` feature).
+//!
+//! Records the messages the Slack bridge posts, and plays the part of Slack
+//! delivering events: `POST /mock/reply` sends a signed thread reply to the
+//! app's Slack events endpoint, so the whole bridge can run without Slack.
+//! Point `SLACK_API_BASE_URL` (or the `slack_api_base_url` setting) at `/api`.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RecordedRequests {
+    /// `chat.postMessage` bodies, with the `ts` they were answered with
+    pub messages: Vec<serde_json::Value>,
+    /// Events sent to the app and the status it answered with
+    pub events: Vec<serde_json::Value>,
+}
+
+#[derive(Clone)]
+pub struct MockSlackServer {
+    /// The app's Slack events endpoint
+    events_url: String,
+    signing_secret: Option<String>,
+    recorded: Arc<Mutex<RecordedRequests>>,
+    message_counter: Arc<AtomicU64>,
+    client: reqwest::Client,
+}
+
+/// A thread reply to deliver to the app, as if a person wrote it in Slack
+#[derive(Debug, Deserialize)]
+struct MockReply {
+    channel: String,
+    thread_ts: String,
+    text: String,
+    #[serde(default = "default_user")]
+    user: String,
+    /// Sign with this secret instead of the configured one, e.g. a wrong one
+    #[serde(default)]
+    signing_secret: Option<String>,
+}
+
+fn default_user() -> String {
+    "U0MOCKUSER".to_string()
+}
+
+impl MockSlackServer {
+    pub fn new(events_url: String, signing_secret: Option<String>) -> Self {
+        Self {
+            events_url,
+            signing_secret,
+            recorded: Arc::default(),
+            message_counter: Arc::default(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/api/chat.postMessage", post(post_message))
+            .route("/api/auth.test", post(auth_test))
+            .route("/mock/requests", get(list_requests).delete(clear_requests))
+            .route("/mock/reply", post(send_reply))
+            .with_state(self.clone())
+    }
+
+    /// Bind and serve in the background, returning the bound address
+    /// (pass port 0 to pick a free port)
+    pub async fn spawn(self, addr: SocketAddr) -> Result<SocketAddr, std::io::Error> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let app = self.router();
+
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                tracing::error!("Mock Slack server stopped: {}", e);
+            }
+        });
+
+        tracing::info!("Mock Slack API listening on http://{}", local_addr);
+        Ok(local_addr)
+    }
+
+    /// Snapshot of everything received and sent so far
+    pub fn recorded(&self) -> RecordedRequests {
+        self.recorded.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        *self.recorded.lock().unwrap() = RecordedRequests::default();
+    }
+
+    /// Post a signed `event_callback` to the app's events endpoint, returning
+    /// the status it answered with
+    pub async fn deliver_event(
+        &self,
+        event: serde_json::Value,
+        signing_secret: Option<&str>,
+    ) -> Result<StatusCode, reqwest::Error> {
+        let event_id = format!("Ev{:010}", self.message_counter.fetch_add(1, Ordering::SeqCst) + 1);
+        let body = serde_json::json!({
+            "type": "event_callback",
+            "team_id": "T0MOCK",
+            "event_id": event_id,
+            "event_time": chrono::Utc::now().timestamp(),
+            "event": event,
+        })
+        .to_string();
+
+        let timestamp = chrono::Utc::now().timestamp().to_string();
+        let secret = signing_secret.or(self.signing_secret.as_deref()).unwrap_or_default();
+        let response = self
+            .client
+            .post(&self.events_url)
+            .header("content-type", "application/json")
+            .header("x-slack-request-timestamp", &timestamp)
+            .header("x-slack-signature", sign(secret, &timestamp, body.as_bytes()))
+            .body(body)
+            .send()
+            .await?;
+
+        let status = StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+        self.recorded.lock().unwrap().events.push(serde_json::json!({
+            "event_id": event_id,
+            "event": event,
+            "status": status.as_u16(),
+        }));
+        Ok(status)
+    }
+
+    fn next_ts(&self) -> String {
+        let n = self.message_counter.fetch_add(1, Ordering::SeqCst) + 1;
+        format!("1700000000.{:06}", n)
+    }
+}
+
+/// Sign a request body the way Slack does
+fn sign(signing_secret: &str, timestamp: &str, body: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(signing_secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("v0:{}:", timestamp).as_bytes());
+    mac.update(body);
+
+    let hex: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("v0={}", hex)
+}
+
+/// Web API methods answer 200 with `"ok": false` on errors
+fn api_error(error: &str) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "ok": false, "error": error }))
+}
+
+fn authorized(headers: &HeaderMap) -> bool {
+    headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|token| !token.is_empty())
+}
+
+async fn post_message(
+    State(server): State<MockSlackServer>,
+    headers: HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> impl IntoResponse {
+    if !authorized(&headers) {
+        return api_error("not_authed");
+    }
+    let Some(channel) = body["channel"].as_str().map(str::to_string) else {
+        return api_error("channel_not_found");
+    };
+    if body["text"].as_str().is_none_or(str::is_empty) {
+        return api_error("no_text");
+    }
+
+    let ts = server.next_ts();
+    let mut recorded = body.clone();
+    recorded["ts"] = ts.clone().into();
+    server.recorded.lock().unwrap().messages.push(recorded);
+
+    Json(serde_json::json!({
+        "ok": true,
+        "channel": channel,
+        "ts": ts,
+        "message": { "text": body["text"], "ts": ts, "thread_ts": body["thread_ts"], "bot_id": "B0MOCK" },
+    }))
+}
+
+async fn auth_test(headers: HeaderMap) -> impl IntoResponse {
+    if !authorized(&headers) {
+        return api_error("not_authed");
+    }
+    Json(serde_json::json!({
+        "ok": true,
+        "url": "https://mock.slack.com/",
+        "team": "Mock Workspace",
+        "user": "line-bridge",
+        "team_id": "T0MOCK",
+        "user_id": "U0MOCKBOT",
+        "bot_id": "B0MOCK",
+    }))
+}
+
+async fn list_requests(State(server): State<MockSlackServer>) -> impl IntoResponse {
+    Json(server.recorded())
+}
+
+async fn clear_requests(State(server): State<MockSlackServer>) -> impl IntoResponse {
+    server.clear();
+    StatusCode::NO_CONTENT
+}
+
+async fn send_reply(State(server): State<MockSlackServer>, Json(reply): Json<MockReply>) -> impl IntoResponse {
+    let ts = server.next_ts();
+    let event = serde_json::json!({
+        "type": "message",
+        "channel": reply.channel,
+        "user": reply.user,
+        "text": reply.text,
+        "ts": ts,
+        "thread_ts": reply.thread_ts,
+        "channel_type": "channel",
+    });
+
+    match server.deliver_event(event, reply.signing_secret.as_deref()).await {
+        Ok(status) => (StatusCode::OK, Json(serde_json::json!({ "ts": ts, "status": status.as_u16() }))),
+        Err(e) => (
+            StatusCode::BAD_GATEWAY,
+            Json(serde_json::json!({ "error": format!("Failed to reach {}: {}", server.events_url, e) })),
+        ),
+    }
+}
@@ -0,0 +1,807 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::analytics::{self, DashboardStats, UserStats};
+use crate::api::auth::{AuthError, Authenticated};
+use crate::api::line_client::{LineApiError, LineClient, Message as LineMessage};
+use crate::api::AppState;
+use crate::db::models::{Calendar, FollowHistory, Message, ScheduledMessage, ScheduledMessageRun, Setting, User};
+use crate::followers;
+use crate::messaging::{self, Sender};
+use crate::scheduler::{self, NewScheduledMessage};
+use crate::secrets::{self, SecretsError};
+use crate::settings::{self, SettingsError};
+use crate::templates::{self, InvalidTemplate};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 500;
+
+/// Routes under `/api/v1`. Every route needs an API key (see `auth`).
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        // Users
+        .route("/users", get(list_users))
+        .route("/users/:line_user_id", get(get_user))
+        .route("/users/:line_user_id/follow-history", get(get_follow_history))
+        .route("/users/:line_user_id/profile/refresh", post(refresh_profile))
+        .route("/users/:line_user_id/stats", get(get_user_stats))
+        // Messages
+        .route("/messages", get(list_messages))
+        .route("/messages/push", post(push_message))
+        .route("/messages/multicast", post(multicast_message))
+        .route("/messages/broadcast", post(broadcast_message))
+        // Scheduled messages
+        .route("/scheduled-messages", get(list_scheduled_messages).post(create_scheduled_message))
+        .route("/scheduled-messages/:id", get(get_scheduled_message))
+        .route("/scheduled-messages/:id/runs", get(get_scheduled_message_runs))
+        .route("/scheduled-messages/:id/cancel", post(cancel_scheduled_message))
+        // Calendar events
+        .route("/calendar-events", get(list_calendar_events).post(create_calendar_event))
+        // Settings
+        .route("/settings", get(list_settings))
+        .route("/settings/:key", get(get_setting).put(put_setting))
+        // Analytics
+        .route("/stats/dashboard", get(get_dashboard_stats))
+}
+
+/// An error as JSON: `{"error": "..."}`
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error(transparent)]
+    Auth(#[from] AuthError),
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("LINE API error: {0}")]
+    Line(#[from] LineApiError),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(e: sqlx::Error) -> Self {
+        ApiError::Internal(e.into())
+    }
+}
+
+impl From<SecretsError> for ApiError {
+    fn from(e: SecretsError) -> Self {
+        ApiError::Internal(e.into())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            ApiError::Auth(e) => return e.into_response(),
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Line(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Internal(ref e) => {
+                tracing::error!("API request failed: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+
+        (status, Json(ErrorBody { error: self.to_string() })).into_response()
+    }
+}
+
+/// An error from sending through `templates`: the request's template is at
+/// fault, LINE, or something on our side
+fn send_error(e: anyhow::Error) -> ApiError {
+    if e.is::<InvalidTemplate>() {
+        return ApiError::BadRequest(e.to_string());
+    }
+    match e.downcast::<LineApiError>() {
+        Ok(e) => ApiError::Line(e),
+        Err(e) => ApiError::Internal(e),
+    }
+}
+
+/// Body of every error response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+/// One page of a list. Ask for the next one with `offset=next_offset`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub limit: i64,
+    pub offset: i64,
+    /// `None` on the last page
+    pub next_offset: Option<i64>,
+}
+
+/// Check `limit`/`offset` and fetch one extra row to know whether there is
+/// a next page
+async fn paginate<T, F, Fut>(limit: Option<i64>, offset: Option<i64>, fetch: F) -> ApiResult<Page<T>>
+where
+    F: FnOnce(i64, i64) -> Fut,
+    Fut: std::future::Future<Output = Result<Vec<T>, sqlx::Error>>,
+{
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    let offset = offset.unwrap_or(0);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(ApiError::BadRequest(format!("limit must be between 1 and {}", MAX_PAGE_SIZE)));
+    }
+    if offset < 0 {
+        return Err(ApiError::BadRequest("offset must not be negative".to_string()));
+    }
+
+    let mut items = fetch(limit + 1, offset).await?;
+    let next_offset = if items.len() as i64 > limit {
+        items.truncate(limit as usize);
+        Some(offset + limit)
+    } else {
+        None
+    };
+
+    Ok(Json(Page {
+        items,
+        limit,
+        offset,
+        next_offset,
+    }))
+}
+
+// Users
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct UserQuery {
+    /// `following`, `unfollowed` or `unknown`
+    follow_status: Option<String>,
+    /// Part of the display name, or an exact user ID
+    search: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users",
+    tag = "users",
+    params(UserQuery),
+    responses(
+        (status = 200, body = Page<User>),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["users:read"]), ("api_key" = ["users:read"])),
+)]
+async fn list_users(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Query(query): Query<UserQuery>,
+) -> ApiResult<Page<User>> {
+    auth.require("users:read")?;
+    paginate(query.limit, query.offset, |limit, offset| {
+        User::search(&state.db, query.follow_status.as_deref(), query.search.as_deref(), limit, offset)
+    })
+    .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{line_user_id}",
+    tag = "users",
+    params(("line_user_id" = String, Path)),
+    responses(
+        (status = 200, body = User),
+        (status = 404, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["users:read"]), ("api_key" = ["users:read"])),
+)]
+async fn get_user(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Path(line_user_id): Path<String>,
+) -> ApiResult<User> {
+    auth.require("users:read")?;
+    User::find_by_line_id(&state.db, &line_user_id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("No user {}", line_user_id)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{line_user_id}/follow-history",
+    tag = "users",
+    params(("line_user_id" = String, Path)),
+    responses(
+        (status = 200, body = Vec<FollowHistory>),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["users:read"]), ("api_key" = ["users:read"])),
+)]
+async fn get_follow_history(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Path(line_user_id): Path<String>,
+) -> ApiResult<Vec<FollowHistory>> {
+    auth.require("users:read")?;
+    Ok(Json(FollowHistory::list_by_user(&state.db, &line_user_id).await?))
+}
+
+/// Fetch the user's profile from LINE now
+#[utoipa::path(
+    post,
+    path = "/api/v1/users/{line_user_id}/profile/refresh",
+    tag = "users",
+    params(("line_user_id" = String, Path)),
+    responses(
+        (status = 200, body = User),
+        (status = 404, body = ErrorBody),
+        (status = 502, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["users:write"]), ("api_key" = ["users:write"])),
+)]
+async fn refresh_profile(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Path(line_user_id): Path<String>,
+) -> ApiResult<User> {
+    auth.require("users:write")?;
+    let client = LineClient::from_settings(&state.db).await?;
+    followers::refresh_profile(&state.db, &client, &line_user_id).await?;
+
+    get_user(State(state), Extension(auth), Path(line_user_id)).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{line_user_id}/stats",
+    tag = "analytics",
+    params(("line_user_id" = String, Path)),
+    responses(
+        (status = 200, body = UserStats),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["analytics:read"]), ("api_key" = ["analytics:read"])),
+)]
+async fn get_user_stats(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Path(line_user_id): Path<String>,
+) -> ApiResult<UserStats> {
+    auth.require("analytics:read")?;
+    Ok(Json(analytics::get_user_stats(&state.db, &line_user_id).await?))
+}
+
+// Messages
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct MessageQuery {
+    line_user_id: Option<String>,
+    /// `inbound` or `outbound`
+    direction: Option<String>,
+    message_type: Option<String>,
+    /// Timestamps, e.g. RFC 3339; `until` is exclusive
+    since: Option<String>,
+    until: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/messages",
+    tag = "messages",
+    params(MessageQuery),
+    responses(
+        (status = 200, body = Page<Message>),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["messages:read"]), ("api_key" = ["messages:read"])),
+)]
+async fn list_messages(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Query(query): Query<MessageQuery>,
+) -> ApiResult<Page<Message>> {
+    auth.require("messages:read")?;
+    paginate(query.limit, query.offset, |limit, offset| {
+        Message::search(
+            &state.db,
+            query.line_user_id.as_deref(),
+            query.direction.as_deref(),
+            query.message_type.as_deref(),
+            query.since.as_deref(),
+            query.until.as_deref(),
+            limit,
+            offset,
+        )
+    })
+    .await
+}
+
+/// Body of the send routes: `messages` (LINE message objects) or just `message_text`
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SendRequest {
+    line_user_id: Option<String>,
+    #[serde(default)]
+    line_user_ids: Vec<String>,
+    /// Text to send. Text messages are templates, rendered for each recipient;
+    /// broadcasts can't use the recipient's variables.
+    message_text: Option<String>,
+    /// LINE message objects, up to 5
+    #[schema(value_type = Option<Vec<Object>>)]
+    messages: Option<Vec<LineMessage>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SendResponse {
+    /// `x-line-request-id` of each request sent to LINE
+    request_ids: Vec<Option<String>>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/messages/push",
+    tag = "messages",
+    request_body = SendRequest,
+    responses(
+        (status = 200, body = SendResponse),
+        (status = 400, body = ErrorBody),
+        (status = 502, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["messages:send"]), ("api_key" = ["messages:send"])),
+)]
+async fn push_message(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Json(body): Json<SendRequest>,
+) -> ApiResult<SendResponse> {
+    auth.require("messages:send")?;
+    let line_user_id = body
+        .line_user_id
+        .ok_or_else(|| ApiError::BadRequest("line_user_id is required".to_string()))?;
+    let messages = messaging::compose_messages(body.message_text, body.messages).map_err(ApiError::BadRequest)?;
+
+    let client = LineClient::from_settings(&state.db).await?;
+    let messages = templates::personalize(&state.db, &messages, &line_user_id)
+        .await
+        .map_err(send_error)?;
+    let request_id = messaging::push(&state.db, &client, Sender::Admin, &line_user_id, messages, None).await?;
+
+    Ok(Json(SendResponse {
+        request_ids: vec![request_id],
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/messages/multicast",
+    tag = "messages",
+    request_body = SendRequest,
+    responses(
+        (status = 200, body = SendResponse),
+        (status = 400, body = ErrorBody),
+        (status = 502, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["messages:send"]), ("api_key" = ["messages:send"])),
+)]
+async fn multicast_message(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Json(body): Json<SendRequest>,
+) -> ApiResult<SendResponse> {
+    auth.require("messages:send")?;
+    if body.line_user_ids.is_empty() {
+        return Err(ApiError::BadRequest("line_user_ids is required".to_string()));
+    }
+    let messages = messaging::compose_messages(body.message_text, body.messages).map_err(ApiError::BadRequest)?;
+
+    let client = LineClient::from_settings(&state.db).await?;
+    let request_ids = templates::multicast(&state.db, &client, Sender::Admin, &body.line_user_ids, &messages, None)
+        .await
+        .map_err(send_error)?;
+
+    Ok(Json(SendResponse { request_ids }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/messages/broadcast",
+    tag = "messages",
+    request_body = SendRequest,
+    responses(
+        (status = 200, body = SendResponse),
+        (status = 400, body = ErrorBody),
+        (status = 502, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["messages:send"]), ("api_key" = ["messages:send"])),
+)]
+async fn broadcast_message(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Json(body): Json<SendRequest>,
+) -> ApiResult<SendResponse> {
+    auth.require("messages:send")?;
+    let messages = messaging::compose_messages(body.message_text, body.messages).map_err(ApiError::BadRequest)?;
+
+    let client = LineClient::from_settings(&state.db).await?;
+    let messages = templates::for_everyone(&state.db, &messages).await.map_err(send_error)?;
+    let request_id = messaging::broadcast(&state.db, &client, Sender::Admin, messages, None).await?;
+
+    Ok(Json(SendResponse {
+        request_ids: vec![request_id],
+    }))
+}
+
+// Scheduled messages
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ScheduledMessageQuery {
+    /// `pending`, `sent`, `failed`, `cancelled`, `completed` or `skipped`
+    status: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Created {
+    id: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/scheduled-messages",
+    tag = "scheduled",
+    params(ScheduledMessageQuery),
+    responses(
+        (status = 200, body = Page<ScheduledMessage>),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["scheduled:read"]), ("api_key" = ["scheduled:read"])),
+)]
+async fn list_scheduled_messages(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Query(query): Query<ScheduledMessageQuery>,
+) -> ApiResult<Page<ScheduledMessage>> {
+    auth.require("scheduled:read")?;
+    paginate(query.limit, query.offset, |limit, offset| {
+        ScheduledMessage::list(&state.db, query.status.as_deref(), limit, offset)
+    })
+    .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/scheduled-messages",
+    tag = "scheduled",
+    request_body = NewScheduledMessage,
+    responses(
+        (status = 201, body = Created),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["scheduled:write"]), ("api_key" = ["scheduled:write"])),
+)]
+async fn create_scheduled_message(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Json(body): Json<NewScheduledMessage>,
+) -> Result<(StatusCode, Json<Created>), ApiError> {
+    auth.require("scheduled:write")?;
+    let id = scheduler::create_scheduled_message(&state.db, body)
+        .await
+        .map_err(ApiError::BadRequest)?;
+
+    tracing::info!("API key {} created scheduled message {}", auth.key_id, id);
+    Ok((StatusCode::CREATED, Json(Created { id })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/scheduled-messages/{id}",
+    tag = "scheduled",
+    params(("id" = i64, Path)),
+    responses(
+        (status = 200, body = ScheduledMessage),
+        (status = 404, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["scheduled:read"]), ("api_key" = ["scheduled:read"])),
+)]
+async fn get_scheduled_message(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Path(id): Path<i64>,
+) -> ApiResult<ScheduledMessage> {
+    auth.require("scheduled:read")?;
+    ScheduledMessage::find_by_id(&state.db, id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("No scheduled message {}", id)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/scheduled-messages/{id}/runs",
+    tag = "scheduled",
+    params(("id" = i64, Path)),
+    responses(
+        (status = 200, body = Vec<ScheduledMessageRun>),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["scheduled:read"]), ("api_key" = ["scheduled:read"])),
+)]
+async fn get_scheduled_message_runs(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Path(id): Path<i64>,
+) -> ApiResult<Vec<ScheduledMessageRun>> {
+    auth.require("scheduled:read")?;
+    Ok(Json(ScheduledMessageRun::list_by_message(&state.db, id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/scheduled-messages/{id}/cancel",
+    tag = "scheduled",
+    params(("id" = i64, Path)),
+    responses(
+        (status = 200, body = ScheduledMessage),
+        (status = 400, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["scheduled:write"]), ("api_key" = ["scheduled:write"])),
+)]
+async fn cancel_scheduled_message(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Path(id): Path<i64>,
+) -> ApiResult<ScheduledMessage> {
+    auth.require("scheduled:write")?;
+    let message = ScheduledMessage::find_by_id(&state.db, id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("No scheduled message {}", id)))?;
+    if message.status != "pending" {
+        return Err(ApiError::BadRequest(format!("Scheduled message {} is already {}", id, message.status)));
+    }
+
+    ScheduledMessage::update_status(&state.db, id, "cancelled", None).await?;
+    get_scheduled_message(State(state), Extension(auth), Path(id)).await
+}
+
+// Calendar events
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CalendarQuery {
+    line_user_id: Option<String>,
+    /// Event times, e.g. RFC 3339; `to` is exclusive
+    from: Option<String>,
+    to: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewCalendarEvent {
+    line_user_id: String,
+    event_title: String,
+    event_description: Option<String>,
+    /// RFC 3339
+    event_time: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/calendar-events",
+    tag = "calendar",
+    params(CalendarQuery),
+    responses(
+        (status = 200, body = Page<Calendar>),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["calendar:read"]), ("api_key" = ["calendar:read"])),
+)]
+async fn list_calendar_events(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Query(query): Query<CalendarQuery>,
+) -> ApiResult<Page<Calendar>> {
+    auth.require("calendar:read")?;
+    paginate(query.limit, query.offset, |limit, offset| {
+        Calendar::list(
+            &state.db,
+            query.line_user_id.as_deref(),
+            query.from.as_deref(),
+            query.to.as_deref(),
+            limit,
+            offset,
+        )
+    })
+    .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/calendar-events",
+    tag = "calendar",
+    request_body = NewCalendarEvent,
+    responses(
+        (status = 201, body = Created),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["calendar:write"]), ("api_key" = ["calendar:write"])),
+)]
+async fn create_calendar_event(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Json(body): Json<NewCalendarEvent>,
+) -> Result<(StatusCode, Json<Created>), ApiError> {
+    auth.require("calendar:write")?;
+    chrono::DateTime::parse_from_rfc3339(&body.event_time)
+        .map_err(|e| ApiError::BadRequest(format!("Invalid event time: {}", e)))?;
+    if body.event_title.trim().is_empty() {
+        return Err(ApiError::BadRequest("event_title is required".to_string()));
+    }
+
+    let id = Calendar::create(
+        &state.db,
+        &body.line_user_id,
+        &body.event_title,
+        body.event_description.as_deref(),
+        &body.event_time,
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(Created { id })))
+}
+
+// Settings
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SettingEntry {
+    key: String,
+    value: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SettingValue {
+    value: String,
+    description: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/settings",
+    tag = "settings",
+    responses(
+        (status = 200, body = Vec<Setting>),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["settings:read"]), ("api_key" = ["settings:read"])),
+)]
+async fn list_settings(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+) -> ApiResult<Vec<Setting>> {
+    auth.require("settings:read")?;
+    Ok(Json(secrets::list_masked_settings(&state.db).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/settings/{key}",
+    tag = "settings",
+    params(("key" = String, Path)),
+    responses(
+        (status = 200, body = SettingEntry),
+        (status = 404, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["settings:read"]), ("api_key" = ["settings:read"])),
+)]
+async fn get_setting(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Path(key): Path<String>,
+) -> ApiResult<SettingEntry> {
+    auth.require("settings:read")?;
+    let value = Setting::get(&state.db, &key)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("No setting {}", key)))?;
+
+    let value = secrets::masked(&key, value);
+    Ok(Json(SettingEntry { key, value }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/settings/{key}",
+    tag = "settings",
+    params(("key" = String, Path)),
+    request_body = SettingValue,
+    responses(
+        (status = 200, body = SettingEntry),
+        (status = 400, body = ErrorBody),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["settings:write"]), ("api_key" = ["settings:write"])),
+)]
+async fn put_setting(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+    Path(key): Path<String>,
+    Json(body): Json<SettingValue>,
+) -> ApiResult<SettingEntry> {
+    auth.require("settings:write")?;
+    settings::set(&state.db, &key, &body.value, body.description.as_deref())
+        .await
+        .map_err(|e| match e {
+            SettingsError::Unknown(_) | SettingsError::Invalid(_) => ApiError::BadRequest(e.to_string()),
+            e => ApiError::Internal(e.into()),
+        })?;
+
+    tracing::info!("API key {} changed setting {}", auth.key_id, key);
+    let value = secrets::masked(&key, body.value);
+    Ok(Json(SettingEntry { key, value }))
+}
+
+// Analytics
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/stats/dashboard",
+    tag = "analytics",
+    responses(
+        (status = 200, body = DashboardStats),
+        (status = 401, body = ErrorBody),
+        (status = 403, body = ErrorBody),
+    ),
+    security(("bearer" = ["analytics:read"]), ("api_key" = ["analytics:read"])),
+)]
+async fn get_dashboard_stats(
+    State(state): State<Arc<AppState>>,
+    Extension(auth): Extension<Authenticated>,
+) -> ApiResult<DashboardStats> {
+    auth.require("analytics:read")?;
+    Ok(Json(analytics::get_dashboard_stats(&state.db).await?))
+}
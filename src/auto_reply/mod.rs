@@ -0,0 +1,181 @@
+use chrono::{Local, NaiveTime};
+use regex::{Regex, RegexBuilder};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+use crate::api::line_client::{LineClient, Message as LineMessage};
+use crate::api::line_message::validate_messages;
+use crate::db::models::{AutoReplyLog, AutoReplyRule};
+use crate::messaging::{self, Sender};
+
+pub const MATCH_TYPES: &[&str] = &["exact", "contains", "regex", "any"];
+
+/// A regex rule's pattern as compiled, `None` if it doesn't compile
+struct CompiledRegex {
+    pattern: String,
+    case_sensitive: bool,
+    regex: Option<Regex>,
+}
+
+/// Compiled patterns of regex rules by rule ID, so each is built once
+/// rather than for every message. Rebuilt when the rule's pattern changes.
+static REGEXES: LazyLock<Mutex<HashMap<i64, CompiledRegex>>> = LazyLock::new(Default::default);
+
+fn parse_time(field: &str, value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value, "%H:%M").map_err(|_| format!("{} must be HH:MM, got '{}'", field, value))
+}
+
+/// Check a rule before it is saved
+pub fn validate_rule(
+    match_type: &str,
+    pattern: Option<&str>,
+    start_time: Option<&str>,
+    end_time: Option<&str>,
+    messages: &[LineMessage],
+) -> Result<(), String> {
+    if !MATCH_TYPES.contains(&match_type) {
+        return Err(format!("Unknown match type '{}' (expected one of {:?})", match_type, MATCH_TYPES));
+    }
+
+    match (match_type, pattern) {
+        ("any", _) => {}
+        (_, None) => return Err(format!("A {} rule needs a pattern", match_type)),
+        (_, Some(pattern)) if pattern.trim().is_empty() => {
+            return Err(format!("A {} rule needs a pattern", match_type));
+        }
+        ("regex", Some(pattern)) => {
+            RegexBuilder::new(pattern)
+                .build()
+                .map_err(|e| format!("Invalid regular expression: {}", e))?;
+        }
+        _ => {}
+    }
+
+    match (start_time, end_time) {
+        (Some(start), Some(end)) => {
+            if parse_time("start_time", start)? == parse_time("end_time", end)? {
+                return Err("start_time and end_time must differ".to_string());
+            }
+        }
+        (None, None) => {}
+        _ => return Err("Set both start_time and end_time, or neither".to_string()),
+    }
+
+    validate_messages(messages)
+}
+
+/// Whether `now` falls in the rule's time-of-day window (always true without one)
+fn in_time_window(rule: &AutoReplyRule, now: NaiveTime) -> bool {
+    let (Some(start), Some(end)) = (&rule.start_time, &rule.end_time) else {
+        return true;
+    };
+    let (Ok(start), Ok(end)) = (parse_time("start_time", start), parse_time("end_time", end)) else {
+        return false;
+    };
+
+    if start <= end {
+        start <= now && now < end
+    } else {
+        // e.g. 18:00-09:00 covers the night
+        now >= start || now < end
+    }
+}
+
+/// Whether a rule matches inbound text at time-of-day `now`
+pub fn rule_matches(rule: &AutoReplyRule, text: &str, now: NaiveTime) -> bool {
+    if !in_time_window(rule, now) {
+        return false;
+    }
+
+    let pattern = rule.pattern.as_deref().unwrap_or_default();
+    let text = text.trim();
+
+    match rule.match_type.as_str() {
+        "any" => true,
+        "exact" if rule.case_sensitive => text == pattern.trim(),
+        "exact" => text.to_lowercase() == pattern.trim().to_lowercase(),
+        "contains" if rule.case_sensitive => text.contains(pattern),
+        "contains" => text.to_lowercase().contains(&pattern.to_lowercase()),
+        "regex" => compiled_regex(rule, pattern).is_some_and(|regex| regex.is_match(text)),
+        other => {
+            tracing::warn!("Auto-reply rule {} has unknown match type {}", rule.id, other);
+            false
+        }
+    }
+}
+
+/// The rule's compiled regex, from the cache unless the pattern changed
+fn compiled_regex(rule: &AutoReplyRule, pattern: &str) -> Option<Regex> {
+    let mut regexes = REGEXES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(compiled) = regexes.get(&rule.id) {
+        if compiled.pattern == pattern && compiled.case_sensitive == rule.case_sensitive {
+            return compiled.regex.clone();
+        }
+    }
+
+    let regex = match RegexBuilder::new(pattern)
+        .case_insensitive(!rule.case_sensitive)
+        .build()
+    {
+        Ok(regex) => Some(regex),
+        Err(e) => {
+            tracing::warn!("Auto-reply rule {} has an invalid regex: {}", rule.id, e);
+            None
+        }
+    };
+    regexes.insert(
+        rule.id,
+        CompiledRegex {
+            pattern: pattern.to_string(),
+            case_sensitive: rule.case_sensitive,
+            regex: regex.clone(),
+        },
+    );
+    regex
+}
+
+/// The first enabled rule, in priority order, that matches the text
+pub async fn find_matching_rule(
+    db: &SqlitePool,
+    text: &str,
+    now: NaiveTime,
+) -> Result<Option<AutoReplyRule>, sqlx::Error> {
+    let rules = AutoReplyRule::list_enabled(db).await?;
+    Ok(rules.into_iter().find(|rule| rule_matches(rule, text, now)))
+}
+
+/// Answer inbound text with the first matching rule, if any, and log the match.
+/// Returns whether a rule matched.
+pub async fn reply_to_text(
+    db: &SqlitePool,
+    line_user_id: &str,
+    reply_token: &str,
+    text: &str,
+) -> Result<bool, anyhow::Error> {
+    let Some(rule) = find_matching_rule(db, text, Local::now().time()).await? else {
+        return Ok(false);
+    };
+
+    tracing::info!("Auto-reply rule '{}' matched text from {}", rule.name, line_user_id);
+
+    let result = send_rule_reply(db, &rule, line_user_id, reply_token).await;
+    let error = result.as_ref().err().map(|e| e.to_string());
+    let status = if error.is_some() { "failed" } else { "sent" };
+
+    AutoReplyLog::create(db, rule.id, line_user_id, text, status, error.as_deref()).await?;
+
+    result.map(|_| true)
+}
+
+async fn send_rule_reply(
+    db: &SqlitePool,
+    rule: &AutoReplyRule,
+    line_user_id: &str,
+    reply_token: &str,
+) -> Result<(), anyhow::Error> {
+    let messages: Vec<LineMessage> = serde_json::from_str(&rule.messages)?;
+    let client = LineClient::from_settings(db).await?;
+    messaging::reply(db, &client, Sender::Rule, line_user_id, reply_token, messages).await?;
+    Ok(())
+}
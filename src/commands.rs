@@ -1,9 +1,32 @@
+use reqwest::StatusCode;
 use sqlx::SqlitePool;
+use std::path::Path;
 use tauri::State;
 
-use crate::db::models::{User, Message, ScheduledMessage, Setting, Calendar};
+use crate::db::models::{
+    User, FollowHistory, Message, Event, ScheduledMessage, ScheduledMessageRun, Setting, Calendar, RichMenu, RichMenuAlias,
+    AutoReplyRule, AutoReplyLog, Conversation, ApiKey, NotificationLog, NotificationRule, MessageTemplate,
+    UserAttribute,
+};
 use crate::analytics::{DashboardStats, UserStats};
-use crate::api::line_client::{LineClient, Message as LineMessage};
+use crate::api::auth::{self, CreatedApiKey};
+use crate::api::line_client::{
+    Audience, BotInfo, DemographicFilter, LineClient, Message as LineMessage, NarrowcastFilter, NarrowcastLimit,
+    NarrowcastProgress, Recipient,
+};
+use crate::api::rich_menu::{self, RichMenu as LineRichMenu, RichMenuResponse};
+use crate::api::slack_client::AuthInfo;
+use crate::auto_reply;
+use crate::followers;
+use crate::inbox;
+use crate::media::{self, MediaStore};
+use crate::messaging::{self, Sender};
+use crate::notification::rules::{self, NewNotificationRule};
+use crate::notification::{self, outbox, ChannelView};
+use crate::secrets;
+use crate::settings::{self, connection, SettingInfo};
+use crate::scheduler::NewScheduledMessage;
+use crate::templates::{self, NewMessageTemplate, TemplatePreview};
 
 pub struct AppState {
     pub db: SqlitePool,
@@ -11,8 +34,15 @@ pub struct AppState {
 
 // User commands
 #[tauri::command]
-pub async fn get_users(state: State<'_, AppState>) -> Result<Vec<User>, String> {
-    User::list_all(&state.db)
+pub async fn get_users(state: State<'_, AppState>, include_unfollowed: Option<bool>) -> Result<Vec<User>, String> {
+    User::list_all(&state.db, include_unfollowed.unwrap_or(false))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_follow_history(state: State<'_, AppState>, line_user_id: String) -> Result<Vec<FollowHistory>, String> {
+    FollowHistory::list_by_user(&state.db, &line_user_id)
         .await
         .map_err(|e| e.to_string())
 }
@@ -24,6 +54,46 @@ pub async fn get_user_by_line_id(state: State<'_, AppState>, line_user_id: Strin
         .map_err(|e| e.to_string())
 }
 
+/// Fetch a user's profile from LINE now instead of waiting for the refresh job
+#[tauri::command]
+pub async fn refresh_user_profile(state: State<'_, AppState>, line_user_id: String) -> Result<Option<User>, String> {
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    followers::refresh_profile(&state.db, &client, &line_user_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    User::find_by_line_id(&state.db, &line_user_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Refresh every missing or out-of-date profile. Returns how many were refreshed.
+#[tauri::command]
+pub async fn refresh_stale_profiles(state: State<'_, AppState>) -> Result<usize, String> {
+    followers::refresh_stale_profiles(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Welcome message commands
+#[tauri::command]
+pub async fn get_welcome_messages(state: State<'_, AppState>) -> Result<Vec<LineMessage>, String> {
+    followers::welcome_messages(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Set the messages replied to new followers; an empty list turns them off
+#[tauri::command]
+pub async fn set_welcome_messages(state: State<'_, AppState>, messages: Vec<LineMessage>) -> Result<(), String> {
+    followers::set_welcome_messages(&state.db, &messages)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 // Message commands
 #[tauri::command]
 pub async fn get_messages(state: State<'_, AppState>, limit: i32) -> Result<Vec<Message>, String> {
@@ -43,29 +113,305 @@ pub async fn get_messages_by_user(
         .map_err(|e| e.to_string())
 }
 
+/// Path of the media a user sent. The UI loads it through the asset
+/// protocol (`convertFileSrc`) rather than having it copied over IPC.
+#[tauri::command]
+pub async fn get_message_media(state: State<'_, AppState>, message_id: i64) -> Result<String, String> {
+    let message = Message::find_by_id(&state.db, message_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Message {} not found", message_id))?;
+
+    let data: serde_json::Value = message
+        .message_data
+        .as_deref()
+        .and_then(|d| serde_json::from_str(d).ok())
+        .unwrap_or_default();
+    let relative = data["path"]
+        .as_str()
+        .ok_or_else(|| "The content of this message has not been downloaded".to_string())?;
+    let path = MediaStore::from_env()
+        .resolve(relative)
+        .ok_or_else(|| format!("Invalid media path {}", relative))?;
+    tokio::fs::metadata(&path)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Download (again) the content of a media message, e.g. after a failure
+#[tauri::command]
+pub async fn download_message_media(state: State<'_, AppState>, message_id: i64) -> Result<(), String> {
+    media::download_message_content(&state.db, &MediaStore::from_env(), message_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Inbox commands
+/// Conversations with the latest activity first. New messages and changes
+/// are also emitted live as `inbox-event`.
+#[tauri::command]
+pub async fn get_conversations(
+    state: State<'_, AppState>,
+    status: Option<String>,
+    assigned_to: Option<String>,
+    unread_only: Option<bool>,
+    limit: i32,
+) -> Result<Vec<Conversation>, String> {
+    Conversation::list(
+        &state.db,
+        status.as_deref(),
+        assigned_to.as_deref(),
+        unread_only.unwrap_or(false),
+        limit,
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn mark_conversation_read(state: State<'_, AppState>, line_user_id: String) -> Result<Conversation, String> {
+    inbox::mark_read(&state.db, &line_user_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_conversation_status(
+    state: State<'_, AppState>,
+    line_user_id: String,
+    status: String,
+) -> Result<Conversation, String> {
+    inbox::set_status(&state.db, &line_user_id, &status)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Assign a conversation to an admin; no name (or an empty one) unassigns it
+#[tauri::command]
+pub async fn assign_conversation(
+    state: State<'_, AppState>,
+    line_user_id: String,
+    assigned_to: Option<String>,
+) -> Result<Conversation, String> {
+    inbox::assign(&state.db, &line_user_id, assigned_to.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Reply to a conversation with a push message, which also marks it read
+#[tauri::command]
+pub async fn reply_to_conversation(
+    state: State<'_, AppState>,
+    line_user_id: String,
+    message_text: Option<String>,
+    messages: Option<Vec<LineMessage>>,
+) -> Result<Conversation, String> {
+    let messages = messaging::compose_messages(message_text, messages)?;
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let messages = templates::personalize(&state.db, &messages, &line_user_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    messaging::push(&state.db, &client, Sender::Admin, &line_user_id, messages, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    inbox::mark_read(&state.db, &line_user_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Webhook event commands
+/// Stored webhook events, newest first, optionally of one type (e.g. "postback")
+#[tauri::command]
+pub async fn get_events(
+    state: State<'_, AppState>,
+    event_type: Option<String>,
+    limit: i32,
+) -> Result<Vec<Event>, String> {
+    Event::list(&state.db, event_type.as_deref(), limit)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 // Scheduled message commands
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn create_scheduled_message(
     state: State<'_, AppState>,
     line_user_id: Option<String>,
-    message_text: String,
+    message_text: Option<String>,
+    messages: Option<Vec<LineMessage>>,
     schedule_time: String,
     cron_expression: Option<String>,
+    end_at: Option<String>,
+    max_occurrences: Option<i64>,
+    audience: Option<Audience>,
+    template_id: Option<i64>,
 ) -> Result<i64, String> {
-    ScheduledMessage::create(
+    let new = NewScheduledMessage {
+        line_user_id,
+        message_text,
+        template_id,
+        messages,
+        schedule_time,
+        cron_expression,
+        end_at,
+        max_occurrences,
+        audience,
+    };
+
+    crate::scheduler::create_scheduled_message(&state.db, new).await
+}
+
+#[tauri::command]
+pub async fn get_scheduled_messages(state: State<'_, AppState>) -> Result<Vec<ScheduledMessage>, String> {
+    ScheduledMessage::list_pending(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_scheduled_message_runs(
+    state: State<'_, AppState>,
+    scheduled_message_id: i64,
+) -> Result<Vec<ScheduledMessageRun>, String> {
+    ScheduledMessageRun::list_by_message(&state.db, scheduled_message_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Auto-reply commands
+/// Validate a rule and serialize its messages for storage
+fn prepare_auto_reply_rule(
+    match_type: &str,
+    pattern: Option<&str>,
+    start_time: Option<&str>,
+    end_time: Option<&str>,
+    messages: &[LineMessage],
+) -> Result<String, String> {
+    auto_reply::validate_rule(match_type, pattern, start_time, end_time, messages)?;
+    serde_json::to_string(messages).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn create_auto_reply_rule(
+    state: State<'_, AppState>,
+    name: String,
+    match_type: String,
+    pattern: Option<String>,
+    case_sensitive: bool,
+    start_time: Option<String>,
+    end_time: Option<String>,
+    messages: Vec<LineMessage>,
+    priority: i64,
+    enabled: bool,
+) -> Result<i64, String> {
+    let messages = prepare_auto_reply_rule(
+        &match_type,
+        pattern.as_deref(),
+        start_time.as_deref(),
+        end_time.as_deref(),
+        &messages,
+    )?;
+
+    AutoReplyRule::create(
         &state.db,
-        line_user_id.as_deref(),
-        &message_text,
-        &schedule_time,
-        cron_expression.as_deref(),
+        &name,
+        &match_type,
+        pattern.as_deref(),
+        case_sensitive,
+        start_time.as_deref(),
+        end_time.as_deref(),
+        &messages,
+        priority,
+        enabled,
     )
     .await
     .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn get_scheduled_messages(state: State<'_, AppState>) -> Result<Vec<ScheduledMessage>, String> {
-    ScheduledMessage::list_pending(&state.db)
+#[allow(clippy::too_many_arguments)]
+pub async fn update_auto_reply_rule(
+    state: State<'_, AppState>,
+    rule_id: i64,
+    name: String,
+    match_type: String,
+    pattern: Option<String>,
+    case_sensitive: bool,
+    start_time: Option<String>,
+    end_time: Option<String>,
+    messages: Vec<LineMessage>,
+    priority: i64,
+    enabled: bool,
+) -> Result<(), String> {
+    let messages = prepare_auto_reply_rule(
+        &match_type,
+        pattern.as_deref(),
+        start_time.as_deref(),
+        end_time.as_deref(),
+        &messages,
+    )?;
+
+    AutoReplyRule::update(
+        &state.db,
+        rule_id,
+        &name,
+        &match_type,
+        pattern.as_deref(),
+        case_sensitive,
+        start_time.as_deref(),
+        end_time.as_deref(),
+        &messages,
+        priority,
+        enabled,
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_auto_reply_rule(state: State<'_, AppState>, rule_id: i64) -> Result<(), String> {
+    AutoReplyRule::delete(&state.db, rule_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_auto_reply_rules(state: State<'_, AppState>) -> Result<Vec<AutoReplyRule>, String> {
+    AutoReplyRule::list_all(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_auto_reply_logs(state: State<'_, AppState>, limit: i32) -> Result<Vec<AutoReplyLog>, String> {
+    AutoReplyLog::list_recent(&state.db, limit)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Dry run: which rule would answer `text` at `time` (HH:MM, default now).
+/// Nothing is sent or logged.
+#[tauri::command]
+pub async fn test_auto_reply(
+    state: State<'_, AppState>,
+    text: String,
+    time: Option<String>,
+) -> Result<Option<AutoReplyRule>, String> {
+    let now = match time {
+        Some(time) => chrono::NaiveTime::parse_from_str(&time, "%H:%M")
+            .map_err(|_| format!("time must be HH:MM, got '{}'", time))?,
+        None => chrono::Local::now().time(),
+    };
+
+    auto_reply::find_matching_rule(&state.db, &text, now)
         .await
         .map_err(|e| e.to_string())
 }
@@ -103,9 +449,15 @@ pub async fn get_calendar_events(
 // Settings commands
 #[tauri::command]
 pub async fn get_setting(state: State<'_, AppState>, key: String) -> Result<Option<String>, String> {
-    Setting::get(&state.db, &key)
+    if settings::find(&key).is_none() {
+        return Err(format!("Unknown setting '{}'", key));
+    }
+
+    let value = Setting::get(&state.db, &key)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(|e| e.to_string())?;
+
+    Ok(value.map(|value| secrets::masked(&key, value)))
 }
 
 #[tauri::command]
@@ -115,18 +467,278 @@ pub async fn set_setting(
     value: String,
     description: Option<String>,
 ) -> Result<(), String> {
-    Setting::set(&state.db, &key, &value, description.as_deref())
+    settings::set(&state.db, &key, &value, description.as_deref())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Every known setting with its type, description and effective value
+#[tauri::command]
+pub async fn get_setting_definitions(state: State<'_, AppState>) -> Result<Vec<SettingInfo>, String> {
+    settings::list(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Connection test commands
+#[tauri::command]
+pub async fn test_line_connection(state: State<'_, AppState>) -> Result<BotInfo, String> {
+    connection::test_line(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn test_slack_bridge_connection(state: State<'_, AppState>) -> Result<AuthInfo, String> {
+    connection::test_slack_bridge(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Check `notion`, `airtable` or `google_sheets`
+#[tauri::command]
+pub async fn test_integration_connection(state: State<'_, AppState>, integration: String) -> Result<(), String> {
+    connection::test_integration(&state.db, &integration)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Notification channel commands
+/// Channels with their credentials masked
+#[tauri::command]
+pub async fn get_notification_channels(state: State<'_, AppState>) -> Result<Vec<ChannelView>, String> {
+    notification::list_channels(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn create_notification_channel(
+    state: State<'_, AppState>,
+    name: String,
+    kind: String,
+    config: serde_json::Value,
+    enabled: bool,
+) -> Result<i64, String> {
+    notification::create_channel(&state.db, &name, &kind, config, enabled)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Masked credentials in `config` keep their saved values
+#[tauri::command]
+pub async fn update_notification_channel(
+    state: State<'_, AppState>,
+    id: i64,
+    name: String,
+    config: serde_json::Value,
+    enabled: bool,
+) -> Result<(), String> {
+    notification::update_channel(&state.db, id, &name, config, enabled)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_notification_channel(state: State<'_, AppState>, id: i64) -> Result<(), String> {
+    notification::delete_channel(&state.db, id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Send a test notification through one channel
+#[tauri::command]
+pub async fn test_notification_channel(state: State<'_, AppState>, id: i64) -> Result<(), String> {
+    notification::test_channel(&state.db, id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Notification log commands
+/// The notification outbox, newest first. `status` is pending, sent or dead;
+/// `since`/`until` are datetimes.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn get_notification_logs(
+    state: State<'_, AppState>,
+    status: Option<String>,
+    channel_id: Option<i64>,
+    event_kind: Option<String>,
+    since: Option<String>,
+    until: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> Result<Vec<NotificationLog>, String> {
+    NotificationLog::search(
+        &state.db,
+        status.as_deref(),
+        channel_id,
+        event_kind.as_deref(),
+        since.as_deref(),
+        until.as_deref(),
+        limit.unwrap_or(50).clamp(1, 500),
+        offset.unwrap_or(0).max(0),
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Notifications per status, e.g. `[["dead", 2], ["sent", 40]]`
+#[tauri::command]
+pub async fn get_notification_log_counts(state: State<'_, AppState>) -> Result<Vec<(String, i64)>, String> {
+    NotificationLog::count_by_status(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Send a notification again with fresh attempts
+#[tauri::command]
+pub async fn resend_notification(state: State<'_, AppState>, id: i64) -> Result<NotificationLog, String> {
+    outbox::resend(&state.db, id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Notification rule commands
+#[tauri::command]
+pub async fn get_notification_rules(state: State<'_, AppState>) -> Result<Vec<NotificationRule>, String> {
+    NotificationRule::list_all(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn create_notification_rule(state: State<'_, AppState>, rule: NewNotificationRule) -> Result<i64, String> {
+    rules::create_rule(&state.db, rule)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn update_notification_rule(
+    state: State<'_, AppState>,
+    id: i64,
+    rule: NewNotificationRule,
+) -> Result<(), String> {
+    rules::update_rule(&state.db, id, rule)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Events the rule still holds for a digest are dropped with it
+#[tauri::command]
+pub async fn delete_notification_rule(state: State<'_, AppState>, id: i64) -> Result<(), String> {
+    NotificationRule::delete(&state.db, id)
         .await
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub async fn get_all_settings(state: State<'_, AppState>) -> Result<Vec<Setting>, String> {
-    Setting::list_all(&state.db)
+    secrets::list_masked_settings(&state.db)
         .await
         .map_err(|e| e.to_string())
 }
 
+// Message template commands
+#[tauri::command]
+pub async fn get_message_templates(state: State<'_, AppState>) -> Result<Vec<MessageTemplate>, String> {
+    MessageTemplate::list_all(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn create_message_template(
+    state: State<'_, AppState>,
+    template: NewMessageTemplate,
+) -> Result<i64, String> {
+    templates::create_template(&state.db, template)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn update_message_template(
+    state: State<'_, AppState>,
+    id: i64,
+    template: NewMessageTemplate,
+) -> Result<(), String> {
+    templates::update_template(&state.db, id, template)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Scheduled messages using the template keep the text they were scheduled with
+#[tauri::command]
+pub async fn delete_message_template(state: State<'_, AppState>, id: i64) -> Result<(), String> {
+    MessageTemplate::delete(&state.db, id)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Render template text for a user, and a calendar event for reminder variables
+#[tauri::command]
+pub async fn preview_message_template(
+    state: State<'_, AppState>,
+    body: String,
+    line_user_id: Option<String>,
+    calendar_id: Option<i64>,
+) -> Result<TemplatePreview, String> {
+    templates::preview(&state.db, &body, line_user_id.as_deref(), calendar_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_user_attributes(state: State<'_, AppState>, line_user_id: String) -> Result<Vec<UserAttribute>, String> {
+    UserAttribute::list_by_user(&state.db, &line_user_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Set a custom attribute, or remove it with an empty value
+#[tauri::command]
+pub async fn set_user_attribute(
+    state: State<'_, AppState>,
+    line_user_id: String,
+    name: String,
+    value: String,
+) -> Result<(), String> {
+    templates::set_user_attribute(&state.db, &line_user_id, &name, &value)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// API key commands
+/// Create a key for the REST API. The key is only returned here.
+#[tauri::command]
+pub async fn create_api_key(
+    state: State<'_, AppState>,
+    name: String,
+    scopes: Vec<String>,
+) -> Result<CreatedApiKey, String> {
+    auth::create_api_key(&state.db, &name, &scopes)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_api_keys(state: State<'_, AppState>) -> Result<Vec<ApiKey>, String> {
+    ApiKey::list_all(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn revoke_api_key(state: State<'_, AppState>, id: i64) -> Result<(), String> {
+    ApiKey::revoke(&state.db, id)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
 // Analytics commands
 #[tauri::command]
 pub async fn get_dashboard_stats(state: State<'_, AppState>) -> Result<DashboardStats, String> {
@@ -147,37 +759,417 @@ pub async fn get_user_stats(state: State<'_, AppState>, line_user_id: String) ->
 pub async fn send_message_to_user(
     state: State<'_, AppState>,
     line_user_id: String,
-    message_text: String,
+    message_text: Option<String>,
+    messages: Option<Vec<LineMessage>>,
+) -> Result<(), String> {
+    let messages = messaging::compose_messages(message_text, messages)?;
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    messaging::push(&state.db, &client, Sender::Admin, &line_user_id, messages, None)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn broadcast_message(
+    state: State<'_, AppState>,
+    message_text: Option<String>,
+    messages: Option<Vec<LineMessage>>,
 ) -> Result<(), String> {
-    let access_token = Setting::get(&state.db, "line_channel_access_token")
+    let messages = messaging::compose_messages(message_text, messages)?;
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let messages = templates::for_everyone(&state.db, &messages)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    messaging::broadcast(&state.db, &client, Sender::Admin, messages, None)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn multicast_message(
+    state: State<'_, AppState>,
+    line_user_ids: Vec<String>,
+    message_text: Option<String>,
+    messages: Option<Vec<LineMessage>>,
+) -> Result<(), String> {
+    if line_user_ids.is_empty() {
+        return Err("No users selected".to_string());
+    }
+
+    let messages = messaging::compose_messages(message_text, messages)?;
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Rendered for each recipient
+    templates::multicast(&state.db, &client, Sender::Admin, &line_user_ids, &messages, None)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Start a narrowcast and return its request ID for `get_narrowcast_progress`
+#[tauri::command]
+pub async fn narrowcast_message(
+    state: State<'_, AppState>,
+    message_text: Option<String>,
+    messages: Option<Vec<LineMessage>>,
+    recipient: Option<Recipient>,
+    filter: Option<DemographicFilter>,
+    limit: Option<NarrowcastLimit>,
+) -> Result<String, String> {
+    let messages = messaging::compose_messages(message_text, messages)?;
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    let filter = filter.map(|demographic| NarrowcastFilter { demographic });
+    let messages = templates::for_everyone(&state.db, &messages)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    messaging::narrowcast(&state.db, &client, Sender::Admin, messages, recipient, filter, limit, None)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_narrowcast_progress(
+    state: State<'_, AppState>,
+    request_id: String,
+) -> Result<NarrowcastProgress, String> {
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    client
+        .get_narrowcast_progress(&request_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Rich menu commands
+async fn find_rich_menu(db: &SqlitePool, rich_menu_id: i64) -> Result<RichMenu, String> {
+    RichMenu::find_by_id(db, rich_menu_id)
         .await
         .map_err(|e| e.to_string())?
-        .ok_or_else(|| "LINE access token not configured".to_string())?;
+        .ok_or_else(|| format!("Rich menu {} not found", rich_menu_id))
+}
+
+/// LINE refuses to show a menu that has no image yet
+fn require_image(menu: &RichMenu) -> Result<(), String> {
+    if menu.image_path.is_none() {
+        return Err(format!("Upload an image for rich menu '{}' first", menu.name));
+    }
+    Ok(())
+}
+
+/// Create a rich menu on LINE from a JSON definition, optionally uploading its
+/// image right away, and store the definition
+#[tauri::command]
+pub async fn create_rich_menu(
+    state: State<'_, AppState>,
+    definition: LineRichMenu,
+    image_path: Option<String>,
+) -> Result<RichMenu, String> {
+    definition.validate()?;
+    // Read the image before creating anything so a bad file doesn't leave an empty menu
+    let image = image_path
+        .as_deref()
+        .map(|path| rich_menu::load_image(Path::new(path)))
+        .transpose()?;
+
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    let line_rich_menu_id = client
+        .create_rich_menu(&definition)
+        .await
+        .map_err(|e| e.to_string())?;
 
-    let client = LineClient::new(access_token);
-    let messages = vec![LineMessage::Text { text: message_text }];
+    if let Some((bytes, content_type)) = image {
+        if let Err(e) = client
+            .upload_rich_menu_image(&line_rich_menu_id, content_type, bytes)
+            .await
+        {
+            if let Err(cleanup) = client.delete_rich_menu(&line_rich_menu_id).await {
+                tracing::warn!("Failed to delete rich menu {} after image upload failed: {}", line_rich_menu_id, cleanup);
+            }
+            return Err(e.to_string());
+        }
+    }
 
+    let definition_json = serde_json::to_string(&definition).map_err(|e| e.to_string())?;
+    let id = RichMenu::create(
+        &state.db,
+        &definition.name,
+        &definition_json,
+        &line_rich_menu_id,
+        image_path.as_deref(),
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    find_rich_menu(&state.db, id).await
+}
+
+#[tauri::command]
+pub async fn upload_rich_menu_image(
+    state: State<'_, AppState>,
+    rich_menu_id: i64,
+    image_path: String,
+) -> Result<(), String> {
+    let menu = find_rich_menu(&state.db, rich_menu_id).await?;
+    if menu.image_path.is_some() {
+        return Err("LINE does not allow replacing a rich menu image; create a new menu instead".to_string());
+    }
+
+    let (bytes, content_type) = rich_menu::load_image(Path::new(&image_path))?;
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
     client
-        .push_message(&line_user_id, messages)
+        .upload_rich_menu_image(&menu.line_rich_menu_id, content_type, bytes)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    RichMenu::set_image_path(&state.db, rich_menu_id, &image_path)
         .await
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn broadcast_message(
+pub async fn get_rich_menus(state: State<'_, AppState>) -> Result<Vec<RichMenu>, String> {
+    RichMenu::list_all(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Every rich menu on the LINE channel, including ones created outside this app
+#[tauri::command]
+pub async fn get_line_rich_menus(state: State<'_, AppState>) -> Result<Vec<RichMenuResponse>, String> {
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    client
+        .list_rich_menus()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_rich_menu(state: State<'_, AppState>, rich_menu_id: i64) -> Result<(), String> {
+    let menu = find_rich_menu(&state.db, rich_menu_id).await?;
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Aliases pointing at the menu have to go first
+    let aliases = RichMenuAlias::list_all(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    for alias in aliases.iter().filter(|a| a.rich_menu_id == rich_menu_id) {
+        match client.delete_rich_menu_alias(&alias.alias_id).await {
+            Ok(()) => {}
+            Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+
+    // A menu already deleted on LINE only needs the local row removed
+    match client.delete_rich_menu(&menu.line_rich_menu_id).await {
+        Ok(()) => {}
+        Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => {
+            tracing::info!("Rich menu {} was already deleted on LINE", menu.line_rich_menu_id);
+        }
+        Err(e) => return Err(e.to_string()),
+    }
+
+    RichMenu::delete(&state.db, rich_menu_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_default_rich_menu(state: State<'_, AppState>, rich_menu_id: i64) -> Result<(), String> {
+    let menu = find_rich_menu(&state.db, rich_menu_id).await?;
+    require_image(&menu)?;
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    client
+        .set_default_rich_menu(&menu.line_rich_menu_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    RichMenu::set_default(&state.db, Some(rich_menu_id))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn clear_default_rich_menu(state: State<'_, AppState>) -> Result<(), String> {
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    client
+        .clear_default_rich_menu()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    RichMenu::set_default(&state.db, None)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn link_rich_menu_to_user(
     state: State<'_, AppState>,
-    message_text: String,
+    rich_menu_id: i64,
+    line_user_id: String,
 ) -> Result<(), String> {
-    let access_token = Setting::get(&state.db, "line_channel_access_token")
+    let menu = find_rich_menu(&state.db, rich_menu_id).await?;
+    require_image(&menu)?;
+    let client = LineClient::from_settings(&state.db)
         .await
-        .map_err(|e| e.to_string())?
-        .ok_or_else(|| "LINE access token not configured".to_string())?;
+        .map_err(|e| e.to_string())?;
+
+    client
+        .link_rich_menu_to_user(&line_user_id, &menu.line_rich_menu_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn unlink_rich_menu_from_user(state: State<'_, AppState>, line_user_id: String) -> Result<(), String> {
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    client
+        .unlink_rich_menu_from_user(&line_user_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// LINE rich menu ID linked to a user, or `None` if the user sees the default menu
+#[tauri::command]
+pub async fn get_user_rich_menu_id(state: State<'_, AppState>, line_user_id: String) -> Result<Option<String>, String> {
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    client
+        .get_user_rich_menu_id(&line_user_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn link_rich_menu_to_users(
+    state: State<'_, AppState>,
+    rich_menu_id: i64,
+    line_user_ids: Vec<String>,
+) -> Result<(), String> {
+    if line_user_ids.is_empty() {
+        return Err("No users selected".to_string());
+    }
+
+    let menu = find_rich_menu(&state.db, rich_menu_id).await?;
+    require_image(&menu)?;
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    client
+        .link_rich_menu_to_users(&line_user_ids, &menu.line_rich_menu_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn unlink_rich_menu_from_users(
+    state: State<'_, AppState>,
+    line_user_ids: Vec<String>,
+) -> Result<(), String> {
+    if line_user_ids.is_empty() {
+        return Err("No users selected".to_string());
+    }
 
-    let client = LineClient::new(access_token);
-    let messages = vec![LineMessage::Text { text: message_text }];
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
 
     client
-        .broadcast_message(messages)
+        .unlink_rich_menu_from_users(&line_user_ids)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Create an alias for a menu, or point an existing alias at it
+#[tauri::command]
+pub async fn set_rich_menu_alias(
+    state: State<'_, AppState>,
+    alias_id: String,
+    rich_menu_id: i64,
+) -> Result<(), String> {
+    rich_menu::validate_alias_id(&alias_id)?;
+    let menu = find_rich_menu(&state.db, rich_menu_id).await?;
+    require_image(&menu)?;
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let exists = client
+        .list_rich_menu_aliases()
+        .await
+        .map_err(|e| e.to_string())?
+        .iter()
+        .any(|alias| alias.rich_menu_alias_id == alias_id);
+
+    let result = if exists {
+        client.update_rich_menu_alias(&alias_id, &menu.line_rich_menu_id).await
+    } else {
+        client.create_rich_menu_alias(&alias_id, &menu.line_rich_menu_id).await
+    };
+    result.map_err(|e| e.to_string())?;
+
+    RichMenuAlias::upsert(&state.db, &alias_id, rich_menu_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_rich_menu_aliases(state: State<'_, AppState>) -> Result<Vec<RichMenuAlias>, String> {
+    RichMenuAlias::list_all(&state.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_rich_menu_alias(state: State<'_, AppState>, alias_id: String) -> Result<(), String> {
+    let client = LineClient::from_settings(&state.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match client.delete_rich_menu_alias(&alias_id).await {
+        Ok(()) => {}
+        Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => {}
+        Err(e) => return Err(e.to_string()),
+    }
+
+    RichMenuAlias::delete(&state.db, &alias_id)
         .await
         .map_err(|e| e.to_string())
 }
@@ -217,12 +1209,7 @@ pub async fn delete_message(state: State<'_, AppState>, message_id: i64) -> Resu
 
 #[tauri::command]
 pub async fn delete_user(state: State<'_, AppState>, user_id: i64) -> Result<(), String> {
-    sqlx::query("DELETE FROM users WHERE id = ?")
-        .bind(user_id)
-        .execute(&state.db)
-        .await
-        .map_err(|e| e.to_string())?;
-    Ok(())
+    User::delete(&state.db, user_id).await.map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -1,14 +1,44 @@
+use sqlx::migrate::{Migrate, MigrateError, Migration, Migrator};
 use sqlx::{Pool, Sqlite, SqlitePool};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 pub mod models;
 
-pub async fn init_db(db_path: &str) -> Result<Pool<Sqlite>, sqlx::Error> {
+/// Migrations embedded from `migrations/`. Applied versions and their
+/// checksums are tracked in the `_sqlx_migrations` table, and each migration
+/// runs in its own transaction.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+#[derive(Debug, thiserror::Error)]
+pub enum InitError {
+    #[error("Failed to open database: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Failed to read migration state: {0}")]
+    State(#[from] MigrateError),
+    #[error("Failed to back up the database before migrating: {0}")]
+    Backup(#[source] sqlx::Error),
+    #[error("Database migration {migration} failed: {source}{}", restore_hint(.backup))]
+    Migration {
+        /// File name of the migration, e.g. `007_webhook_events`
+        migration: String,
+        source: MigrateError,
+        backup: Option<PathBuf>,
+    },
+}
+
+fn restore_hint(backup: &Option<PathBuf>) -> String {
+    match backup {
+        Some(path) => format!(" (the database before migrating was saved to {})", path.display()),
+        None => String::new(),
+    }
+}
+
+pub async fn init_db(db_path: &str) -> Result<Pool<Sqlite>, InitError> {
     // Create database directory if it doesn't exist
     let path = PathBuf::from(db_path);
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent)
-            .map_err(|e| sqlx::Error::Io(e))?;
+            .map_err(sqlx::Error::Io)?;
     }
 
     // Connect to database with create_if_missing option
@@ -16,17 +46,103 @@ pub async fn init_db(db_path: &str) -> Result<Pool<Sqlite>, sqlx::Error> {
     let pool = SqlitePool::connect(&db_url).await?;
 
     // Run migrations
-    run_migrations(&pool).await?;
+    run_migrations(&pool, &path).await?;
 
     Ok(pool)
 }
 
-async fn run_migrations(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
-    let migration_sql = include_str!("../../migrations/001_init.sql");
+async fn run_migrations(pool: &Pool<Sqlite>, db_path: &Path) -> Result<(), InitError> {
+    let pending = pending_migrations(pool).await?;
+    let Some(first) = pending.first() else {
+        return Ok(());
+    };
+
+    let backup = if has_existing_data(pool).await? {
+        Some(backup_database(pool, db_path).await?)
+    } else {
+        None
+    };
+
+    for migration in &pending {
+        tracing::info!("Applying database migration {}", migration_name(migration));
+    }
+
+    MIGRATOR.run(pool).await.map_err(|source| {
+        // Name the migration that failed; errors that aren't about one
+        // migration happened before or while applying the first pending one
+        let migration = match failed_version(&source) {
+            Some(version) => MIGRATOR
+                .iter()
+                .find(|m| m.version == version)
+                .map(migration_name)
+                .unwrap_or_else(|| format!("{:03}", version)),
+            None => migration_name(first),
+        };
+
+        InitError::Migration {
+            migration,
+            source,
+            backup,
+        }
+    })
+}
+
+/// Migrations not yet recorded as applied, in order
+async fn pending_migrations(pool: &Pool<Sqlite>) -> Result<Vec<&'static Migration>, InitError> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let applied = conn.list_applied_migrations().await?;
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|m| m.migration_type.is_up_migration())
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+        .collect())
+}
+
+/// Whether the database holds anything worth backing up (i.e. isn't brand new)
+async fn has_existing_data(pool: &Pool<Sqlite>) -> Result<bool, sqlx::Error> {
+    let (tables,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM sqlite_master
+         WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name != '_sqlx_migrations'"
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(tables > 0)
+}
+
+/// Copy the database next to itself, e.g. `line_admin.db.20260101-120000.bak`
+async fn backup_database(pool: &Pool<Sqlite>, db_path: &Path) -> Result<PathBuf, InitError> {
+    let mut backup = db_path.as_os_str().to_owned();
+    backup.push(format!(".{}.bak", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+    let backup = PathBuf::from(backup);
 
-    sqlx::query(migration_sql)
+    // VACUUM INTO writes a consistent copy, even with other connections open
+    sqlx::query("VACUUM INTO ?")
+        .bind(backup.to_string_lossy().into_owned())
         .execute(pool)
-        .await?;
+        .await
+        .map_err(InitError::Backup)?;
+
+    tracing::info!("Backed up database to {} before migrating", backup.display());
+    Ok(backup)
+}
+
+fn failed_version(error: &MigrateError) -> Option<i64> {
+    match error {
+        MigrateError::ExecuteMigration(_, version)
+        | MigrateError::VersionMissing(version)
+        | MigrateError::VersionMismatch(version)
+        | MigrateError::VersionNotPresent(version)
+        | MigrateError::VersionTooOld(version, _)
+        | MigrateError::VersionTooNew(version, _)
+        | MigrateError::Dirty(version) => Some(*version),
+        _ => None,
+    }
+}
 
-    Ok(())
+/// The migration's file name without extension, e.g. `007_webhook_events`
+fn migration_name(migration: &Migration) -> String {
+    format!("{:03}_{}", migration.version, migration.description.replace(' ', "_"))
 }
@@ -1,8 +1,9 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, SqlitePool};
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct User {
     pub id: i64,
     pub line_user_id: String,
@@ -11,19 +12,114 @@ pub struct User {
     pub status_message: Option<String>,
     pub created_at: String,
     pub updated_at: String,
+    pub profile_refreshed_at: Option<String>,
+    /// `following`, `unfollowed` (blocked the account) or `unknown` (no
+    /// follow seen, e.g. only in groups)
+    pub follow_status: String,
+    /// First follow
+    pub followed_at: Option<String>,
+    /// Most recent unfollow
+    pub unfollowed_at: Option<String>,
+    /// Most recent follow after an unfollow
+    pub refollowed_at: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
-pub struct Message {
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct FollowHistory {
     pub id: i64,
     pub line_user_id: String,
+    pub event_type: String,
+    pub is_unblocked: bool,
+    pub occurred_at: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct Message {
+    pub id: i64,
+    /// `None` for broadcasts and narrowcasts
+    pub line_user_id: Option<String>,
     pub message_type: String,
     pub message_text: Option<String>,
     pub message_data: Option<String>,
     pub timestamp: String,
+    pub line_message_id: Option<String>,
+    pub source_type: String,
+    pub source_id: Option<String>,
+    pub retracted_at: Option<String>,
+    /// `inbound` or `outbound`
+    pub direction: String,
+    /// Who sent an outbound message: `admin`, `bot`, `scheduler`, `rule` or `slack`
+    pub sender: Option<String>,
+    /// `push`, `reply`, `multicast`, `broadcast` or `narrowcast`
+    pub send_method: Option<String>,
+    pub line_request_id: Option<String>,
+    /// `sent` or `failed` for outbound messages
+    pub delivery_status: Option<String>,
+    pub error_message: Option<String>,
+}
+
+/// A user's 1:1 chat in the inbox, with their profile for display
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Conversation {
+    pub id: i64,
+    pub line_user_id: String,
+    /// `open`, `pending` (waiting on the user) or `resolved`
+    pub status: String,
+    pub assigned_to: Option<String>,
+    pub unread_count: i64,
+    pub last_inbound_at: Option<String>,
+    pub last_outbound_at: Option<String>,
+    pub last_read_at: Option<String>,
+    pub last_message_preview: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    /// Slack channel and thread the conversation is mirrored to
+    pub slack_channel: Option<String>,
+    pub slack_thread_ts: Option<String>,
+    pub display_name: Option<String>,
+    pub picture_url: Option<String>,
+    pub follow_status: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Event {
+    pub id: i64,
+    pub event_type: String,
+    pub webhook_event_id: Option<String>,
+    pub source_type: Option<String>,
+    pub line_user_id: Option<String>,
+    pub source_id: Option<String>,
+    pub payload: String,
+    pub timestamp: String,
+    pub is_redelivery: bool,
+    pub created_at: String,
+}
+
+/// A Slack Events API delivery the bridge has acted on
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SlackEventReceipt {
+    pub event_id: String,
+    pub received_at: String,
 }
 
+/// An inbound message queued for the Slack bridge
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SlackBridgePost {
+    pub id: i64,
+    pub message_id: i64,
+    pub line_user_id: String,
+    /// pending, sent or dead
+    pub status: String,
+    pub attempts: i64,
+    pub next_attempt_at: Option<String>,
+    /// Error of the last attempt
+    pub error_message: Option<String>,
+    pub created_at: String,
+    pub posted_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct ScheduledMessage {
     pub id: i64,
     pub line_user_id: Option<String>,
@@ -35,9 +131,32 @@ pub struct ScheduledMessage {
     pub error_message: Option<String>,
     pub created_at: String,
     pub updated_at: String,
+    pub next_run_at: Option<String>,
+    pub end_at: Option<String>,
+    pub max_occurrences: Option<i64>,
+    pub run_count: i64,
+    pub retry_key: Option<String>,
+    pub retry_started_at: Option<String>,
+    pub audience: Option<String>,
+    pub messages: Option<String>,
+    /// Stored template sent instead of `message_text`, rendered when due
+    pub template_id: Option<i64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct ScheduledMessageRun {
+    pub id: i64,
+    pub scheduled_message_id: i64,
+    pub status: String,
+    pub error_message: Option<String>,
+    pub run_at: String,
+    pub line_request_id: Option<String>,
+    /// Last seen phase of the run's narrowcast: `waiting`, `sending`,
+    /// `succeeded` or `failed`
+    pub narrowcast_phase: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct Setting {
     pub key: String,
     pub value: String,
@@ -46,7 +165,7 @@ pub struct Setting {
     pub updated_at: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct Calendar {
     pub id: i64,
     pub line_user_id: String,
@@ -56,26 +175,197 @@ pub struct Calendar {
     pub reminder_sent: bool,
     pub created_at: String,
     pub updated_at: String,
+    pub reminder_skipped_reason: Option<String>,
+    /// `X-Line-Retry-Key` reused by every attempt at the reminder
+    pub reminder_retry_key: Option<String>,
+    /// Last transient error while the reminder is being retried
+    pub reminder_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RichMenu {
+    pub id: i64,
+    pub name: String,
+    pub definition: String,
+    pub line_rich_menu_id: String,
+    pub image_path: Option<String>,
+    pub is_default: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RichMenuAlias {
+    pub alias_id: String,
+    pub rich_menu_id: i64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AutoReplyRule {
+    pub id: i64,
+    pub name: String,
+    pub match_type: String,
+    pub pattern: Option<String>,
+    pub case_sensitive: bool,
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    pub messages: String,
+    pub priority: i64,
+    pub enabled: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AutoReplyLog {
+    pub id: i64,
+    pub rule_id: i64,
+    pub line_user_id: String,
+    pub message_text: String,
+    pub status: String,
+    pub error_message: Option<String>,
+    pub matched_at: String,
+}
+
+/// A key for the REST admin API. The key itself is never stored.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    pub key_prefix: String,
+    /// Space-separated scopes, e.g. `users:read analytics:read`
+    pub scopes: String,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+/// Which process currently runs the scheduler
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SchedulerLease {
+    pub id: i64,
+    pub owner: String,
+    pub description: Option<String>,
+    pub acquired_at: String,
+    pub expires_at: String,
+}
+
+/// Where admin notifications are sent
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct NotificationChannel {
+    pub id: i64,
+    pub name: String,
+    /// slack, discord, teams, email, webhook, line_push or line_notify
+    pub kind: String,
+    /// JSON settings of the kind, encrypted at rest
+    pub config: String,
+    pub enabled: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Sends one event kind to a channel, with optional filters and batching
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct NotificationRule {
+    pub id: i64,
+    pub name: String,
+    pub event_kind: String,
+    pub channel_id: i64,
+    /// JSON array of keywords, any of which the event text must contain
+    pub keywords: Option<String>,
+    pub quiet_start: Option<String>,
+    pub quiet_end: Option<String>,
+    pub rate_limit: Option<i64>,
+    pub rate_window_minutes: Option<i64>,
+    pub digest_minutes: Option<i64>,
+    pub enabled: bool,
+    pub last_digest_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// An event matched by a notification rule
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct NotificationEvent {
+    pub id: i64,
+    pub rule_id: i64,
+    pub event_kind: String,
+    pub line_user_id: Option<String>,
+    pub title: String,
+    pub text: String,
+    /// sent, held or digested
+    pub status: String,
+    pub created_at: String,
+}
+
+/// Which master key the secret settings are encrypted with
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SecretsMasterKey {
+    pub id: i64,
+    pub key_id: String,
+    pub kdf_salt: Option<String>,
+    pub created_at: String,
+    pub rotated_at: Option<String>,
+}
+
+/// Message text with placeholders, rendered for each recipient at send time
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct MessageTemplate {
+    pub id: i64,
+    pub name: String,
+    pub body: String,
+    pub description: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A custom attribute of a user, e.g. a membership rank
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UserAttribute {
+    pub line_user_id: String,
+    pub name: String,
+    pub value: String,
+    pub updated_at: String,
 }
 
+/// A notification for one channel in the outbox, and its delivery state
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct NotificationLog {
     pub id: i64,
+    /// Kind of the channel, e.g. slack
     pub notification_type: String,
+    /// Name of the channel
     pub recipient: String,
     pub message: String,
+    /// pending, sent or dead
     pub status: String,
+    /// Error of the last attempt
     pub error_message: Option<String>,
-    pub sent_at: String,
+    pub created_at: String,
+    pub channel_id: Option<i64>,
+    pub event_kind: Option<String>,
+    pub title: Option<String>,
+    pub text: Option<String>,
+    pub attempts: i64,
+    pub next_attempt_at: Option<String>,
+    pub delivered_at: Option<String>,
+    /// The LINE user the notification is about
+    pub line_user_id: Option<String>,
+    /// `X-Line-Retry-Key` of every attempt, for channels that send through LINE
+    pub retry_key: Option<String>,
 }
 
 // Database operations for User
 impl User {
+    /// Record a user seen in a webhook event. New users are `unknown` until
+    /// they follow the account or chat with it 1:1.
     pub async fn create(pool: &SqlitePool, line_user_id: &str, display_name: Option<&str>) -> Result<i64, sqlx::Error> {
         let result = sqlx::query(
-            "INSERT INTO users (line_user_id, display_name) VALUES (?, ?)
+            "INSERT INTO users (line_user_id, display_name, follow_status, followed_at) VALUES (?, ?, 'unknown', NULL)
              ON CONFLICT(line_user_id) DO UPDATE SET
-             display_name = excluded.display_name,
+             display_name = COALESCE(excluded.display_name, users.display_name),
              updated_at = CURRENT_TIMESTAMP"
         )
         .bind(line_user_id)
@@ -95,137 +385,269 @@ impl User {
         .await
     }
 
-    pub async fn list_all(pool: &SqlitePool) -> Result<Vec<User>, sqlx::Error> {
+    /// Make sure a row exists for a user, without touching an existing one
+    pub async fn ensure_exists(pool: &SqlitePool, line_user_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT OR IGNORE INTO users (line_user_id) VALUES (?)")
+            .bind(line_user_id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Users who haven't unfollowed the account, plus those who did if asked for
+    pub async fn list_all(pool: &SqlitePool, include_unfollowed: bool) -> Result<Vec<User>, sqlx::Error> {
         sqlx::query_as::<_, User>(
-            "SELECT * FROM users ORDER BY created_at DESC"
+            "SELECT * FROM users WHERE follow_status != 'unfollowed' OR ? ORDER BY created_at DESC"
         )
+        .bind(include_unfollowed)
         .fetch_all(pool)
         .await
     }
-}
 
-// Database operations for Message
-impl Message {
-    pub async fn create(
+    /// A page of users, newest first, optionally with one follow status and
+    /// matching `search` in the display name or user ID
+    pub async fn search(
         pool: &SqlitePool,
-        line_user_id: &str,
-        message_type: &str,
-        message_text: Option<&str>,
-        message_data: Option<&str>,
-    ) -> Result<i64, sqlx::Error> {
-        let result = sqlx::query(
-            "INSERT INTO messages (line_user_id, message_type, message_text, message_data)
-             VALUES (?, ?, ?, ?)"
+        follow_status: Option<&str>,
+        search: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<User>, sqlx::Error> {
+        sqlx::query_as::<_, User>(
+            "SELECT * FROM users
+             WHERE (? IS NULL OR follow_status = ?)
+               AND (? IS NULL OR display_name LIKE '%' || ? || '%' OR line_user_id = ?)
+             ORDER BY created_at DESC, id DESC
+             LIMIT ? OFFSET ?"
         )
-        .bind(line_user_id)
-        .bind(message_type)
-        .bind(message_text)
-        .bind(message_data)
-        .execute(pool)
-        .await?;
+        .bind(follow_status)
+        .bind(follow_status)
+        .bind(search)
+        .bind(search)
+        .bind(search)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await
+    }
 
-        Ok(result.last_insert_rowid())
+    /// Whether the user follows the account. Users we have never seen are
+    /// assumed to, since we can't know otherwise; users only seen in groups
+    /// or through postbacks (`unknown`) are not.
+    pub async fn is_following(pool: &SqlitePool, line_user_id: &str) -> Result<bool, sqlx::Error> {
+        let status: Option<(String,)> = sqlx::query_as("SELECT follow_status FROM users WHERE line_user_id = ?")
+            .bind(line_user_id)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(status.is_none_or(|(status,)| status == "following"))
     }
 
-    pub async fn list_by_user(pool: &SqlitePool, line_user_id: &str, limit: i32) -> Result<Vec<Message>, sqlx::Error> {
-        sqlx::query_as::<_, Message>(
-            "SELECT * FROM messages WHERE line_user_id = ? ORDER BY timestamp DESC LIMIT ?"
+    /// The given user IDs minus those known not to follow, in the same order
+    pub async fn filter_following(pool: &SqlitePool, line_user_ids: &[String]) -> Result<Vec<String>, sqlx::Error> {
+        let not_following: Vec<(String,)> = sqlx::query_as(
+            "SELECT line_user_id FROM users
+             WHERE follow_status != 'following'
+             AND line_user_id IN (SELECT value FROM json_each(?))"
         )
-        .bind(line_user_id)
-        .bind(limit)
+        .bind(serde_json::to_string(line_user_ids).unwrap_or_default())
         .fetch_all(pool)
-        .await
+        .await?;
+
+        let not_following: std::collections::HashSet<String> = not_following.into_iter().map(|(id,)| id).collect();
+        Ok(line_user_ids.iter().filter(|id| !not_following.contains(*id)).cloned().collect())
     }
 
-    pub async fn list_all(pool: &SqlitePool, limit: i32) -> Result<Vec<Message>, sqlx::Error> {
-        sqlx::query_as::<_, Message>(
-            "SELECT * FROM messages ORDER BY timestamp DESC LIMIT ?"
+    /// A 1:1 message shows the user follows the account, even without a
+    /// follow event (e.g. they followed before the webhook was set up)
+    pub async fn record_chat(pool: &SqlitePool, line_user_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE users SET follow_status = 'following', updated_at = CURRENT_TIMESTAMP
+             WHERE line_user_id = ? AND follow_status = 'unknown'"
         )
-        .bind(limit)
-        .fetch_all(pool)
-        .await
+        .bind(line_user_id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
     }
-}
 
-// Database operations for ScheduledMessage
-impl ScheduledMessage {
-    pub async fn create(
+    /// Record a follow event. A follow by someone who had unfollowed, or that
+    /// LINE flags as an unblock, counts as a re-follow.
+    pub async fn record_follow(
         pool: &SqlitePool,
-        line_user_id: Option<&str>,
-        message_text: &str,
-        schedule_time: &str,
-        cron_expression: Option<&str>,
-    ) -> Result<i64, sqlx::Error> {
-        let result = sqlx::query(
-            "INSERT INTO scheduled_messages (line_user_id, message_text, schedule_time, cron_expression)
-             VALUES (?, ?, ?, ?)"
+        line_user_id: &str,
+        is_unblocked: bool,
+        occurred_at: &str,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query(
+            "INSERT INTO users (line_user_id, follow_status, followed_at) VALUES (?, 'following', ?)
+             ON CONFLICT(line_user_id) DO UPDATE SET
+             refollowed_at = CASE
+                 WHEN users.follow_status = 'unfollowed' OR ? THEN excluded.followed_at
+                 ELSE users.refollowed_at
+             END,
+             followed_at = COALESCE(users.followed_at, excluded.followed_at),
+             follow_status = 'following',
+             updated_at = CURRENT_TIMESTAMP"
         )
         .bind(line_user_id)
-        .bind(message_text)
-        .bind(schedule_time)
-        .bind(cron_expression)
-        .execute(pool)
+        .bind(occurred_at)
+        .bind(is_unblocked)
+        .execute(&mut *tx)
         .await?;
 
-        Ok(result.last_insert_rowid())
+        FollowHistory::create(&mut tx, line_user_id, "follow", is_unblocked, occurred_at).await?;
+
+        tx.commit().await
     }
 
-    pub async fn list_pending(pool: &SqlitePool) -> Result<Vec<ScheduledMessage>, sqlx::Error> {
-        sqlx::query_as::<_, ScheduledMessage>(
-            "SELECT * FROM scheduled_messages WHERE status = 'pending' ORDER BY schedule_time ASC"
+    /// Record an unfollow (the user blocked the account)
+    pub async fn record_unfollow(pool: &SqlitePool, line_user_id: &str, occurred_at: &str) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query(
+            "INSERT INTO users (line_user_id, follow_status, unfollowed_at) VALUES (?, 'unfollowed', ?)
+             ON CONFLICT(line_user_id) DO UPDATE SET
+             follow_status = 'unfollowed',
+             unfollowed_at = excluded.unfollowed_at,
+             updated_at = CURRENT_TIMESTAMP"
         )
-        .fetch_all(pool)
-        .await
+        .bind(line_user_id)
+        .bind(occurred_at)
+        .execute(&mut *tx)
+        .await?;
+
+        FollowHistory::create(&mut tx, line_user_id, "unfollow", false, occurred_at).await?;
+
+        tx.commit().await
     }
 
-    pub async fn update_status(
+    /// Delete a user with everything recorded about them: messages,
+    /// conversation, follow history, reminders and messages scheduled for them
+    pub async fn delete(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        let Some((line_user_id,)): Option<(String,)> = sqlx::query_as("SELECT line_user_id FROM users WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+        else {
+            return Ok(());
+        };
+
+        sqlx::query(
+            "DELETE FROM scheduled_message_runs
+             WHERE scheduled_message_id IN (SELECT id FROM scheduled_messages WHERE line_user_id = ?)"
+        )
+        .bind(&line_user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        // Attributes go with the user (ON DELETE CASCADE), Slack bridge posts with the messages
+        for table in ["scheduled_messages", "calendars", "messages", "conversations", "follow_history"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE line_user_id = ?", table))
+                .bind(&line_user_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        sqlx::query("DELETE FROM users WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await
+    }
+
+    /// Store a profile fetched from LINE, creating the user if needed
+    pub async fn update_profile(
         pool: &SqlitePool,
-        id: i64,
-        status: &str,
-        error_message: Option<&str>,
+        line_user_id: &str,
+        display_name: &str,
+        picture_url: Option<&str>,
+        status_message: Option<&str>,
     ) -> Result<(), sqlx::Error> {
         sqlx::query(
-            "UPDATE scheduled_messages SET status = ?, error_message = ?,
-             sent_at = CASE WHEN ? = 'sent' THEN CURRENT_TIMESTAMP ELSE sent_at END,
-             updated_at = CURRENT_TIMESTAMP WHERE id = ?"
+            "INSERT INTO users (line_user_id, display_name, picture_url, status_message, profile_refreshed_at)
+             VALUES (?, ?, ?, ?, CURRENT_TIMESTAMP)
+             ON CONFLICT(line_user_id) DO UPDATE SET
+             display_name = excluded.display_name,
+             picture_url = excluded.picture_url,
+             status_message = excluded.status_message,
+             profile_refreshed_at = CURRENT_TIMESTAMP,
+             updated_at = CURRENT_TIMESTAMP"
         )
-        .bind(status)
-        .bind(error_message)
-        .bind(status)
-        .bind(id)
+        .bind(line_user_id)
+        .bind(display_name)
+        .bind(picture_url)
+        .bind(status_message)
         .execute(pool)
         .await?;
 
         Ok(())
     }
-}
 
-// Database operations for Calendar
-impl Calendar {
-    pub async fn create(
+    /// Record a refresh attempt that didn't return a profile, so the user
+    /// goes to the back of the refresh queue
+    pub async fn touch_profile_refreshed(pool: &SqlitePool, line_user_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE users SET profile_refreshed_at = CURRENT_TIMESTAMP WHERE line_user_id = ?")
+            .bind(line_user_id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Users whose profile was never fetched or is older than `max_age_hours`,
+    /// oldest first
+    pub async fn list_stale_profiles(
         pool: &SqlitePool,
+        max_age_hours: i64,
+        limit: i64,
+    ) -> Result<Vec<User>, sqlx::Error> {
+        sqlx::query_as::<_, User>(
+            "SELECT * FROM users
+             WHERE profile_refreshed_at IS NULL
+                OR profile_refreshed_at < datetime('now', '-' || ? || ' hours')
+             ORDER BY profile_refreshed_at IS NOT NULL, profile_refreshed_at ASC
+             LIMIT ?"
+        )
+        .bind(max_age_hours)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+}
+
+// Database operations for FollowHistory
+impl FollowHistory {
+    async fn create(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
         line_user_id: &str,
-        event_title: &str,
-        event_description: Option<&str>,
-        event_time: &str,
+        event_type: &str,
+        is_unblocked: bool,
+        occurred_at: &str,
     ) -> Result<i64, sqlx::Error> {
         let result = sqlx::query(
-            "INSERT INTO calendars (line_user_id, event_title, event_description, event_time)
-             VALUES (?, ?, ?, ?)"
+            "INSERT INTO follow_history (line_user_id, event_type, is_unblocked, occurred_at) VALUES (?, ?, ?, ?)"
         )
         .bind(line_user_id)
-        .bind(event_title)
-        .bind(event_description)
-        .bind(event_time)
-        .execute(pool)
+        .bind(event_type)
+        .bind(is_unblocked)
+        .bind(occurred_at)
+        .execute(&mut **tx)
         .await?;
 
         Ok(result.last_insert_rowid())
     }
 
-    pub async fn list_by_user(pool: &SqlitePool, line_user_id: &str) -> Result<Vec<Calendar>, sqlx::Error> {
-        sqlx::query_as::<_, Calendar>(
-            "SELECT * FROM calendars WHERE line_user_id = ? ORDER BY event_time ASC"
+    pub async fn list_by_user(pool: &SqlitePool, line_user_id: &str) -> Result<Vec<FollowHistory>, sqlx::Error> {
+        sqlx::query_as::<_, FollowHistory>(
+            "SELECT * FROM follow_history WHERE line_user_id = ? ORDER BY occurred_at ASC, id ASC"
         )
         .bind(line_user_id)
         .fetch_all(pool)
@@ -233,38 +655,2005 @@ impl Calendar {
     }
 }
 
-// Database operations for Setting
+// Database operations for Message
+impl Message {
+    /// Store an inbound message. A message with the same `line_message_id`
+    /// is only stored once; storing it again returns the stored row's ID.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create(
+        pool: &SqlitePool,
+        line_user_id: &str,
+        message_type: &str,
+        message_text: Option<&str>,
+        message_data: Option<&str>,
+        line_message_id: Option<&str>,
+        source_type: &str,
+        source_id: Option<&str>,
+    ) -> Result<i64, sqlx::Error> {
+        let (id,): (i64,) = sqlx::query_as(
+            "INSERT INTO messages (line_user_id, message_type, message_text, message_data, line_message_id, source_type, source_id)
+             VALUES (?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(line_message_id) DO UPDATE SET line_message_id = excluded.line_message_id
+             RETURNING id"
+        )
+        .bind(line_user_id)
+        .bind(message_type)
+        .bind(message_text)
+        .bind(message_data)
+        .bind(line_message_id)
+        .bind(source_type)
+        .bind(source_id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Record a message we sent (or tried to send). `message_data` holds the
+    /// message objects as JSON.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create_outbound(
+        pool: &SqlitePool,
+        line_user_id: Option<&str>,
+        message_type: &str,
+        message_text: &str,
+        message_data: &str,
+        sender: &str,
+        send_method: &str,
+        line_request_id: Option<&str>,
+        delivery_status: &str,
+        error_message: Option<&str>,
+        retry_key: Option<&str>,
+    ) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO messages (
+                line_user_id, message_type, message_text, message_data, direction,
+                sender, send_method, line_request_id, delivery_status, error_message, retry_key
+             )
+             VALUES (?, ?, ?, ?, 'outbound', ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(line_user_id)
+        .bind(message_type)
+        .bind(message_text)
+        .bind(message_data)
+        .bind(sender)
+        .bind(send_method)
+        .bind(line_request_id)
+        .bind(delivery_status)
+        .bind(error_message)
+        .bind(retry_key)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// The outbound message an earlier attempt with the same retry key stored
+    /// for `line_user_id` (`None` for broadcasts and narrowcasts)
+    pub async fn find_outbound_by_retry_key(
+        pool: &SqlitePool,
+        retry_key: &str,
+        line_user_id: Option<&str>,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT id FROM messages
+             WHERE direction = 'outbound' AND retry_key = ? AND line_user_id IS ?
+             ORDER BY id DESC LIMIT 1"
+        )
+        .bind(retry_key)
+        .bind(line_user_id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Record the outcome of another attempt at an outbound message
+    pub async fn update_outbound(
+        pool: &SqlitePool,
+        id: i64,
+        message_text: &str,
+        message_data: &str,
+        line_request_id: Option<&str>,
+        delivery_status: &str,
+        error_message: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE messages
+             SET message_text = ?, message_data = ?, line_request_id = ?, delivery_status = ?, error_message = ?,
+                 timestamp = CURRENT_TIMESTAMP
+             WHERE id = ?"
+        )
+        .bind(message_text)
+        .bind(message_data)
+        .bind(line_request_id)
+        .bind(delivery_status)
+        .bind(error_message)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_id(pool: &SqlitePool, id: i64) -> Result<Option<Message>, sqlx::Error> {
+        sqlx::query_as::<_, Message>(
+            "SELECT * FROM messages WHERE id = ?"
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Messages to or from a user stored after `after_id`, oldest first
+    pub async fn list_user_messages_after(pool: &SqlitePool, after_id: i64, limit: i32) -> Result<Vec<Message>, sqlx::Error> {
+        sqlx::query_as::<_, Message>(
+            "SELECT * FROM messages WHERE id > ? AND line_user_id IS NOT NULL AND source_type = 'user'
+             ORDER BY id LIMIT ?"
+        )
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn last_id(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM messages")
+            .fetch_one(pool)
+            .await
+    }
+
+    pub async fn update_data(pool: &SqlitePool, id: i64, message_data: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE messages SET message_data = ? WHERE id = ?")
+            .bind(message_data)
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Mark a message as unsent by the user. Returns how many rows matched
+    /// (0 if the message was never stored).
+    pub async fn mark_retracted(pool: &SqlitePool, line_message_id: &str) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE messages SET retracted_at = CURRENT_TIMESTAMP
+             WHERE line_message_id = ? AND retracted_at IS NULL"
+        )
+        .bind(line_message_id)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// The conversation with a user, newest first: what they sent, what we sent
+    /// them, and broadcasts sent while they followed the account
+    pub async fn list_by_user(pool: &SqlitePool, line_user_id: &str, limit: i32) -> Result<Vec<Message>, sqlx::Error> {
+        sqlx::query_as::<_, Message>(
+            "SELECT * FROM messages
+             WHERE line_user_id = ?
+                OR (line_user_id IS NULL AND send_method = 'broadcast' AND delivery_status = 'sent'
+                    AND timestamp >= (SELECT COALESCE(followed_at, created_at) FROM users WHERE line_user_id = ?))
+             ORDER BY timestamp DESC, id DESC LIMIT ?"
+        )
+        .bind(line_user_id)
+        .bind(line_user_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn list_all(pool: &SqlitePool, limit: i32) -> Result<Vec<Message>, sqlx::Error> {
+        sqlx::query_as::<_, Message>(
+            "SELECT * FROM messages ORDER BY timestamp DESC, id DESC LIMIT ?"
+        )
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// A page of messages, newest first. Each filter applies when given;
+    /// `since` and `until` are timestamps SQLite understands, e.g. RFC 3339.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn search(
+        pool: &SqlitePool,
+        line_user_id: Option<&str>,
+        direction: Option<&str>,
+        message_type: Option<&str>,
+        since: Option<&str>,
+        until: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Message>, sqlx::Error> {
+        sqlx::query_as::<_, Message>(
+            "SELECT * FROM messages
+             WHERE (? IS NULL OR line_user_id = ?)
+               AND (? IS NULL OR direction = ?)
+               AND (? IS NULL OR message_type = ?)
+               AND (? IS NULL OR timestamp >= datetime(?))
+               AND (? IS NULL OR timestamp < datetime(?))
+             ORDER BY timestamp DESC, id DESC
+             LIMIT ? OFFSET ?"
+        )
+        .bind(line_user_id)
+        .bind(line_user_id)
+        .bind(direction)
+        .bind(direction)
+        .bind(message_type)
+        .bind(message_type)
+        .bind(since)
+        .bind(since)
+        .bind(until)
+        .bind(until)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await
+    }
+}
+
+// Database operations for Conversation
+impl Conversation {
+    const SELECT: &'static str =
+        "SELECT c.*, u.display_name, u.picture_url, u.follow_status
+         FROM conversations c LEFT JOIN users u ON u.line_user_id = c.line_user_id";
+
+    pub async fn find_by_user(pool: &SqlitePool, line_user_id: &str) -> Result<Option<Conversation>, sqlx::Error> {
+        sqlx::query_as::<_, Conversation>(&format!("{} WHERE c.line_user_id = ?", Self::SELECT))
+            .bind(line_user_id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// The conversation mirrored to a Slack thread
+    pub async fn find_by_slack_thread(
+        pool: &SqlitePool,
+        slack_channel: &str,
+        slack_thread_ts: &str,
+    ) -> Result<Option<Conversation>, sqlx::Error> {
+        sqlx::query_as::<_, Conversation>(&format!(
+            "{} WHERE c.slack_channel = ? AND c.slack_thread_ts = ?",
+            Self::SELECT
+        ))
+        .bind(slack_channel)
+        .bind(slack_thread_ts)
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn set_slack_thread(
+        pool: &SqlitePool,
+        line_user_id: &str,
+        slack_channel: &str,
+        slack_thread_ts: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE conversations SET slack_channel = ?, slack_thread_ts = ?, updated_at = CURRENT_TIMESTAMP
+             WHERE line_user_id = ?"
+        )
+        .bind(slack_channel)
+        .bind(slack_thread_ts)
+        .bind(line_user_id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Conversations changed at or after `since` (a `CURRENT_TIMESTAMP`)
+    pub async fn list_updated_since(pool: &SqlitePool, since: &str) -> Result<Vec<Conversation>, sqlx::Error> {
+        sqlx::query_as::<_, Conversation>(&format!("{} WHERE c.updated_at >= ? ORDER BY c.updated_at", Self::SELECT))
+            .bind(since)
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Conversations with the most recent activity first. `status` and
+    /// `assigned_to` filter when given; `unread_only` keeps only unread ones.
+    pub async fn list(
+        pool: &SqlitePool,
+        status: Option<&str>,
+        assigned_to: Option<&str>,
+        unread_only: bool,
+        limit: i32,
+    ) -> Result<Vec<Conversation>, sqlx::Error> {
+        sqlx::query_as::<_, Conversation>(&format!(
+            "{} WHERE (? IS NULL OR c.status = ?)
+               AND (? IS NULL OR c.assigned_to = ?)
+               AND (? = 0 OR c.unread_count > 0)
+             ORDER BY MAX(COALESCE(c.last_inbound_at, ''), COALESCE(c.last_outbound_at, '')) DESC, c.id DESC
+             LIMIT ?",
+            Self::SELECT
+        ))
+        .bind(status)
+        .bind(status)
+        .bind(assigned_to)
+        .bind(assigned_to)
+        .bind(unread_only)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// A message arrived from the user: count it as unread and reopen the
+    /// conversation
+    pub async fn record_inbound(pool: &SqlitePool, line_user_id: &str, preview: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO conversations (line_user_id, unread_count, last_inbound_at, last_message_preview)
+             VALUES (?, 1, CURRENT_TIMESTAMP, ?)
+             ON CONFLICT(line_user_id) DO UPDATE SET
+                status = 'open',
+                unread_count = conversations.unread_count + 1,
+                last_inbound_at = CURRENT_TIMESTAMP,
+                last_message_preview = excluded.last_message_preview,
+                updated_at = CURRENT_TIMESTAMP"
+        )
+        .bind(line_user_id)
+        .bind(preview)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// A message was sent to the user. Only admin replies start a
+    /// conversation or move it to `pending`; automatic sends just update the
+    /// times of conversations that already exist. Returns whether a
+    /// conversation was touched.
+    pub async fn record_outbound(
+        pool: &SqlitePool,
+        line_user_id: &str,
+        preview: &str,
+        from_admin: bool,
+    ) -> Result<bool, sqlx::Error> {
+        let result = if from_admin {
+            sqlx::query(
+                "INSERT INTO conversations (line_user_id, status, last_outbound_at, last_message_preview)
+                 VALUES (?, 'pending', CURRENT_TIMESTAMP, ?)
+                 ON CONFLICT(line_user_id) DO UPDATE SET
+                    status = CASE WHEN conversations.status = 'open' THEN 'pending' ELSE conversations.status END,
+                    last_outbound_at = CURRENT_TIMESTAMP,
+                    last_message_preview = excluded.last_message_preview,
+                    updated_at = CURRENT_TIMESTAMP"
+            )
+            .bind(line_user_id)
+            .bind(preview)
+            .execute(pool)
+            .await?
+        } else {
+            sqlx::query(
+                "UPDATE conversations SET
+                    last_outbound_at = CURRENT_TIMESTAMP,
+                    last_message_preview = ?,
+                    updated_at = CURRENT_TIMESTAMP
+                 WHERE line_user_id = ?"
+            )
+            .bind(preview)
+            .bind(line_user_id)
+            .execute(pool)
+            .await?
+        };
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn mark_read(pool: &SqlitePool, line_user_id: &str) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE conversations SET unread_count = 0, last_read_at = CURRENT_TIMESTAMP, updated_at = CURRENT_TIMESTAMP
+             WHERE line_user_id = ?"
+        )
+        .bind(line_user_id)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn set_status(pool: &SqlitePool, line_user_id: &str, status: &str) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE conversations SET status = ?, updated_at = CURRENT_TIMESTAMP WHERE line_user_id = ?"
+        )
+        .bind(status)
+        .bind(line_user_id)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Assign the conversation to an admin, or unassign it with `None`
+    pub async fn assign(pool: &SqlitePool, line_user_id: &str, assigned_to: Option<&str>) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE conversations SET assigned_to = ?, updated_at = CURRENT_TIMESTAMP WHERE line_user_id = ?"
+        )
+        .bind(assigned_to)
+        .bind(line_user_id)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+// Database operations for Event
+impl Event {
+    /// Store a webhook event. Returns `None` if an event with the same
+    /// `webhook_event_id` was already stored (a redelivery).
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create(
+        pool: &SqlitePool,
+        event_type: &str,
+        webhook_event_id: Option<&str>,
+        source_type: Option<&str>,
+        line_user_id: Option<&str>,
+        source_id: Option<&str>,
+        payload: &str,
+        timestamp: &str,
+        is_redelivery: bool,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO events
+             (event_type, webhook_event_id, source_type, line_user_id, source_id, payload, timestamp, is_redelivery)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(event_type)
+        .bind(webhook_event_id)
+        .bind(source_type)
+        .bind(line_user_id)
+        .bind(source_id)
+        .bind(payload)
+        .bind(timestamp)
+        .bind(is_redelivery)
+        .execute(pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+        Ok(Some(result.last_insert_rowid()))
+    }
+
+    /// Remove an event that couldn't be handled, so a redelivery of it isn't skipped
+    pub async fn delete(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM events WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn list(pool: &SqlitePool, event_type: Option<&str>, limit: i32) -> Result<Vec<Event>, sqlx::Error> {
+        sqlx::query_as::<_, Event>(
+            "SELECT * FROM events WHERE (?1 IS NULL OR event_type = ?1)
+             ORDER BY timestamp DESC, id DESC LIMIT ?2"
+        )
+        .bind(event_type)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+}
+
+// Database operations for SlackEventReceipt
+impl SlackEventReceipt {
+    /// Record a delivery. Returns `false` if it was already recorded (a retry).
+    pub async fn record(pool: &SqlitePool, event_id: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("INSERT OR IGNORE INTO slack_event_receipts (event_id) VALUES (?)")
+            .bind(event_id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Forget a delivery that couldn't be handled, so Slack's retry of it is handled
+    pub async fn forget(pool: &SqlitePool, event_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM slack_event_receipts WHERE event_id = ?")
+            .bind(event_id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+// Database operations for SlackBridgePost
+impl SlackBridgePost {
+    /// Queue a message, due now. Messages already queued are left as they are.
+    pub async fn enqueue(pool: &SqlitePool, message_id: i64, line_user_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT OR IGNORE INTO slack_bridge_posts (message_id, line_user_id, next_attempt_at)
+             VALUES (?, ?, datetime('now'))"
+        )
+        .bind(message_id)
+        .bind(line_user_id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Pending posts whose next attempt is due, oldest message first. A post waits
+    /// while an earlier one of the same user is pending, so a conversation's
+    /// messages reach the thread in order.
+    pub async fn list_due(pool: &SqlitePool, limit: i64) -> Result<Vec<SlackBridgePost>, sqlx::Error> {
+        sqlx::query_as::<_, SlackBridgePost>(
+            "SELECT * FROM slack_bridge_posts p
+             WHERE status = 'pending' AND next_attempt_at <= datetime('now')
+               AND NOT EXISTS (
+                   SELECT 1 FROM slack_bridge_posts e
+                   WHERE e.line_user_id = p.line_user_id AND e.status = 'pending' AND e.message_id < p.message_id
+               )
+             ORDER BY message_id ASC
+             LIMIT ?"
+        )
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Take a due post for an attempt by pushing its next attempt `seconds`
+    /// out. False when someone else took it first.
+    pub async fn claim(pool: &SqlitePool, id: i64, seconds: i64) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE slack_bridge_posts SET next_attempt_at = datetime('now', '+' || ? || ' seconds')
+             WHERE id = ? AND status = 'pending' AND next_attempt_at <= datetime('now')"
+        )
+        .bind(seconds)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn mark_sent(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE slack_bridge_posts
+             SET status = 'sent', attempts = attempts + 1, error_message = NULL, next_attempt_at = NULL,
+                 posted_at = CURRENT_TIMESTAMP
+             WHERE id = ?"
+        )
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record a failed attempt and retry in `delay_seconds`
+    pub async fn mark_retry(pool: &SqlitePool, id: i64, error: &str, delay_seconds: i64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE slack_bridge_posts
+             SET attempts = attempts + 1, error_message = ?,
+                 next_attempt_at = datetime('now', '+' || ? || ' seconds')
+             WHERE id = ?"
+        )
+        .bind(error)
+        .bind(delay_seconds)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record a failed attempt and give up
+    pub async fn mark_dead(pool: &SqlitePool, id: i64, error: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE slack_bridge_posts
+             SET status = 'dead', attempts = attempts + 1, error_message = ?, next_attempt_at = NULL
+             WHERE id = ?"
+        )
+        .bind(error)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// Database operations for ScheduledMessage
+impl ScheduledMessage {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create(
+        pool: &SqlitePool,
+        line_user_id: Option<&str>,
+        message_text: &str,
+        messages: Option<&str>,
+        schedule_time: &str,
+        cron_expression: Option<&str>,
+        next_run_at: Option<&str>,
+        end_at: Option<&str>,
+        max_occurrences: Option<i64>,
+        audience: Option<&str>,
+        template_id: Option<i64>,
+    ) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO scheduled_messages
+             (line_user_id, message_text, messages, schedule_time, cron_expression, next_run_at, end_at, max_occurrences, audience, template_id)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(line_user_id)
+        .bind(message_text)
+        .bind(messages)
+        .bind(schedule_time)
+        .bind(cron_expression)
+        .bind(next_run_at)
+        .bind(end_at)
+        .bind(max_occurrences)
+        .bind(audience)
+        .bind(template_id)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    pub async fn list_pending(pool: &SqlitePool) -> Result<Vec<ScheduledMessage>, sqlx::Error> {
+        sqlx::query_as::<_, ScheduledMessage>(
+            "SELECT * FROM scheduled_messages WHERE status = 'pending'
+             ORDER BY COALESCE(next_run_at, schedule_time) ASC"
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// IDs of the pending broadcasts and narrowcasts sending a template
+    pub async fn list_pending_to_everyone_by_template(pool: &SqlitePool, template_id: i64) -> Result<Vec<i64>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT id FROM scheduled_messages
+             WHERE status = 'pending' AND template_id = ? AND line_user_id IS NULL
+               AND (audience IS NULL OR json_extract(audience, '$.type') != 'multicast')
+             ORDER BY id"
+        )
+        .bind(template_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// A page of scheduled messages in any status (or just `status`), most
+    /// recently created first
+    pub async fn list(
+        pool: &SqlitePool,
+        status: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ScheduledMessage>, sqlx::Error> {
+        sqlx::query_as::<_, ScheduledMessage>(
+            "SELECT * FROM scheduled_messages
+             WHERE (? IS NULL OR status = ?)
+             ORDER BY created_at DESC, id DESC
+             LIMIT ? OFFSET ?"
+        )
+        .bind(status)
+        .bind(status)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn find_by_id(pool: &SqlitePool, id: i64) -> Result<Option<ScheduledMessage>, sqlx::Error> {
+        sqlx::query_as::<_, ScheduledMessage>("SELECT * FROM scheduled_messages WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Time the message is next due: `next_run_at` for recurring messages, else `schedule_time`
+    pub fn due_time(&self) -> &str {
+        self.next_run_at.as_deref().unwrap_or(&self.schedule_time)
+    }
+
+    pub async fn update_status(
+        pool: &SqlitePool,
+        id: i64,
+        status: &str,
+        error_message: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE scheduled_messages SET status = ?, error_message = ?,
+             retry_key = NULL, retry_started_at = NULL,
+             sent_at = CASE WHEN ? = 'sent' THEN CURRENT_TIMESTAMP ELSE sent_at END,
+             updated_at = CURRENT_TIMESTAMP WHERE id = ?"
+        )
+        .bind(status)
+        .bind(error_message)
+        .bind(status)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record a run of a recurring message and move it to its next fire time.
+    /// Passing `None` for `next_run_at` ends the series and marks it `completed`.
+    pub async fn advance(
+        pool: &SqlitePool,
+        id: i64,
+        next_run_at: Option<&str>,
+        error_message: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE scheduled_messages SET
+             run_count = run_count + 1,
+             next_run_at = ?,
+             status = CASE WHEN ? IS NULL THEN 'completed' ELSE 'pending' END,
+             error_message = ?,
+             retry_key = NULL, retry_started_at = NULL,
+             sent_at = CASE WHEN ? IS NULL THEN CURRENT_TIMESTAMP ELSE sent_at END,
+             updated_at = CURRENT_TIMESTAMP WHERE id = ?"
+        )
+        .bind(next_run_at)
+        .bind(next_run_at)
+        .bind(error_message)
+        .bind(error_message)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remember the retry key for the delivery in progress
+    pub async fn set_retry_key(
+        pool: &SqlitePool,
+        id: i64,
+        retry_key: &str,
+        retry_started_at: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE scheduled_messages SET retry_key = ?, retry_started_at = ?,
+             updated_at = CURRENT_TIMESTAMP WHERE id = ?"
+        )
+        .bind(retry_key)
+        .bind(retry_started_at)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Keep the message pending after a transient failure so the next tick retries it
+    pub async fn mark_retrying(pool: &SqlitePool, id: i64, error_message: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE scheduled_messages SET error_message = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?"
+        )
+        .bind(error_message)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// Database operations for ScheduledMessageRun
+impl ScheduledMessageRun {
+    pub async fn create(
+        pool: &SqlitePool,
+        scheduled_message_id: i64,
+        status: &str,
+        error_message: Option<&str>,
+        line_request_id: Option<&str>,
+    ) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO scheduled_message_runs (scheduled_message_id, status, error_message, line_request_id)
+             VALUES (?, ?, ?, ?)"
+        )
+        .bind(scheduled_message_id)
+        .bind(status)
+        .bind(error_message)
+        .bind(line_request_id)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Record the narrowcast's phase, and its error if it failed
+    pub async fn set_narrowcast_phase(
+        pool: &SqlitePool,
+        id: i64,
+        phase: &str,
+        error_message: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE scheduled_message_runs
+             SET narrowcast_phase = ?,
+                 status = CASE WHEN ? = 'failed' THEN 'failed' ELSE status END,
+                 error_message = COALESCE(?, error_message)
+             WHERE id = ?"
+        )
+        .bind(phase)
+        .bind(phase)
+        .bind(error_message)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Narrowcasts started in the last day whose outcome isn't known yet
+    pub async fn list_unfinished_narrowcasts(pool: &SqlitePool) -> Result<Vec<ScheduledMessageRun>, sqlx::Error> {
+        sqlx::query_as::<_, ScheduledMessageRun>(
+            "SELECT * FROM scheduled_message_runs
+             WHERE line_request_id IS NOT NULL AND status = 'sent'
+               AND (narrowcast_phase IS NULL OR narrowcast_phase IN ('waiting', 'sending'))
+               AND run_at >= datetime('now', '-1 day')"
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn list_by_message(pool: &SqlitePool, scheduled_message_id: i64) -> Result<Vec<ScheduledMessageRun>, sqlx::Error> {
+        sqlx::query_as::<_, ScheduledMessageRun>(
+            "SELECT * FROM scheduled_message_runs WHERE scheduled_message_id = ? ORDER BY run_at DESC"
+        )
+        .bind(scheduled_message_id)
+        .fetch_all(pool)
+        .await
+    }
+}
+
+// Database operations for Calendar
+impl Calendar {
+    pub async fn create(
+        pool: &SqlitePool,
+        line_user_id: &str,
+        event_title: &str,
+        event_description: Option<&str>,
+        event_time: &str,
+    ) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO calendars (line_user_id, event_title, event_description, event_time)
+             VALUES (?, ?, ?, ?)"
+        )
+        .bind(line_user_id)
+        .bind(event_title)
+        .bind(event_description)
+        .bind(event_time)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    pub async fn list_by_user(pool: &SqlitePool, line_user_id: &str) -> Result<Vec<Calendar>, sqlx::Error> {
+        sqlx::query_as::<_, Calendar>(
+            "SELECT * FROM calendars WHERE line_user_id = ? ORDER BY event_time ASC"
+        )
+        .bind(line_user_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// A page of calendar events in time order, optionally for one user and
+    /// between `from` (inclusive) and `to` (exclusive)
+    pub async fn list(
+        pool: &SqlitePool,
+        line_user_id: Option<&str>,
+        from: Option<&str>,
+        to: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Calendar>, sqlx::Error> {
+        sqlx::query_as::<_, Calendar>(
+            "SELECT * FROM calendars
+             WHERE (? IS NULL OR line_user_id = ?)
+               AND (? IS NULL OR datetime(event_time) >= datetime(?))
+               AND (? IS NULL OR datetime(event_time) < datetime(?))
+             ORDER BY event_time ASC, id ASC
+             LIMIT ? OFFSET ?"
+        )
+        .bind(line_user_id)
+        .bind(line_user_id)
+        .bind(from)
+        .bind(from)
+        .bind(to)
+        .bind(to)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn find_by_id(pool: &SqlitePool, id: i64) -> Result<Option<Calendar>, sqlx::Error> {
+        sqlx::query_as::<_, Calendar>("SELECT * FROM calendars WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Mark a reminder as dealt with without sending it, so it isn't retried
+    pub async fn skip_reminder(pool: &SqlitePool, id: i64, reason: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE calendars SET reminder_sent = 1, reminder_skipped_reason = ?, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?"
+        )
+        .bind(reason)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn set_reminder_retry_key(pool: &SqlitePool, id: i64, retry_key: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE calendars SET reminder_retry_key = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(retry_key)
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Keep the reminder pending after a transient failure so the next check retries it
+    pub async fn mark_reminder_retrying(pool: &SqlitePool, id: i64, error: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE calendars SET reminder_error = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(error)
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Reminders still being retried when their event started
+    pub async fn list_expired_reminders(pool: &SqlitePool) -> Result<Vec<Calendar>, sqlx::Error> {
+        sqlx::query_as::<_, Calendar>(
+            "SELECT * FROM calendars
+             WHERE reminder_sent = 0 AND reminder_retry_key IS NOT NULL AND datetime(event_time) < datetime('now')"
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+// Database operations for RichMenu
+impl RichMenu {
+    pub async fn create(
+        pool: &SqlitePool,
+        name: &str,
+        definition: &str,
+        line_rich_menu_id: &str,
+        image_path: Option<&str>,
+    ) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO rich_menus (name, definition, line_rich_menu_id, image_path)
+             VALUES (?, ?, ?, ?)"
+        )
+        .bind(name)
+        .bind(definition)
+        .bind(line_rich_menu_id)
+        .bind(image_path)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    pub async fn find_by_id(pool: &SqlitePool, id: i64) -> Result<Option<RichMenu>, sqlx::Error> {
+        sqlx::query_as::<_, RichMenu>(
+            "SELECT * FROM rich_menus WHERE id = ?"
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn list_all(pool: &SqlitePool) -> Result<Vec<RichMenu>, sqlx::Error> {
+        sqlx::query_as::<_, RichMenu>(
+            "SELECT * FROM rich_menus ORDER BY created_at DESC"
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn set_image_path(pool: &SqlitePool, id: i64, image_path: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE rich_menus SET image_path = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?"
+        )
+        .bind(image_path)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Mark one menu as the default (or none with `None`)
+    pub async fn set_default(pool: &SqlitePool, id: Option<i64>) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query(
+            "UPDATE rich_menus SET is_default = 0, updated_at = CURRENT_TIMESTAMP WHERE is_default = 1"
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if let Some(id) = id {
+            sqlx::query(
+                "UPDATE rich_menus SET is_default = 1, updated_at = CURRENT_TIMESTAMP WHERE id = ?"
+            )
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await
+    }
+
+    /// Delete a menu together with its aliases
+    pub async fn delete(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query("DELETE FROM rich_menu_aliases WHERE rich_menu_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM rich_menus WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await
+    }
+}
+
+// Database operations for RichMenuAlias
+impl RichMenuAlias {
+    pub async fn upsert(pool: &SqlitePool, alias_id: &str, rich_menu_id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO rich_menu_aliases (alias_id, rich_menu_id) VALUES (?, ?)
+             ON CONFLICT(alias_id) DO UPDATE SET
+             rich_menu_id = excluded.rich_menu_id,
+             updated_at = CURRENT_TIMESTAMP"
+        )
+        .bind(alias_id)
+        .bind(rich_menu_id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_all(pool: &SqlitePool) -> Result<Vec<RichMenuAlias>, sqlx::Error> {
+        sqlx::query_as::<_, RichMenuAlias>(
+            "SELECT * FROM rich_menu_aliases ORDER BY alias_id ASC"
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn delete(pool: &SqlitePool, alias_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM rich_menu_aliases WHERE alias_id = ?")
+            .bind(alias_id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+// Database operations for AutoReplyRule
+impl AutoReplyRule {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create(
+        pool: &SqlitePool,
+        name: &str,
+        match_type: &str,
+        pattern: Option<&str>,
+        case_sensitive: bool,
+        start_time: Option<&str>,
+        end_time: Option<&str>,
+        messages: &str,
+        priority: i64,
+        enabled: bool,
+    ) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO auto_reply_rules
+             (name, match_type, pattern, case_sensitive, start_time, end_time, messages, priority, enabled)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(name)
+        .bind(match_type)
+        .bind(pattern)
+        .bind(case_sensitive)
+        .bind(start_time)
+        .bind(end_time)
+        .bind(messages)
+        .bind(priority)
+        .bind(enabled)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn update(
+        pool: &SqlitePool,
+        id: i64,
+        name: &str,
+        match_type: &str,
+        pattern: Option<&str>,
+        case_sensitive: bool,
+        start_time: Option<&str>,
+        end_time: Option<&str>,
+        messages: &str,
+        priority: i64,
+        enabled: bool,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE auto_reply_rules
+             SET name = ?, match_type = ?, pattern = ?, case_sensitive = ?, start_time = ?, end_time = ?,
+                 messages = ?, priority = ?, enabled = ?, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?"
+        )
+        .bind(name)
+        .bind(match_type)
+        .bind(pattern)
+        .bind(case_sensitive)
+        .bind(start_time)
+        .bind(end_time)
+        .bind(messages)
+        .bind(priority)
+        .bind(enabled)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_all(pool: &SqlitePool) -> Result<Vec<AutoReplyRule>, sqlx::Error> {
+        sqlx::query_as::<_, AutoReplyRule>(
+            "SELECT * FROM auto_reply_rules ORDER BY priority DESC, id ASC"
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Enabled rules in evaluation order
+    pub async fn list_enabled(pool: &SqlitePool) -> Result<Vec<AutoReplyRule>, sqlx::Error> {
+        sqlx::query_as::<_, AutoReplyRule>(
+            "SELECT * FROM auto_reply_rules WHERE enabled = 1 ORDER BY priority DESC, id ASC"
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Delete a rule together with its match log
+    pub async fn delete(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query("DELETE FROM auto_reply_logs WHERE rule_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM auto_reply_rules WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await
+    }
+}
+
+// Database operations for AutoReplyLog
+impl AutoReplyLog {
+    pub async fn create(
+        pool: &SqlitePool,
+        rule_id: i64,
+        line_user_id: &str,
+        message_text: &str,
+        status: &str,
+        error_message: Option<&str>,
+    ) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO auto_reply_logs (rule_id, line_user_id, message_text, status, error_message)
+             VALUES (?, ?, ?, ?, ?)"
+        )
+        .bind(rule_id)
+        .bind(line_user_id)
+        .bind(message_text)
+        .bind(status)
+        .bind(error_message)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    pub async fn list_recent(pool: &SqlitePool, limit: i32) -> Result<Vec<AutoReplyLog>, sqlx::Error> {
+        sqlx::query_as::<_, AutoReplyLog>(
+            "SELECT * FROM auto_reply_logs ORDER BY matched_at DESC, id DESC LIMIT ?"
+        )
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+}
+
+// Database operations for Setting
 impl Setting {
     pub async fn set(pool: &SqlitePool, key: &str, value: &str, description: Option<&str>) -> Result<(), sqlx::Error> {
         sqlx::query(
-            "INSERT INTO settings (key, value, description) VALUES (?, ?, ?)
-             ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = CURRENT_TIMESTAMP"
+            "INSERT INTO settings (key, value, description) VALUES (?, ?, ?)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = CURRENT_TIMESTAMP"
+        )
+        .bind(key)
+        .bind(value)
+        .bind(description)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(pool: &SqlitePool, key: &str) -> Result<Option<String>, sqlx::Error> {
+        let setting: Option<Setting> = sqlx::query_as::<_, Setting>(
+            "SELECT * FROM settings WHERE key = ?"
+        )
+        .bind(key)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(setting.map(|s| s.value))
+    }
+
+    pub async fn list_all(pool: &SqlitePool) -> Result<Vec<Setting>, sqlx::Error> {
+        sqlx::query_as::<_, Setting>(
+            "SELECT * FROM settings ORDER BY key ASC"
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn delete(pool: &SqlitePool, key: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM settings WHERE key = ?")
+            .bind(key)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+// Database operations for NotificationChannel
+impl NotificationChannel {
+    pub async fn create(pool: &SqlitePool, name: &str, kind: &str, config: &str, enabled: bool) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO notification_channels (name, kind, config, enabled) VALUES (?, ?, ?, ?)"
+        )
+        .bind(name)
+        .bind(kind)
+        .bind(config)
+        .bind(enabled)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    pub async fn update(pool: &SqlitePool, id: i64, name: &str, config: &str, enabled: bool) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE notification_channels SET name = ?, config = ?, enabled = ?, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?"
+        )
+        .bind(name)
+        .bind(config)
+        .bind(enabled)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM notification_channels WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_id(pool: &SqlitePool, id: i64) -> Result<Option<NotificationChannel>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationChannel>("SELECT * FROM notification_channels WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    pub async fn list_all(pool: &SqlitePool) -> Result<Vec<NotificationChannel>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationChannel>("SELECT * FROM notification_channels ORDER BY id ASC")
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn list_enabled(pool: &SqlitePool) -> Result<Vec<NotificationChannel>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationChannel>(
+            "SELECT * FROM notification_channels WHERE enabled = 1 ORDER BY id ASC"
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+// Database operations for NotificationRule
+impl NotificationRule {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create(
+        pool: &SqlitePool,
+        name: &str,
+        event_kind: &str,
+        channel_id: i64,
+        keywords: Option<&str>,
+        quiet_start: Option<&str>,
+        quiet_end: Option<&str>,
+        rate_limit: Option<i64>,
+        rate_window_minutes: Option<i64>,
+        digest_minutes: Option<i64>,
+        enabled: bool,
+    ) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO notification_rules
+             (name, event_kind, channel_id, keywords, quiet_start, quiet_end, rate_limit, rate_window_minutes,
+              digest_minutes, enabled)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(name)
+        .bind(event_kind)
+        .bind(channel_id)
+        .bind(keywords)
+        .bind(quiet_start)
+        .bind(quiet_end)
+        .bind(rate_limit)
+        .bind(rate_window_minutes)
+        .bind(digest_minutes)
+        .bind(enabled)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn update(
+        pool: &SqlitePool,
+        id: i64,
+        name: &str,
+        event_kind: &str,
+        channel_id: i64,
+        keywords: Option<&str>,
+        quiet_start: Option<&str>,
+        quiet_end: Option<&str>,
+        rate_limit: Option<i64>,
+        rate_window_minutes: Option<i64>,
+        digest_minutes: Option<i64>,
+        enabled: bool,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE notification_rules
+             SET name = ?, event_kind = ?, channel_id = ?, keywords = ?, quiet_start = ?, quiet_end = ?,
+                 rate_limit = ?, rate_window_minutes = ?, digest_minutes = ?, enabled = ?,
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?"
+        )
+        .bind(name)
+        .bind(event_kind)
+        .bind(channel_id)
+        .bind(keywords)
+        .bind(quiet_start)
+        .bind(quiet_end)
+        .bind(rate_limit)
+        .bind(rate_window_minutes)
+        .bind(digest_minutes)
+        .bind(enabled)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM notification_rules WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_all(pool: &SqlitePool) -> Result<Vec<NotificationRule>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationRule>("SELECT * FROM notification_rules ORDER BY event_kind, id")
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Rules for an event kind, disabled ones included
+    pub async fn list_for_kind(pool: &SqlitePool, event_kind: &str) -> Result<Vec<NotificationRule>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationRule>(
+            "SELECT * FROM notification_rules WHERE event_kind = ? ORDER BY id"
+        )
+        .bind(event_kind)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Enabled rules with held events whose last digest was at least their
+    /// digest interval ago. The interval is `digest_minutes`, else
+    /// `rate_window_minutes`, else `default_minutes`.
+    pub async fn list_digest_due(pool: &SqlitePool, default_minutes: i64) -> Result<Vec<NotificationRule>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationRule>(
+            "SELECT * FROM notification_rules r
+             WHERE r.enabled = 1
+             AND EXISTS (SELECT 1 FROM notification_events e WHERE e.rule_id = r.id AND e.status = 'held')
+             AND (r.last_digest_at IS NULL
+                  OR r.last_digest_at <= datetime('now',
+                      '-' || COALESCE(r.digest_minutes, r.rate_window_minutes, ?) || ' minutes'))
+             ORDER BY r.id"
+        )
+        .bind(default_minutes)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn set_last_digest(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE notification_rules SET last_digest_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+// Database operations for NotificationEvent
+impl NotificationEvent {
+    pub async fn create(
+        pool: &SqlitePool,
+        rule_id: i64,
+        event_kind: &str,
+        line_user_id: Option<&str>,
+        title: &str,
+        text: &str,
+        status: &str,
+    ) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO notification_events (rule_id, event_kind, line_user_id, title, text, status)
+             VALUES (?, ?, ?, ?, ?, ?)"
+        )
+        .bind(rule_id)
+        .bind(event_kind)
+        .bind(line_user_id)
+        .bind(title)
+        .bind(text)
+        .bind(status)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Events about a user the rule sent right away in the last `minutes`
+    pub async fn count_sent_since(
+        pool: &SqlitePool,
+        rule_id: i64,
+        line_user_id: &str,
+        minutes: i64,
+    ) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT COUNT(*) FROM notification_events
+             WHERE rule_id = ? AND line_user_id = ? AND status = 'sent'
+             AND created_at > datetime('now', '-' || ? || ' minutes')"
+        )
+        .bind(rule_id)
+        .bind(line_user_id)
+        .bind(minutes)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Events waiting for the rule's next digest, oldest first
+    pub async fn list_held(pool: &SqlitePool, rule_id: i64) -> Result<Vec<NotificationEvent>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationEvent>(
+            "SELECT * FROM notification_events WHERE rule_id = ? AND status = 'held' ORDER BY id"
+        )
+        .bind(rule_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Mark the rule's held events up to `last_id` as sent in a digest
+    pub async fn mark_digested(pool: &SqlitePool, rule_id: i64, last_id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE notification_events SET status = 'digested' WHERE rule_id = ? AND status = 'held' AND id <= ?"
         )
-        .bind(key)
+        .bind(rule_id)
+        .bind(last_id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Forget delivered events older than `days`
+    pub async fn delete_delivered_before(pool: &SqlitePool, days: i64) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "DELETE FROM notification_events
+             WHERE status != 'held' AND created_at < datetime('now', '-' || ? || ' days')"
+        )
+        .bind(days)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+// Database operations for NotificationLog
+impl NotificationLog {
+    /// Add a pending notification, due `delay_seconds` from now
+    #[allow(clippy::too_many_arguments)]
+    pub async fn enqueue(
+        pool: &SqlitePool,
+        channel: &NotificationChannel,
+        event_kind: Option<&str>,
+        title: &str,
+        text: &str,
+        line_user_id: Option<&str>,
+        message: &str,
+        retry_key: &str,
+        delay_seconds: i64,
+    ) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO notification_logs
+             (notification_type, recipient, message, status, channel_id, event_kind, title, text, line_user_id,
+              retry_key, next_attempt_at)
+             VALUES (?, ?, ?, 'pending', ?, ?, ?, ?, ?, ?, datetime('now', '+' || ? || ' seconds'))"
+        )
+        .bind(&channel.kind)
+        .bind(&channel.name)
+        .bind(message)
+        .bind(channel.id)
+        .bind(event_kind)
+        .bind(title)
+        .bind(text)
+        .bind(line_user_id)
+        .bind(retry_key)
+        .bind(delay_seconds)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    pub async fn find_by_id(pool: &SqlitePool, id: i64) -> Result<Option<NotificationLog>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationLog>("SELECT * FROM notification_logs WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Newest first, optionally filtered. `since`/`until` are datetimes.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn search(
+        pool: &SqlitePool,
+        status: Option<&str>,
+        channel_id: Option<i64>,
+        event_kind: Option<&str>,
+        since: Option<&str>,
+        until: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<NotificationLog>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationLog>(
+            "SELECT * FROM notification_logs
+             WHERE (? IS NULL OR status = ?)
+               AND (? IS NULL OR channel_id = ?)
+               AND (? IS NULL OR event_kind = ?)
+               AND (? IS NULL OR created_at >= datetime(?))
+               AND (? IS NULL OR created_at < datetime(?))
+             ORDER BY id DESC
+             LIMIT ? OFFSET ?"
+        )
+        .bind(status)
+        .bind(status)
+        .bind(channel_id)
+        .bind(channel_id)
+        .bind(event_kind)
+        .bind(event_kind)
+        .bind(since)
+        .bind(since)
+        .bind(until)
+        .bind(until)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Pending notifications whose next attempt is due, oldest first
+    pub async fn list_due(pool: &SqlitePool, limit: i64) -> Result<Vec<NotificationLog>, sqlx::Error> {
+        sqlx::query_as::<_, NotificationLog>(
+            "SELECT * FROM notification_logs
+             WHERE status = 'pending' AND next_attempt_at <= datetime('now')
+             ORDER BY next_attempt_at ASC, id ASC
+             LIMIT ?"
+        )
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Take a due notification for an attempt by pushing its next attempt
+    /// `seconds` out. False when someone else took it first.
+    pub async fn claim(pool: &SqlitePool, id: i64, seconds: i64) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE notification_logs SET next_attempt_at = datetime('now', '+' || ? || ' seconds')
+             WHERE id = ? AND status = 'pending' AND next_attempt_at <= datetime('now')"
+        )
+        .bind(seconds)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn mark_sent(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE notification_logs
+             SET status = 'sent', attempts = attempts + 1, error_message = NULL, next_attempt_at = NULL,
+                 delivered_at = CURRENT_TIMESTAMP
+             WHERE id = ?"
+        )
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record a failed attempt and retry in `delay_seconds`
+    pub async fn mark_retry(pool: &SqlitePool, id: i64, error: &str, delay_seconds: i64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE notification_logs
+             SET attempts = attempts + 1, error_message = ?,
+                 next_attempt_at = datetime('now', '+' || ? || ' seconds')
+             WHERE id = ?"
+        )
+        .bind(error)
+        .bind(delay_seconds)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record a failed attempt and give up
+    pub async fn mark_dead(pool: &SqlitePool, id: i64, error: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE notification_logs
+             SET status = 'dead', attempts = attempts + 1, error_message = ?, next_attempt_at = NULL
+             WHERE id = ?"
+        )
+        .bind(error)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Queue a notification again with fresh attempts, due now
+    pub async fn requeue(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE notification_logs
+             SET status = 'pending', attempts = 0, error_message = NULL, delivered_at = NULL,
+                 next_attempt_at = datetime('now')
+             WHERE id = ?"
+        )
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Notifications per status, e.g. `[("dead", 2), ("sent", 40)]`
+    pub async fn count_by_status(pool: &SqlitePool) -> Result<Vec<(String, i64)>, sqlx::Error> {
+        sqlx::query_as("SELECT status, COUNT(*) FROM notification_logs GROUP BY status ORDER BY status")
+            .fetch_all(pool)
+            .await
+    }
+}
+
+// Database operations for MessageTemplate
+impl MessageTemplate {
+    pub async fn create(
+        pool: &SqlitePool,
+        name: &str,
+        body: &str,
+        description: Option<&str>,
+    ) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO message_templates (name, body, description) VALUES (?, ?, ?)"
+        )
+        .bind(name)
+        .bind(body)
+        .bind(description)
+        .execute(pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    pub async fn update(
+        pool: &SqlitePool,
+        id: i64,
+        name: &str,
+        body: &str,
+        description: Option<&str>,
+    ) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE message_templates SET name = ?, body = ?, description = ?, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?"
+        )
+        .bind(name)
+        .bind(body)
+        .bind(description)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn find_by_id(pool: &SqlitePool, id: i64) -> Result<Option<MessageTemplate>, sqlx::Error> {
+        sqlx::query_as::<_, MessageTemplate>("SELECT * FROM message_templates WHERE id = ?")
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+    }
+
+    pub async fn find_by_name(pool: &SqlitePool, name: &str) -> Result<Option<MessageTemplate>, sqlx::Error> {
+        sqlx::query_as::<_, MessageTemplate>("SELECT * FROM message_templates WHERE name = ?")
+            .bind(name)
+            .fetch_optional(pool)
+            .await
+    }
+
+    pub async fn list_all(pool: &SqlitePool) -> Result<Vec<MessageTemplate>, sqlx::Error> {
+        sqlx::query_as::<_, MessageTemplate>("SELECT * FROM message_templates ORDER BY name ASC")
+            .fetch_all(pool)
+            .await
+    }
+
+    pub async fn delete(pool: &SqlitePool, id: i64) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM message_templates WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+// Database operations for UserAttribute
+impl UserAttribute {
+    pub async fn list_by_user(pool: &SqlitePool, line_user_id: &str) -> Result<Vec<UserAttribute>, sqlx::Error> {
+        sqlx::query_as::<_, UserAttribute>(
+            "SELECT * FROM user_attributes WHERE line_user_id = ? ORDER BY name ASC"
+        )
+        .bind(line_user_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn set(pool: &SqlitePool, line_user_id: &str, name: &str, value: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO user_attributes (line_user_id, name, value) VALUES (?, ?, ?)
+             ON CONFLICT(line_user_id, name) DO UPDATE SET
+             value = excluded.value,
+             updated_at = CURRENT_TIMESTAMP"
+        )
+        .bind(line_user_id)
+        .bind(name)
         .bind(value)
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(pool: &SqlitePool, line_user_id: &str, name: &str) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM user_attributes WHERE line_user_id = ? AND name = ?")
+            .bind(line_user_id)
+            .bind(name)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+// Database operations for SecretsMasterKey
+impl SecretsMasterKey {
+    pub async fn get(pool: &SqlitePool) -> Result<Option<SecretsMasterKey>, sqlx::Error> {
+        sqlx::query_as::<_, SecretsMasterKey>("SELECT * FROM secrets_master_key WHERE id = 1")
+            .fetch_optional(pool)
+            .await
+    }
+
+    /// Record the first master key
+    pub async fn create(pool: &SqlitePool, key_id: &str, kdf_salt: Option<&str>) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT INTO secrets_master_key (id, key_id, kdf_salt) VALUES (1, ?, ?)")
+            .bind(key_id)
+            .bind(kdf_salt)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Switch to a new master key, replacing the secret settings and channel
+    /// configs with their values encrypted under it in the same transaction
+    pub async fn rotate(
+        pool: &SqlitePool,
+        key_id: &str,
+        kdf_salt: Option<&str>,
+        values: &[(String, String)],
+        channel_configs: &[(i64, String)],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        for (key, value) in values {
+            sqlx::query("UPDATE settings SET value = ?, updated_at = CURRENT_TIMESTAMP WHERE key = ?")
+                .bind(value)
+                .bind(key)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        for (id, config) in channel_configs {
+            sqlx::query("UPDATE notification_channels SET config = ? WHERE id = ?")
+                .bind(config)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        sqlx::query(
+            "UPDATE secrets_master_key SET key_id = ?, kdf_salt = ?, rotated_at = CURRENT_TIMESTAMP WHERE id = 1"
+        )
+        .bind(key_id)
+        .bind(kdf_salt)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    }
+}
+
+// Database operations for SchedulerLease
+impl SchedulerLease {
+    /// Take the lease, or extend it if `owner` already holds it. Returns
+    /// false while another owner holds an unexpired lease.
+    pub async fn acquire(
+        pool: &SqlitePool,
+        owner: &str,
+        description: &str,
+        ttl_seconds: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO scheduler_lease (id, owner, description, expires_at)
+             VALUES (1, ?, ?, datetime('now', '+' || ? || ' seconds'))
+             ON CONFLICT(id) DO UPDATE SET
+                owner = excluded.owner,
+                description = excluded.description,
+                acquired_at = CASE WHEN scheduler_lease.owner = excluded.owner
+                    THEN scheduler_lease.acquired_at ELSE CURRENT_TIMESTAMP END,
+                expires_at = excluded.expires_at
+             WHERE scheduler_lease.owner = excluded.owner OR scheduler_lease.expires_at < CURRENT_TIMESTAMP"
+        )
+        .bind(owner)
         .bind(description)
+        .bind(ttl_seconds)
         .execute(pool)
         .await?;
 
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Give up the lease so another process can take over right away
+    pub async fn release(pool: &SqlitePool, owner: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM scheduler_lease WHERE owner = ?")
+            .bind(owner)
+            .execute(pool)
+            .await?;
+
         Ok(())
     }
 
-    pub async fn get(pool: &SqlitePool, key: &str) -> Result<Option<String>, sqlx::Error> {
-        let setting: Option<Setting> = sqlx::query_as::<_, Setting>(
-            "SELECT * FROM settings WHERE key = ?"
+    pub async fn current(pool: &SqlitePool) -> Result<Option<SchedulerLease>, sqlx::Error> {
+        sqlx::query_as::<_, SchedulerLease>("SELECT * FROM scheduler_lease WHERE id = 1")
+            .fetch_optional(pool)
+            .await
+    }
+}
+
+// Database operations for ApiKey
+impl ApiKey {
+    pub async fn create(
+        pool: &SqlitePool,
+        name: &str,
+        key_prefix: &str,
+        key_hash: &str,
+        scopes: &str,
+    ) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO api_keys (name, key_prefix, key_hash, scopes) VALUES (?, ?, ?, ?)"
         )
-        .bind(key)
-        .fetch_optional(pool)
+        .bind(name)
+        .bind(key_prefix)
+        .bind(key_hash)
+        .bind(scopes)
+        .execute(pool)
         .await?;
 
-        Ok(setting.map(|s| s.value))
+        Ok(result.last_insert_rowid())
     }
 
-    pub async fn list_all(pool: &SqlitePool) -> Result<Vec<Setting>, sqlx::Error> {
-        sqlx::query_as::<_, Setting>(
-            "SELECT * FROM settings ORDER BY key ASC"
+    /// The key with this hash, unless it was revoked
+    pub async fn find_active_by_hash(pool: &SqlitePool, key_hash: &str) -> Result<Option<ApiKey>, sqlx::Error> {
+        sqlx::query_as::<_, ApiKey>(
+            "SELECT * FROM api_keys WHERE key_hash = ? AND revoked_at IS NULL"
+        )
+        .bind(key_hash)
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn list_all(pool: &SqlitePool) -> Result<Vec<ApiKey>, sqlx::Error> {
+        sqlx::query_as::<_, ApiKey>(
+            "SELECT * FROM api_keys ORDER BY created_at DESC, id DESC"
         )
         .fetch_all(pool)
         .await
     }
+
+    pub async fn touch(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE api_keys SET last_used_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn revoke(pool: &SqlitePool, id: i64) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE api_keys SET revoked_at = CURRENT_TIMESTAMP WHERE id = ? AND revoked_at IS NULL"
+        )
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
 }
@@ -0,0 +1,151 @@
+use reqwest::StatusCode;
+use sqlx::SqlitePool;
+
+use crate::api::line_client::{LineApiError, LineClient, Message as LineMessage, UserProfile};
+use crate::api::line_message::validate_messages;
+use crate::db::models::User;
+use crate::messaging::{self, Sender};
+use crate::settings;
+
+/// Profiles older than this are fetched again by the refresh job
+const PROFILE_MAX_AGE_HOURS: i64 = 24;
+/// How many profiles one run of the refresh job fetches at most
+const PROFILE_REFRESH_BATCH: i64 = 200;
+
+/// The configured welcome sequence (empty when none is set)
+pub async fn welcome_messages(db: &SqlitePool) -> Result<Vec<LineMessage>, anyhow::Error> {
+    match settings::get(db, settings::WELCOME_MESSAGES).await? {
+        Some(json) if !json.trim().is_empty() => serde_json::from_str(&json)
+            .map_err(|e| anyhow::anyhow!("Invalid welcome messages: {}", e)),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Validate and store the welcome sequence. An empty list turns it off.
+pub async fn set_welcome_messages(db: &SqlitePool, messages: &[LineMessage]) -> Result<(), anyhow::Error> {
+    if !messages.is_empty() {
+        validate_messages(messages).map_err(|e| anyhow::anyhow!(e))?;
+    }
+
+    let json = serde_json::to_string(messages)?;
+    settings::set(db, settings::WELCOME_MESSAGES, &json, None).await?;
+    Ok(())
+}
+
+/// Fetch a user's profile from LINE and store it. Returns `None` when LINE
+/// has no profile for the user (they blocked the account or never added it).
+pub async fn refresh_profile(
+    db: &SqlitePool,
+    client: &LineClient,
+    line_user_id: &str,
+) -> Result<Option<UserProfile>, anyhow::Error> {
+    let profile = match client.get_profile(line_user_id).await {
+        Ok(profile) => profile,
+        Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => {
+            tracing::info!("No profile available for {}", line_user_id);
+            User::touch_profile_refreshed(db, line_user_id).await?;
+            return Ok(None);
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    User::update_profile(
+        db,
+        line_user_id,
+        &profile.display_name,
+        profile.picture_url.as_deref(),
+        profile.status_message.as_deref(),
+    )
+    .await?;
+
+    Ok(Some(profile))
+}
+
+/// Handle a follow: record it, store the user's profile and reply with the
+/// welcome sequence. Returns the display name, if the profile could be fetched.
+pub async fn handle_follow(
+    db: &SqlitePool,
+    line_user_id: &str,
+    reply_token: &str,
+    is_unblocked: bool,
+    occurred_at: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    User::record_follow(db, line_user_id, is_unblocked, occurred_at).await?;
+
+    let client = LineClient::from_settings(db).await?;
+
+    // Reply first: the reply token expires soon after the event. A failed
+    // welcome message shouldn't stop the profile from being stored.
+    if let Err(e) = send_welcome(db, &client, line_user_id, reply_token).await {
+        tracing::error!("Failed to send welcome messages to {}: {}", line_user_id, e);
+    }
+
+    let display_name = match refresh_profile(db, &client, line_user_id).await {
+        Ok(profile) => profile.map(|p| p.display_name),
+        Err(e) => {
+            tracing::error!("Failed to fetch profile of {}: {}", line_user_id, e);
+            None
+        }
+    };
+
+    Ok(display_name)
+}
+
+async fn send_welcome(
+    db: &SqlitePool,
+    client: &LineClient,
+    line_user_id: &str,
+    reply_token: &str,
+) -> Result<(), anyhow::Error> {
+    let messages = welcome_messages(db).await?;
+    if !messages.is_empty() {
+        messaging::reply(db, client, Sender::Bot, line_user_id, reply_token, messages).await?;
+        tracing::info!("Sent welcome messages to {}", line_user_id);
+    }
+    Ok(())
+}
+
+/// Handle an unfollow. Returns the display name we last knew, if any.
+pub async fn handle_unfollow(
+    db: &SqlitePool,
+    line_user_id: &str,
+    occurred_at: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    User::record_unfollow(db, line_user_id, occurred_at).await?;
+
+    let user = User::find_by_line_id(db, line_user_id).await?;
+    Ok(user.and_then(|u| u.display_name))
+}
+
+/// Refresh the profiles that are missing or out of date, oldest first.
+/// Returns how many were refreshed.
+pub async fn refresh_stale_profiles(db: &SqlitePool) -> Result<usize, anyhow::Error> {
+    let users = User::list_stale_profiles(db, PROFILE_MAX_AGE_HOURS, PROFILE_REFRESH_BATCH).await?;
+    if users.is_empty() {
+        return Ok(0);
+    }
+
+    let client = LineClient::from_settings(db).await?;
+    let mut refreshed = 0;
+
+    for user in users {
+        match refresh_profile(db, &client, &user.line_user_id).await {
+            Ok(Some(_)) => refreshed += 1,
+            Ok(None) => {}
+            Err(e) => {
+                let status = e.downcast_ref::<LineApiError>().and_then(|le| le.status());
+                // An invalid token fails for every user, so stop here
+                if matches!(status, Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN)) {
+                    return Err(e);
+                }
+                tracing::warn!("Failed to refresh profile of {}: {}", user.line_user_id, e);
+                // Don't let one broken user block the rest of the queue
+                if status.is_some_and(|s| s.is_client_error()) {
+                    User::touch_profile_refreshed(db, &user.line_user_id).await?;
+                }
+            }
+        }
+    }
+
+    Ok(refreshed)
+}
@@ -0,0 +1,190 @@
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::db::models::{Conversation, Message};
+use crate::messaging::Sender;
+use crate::slack_bridge;
+
+pub const STATUSES: &[&str] = &["open", "pending", "resolved"];
+
+/// Longest preview kept on a conversation, in characters
+const PREVIEW_MAX_CHARS: usize = 100;
+
+/// How often `watch_database` looks for changes made by another process
+const WATCH_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Something that changed in the inbox, for live views
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum InboxEvent {
+    /// A message was received from or sent to the user
+    Message {
+        conversation: Conversation,
+        message: Box<Message>,
+    },
+    /// The conversation was read, assigned or had its status changed
+    Updated { conversation: Conversation },
+}
+
+// Inbox changes are published process-wide, since messages are stored from
+// the webhook, the scheduler and commands alike. Events sent while nobody
+// is subscribed are dropped.
+static EVENTS: LazyLock<broadcast::Sender<InboxEvent>> = LazyLock::new(|| broadcast::channel(256).0);
+
+/// Receive inbox events from now on
+pub fn subscribe() -> broadcast::Receiver<InboxEvent> {
+    EVENTS.subscribe()
+}
+
+fn publish(event: InboxEvent) {
+    let _ = EVENTS.send(event);
+}
+
+/// Add a stored inbound message to its user's conversation. Messages from
+/// groups and rooms aren't part of the inbox.
+pub async fn record_inbound(db: &SqlitePool, message_id: i64) -> Result<(), sqlx::Error> {
+    let Some(message) = Message::find_by_id(db, message_id).await? else {
+        return Ok(());
+    };
+    let Some(line_user_id) = message.line_user_id.clone() else {
+        return Ok(());
+    };
+    if message.source_type != "user" {
+        return Ok(());
+    }
+
+    Conversation::record_inbound(db, &line_user_id, &preview(&message)).await?;
+    // Queued here rather than from the published event, which a lagging
+    // subscriber can miss
+    if let Err(e) = slack_bridge::enqueue(db, &message).await {
+        tracing::error!("Failed to queue message {} for Slack: {}", message.id, e);
+    }
+    publish_message(db, &line_user_id, message).await
+}
+
+/// Add a stored outbound message to its recipient's conversation
+pub async fn record_outbound(db: &SqlitePool, message_id: i64, sender: Sender) -> Result<(), sqlx::Error> {
+    let Some(message) = Message::find_by_id(db, message_id).await? else {
+        return Ok(());
+    };
+    let Some(line_user_id) = message.line_user_id.clone() else {
+        return Ok(());
+    };
+    // A failed send never reached the user
+    if message.delivery_status.as_deref() != Some("sent") {
+        return Ok(());
+    }
+
+    let from_staff = matches!(sender, Sender::Admin | Sender::Slack);
+    let touched = Conversation::record_outbound(db, &line_user_id, &preview(&message), from_staff).await?;
+    if touched {
+        publish_message(db, &line_user_id, message).await?;
+    }
+    Ok(())
+}
+
+pub async fn mark_read(db: &SqlitePool, line_user_id: &str) -> Result<Conversation, anyhow::Error> {
+    Conversation::mark_read(db, line_user_id).await?;
+    publish_updated(db, line_user_id).await
+}
+
+pub async fn set_status(db: &SqlitePool, line_user_id: &str, status: &str) -> Result<Conversation, anyhow::Error> {
+    if !STATUSES.contains(&status) {
+        anyhow::bail!("Unknown conversation status '{}' (expected one of {:?})", status, STATUSES);
+    }
+
+    Conversation::set_status(db, line_user_id, status).await?;
+    publish_updated(db, line_user_id).await
+}
+
+pub async fn assign(
+    db: &SqlitePool,
+    line_user_id: &str,
+    assigned_to: Option<&str>,
+) -> Result<Conversation, anyhow::Error> {
+    let assigned_to = assigned_to.map(str::trim).filter(|a| !a.is_empty());
+
+    Conversation::assign(db, line_user_id, assigned_to).await?;
+    publish_updated(db, line_user_id).await
+}
+
+/// Publish inbox changes another process (a headless server on the same
+/// database) makes, by polling for new messages and changed conversations.
+/// Changes made by this process may be published twice.
+pub async fn watch_database(db: SqlitePool) {
+    let mut last_message_id = None;
+    let mut since = None;
+    let mut seen = HashMap::new();
+
+    loop {
+        if let Err(e) = publish_changes(&db, &mut last_message_id, &mut since, &mut seen).await {
+            tracing::error!("Failed to check the inbox for changes: {}", e);
+        }
+        tokio::time::sleep(WATCH_INTERVAL).await;
+    }
+}
+
+/// One poll of `watch_database`. The first only records where to start.
+async fn publish_changes(
+    db: &SqlitePool,
+    last_message_id: &mut Option<i64>,
+    since: &mut Option<String>,
+    seen: &mut HashMap<i64, String>,
+) -> Result<(), anyhow::Error> {
+    match *last_message_id {
+        None => *last_message_id = Some(Message::last_id(db).await?),
+        Some(after_id) => {
+            for message in Message::list_user_messages_after(db, after_id, 100).await? {
+                *last_message_id = Some(message.id);
+                if let Some(line_user_id) = message.line_user_id.clone() {
+                    publish_message(db, &line_user_id, message).await?;
+                }
+            }
+        }
+    }
+
+    // `updated_at` only has whole seconds, so the last second is read again
+    // and conversations that look the same as before are skipped
+    let first = since.is_none();
+    for conversation in Conversation::list_updated_since(db, since.as_deref().unwrap_or("")).await? {
+        *since = Some(conversation.updated_at.clone());
+        let state = serde_json::to_string(&conversation)?;
+        if seen.insert(conversation.id, state.clone()).as_ref() != Some(&state) && !first {
+            publish(InboxEvent::Updated { conversation });
+        }
+    }
+    Ok(())
+}
+
+async fn publish_message(db: &SqlitePool, line_user_id: &str, message: Message) -> Result<(), sqlx::Error> {
+    if let Some(conversation) = Conversation::find_by_user(db, line_user_id).await? {
+        publish(InboxEvent::Message {
+            conversation,
+            message: Box::new(message),
+        });
+    }
+    Ok(())
+}
+
+async fn publish_updated(db: &SqlitePool, line_user_id: &str) -> Result<Conversation, anyhow::Error> {
+    let conversation = Conversation::find_by_user(db, line_user_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("No conversation with {}", line_user_id))?;
+
+    publish(InboxEvent::Updated {
+        conversation: conversation.clone(),
+    });
+    Ok(conversation)
+}
+
+/// Short text shown for the latest message in the conversation list
+fn preview(message: &Message) -> String {
+    match message.message_text.as_deref().filter(|t| !t.is_empty()) {
+        Some(text) => text.chars().take(PREVIEW_MAX_CHARS).collect(),
+        None => format!("[{}]", message.message_type),
+    }
+}
@@ -1,6 +1,11 @@
-use super::{ExternalIntegration, ExternalRecord};
+use super::{check_response, required_setting, ExternalIntegration, ExternalRecord};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::settings;
+
+const AIRTABLE_API_URL: &str = "https://api.airtable.com/v0";
 
 pub struct AirtableClient {
     client: Client,
@@ -18,13 +23,30 @@ impl AirtableClient {
             table_name,
         }
     }
+
+    /// Create a client from the `airtable_*` settings
+    pub async fn from_settings(db: &SqlitePool) -> Result<Self, anyhow::Error> {
+        Ok(Self::new(
+            required_setting(db, settings::AIRTABLE_API_KEY).await?,
+            required_setting(db, settings::AIRTABLE_BASE_ID).await?,
+            required_setting(db, settings::AIRTABLE_TABLE_NAME).await?,
+        ))
+    }
 }
 
 #[async_trait::async_trait]
 impl ExternalIntegration for AirtableClient {
     async fn connect(&self) -> Result<(), anyhow::Error> {
         tracing::info!("Connecting to Airtable base: {}", self.base_id);
-        // Stub: Test connection
+
+        let response = self
+            .client
+            .get(format!("{}/{}/{}", AIRTABLE_API_URL, self.base_id, self.table_name))
+            .query(&[("maxRecords", "1")])
+            .bearer_auth(&self.api_key)
+            .send()
+            .await?;
+        check_response("Airtable", response).await?;
         Ok(())
     }
 
@@ -44,7 +66,7 @@ impl ExternalIntegration for AirtableClient {
 
         // Stub: Would create records in Airtable
         for msg in messages {
-            tracing::debug!("Would sync message from {} to Airtable", msg.line_user_id);
+            tracing::debug!("Would sync message from {} to Airtable", msg.line_user_id.as_deref().unwrap_or("-"));
         }
 
         Ok(())
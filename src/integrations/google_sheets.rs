@@ -1,27 +1,47 @@
-use super::{ExternalIntegration, ExternalRecord};
+use super::{check_response, required_setting, ExternalIntegration, ExternalRecord};
 use reqwest::Client;
+use sqlx::SqlitePool;
+
+use crate::settings;
+
+const SHEETS_API_URL: &str = "https://sheets.googleapis.com/v4";
 
 pub struct GoogleSheetsClient {
     client: Client,
-    credentials: String,
+    api_key: String,
     spreadsheet_id: String,
 }
 
 impl GoogleSheetsClient {
-    pub fn new(credentials: String, spreadsheet_id: String) -> Self {
+    pub fn new(api_key: String, spreadsheet_id: String) -> Self {
         Self {
             client: Client::new(),
-            credentials,
+            api_key,
             spreadsheet_id,
         }
     }
+
+    /// Create a client from the `google_sheets_*` settings
+    pub async fn from_settings(db: &SqlitePool) -> Result<Self, anyhow::Error> {
+        Ok(Self::new(
+            required_setting(db, settings::GOOGLE_SHEETS_API_KEY).await?,
+            required_setting(db, settings::GOOGLE_SHEETS_SPREADSHEET_ID).await?,
+        ))
+    }
 }
 
 #[async_trait::async_trait]
 impl ExternalIntegration for GoogleSheetsClient {
     async fn connect(&self) -> Result<(), anyhow::Error> {
         tracing::info!("Connecting to Google Sheets: {}", self.spreadsheet_id);
-        // Stub: Would authenticate with Google OAuth2
+
+        let response = self
+            .client
+            .get(format!("{}/spreadsheets/{}", SHEETS_API_URL, self.spreadsheet_id))
+            .query(&[("key", self.api_key.as_str()), ("fields", "properties.title")])
+            .send()
+            .await?;
+        check_response("Google Sheets", response).await?;
         Ok(())
     }
 
@@ -41,7 +61,7 @@ impl ExternalIntegration for GoogleSheetsClient {
 
         // Stub: Would append rows to Google Sheets
         for msg in messages {
-            tracing::debug!("Would sync message from {} to Google Sheets", msg.line_user_id);
+            tracing::debug!("Would sync message from {} to Google Sheets", msg.line_user_id.as_deref().unwrap_or("-"));
         }
 
         Ok(())
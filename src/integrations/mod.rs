@@ -3,6 +3,9 @@ pub mod airtable;
 pub mod google_sheets;
 
 use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::settings;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExternalRecord {
@@ -12,8 +15,32 @@ pub struct ExternalRecord {
 
 #[async_trait::async_trait]
 pub trait ExternalIntegration {
+    /// Check the credentials and target with a lightweight request
     async fn connect(&self) -> Result<(), anyhow::Error>;
     async fn sync_users(&self, users: Vec<crate::db::models::User>) -> Result<(), anyhow::Error>;
     async fn sync_messages(&self, messages: Vec<crate::db::models::Message>) -> Result<(), anyhow::Error>;
     async fn fetch_records(&self) -> Result<Vec<ExternalRecord>, anyhow::Error>;
 }
+
+/// A setting an integration can't work without
+async fn required_setting(db: &SqlitePool, key: &str) -> Result<String, anyhow::Error> {
+    settings::get(db, key)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Setting {} is not configured", key))
+}
+
+/// Turn a non-success response into an error with the service's message
+pub(crate) async fn check_response(service: &str, response: reqwest::Response) -> Result<reqwest::Response, anyhow::Error> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(anyhow::anyhow!(
+        "{} returned {}: {}",
+        service,
+        status,
+        body.chars().take(300).collect::<String>()
+    ))
+}
@@ -1,6 +1,12 @@
-use super::{ExternalIntegration, ExternalRecord};
+use super::{check_response, required_setting, ExternalIntegration, ExternalRecord};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::settings;
+
+const NOTION_API_URL: &str = "https://api.notion.com/v1";
+const NOTION_VERSION: &str = "2022-06-28";
 
 pub struct NotionClient {
     client: Client,
@@ -16,13 +22,29 @@ impl NotionClient {
             database_id,
         }
     }
+
+    /// Create a client from the `notion_api_key` and `notion_database_id` settings
+    pub async fn from_settings(db: &SqlitePool) -> Result<Self, anyhow::Error> {
+        Ok(Self::new(
+            required_setting(db, settings::NOTION_API_KEY).await?,
+            required_setting(db, settings::NOTION_DATABASE_ID).await?,
+        ))
+    }
 }
 
 #[async_trait::async_trait]
 impl ExternalIntegration for NotionClient {
     async fn connect(&self) -> Result<(), anyhow::Error> {
-        tracing::info!("Connecting to Notion...");
-        // Stub: Test connection
+        tracing::info!("Connecting to Notion database {}", self.database_id);
+
+        let response = self
+            .client
+            .get(format!("{}/databases/{}", NOTION_API_URL, self.database_id))
+            .bearer_auth(&self.api_key)
+            .header("Notion-Version", NOTION_VERSION)
+            .send()
+            .await?;
+        check_response("Notion", response).await?;
         Ok(())
     }
 
@@ -42,7 +64,7 @@ impl ExternalIntegration for NotionClient {
 
         // Stub: Would send messages to Notion
         for msg in messages {
-            tracing::debug!("Would sync message from user {} to Notion", msg.line_user_id);
+            tracing::debug!("Would sync message from user {} to Notion", msg.line_user_id.as_deref().unwrap_or("-"));
         }
 
         Ok(())
@@ -8,9 +8,19 @@ mod notification;
 mod scheduler;
 mod analytics;
 mod integrations;
+mod auto_reply;
+mod followers;
+mod media;
+mod messaging;
+mod inbox;
+mod secrets;
+mod settings;
+mod slack_bridge;
+mod templates;
 
 use std::net::SocketAddr;
-use tauri::Manager;
+use tauri::{Emitter, Manager};
+use scheduler::lease::SchedulerOwner;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
@@ -21,7 +31,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| "line_admin_app=info,tower_http=debug".into()),
         )
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
         .init();
 
     tracing::info!("Starting LINE Admin App");
@@ -42,45 +52,131 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing::info!("Database path: {}", db_path);
 
     // Initialize database
-    let db = db::init_db(&db_path).await?;
+    let db = db::init_db(&db_path).await.inspect_err(|e| tracing::error!("{}", e))?;
     tracing::info!("Database initialized");
 
-    // Clone db for different uses
-    let db_for_tauri = db.clone();
-    let db_for_server = db.clone();
-    let db_for_scheduler = db.clone();
+    let args: Vec<String> = std::env::args().collect();
 
-    // Start scheduler
-    let scheduler = scheduler::init_scheduler(db_for_scheduler).await?;
-    tracing::info!("Scheduler initialized");
+    // Re-encrypt the secret settings under a new master key, then exit. Stop
+    // other processes using the database first; they keep the old key.
+    if args.iter().any(|arg| arg == "--rotate-master-key") {
+        let note = secrets::rotate_master_key(&db, &db_path).await?;
+        println!("{}", note);
+        return Ok(());
+    }
+
+    secrets::init(&db, &db_path).await.inspect_err(|e| tracing::error!("{}", e))?;
+    notification::migrate_legacy_settings(&db)
+        .await
+        .inspect_err(|e| tracing::error!("Failed to move notification settings to channels: {}", e))?;
+
+    // Create a REST API key without the GUI: --create-api-key <name> <scope,scope,...>
+    if let Some(index) = args.iter().position(|arg| arg == "--create-api-key") {
+        let (Some(name), Some(scopes)) = (args.get(index + 1), args.get(index + 2)) else {
+            return Err(format!(
+                "Usage: --create-api-key <name> <scopes>, with scopes comma-separated from: {}",
+                api::auth::SCOPES.join(", ")
+            )
+            .into());
+        };
+        let scopes: Vec<String> = scopes.split(',').map(|s| s.trim().to_string()).collect();
+        let created = api::auth::create_api_key(&db, name, &scopes).await?;
+        println!("{}", created.key);
+        return Ok(());
+    }
 
-    // Start web server in background
     let server_port = std::env::var("SERVER_PORT")
         .unwrap_or_else(|_| "3000".to_string())
         .parse::<u16>()
         .unwrap_or(3000);
 
-    tokio::spawn(async move {
-        start_web_server(db_for_server, server_port).await;
-    });
+    // Start the mock LINE API when requested (development / CI only)
+    #[cfg(feature = "line-mock")]
+    if let Ok(mock_port) = std::env::var("LINE_MOCK_PORT") {
+        let mock_port = mock_port.parse::<u16>().unwrap_or(3001);
+        let mock_addr = api::line_mock::MockLineServer::new()
+            .spawn(SocketAddr::from(([127, 0, 0, 1], mock_port)))
+            .await?;
+        tracing::info!("Set LINE_API_BASE_URL=http://{} to use the mock LINE API", mock_addr);
+    }
+
+    // Start the mock Slack API when requested; it delivers its events to our webhook server
+    #[cfg(feature = "slack-mock")]
+    if let Ok(mock_port) = std::env::var("SLACK_MOCK_PORT") {
+        let mock_port = mock_port.parse::<u16>().unwrap_or(3002);
+        let events_url = std::env::var("SLACK_MOCK_EVENTS_URL")
+            .unwrap_or_else(|_| format!("http://127.0.0.1:{}/webhook/slack", server_port));
+        let signing_secret = settings::get(&db, settings::SLACK_SIGNING_SECRET).await?;
+        let mock_addr = api::slack_mock::MockSlackServer::new(events_url, signing_secret)
+            .spawn(SocketAddr::from(([127, 0, 0, 1], mock_port)))
+            .await?;
+        tracing::info!("Set SLACK_API_BASE_URL=http://{}/api to use the mock Slack API", mock_addr);
+    }
+
+    if args.iter().any(|arg| arg == "--headless") {
+        return run_headless(db, server_port).await;
+    }
+
+    // The desktop app runs the webhook server and scheduler itself, unless
+    // a headless server on the same database does
+    let embedded_server = std::env::var("EMBEDDED_SERVER")
+        .map(|v| v != "false" && v != "0")
+        .unwrap_or(true);
+
+    let mut scheduler = if embedded_server {
+        let db_for_server = db.clone();
+        tokio::spawn(async move {
+            if let Err(e) = start_web_server(db_for_server, server_port, std::future::pending()).await {
+                tracing::error!("Web server failed: {}", e);
+            }
+        });
+
+        Some(SchedulerOwner::spawn(db.clone(), "desktop"))
+    } else {
+        tracing::info!("Embedded server disabled; webhooks and scheduled messages are handled by the headless server");
+        // Its inbox changes reach the UI through the database
+        tokio::spawn(inbox::watch_database(db.clone()));
+        None
+    };
 
     // Build Tauri app
-    tauri::Builder::default()
+    let app = tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
-        .manage(commands::AppState { db: db_for_tauri })
+        .manage(commands::AppState { db })
         .invoke_handler(tauri::generate_handler![
             // User commands
             commands::get_users,
             commands::get_user_by_line_id,
+            commands::get_follow_history,
+            commands::refresh_user_profile,
+            commands::refresh_stale_profiles,
             commands::delete_user,
             // Message commands
             commands::get_messages,
             commands::get_messages_by_user,
             commands::delete_message,
+            commands::get_message_media,
+            commands::download_message_media,
+            // Inbox commands
+            commands::get_conversations,
+            commands::mark_conversation_read,
+            commands::set_conversation_status,
+            commands::assign_conversation,
+            commands::reply_to_conversation,
+            // Webhook event commands
+            commands::get_events,
             // Scheduled message commands
             commands::create_scheduled_message,
             commands::get_scheduled_messages,
+            commands::get_scheduled_message_runs,
             commands::cancel_scheduled_message,
+            // Auto-reply commands
+            commands::create_auto_reply_rule,
+            commands::update_auto_reply_rule,
+            commands::delete_auto_reply_rule,
+            commands::get_auto_reply_rules,
+            commands::get_auto_reply_logs,
+            commands::test_auto_reply,
             // Calendar commands
             commands::create_calendar_event,
             commands::get_calendar_events,
@@ -88,18 +184,77 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             commands::get_setting,
             commands::set_setting,
             commands::get_all_settings,
+            commands::get_setting_definitions,
+            // Connection test commands
+            commands::test_line_connection,
+            commands::test_slack_bridge_connection,
+            commands::test_integration_connection,
+            // Notification channel commands
+            commands::get_notification_channels,
+            commands::create_notification_channel,
+            commands::update_notification_channel,
+            commands::delete_notification_channel,
+            commands::test_notification_channel,
+            // Notification log commands
+            commands::get_notification_logs,
+            commands::get_notification_log_counts,
+            commands::resend_notification,
+            // Notification rule commands
+            commands::get_notification_rules,
+            commands::create_notification_rule,
+            commands::update_notification_rule,
+            commands::delete_notification_rule,
+            // Message template commands
+            commands::get_message_templates,
+            commands::create_message_template,
+            commands::update_message_template,
+            commands::delete_message_template,
+            commands::preview_message_template,
+            commands::get_user_attributes,
+            commands::set_user_attribute,
+            // API key commands
+            commands::create_api_key,
+            commands::get_api_keys,
+            commands::revoke_api_key,
+            // Welcome message commands
+            commands::get_welcome_messages,
+            commands::set_welcome_messages,
             // Analytics commands
             commands::get_dashboard_stats,
             commands::get_user_stats,
             // LINE messaging commands
             commands::send_message_to_user,
             commands::broadcast_message,
+            commands::multicast_message,
+            commands::narrowcast_message,
+            commands::get_narrowcast_progress,
+            // Rich menu commands
+            commands::create_rich_menu,
+            commands::upload_rich_menu_image,
+            commands::get_rich_menus,
+            commands::get_line_rich_menus,
+            commands::delete_rich_menu,
+            commands::set_default_rich_menu,
+            commands::clear_default_rich_menu,
+            commands::link_rich_menu_to_user,
+            commands::unlink_rich_menu_from_user,
+            commands::get_user_rich_menu_id,
+            commands::link_rich_menu_to_users,
+            commands::unlink_rich_menu_from_users,
+            commands::set_rich_menu_alias,
+            commands::get_rich_menu_aliases,
+            commands::delete_rich_menu_alias,
             // External integration commands
             commands::sync_to_notion,
             commands::sync_to_airtable,
             commands::sync_to_google_sheets,
         ])
         .setup(|app| {
+            tauri::async_runtime::spawn(forward_inbox_events(app.handle().clone()));
+
+            // Received media is shown from disk through the asset protocol
+            app.asset_protocol_scope().allow_directory(media::MediaStore::from_env().root(), true)?;
+
             #[cfg(debug_assertions)]
             {
                 let window = app.get_webview_window("main").unwrap();
@@ -107,23 +262,106 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application");
+
+    // Stop the scheduler when the app exits and release its lease, so a
+    // headless server can take over right away instead of after the lease expires
+    let runtime = tokio::runtime::Handle::current();
+    app.run(move |_, event| {
+        if let tauri::RunEvent::ExitRequested { .. } | tauri::RunEvent::Exit = event {
+            if let Some(scheduler) = scheduler.take() {
+                tracing::info!("Shutting down");
+                tokio::task::block_in_place(|| runtime.block_on(scheduler.shutdown()));
+            }
+        }
+    });
 
     Ok(())
 }
 
-async fn start_web_server(db: sqlx::SqlitePool, port: u16) {
+/// Emit inbox changes to the UI as `inbox-event`, so new messages show up
+/// without polling
+async fn forward_inbox_events(app: tauri::AppHandle) {
+    let mut events = inbox::subscribe();
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                if let Err(e) = app.emit("inbox-event", event) {
+                    tracing::error!("Failed to emit inbox event: {}", e);
+                }
+            }
+            Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                tracing::warn!("Inbox view fell behind, {} events dropped", skipped);
+            }
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Run without a window: database, scheduler and webhook server, until
+/// SIGTERM or Ctrl-C
+async fn run_headless(db: sqlx::SqlitePool, port: u16) -> Result<(), Box<dyn std::error::Error>> {
+    tracing::info!("Running headless");
+
+    let scheduler = SchedulerOwner::spawn(db.clone(), "headless");
+
+    // Requests in flight are finished before the server stops
+    let result = start_web_server(db.clone(), port, shutdown_signal()).await;
+
+    tracing::info!("Shutting down");
+    scheduler.shutdown().await;
+    db.close().await;
+
+    result?;
+    Ok(())
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("Received Ctrl-C"),
+        _ = terminate => tracing::info!("Received SIGTERM"),
+    }
+}
+
+async fn start_web_server(
+    db: sqlx::SqlitePool,
+    port: u16,
+    shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+) -> std::io::Result<()> {
+    // The Slack bridge posts the messages this server receives
+    slack_bridge::spawn(db.clone());
+
     let app = api::create_router(db);
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
 
     tracing::info!("Web server starting on http://{}", addr);
 
-    let listener = tokio::net::TcpListener::bind(addr)
-        .await
-        .expect("Failed to bind server");
+    let listener = tokio::net::TcpListener::bind(addr).await?;
 
     axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown)
         .await
-        .expect("Failed to start server");
 }
@@ -0,0 +1,278 @@
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use crate::api::line_client::{LineClient, MessageContent};
+use crate::db::models::Message;
+
+/// How often to ask LINE whether a video or audio message is ready
+const TRANSCODING_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Give up waiting for transcoding after this long
+const TRANSCODING_TIMEOUT: Duration = Duration::from_secs(600);
+/// Largest external content we download (LINE's limit for videos and audio)
+const EXTERNAL_CONTENT_MAX_BYTES: u64 = 200 * 1024 * 1024;
+
+/// Content-addressed store for media users send to the account. Files are
+/// named by the SHA-256 of their bytes, so the same image sent twice is kept once.
+#[derive(Debug, Clone)]
+pub struct MediaStore {
+    root: PathBuf,
+}
+
+/// Where a piece of content ended up in the store
+#[derive(Debug, Clone)]
+pub struct StoredMedia {
+    /// Path relative to the store root
+    pub path: String,
+    pub sha256: String,
+    pub size: u64,
+    pub mime_type: String,
+}
+
+impl MediaStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// The `MEDIA_DIR` environment variable, or `media` next to the
+    /// database (`DATABASE_PATH`), or `media` under the app data dir
+    pub fn from_env() -> Self {
+        let env = |name| std::env::var(name).ok().filter(|value: &String| !value.is_empty());
+        let root = if let Some(dir) = env("MEDIA_DIR") {
+            PathBuf::from(dir)
+        } else if let Some(db_path) = env("DATABASE_PATH") {
+            Path::new(&db_path)
+                .parent()
+                .unwrap_or_else(|| Path::new("."))
+                .join("media")
+        } else {
+            let mut path = dirs::data_local_dir().unwrap_or_default();
+            path.push("line_admin_app");
+            path.push("media");
+            path
+        };
+        Self::new(root)
+    }
+
+    /// Write content to the store (unless identical content is already there)
+    pub async fn store(
+        &self,
+        bytes: &[u8],
+        mime_type: &str,
+        file_name: Option<&str>,
+    ) -> Result<StoredMedia, std::io::Error> {
+        let sha256 = format!("{:x}", Sha256::digest(bytes));
+        let extension = file_name
+            .and_then(|name| Path::new(name).extension())
+            .and_then(|ext| ext.to_str())
+            .filter(|ext| ext.len() <= 8 && ext.chars().all(|c| c.is_ascii_alphanumeric()))
+            .map(|ext| ext.to_ascii_lowercase())
+            .unwrap_or_else(|| extension_for(mime_type).to_string());
+
+        let relative = format!("{}/{}/{}.{}", &sha256[..2], &sha256[2..4], sha256, extension);
+        let path = self.root.join(&relative);
+
+        if tokio::fs::metadata(&path).await.is_err() {
+            if let Some(dir) = path.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            // Write to a temporary name first so a crash never leaves a partial file
+            let temp = path.with_extension(format!("{}.tmp", extension));
+            tokio::fs::write(&temp, bytes).await?;
+            tokio::fs::rename(&temp, &path).await?;
+        }
+
+        Ok(StoredMedia {
+            path: relative,
+            sha256,
+            size: bytes.len() as u64,
+            mime_type: mime_type.to_string(),
+        })
+    }
+
+    /// Directory the files are stored in
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Absolute path for a stored file, refusing anything outside the store
+    pub fn resolve(&self, relative: &str) -> Option<PathBuf> {
+        let relative = Path::new(relative);
+        if relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return None;
+        }
+        Some(self.root.join(relative))
+    }
+}
+
+/// File extension for a MIME type, for naming stored files
+fn extension_for(mime_type: &str) -> &'static str {
+    match mime_type {
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "video/mp4" => "mp4",
+        "video/quicktime" => "mov",
+        "audio/m4a" | "audio/x-m4a" | "audio/mp4" => "m4a",
+        "audio/mpeg" => "mp3",
+        "audio/aac" => "aac",
+        "application/pdf" => "pdf",
+        "application/zip" => "zip",
+        "text/plain" => "txt",
+        _ => "bin",
+    }
+}
+
+/// Best guess at a MIME type when the response didn't say
+fn sniff_mime_type(bytes: &[u8], message_type: &str) -> &'static str {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        "image/jpeg"
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if bytes.starts_with(b"GIF8") {
+        "image/gif"
+    } else if bytes.len() > 8 && &bytes[4..8] == b"ftyp" {
+        if message_type == "audio" { "audio/m4a" } else { "video/mp4" }
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// Download the content of a stored image/video/audio/file message into the
+/// media store and record where it went in the message's `message_data`.
+/// Failures are recorded there too, so the download can be retried later.
+pub async fn download_message_content(db: &SqlitePool, store: &MediaStore, message_id: i64) -> Result<(), anyhow::Error> {
+    let message = Message::find_by_id(db, message_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Message {} not found", message_id))?;
+
+    let mut data: serde_json::Map<String, serde_json::Value> = message
+        .message_data
+        .as_deref()
+        .and_then(|d| serde_json::from_str(d).ok())
+        .unwrap_or_default();
+
+    let result = fetch_and_store(db, store, &message, &data).await;
+    match &result {
+        Ok(stored) => {
+            tracing::info!(
+                "Stored {} content of message {} at {} ({} bytes)",
+                message.message_type,
+                message_id,
+                stored.path,
+                stored.size
+            );
+            data.insert("status".into(), "downloaded".into());
+            data.insert("path".into(), stored.path.clone().into());
+            data.insert("sha256".into(), stored.sha256.clone().into());
+            data.insert("mimeType".into(), stored.mime_type.clone().into());
+            data.insert("size".into(), stored.size.into());
+            data.remove("error");
+        }
+        Err(e) => {
+            tracing::error!("Failed to download content of message {}: {}", message_id, e);
+            data.insert("status".into(), "failed".into());
+            data.insert("error".into(), e.to_string().into());
+        }
+    }
+
+    Message::update_data(db, message_id, &serde_json::Value::Object(data).to_string()).await?;
+    result.map(|_| ())
+}
+
+async fn fetch_and_store(
+    db: &SqlitePool,
+    store: &MediaStore,
+    message: &Message,
+    data: &serde_json::Map<String, serde_json::Value>,
+) -> Result<StoredMedia, anyhow::Error> {
+    let provider = data.get("contentProvider");
+    let is_external = provider.and_then(|p| p.get("type")).and_then(|t| t.as_str()) == Some("external");
+
+    let content = if is_external {
+        // Content hosted by the sender's service, e.g. images sent through LIFF
+        let url = provider
+            .and_then(|p| p.get("originalContentUrl"))
+            .and_then(|u| u.as_str())
+            .ok_or_else(|| anyhow::anyhow!("External content has no originalContentUrl"))?;
+        fetch_external(url).await?
+    } else {
+        let line_message_id = message
+            .line_message_id
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Message {} has no LINE message ID", message.id))?;
+        let client = LineClient::from_settings(db).await?;
+        fetch_from_line(&client, line_message_id, &message.message_type).await?
+    };
+
+    let mime_type = content
+        .content_type
+        .filter(|t| t != "application/octet-stream")
+        .unwrap_or_else(|| sniff_mime_type(&content.bytes, &message.message_type).to_string());
+    let file_name = data.get("fileName").and_then(|n| n.as_str());
+
+    Ok(store.store(&content.bytes, &mime_type, file_name).await?)
+}
+
+/// Download from LINE, waiting for video/audio transcoding to finish first
+async fn fetch_from_line(
+    client: &LineClient,
+    line_message_id: &str,
+    message_type: &str,
+) -> Result<MessageContent, anyhow::Error> {
+    let deadline = tokio::time::Instant::now() + TRANSCODING_TIMEOUT;
+
+    if matches!(message_type, "video" | "audio") {
+        loop {
+            match client.get_content_transcoding_status(line_message_id).await?.as_str() {
+                "succeeded" => break,
+                "failed" => return Err(anyhow::anyhow!("LINE failed to transcode the {}", message_type)),
+                status => tracing::debug!("Content of {} is {}, waiting", line_message_id, status),
+            }
+            if tokio::time::Instant::now() + TRANSCODING_POLL_INTERVAL > deadline {
+                return Err(anyhow::anyhow!("Timed out waiting for LINE to transcode the {}", message_type));
+            }
+            tokio::time::sleep(TRANSCODING_POLL_INTERVAL).await;
+        }
+    }
+
+    loop {
+        if let Some(content) = client.get_message_content(line_message_id).await? {
+            return Ok(content);
+        }
+        if tokio::time::Instant::now() + TRANSCODING_POLL_INTERVAL > deadline {
+            return Err(anyhow::anyhow!("Timed out waiting for LINE to prepare the content"));
+        }
+        tokio::time::sleep(TRANSCODING_POLL_INTERVAL).await;
+    }
+}
+
+/// Download content from the sender's server, up to `EXTERNAL_CONTENT_MAX_BYTES`
+async fn fetch_external(url: &str) -> Result<MessageContent, anyhow::Error> {
+    let mut response = reqwest::get(url).await?.error_for_status()?;
+    if response.content_length().is_some_and(|length| length > EXTERNAL_CONTENT_MAX_BYTES) {
+        anyhow::bail!("External content is larger than {} bytes", EXTERNAL_CONTENT_MAX_BYTES);
+    }
+
+    let content_type = response
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or(v).trim().to_string());
+
+    // The length header may be missing or wrong, so count while reading
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if (bytes.len() + chunk.len()) as u64 > EXTERNAL_CONTENT_MAX_BYTES {
+            anyhow::bail!("External content is larger than {} bytes", EXTERNAL_CONTENT_MAX_BYTES);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(MessageContent { bytes, content_type })
+}
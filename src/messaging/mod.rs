@@ -0,0 +1,286 @@
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::api::line_client::{
+    new_retry_key, LineApiError, LineClient, Message as LineMessage, NarrowcastFilter, NarrowcastLimit,
+    Recipient, MULTICAST_MAX_RECIPIENTS,
+};
+use crate::api::line_message::validate_messages;
+use crate::db::models::{Message, User};
+use crate::inbox;
+
+/// Who an outbound message came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Sender {
+    /// Sent by hand from the admin app
+    Admin,
+    /// Sent automatically in response to an event, e.g. the welcome message
+    Bot,
+    /// Scheduled messages and calendar reminders
+    Scheduler,
+    /// Auto-reply rules
+    Rule,
+    /// Replies relayed from a Slack thread by the Slack bridge
+    Slack,
+}
+
+impl Sender {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Sender::Admin => "admin",
+            Sender::Bot => "bot",
+            Sender::Scheduler => "scheduler",
+            Sender::Rule => "rule",
+            Sender::Slack => "slack",
+        }
+    }
+}
+
+/// Messages for a send command or API request: the full message objects if given, otherwise
+/// a single text message. Validated against LINE's limits either way.
+pub fn compose_messages(
+    message_text: Option<String>,
+    messages: Option<Vec<LineMessage>>,
+) -> Result<Vec<LineMessage>, String> {
+    let messages = match (messages, message_text) {
+        (Some(messages), _) => messages,
+        (None, Some(text)) => vec![LineMessage::text(text)],
+        (None, None) => return Err("No message to send".to_string()),
+    };
+
+    validate_messages(&messages)?;
+    Ok(messages)
+}
+
+// Each function sends through the LINE client and then stores what was sent,
+// with its outcome, in `messages`. A failure to store is logged rather than
+// returned, since the send itself already happened (or failed) by then.
+
+/// Push to one user
+pub async fn push(
+    db: &SqlitePool,
+    client: &LineClient,
+    sender: Sender,
+    line_user_id: &str,
+    messages: Vec<LineMessage>,
+    retry_key: Option<&str>,
+) -> Result<Option<String>, LineApiError> {
+    let retry_key = retry_key.map(str::to_string).unwrap_or_else(new_retry_key);
+    let result = client
+        .push_message_with_retry_key(line_user_id, messages.clone(), &retry_key)
+        .await;
+
+    record(db, Some(line_user_id), &messages, sender, "push", Some(&retry_key), &result).await;
+    result
+}
+
+/// Reply to an event with its reply token
+pub async fn reply(
+    db: &SqlitePool,
+    client: &LineClient,
+    sender: Sender,
+    line_user_id: &str,
+    reply_token: &str,
+    messages: Vec<LineMessage>,
+) -> Result<Option<String>, LineApiError> {
+    let result = client.reply_message(reply_token, messages.clone()).await;
+
+    record(db, Some(line_user_id), &messages, sender, "reply", None, &result).await;
+    result
+}
+
+/// Send to many users. Each recipient gets their own copy in `messages`.
+/// Fails with the first failed chunk's error, after every chunk was tried.
+pub async fn multicast(
+    db: &SqlitePool,
+    client: &LineClient,
+    sender: Sender,
+    line_user_ids: &[String],
+    messages: Vec<LineMessage>,
+    retry_key: Option<&str>,
+) -> Result<Vec<Option<String>>, LineApiError> {
+    let retry_key = retry_key.map(str::to_string).unwrap_or_else(new_retry_key);
+    let results = client
+        .multicast_message_with_retry_key(line_user_ids, messages.clone(), &retry_key)
+        .await;
+
+    // Recipients are sent in chunks, each with its own outcome and request ID
+    for (chunk, result) in line_user_ids.chunks(MULTICAST_MAX_RECIPIENTS).zip(&results) {
+        for line_user_id in chunk {
+            let outcome = match result {
+                Ok(request_id) => Outcome::sent(request_id.clone()),
+                Err(e) => Outcome::failed(e),
+            };
+            record_outcome(db, Some(line_user_id), &messages, sender, "multicast", Some(&retry_key), outcome).await;
+        }
+    }
+
+    results.into_iter().collect()
+}
+
+/// Send to every follower
+pub async fn broadcast(
+    db: &SqlitePool,
+    client: &LineClient,
+    sender: Sender,
+    messages: Vec<LineMessage>,
+    retry_key: Option<&str>,
+) -> Result<Option<String>, LineApiError> {
+    let retry_key = retry_key.map(str::to_string).unwrap_or_else(new_retry_key);
+    let result = client.broadcast_message_with_retry_key(messages.clone(), &retry_key).await;
+
+    record(db, None, &messages, sender, "broadcast", Some(&retry_key), &result).await;
+    result
+}
+
+/// Start a narrowcast. Returns its request ID for progress lookups.
+#[allow(clippy::too_many_arguments)]
+pub async fn narrowcast(
+    db: &SqlitePool,
+    client: &LineClient,
+    sender: Sender,
+    messages: Vec<LineMessage>,
+    recipient: Option<Recipient>,
+    filter: Option<NarrowcastFilter>,
+    limit: Option<NarrowcastLimit>,
+    retry_key: Option<&str>,
+) -> Result<String, LineApiError> {
+    let retry_key = retry_key.map(str::to_string).unwrap_or_else(new_retry_key);
+    let result = client
+        .narrowcast_message_with_retry_key(messages.clone(), recipient, filter, limit, &retry_key)
+        .await;
+
+    let outcome = match &result {
+        Ok(request_id) => Outcome::sent(Some(request_id.clone()).filter(|id| !id.is_empty())),
+        Err(e) => Outcome::failed(e),
+    };
+    record_outcome(db, None, &messages, sender, "narrowcast", Some(&retry_key), outcome).await;
+    result
+}
+
+/// How a send went, as stored with the message
+struct Outcome {
+    request_id: Option<String>,
+    error: Option<String>,
+}
+
+impl Outcome {
+    fn sent(request_id: Option<String>) -> Self {
+        Self { request_id, error: None }
+    }
+
+    fn failed(error: &LineApiError) -> Self {
+        Self {
+            request_id: error.request_id().map(str::to_string),
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+async fn record(
+    db: &SqlitePool,
+    line_user_id: Option<&str>,
+    messages: &[LineMessage],
+    sender: Sender,
+    send_method: &str,
+    retry_key: Option<&str>,
+    result: &Result<Option<String>, LineApiError>,
+) {
+    let outcome = match result {
+        Ok(request_id) => Outcome::sent(request_id.clone()),
+        Err(e) => Outcome::failed(e),
+    };
+    record_outcome(db, line_user_id, messages, sender, send_method, retry_key, outcome).await;
+}
+
+async fn record_outcome(
+    db: &SqlitePool,
+    line_user_id: Option<&str>,
+    messages: &[LineMessage],
+    sender: Sender,
+    send_method: &str,
+    retry_key: Option<&str>,
+    outcome: Outcome,
+) {
+    if let Err(e) = store(db, line_user_id, messages, sender, send_method, retry_key, outcome).await {
+        tracing::error!("Failed to store outbound {} message: {}", send_method, e);
+    }
+}
+
+async fn store(
+    db: &SqlitePool,
+    line_user_id: Option<&str>,
+    messages: &[LineMessage],
+    sender: Sender,
+    send_method: &str,
+    retry_key: Option<&str>,
+    outcome: Outcome,
+) -> Result<(), anyhow::Error> {
+    if let Some(line_user_id) = line_user_id {
+        User::ensure_exists(db, line_user_id).await?;
+    }
+
+    let message_type = match messages {
+        [single] => message_type(single)?,
+        _ => "multiple".to_string(),
+    };
+    let message_text = messages.iter().map(|m| m.preview_text()).collect::<Vec<_>>().join("\n");
+    let message_data = serde_json::to_string(messages)?;
+
+    let status = if outcome.error.is_some() { "failed" } else { "sent" };
+
+    // Retries of a send reuse its retry key, and update the message stored
+    // for the earlier attempt, so a send retried for hours is stored once
+    let earlier = match retry_key {
+        Some(retry_key) => Message::find_outbound_by_retry_key(db, retry_key, line_user_id).await?,
+        None => None,
+    };
+    let message_id = match earlier {
+        Some(message_id) => {
+            Message::update_outbound(
+                db,
+                message_id,
+                &message_text,
+                &message_data,
+                outcome.request_id.as_deref(),
+                status,
+                outcome.error.as_deref(),
+            )
+            .await?;
+            message_id
+        }
+        None => {
+            Message::create_outbound(
+                db,
+                line_user_id,
+                &message_type,
+                &message_text,
+                &message_data,
+                sender.as_str(),
+                send_method,
+                outcome.request_id.as_deref(),
+                status,
+                outcome.error.as_deref(),
+                retry_key,
+            )
+            .await?
+        }
+    };
+
+    if line_user_id.is_some() {
+        inbox::record_outbound(db, message_id, sender).await?;
+    }
+
+    Ok(())
+}
+
+/// The `type` field of a message object, e.g. `text` or `flex`
+fn message_type(message: &LineMessage) -> Result<String, serde_json::Error> {
+    let value = serde_json::to_value(message)?;
+    Ok(value
+        .get("type")
+        .and_then(|t| t.as_str())
+        .unwrap_or("unknown")
+        .to_string())
+}
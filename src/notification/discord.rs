@@ -0,0 +1,46 @@
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{http_client, send_request, truncate, Notification, Notifier};
+
+/// Discord's limits for an embed's title and description
+const TITLE_MAX_CHARS: usize = 256;
+const DESCRIPTION_MAX_CHARS: usize = 4096;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscordConfig {
+    /// Channel webhook URL (https://discord.com/api/webhooks/...)
+    pub webhook_url: String,
+}
+
+pub struct DiscordNotifier {
+    client: Client,
+    config: DiscordConfig,
+}
+
+impl DiscordNotifier {
+    pub fn new(config: DiscordConfig) -> Self {
+        Self {
+            client: http_client(),
+            config,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for DiscordNotifier {
+    async fn send(&self, notification: &Notification) -> Result<(), anyhow::Error> {
+        let payload = json!({
+            "embeds": [{
+                "title": truncate(&notification.title, TITLE_MAX_CHARS),
+                "description": truncate(&notification.text, DESCRIPTION_MAX_CHARS),
+            }],
+            // Don't ping anyone mentioned in a forwarded message
+            "allowed_mentions": { "parse": [] },
+        });
+
+        let request = self.client.post(&self.config.webhook_url).json(&payload);
+        send_request("Discord", request).await
+    }
+}
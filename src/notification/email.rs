@@ -0,0 +1,95 @@
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+
+use super::{Notification, Notifier, REQUEST_TIMEOUT};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Upgrade with STARTTLS (usually port 587)
+    #[default]
+    Starttls,
+    /// TLS from the start (usually port 465)
+    Tls,
+    /// Unencrypted, e.g. a local relay
+    None,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailConfig {
+    pub host: String,
+    /// Defaults to the port of `security`
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+impl EmailConfig {
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        if self.host.trim().is_empty() {
+            anyhow::bail!("host is required");
+        }
+        if self.to.is_empty() {
+            anyhow::bail!("to needs at least one address");
+        }
+        for address in std::iter::once(&self.from).chain(&self.to) {
+            address
+                .parse::<Mailbox>()
+                .map_err(|e| anyhow::anyhow!("Invalid email address '{}': {}", address, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Sends a plain-text email over SMTP
+pub struct EmailNotifier {
+    config: EmailConfig,
+}
+
+impl EmailNotifier {
+    pub fn new(config: EmailConfig) -> Self {
+        Self { config }
+    }
+
+    fn transport(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>, anyhow::Error> {
+        let host = self.config.host.trim();
+        let mut builder = match self.config.security {
+            SmtpSecurity::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+        if let Some(port) = self.config.port {
+            builder = builder.port(port);
+        }
+        if let (Some(username), Some(password)) = (&self.config.username, &self.config.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        Ok(builder.timeout(Some(REQUEST_TIMEOUT)).build())
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for EmailNotifier {
+    async fn send(&self, notification: &Notification) -> Result<(), anyhow::Error> {
+        let mut message = Message::builder()
+            .from(self.config.from.parse()?)
+            .subject(notification.title.as_str())
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.config.to {
+            message = message.to(to.parse()?);
+        }
+        let message = message.body(notification.text.clone())?;
+
+        self.transport()?.send(message).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use super::{http_client, send_request, truncate, Notification, Notifier, REQUEST_TIMEOUT};
+use crate::api::line_client::{new_retry_key, LineClient, Message as LineMessage};
+
+/// LINE's limit for a text message
+const TEXT_MAX_CHARS: usize = 5000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinePushConfig {
+    /// Admins who have added the official account as a friend
+    pub line_user_ids: Vec<String>,
+}
+
+impl LinePushConfig {
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        if self.line_user_ids.is_empty() {
+            anyhow::bail!("line_user_ids needs at least one user");
+        }
+        Ok(())
+    }
+}
+
+/// Pushes notifications to admin LINE accounts through the Messaging API,
+/// from the official account itself. The pushes are not stored as messages,
+/// so alerts stay out of the admins' conversations in the inbox.
+pub struct LinePushNotifier {
+    db: SqlitePool,
+    config: LinePushConfig,
+}
+
+impl LinePushNotifier {
+    pub fn new(db: SqlitePool, config: LinePushConfig) -> Self {
+        Self { db, config }
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for LinePushNotifier {
+    async fn send(&self, notification: &Notification) -> Result<(), anyhow::Error> {
+        let client = LineClient::from_settings(&self.db).await?;
+        let text = truncate(&format!("{}\n{}", notification.title, notification.text), TEXT_MAX_CHARS);
+
+        // Queued notifications reuse their key, so LINE drops a retried push it
+        // already accepted
+        let retry_key = notification.retry_key.clone().unwrap_or_else(new_retry_key);
+        let sent = client.multicast_message_with_retry_key(&self.config.line_user_ids, vec![LineMessage::text(text)], &retry_key);
+        for result in tokio::time::timeout(REQUEST_TIMEOUT, sent)
+            .await
+            .map_err(|_| anyhow::anyhow!("LINE did not respond in time"))?
+        {
+            result?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LineNotifyConfig {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize)]
+struct LineNotifyPayload {
+    message: String,
+}
+
+/// LINE Notify, which ended in March 2025. Only channels carried over from
+/// earlier versions use it.
+pub struct LineNotifyNotifier {
+    client: Client,
+    config: LineNotifyConfig,
+}
+
+impl LineNotifyNotifier {
+    pub fn new(config: LineNotifyConfig) -> Self {
+        Self {
+            client: http_client(),
+            config,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for LineNotifyNotifier {
+    async fn send(&self, notification: &Notification) -> Result<(), anyhow::Error> {
+        let request = self
+            .client
+            .post("https://notify-api.line.me/api/notify")
+            .bearer_auth(&self.config.token)
+            .form(&LineNotifyPayload {
+                message: format!("{}\n{}", notification.title, notification.text),
+            });
+        send_request("LINE Notify", request).await
+    }
+}
@@ -1,129 +1,362 @@
-use reqwest::Client;
-use serde::Serialize;
+pub mod discord;
+pub mod email;
+pub mod line;
+pub mod outbox;
+pub mod rules;
+pub mod slack;
+pub mod teams;
+pub mod webhook;
+
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
-use crate::db::models::Setting;
+use std::time::Duration;
 
-#[derive(Debug, Serialize)]
-struct LineNotifyPayload {
-    message: String,
+use crate::db::models::{NotificationChannel, Setting};
+use crate::integrations::check_response;
+use crate::secrets::{self, MASK};
+
+/// How long a notifier waits for a chat service or mail server
+pub(crate) const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What a notification is about. Routing rules pick channels by kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    NewMessage,
+    NewFollower,
+    Unfollow,
+    /// The bot joined or left a group or room
+    BotMembership,
+    ScheduledSendFailed,
+    ReminderFailed,
 }
 
-#[derive(Debug, Serialize)]
-struct SlackPayload {
-    text: String,
+impl NotificationKind {
+    pub const ALL: &'static [NotificationKind] = &[
+        NotificationKind::NewMessage,
+        NotificationKind::NewFollower,
+        NotificationKind::Unfollow,
+        NotificationKind::BotMembership,
+        NotificationKind::ScheduledSendFailed,
+        NotificationKind::ReminderFailed,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NotificationKind::NewMessage => "new_message",
+            NotificationKind::NewFollower => "new_follower",
+            NotificationKind::Unfollow => "unfollow",
+            NotificationKind::BotMembership => "bot_membership",
+            NotificationKind::ScheduledSendFailed => "scheduled_send_failed",
+            NotificationKind::ReminderFailed => "reminder_failed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| kind.as_str() == value)
+    }
 }
 
-/// Send notifications to both LINE Notify and Slack
-pub async fn send_notifications(db: &SqlitePool, message: &str) {
-    // Send to LINE Notify
-    if let Err(e) = send_line_notify(db, message).await {
-        tracing::error!("Failed to send LINE Notify: {}", e);
+/// Something the admins should know about
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    /// None for test notifications and digests of several kinds
+    pub kind: Option<NotificationKind>,
+    pub title: String,
+    pub text: String,
+    /// The LINE user it's about, for per-user rate limits
+    pub line_user_id: Option<String>,
+    /// Retry key of a queued notification, the same for every attempt
+    #[serde(skip)]
+    pub retry_key: Option<String>,
+}
+
+impl Notification {
+    pub fn new(title: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            kind: None,
+            title: title.into(),
+            text: text.into(),
+            line_user_id: None,
+            retry_key: None,
+        }
     }
 
-    // Send to Slack
-    if let Err(e) = send_slack(db, message).await {
-        tracing::error!("Failed to send Slack notification: {}", e);
+    pub fn event(kind: NotificationKind, title: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            kind: Some(kind),
+            ..Self::new(title, text)
+        }
     }
+
+    pub fn about_user(mut self, line_user_id: impl Into<String>) -> Self {
+        self.line_user_id = Some(line_user_id.into());
+        self
+    }
+}
+
+/// A way of delivering notifications, configured by a `notification_channels` row
+#[async_trait::async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send(&self, notification: &Notification) -> Result<(), anyhow::Error>;
 }
 
-/// Send notification via LINE Notify
-pub async fn send_line_notify(db: &SqlitePool, message: &str) -> Result<(), anyhow::Error> {
-    // Get LINE Notify token from settings
-    let token = match Setting::get(db, "line_notify_token").await? {
-        Some(t) => t,
-        None => {
-            tracing::warn!("LINE Notify token not configured");
-            return Ok(());
+/// Settings of a channel, by kind. Stored as the channel's `kind` and its
+/// `config` JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "config", rename_all = "snake_case")]
+pub enum ChannelConfig {
+    Slack(slack::SlackConfig),
+    Discord(discord::DiscordConfig),
+    Teams(teams::TeamsConfig),
+    Email(email::EmailConfig),
+    Webhook(webhook::WebhookConfig),
+    LinePush(line::LinePushConfig),
+    /// LINE Notify ended in March 2025. Kept only for channels carried over
+    /// from the old `line_notify_token` setting, so they can be removed.
+    LineNotify(line::LineNotifyConfig),
+}
+
+pub const KINDS: &[&str] = &["slack", "discord", "teams", "email", "webhook", "line_push", "line_notify"];
+
+/// Config fields that are credentials: encrypted with the rest of the
+/// config, and masked when channels are listed
+const SECRET_FIELDS: &[&str] = &["webhook_url", "password", "secret", "token"];
+
+impl ChannelConfig {
+    fn parse(kind: &str, config: serde_json::Value) -> Result<Self, anyhow::Error> {
+        if !KINDS.contains(&kind) {
+            anyhow::bail!("Unknown notification channel kind '{}' (expected one of {:?})", kind, KINDS);
         }
-    };
+        serde_json::from_value(serde_json::json!({ "kind": kind, "config": config }))
+            .map_err(|e| anyhow::anyhow!("Invalid {} channel settings: {}", kind, e))
+    }
 
-    if token.is_empty() {
-        tracing::warn!("LINE Notify token is empty");
-        return Ok(());
+    fn validate(&self) -> Result<(), anyhow::Error> {
+        match self {
+            ChannelConfig::Slack(config) => check_url("webhook_url", &config.webhook_url),
+            ChannelConfig::Discord(config) => check_url("webhook_url", &config.webhook_url),
+            ChannelConfig::Teams(config) => check_url("webhook_url", &config.webhook_url),
+            ChannelConfig::Email(config) => config.validate(),
+            ChannelConfig::Webhook(config) => check_url("url", &config.url),
+            ChannelConfig::LinePush(config) => config.validate(),
+            ChannelConfig::LineNotify(_) => Ok(()),
+        }
     }
 
-    let client = Client::new();
-    let response = client
-        .post("https://notify-api.line.me/api/notify")
-        .header("Authorization", format!("Bearer {}", token))
-        .form(&LineNotifyPayload {
-            message: message.to_string(),
-        })
-        .send()
-        .await?;
+    fn notifier(self, db: &SqlitePool) -> Box<dyn Notifier> {
+        match self {
+            ChannelConfig::Slack(config) => Box::new(slack::SlackNotifier::new(config)),
+            ChannelConfig::Discord(config) => Box::new(discord::DiscordNotifier::new(config)),
+            ChannelConfig::Teams(config) => Box::new(teams::TeamsNotifier::new(config)),
+            ChannelConfig::Email(config) => Box::new(email::EmailNotifier::new(config)),
+            ChannelConfig::Webhook(config) => Box::new(webhook::WebhookNotifier::new(config)),
+            ChannelConfig::LinePush(config) => Box::new(line::LinePushNotifier::new(db.clone(), config)),
+            ChannelConfig::LineNotify(config) => Box::new(line::LineNotifyNotifier::new(config)),
+        }
+    }
+}
 
-    if response.status().is_success() {
-        tracing::info!("LINE Notify sent successfully");
-        log_notification(db, "line_notify", &token[..10], message, "success", None).await;
-    } else {
-        let error = format!("LINE Notify failed with status: {}", response.status());
-        tracing::error!("{}", error);
-        log_notification(db, "line_notify", &token[..10], message, "failed", Some(&error)).await;
-        return Err(anyhow::anyhow!(error));
+fn check_url(field: &str, value: &str) -> Result<(), anyhow::Error> {
+    match reqwest::Url::parse(value) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(()),
+        _ => anyhow::bail!("{} must be an http or https URL", field),
     }
+}
+
+/// HTTP client for notifiers. Requests give up after `REQUEST_TIMEOUT`, well
+/// within the outbox's claim on a notification.
+pub(crate) fn http_client() -> Client {
+    Client::builder().timeout(REQUEST_TIMEOUT).build().unwrap_or_default()
+}
 
+/// Send a request to a chat service and fail on a non-success response
+pub(crate) async fn send_request(service: &str, request: RequestBuilder) -> Result<(), anyhow::Error> {
+    check_response(service, request.send().await?).await?;
     Ok(())
 }
 
-/// Send notification via Slack
-pub async fn send_slack(db: &SqlitePool, message: &str) -> Result<(), anyhow::Error> {
-    // Get Slack webhook URL from settings
-    let webhook_url = match Setting::get(db, "slack_webhook_url").await? {
-        Some(url) => url,
-        None => {
-            tracing::warn!("Slack webhook URL not configured");
-            return Ok(());
+/// Shorten text to a service's length limit, marking the cut
+pub(crate) fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Send a notification to the channels of the routing rules for its kind,
+/// or to every enabled channel when its kind has no rules. Failed sends are
+/// retried per channel and don't hold up the others.
+pub async fn send_notifications(db: &SqlitePool, notification: &Notification) {
+    if let Some(kind) = notification.kind {
+        match rules::route(db, kind, notification).await {
+            Ok(true) => return,
+            Ok(false) => {}
+            Err(e) => {
+                tracing::error!("Failed to route {} notification: {}", kind.as_str(), e);
+                return;
+            }
+        }
+    }
+
+    let channels = match NotificationChannel::list_enabled(db).await {
+        Ok(channels) => channels,
+        Err(e) => {
+            tracing::error!("Failed to load notification channels: {}", e);
+            return;
         }
     };
 
-    if webhook_url.is_empty() {
-        tracing::warn!("Slack webhook URL is empty");
-        return Ok(());
+    for channel in channels {
+        deliver(db, &channel, notification).await;
     }
+}
+
+/// Send a notification through one channel via the outbox, which retries
+/// it until it's delivered
+pub(crate) async fn deliver(db: &SqlitePool, channel: &NotificationChannel, notification: &Notification) {
+    outbox::send(db, channel, notification).await;
+}
+
+async fn send_to_channel(
+    db: &SqlitePool,
+    channel: &NotificationChannel,
+    notification: &Notification,
+) -> Result<(), anyhow::Error> {
+    load_config(channel)?.notifier(db).send(notification).await
+}
+
+fn load_config(channel: &NotificationChannel) -> Result<ChannelConfig, anyhow::Error> {
+    let config = secrets::decrypt(&format!("notification channel {}", channel.id), &channel.config)?;
+    ChannelConfig::parse(&channel.kind, serde_json::from_str(&config)?)
+}
+
+/// A channel for display, with credentials masked
+#[derive(Debug, Serialize)]
+pub struct ChannelView {
+    pub id: i64,
+    pub name: String,
+    pub kind: String,
+    pub config: serde_json::Value,
+    pub enabled: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
 
-    let client = Client::new();
-    let response = client
-        .post(&webhook_url)
-        .json(&SlackPayload {
-            text: message.to_string(),
-        })
-        .send()
-        .await?;
+pub async fn list_channels(db: &SqlitePool) -> Result<Vec<ChannelView>, anyhow::Error> {
+    let mut views = Vec::new();
+    for channel in NotificationChannel::list_all(db).await? {
+        let mut config = serde_json::to_value(load_config(&channel)?)?["config"].take();
+        if let Some(fields) = config.as_object_mut() {
+            for (field, value) in fields.iter_mut() {
+                if SECRET_FIELDS.contains(&field.as_str()) && value.as_str().is_some_and(|v| !v.is_empty()) {
+                    *value = MASK.into();
+                }
+            }
+        }
 
-    if response.status().is_success() {
-        tracing::info!("Slack notification sent successfully");
-        log_notification(db, "slack", "webhook", message, "success", None).await;
-    } else {
-        let error = format!("Slack notification failed with status: {}", response.status());
-        tracing::error!("{}", error);
-        log_notification(db, "slack", "webhook", message, "failed", Some(&error)).await;
-        return Err(anyhow::anyhow!(error));
+        views.push(ChannelView {
+            id: channel.id,
+            name: channel.name,
+            kind: channel.kind,
+            config,
+            enabled: channel.enabled,
+            created_at: channel.created_at,
+            updated_at: channel.updated_at,
+        });
     }
+    Ok(views)
+}
 
-    Ok(())
+pub async fn create_channel(
+    db: &SqlitePool,
+    name: &str,
+    kind: &str,
+    config: serde_json::Value,
+    enabled: bool,
+) -> Result<i64, anyhow::Error> {
+    if name.trim().is_empty() {
+        anyhow::bail!("A notification channel needs a name");
+    }
+    let config = ChannelConfig::parse(kind, config)?;
+    config.validate()?;
+
+    Ok(NotificationChannel::create(db, name.trim(), kind, &encrypt_config(&config)?, enabled).await?)
 }
 
-async fn log_notification(
+/// Change a channel. Masked credentials in `config` keep their saved values.
+pub async fn update_channel(
     db: &SqlitePool,
-    notification_type: &str,
-    recipient: &str,
-    message: &str,
-    status: &str,
-    error_message: Option<&str>,
-) {
-    let result = sqlx::query(
-        "INSERT INTO notification_logs (notification_type, recipient, message, status, error_message)
-         VALUES (?, ?, ?, ?, ?)"
-    )
-    .bind(notification_type)
-    .bind(recipient)
-    .bind(message)
-    .bind(status)
-    .bind(error_message)
-    .execute(db)
-    .await;
-
-    if let Err(e) = result {
-        tracing::error!("Failed to log notification: {}", e);
+    id: i64,
+    name: &str,
+    mut config: serde_json::Value,
+    enabled: bool,
+) -> Result<(), anyhow::Error> {
+    if name.trim().is_empty() {
+        anyhow::bail!("A notification channel needs a name");
+    }
+    let channel = NotificationChannel::find_by_id(db, id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Notification channel {} not found", id))?;
+
+    let saved = serde_json::to_value(load_config(&channel)?)?["config"].take();
+    if let Some(fields) = config.as_object_mut() {
+        for (field, value) in fields.iter_mut() {
+            if value.as_str() == Some(MASK) {
+                *value = saved.get(field).cloned().unwrap_or_default();
+            }
+        }
+    }
+
+    let config = ChannelConfig::parse(&channel.kind, config)?;
+    config.validate()?;
+    NotificationChannel::update(db, id, name.trim(), &encrypt_config(&config)?, enabled).await?;
+    Ok(())
+}
+
+pub async fn delete_channel(db: &SqlitePool, id: i64) -> Result<(), anyhow::Error> {
+    NotificationChannel::delete(db, id).await?;
+    Ok(())
+}
+
+/// Send a test notification through one channel, enabled or not
+pub async fn test_channel(db: &SqlitePool, id: i64) -> Result<(), anyhow::Error> {
+    let channel = NotificationChannel::find_by_id(db, id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Notification channel {} not found", id))?;
+
+    let notification = Notification::new("テスト通知", format!("「{}」への通知のテストです。", channel.name));
+    send_to_channel(db, &channel, &notification).await
+}
+
+fn encrypt_config(config: &ChannelConfig) -> Result<String, anyhow::Error> {
+    let json = serde_json::to_value(config)?["config"].take();
+    Ok(secrets::encrypt(&json.to_string())?)
+}
+
+/// Move the fixed `slack_webhook_url` and `line_notify_token` settings of
+/// earlier versions into channels. LINE Notify channels start disabled since
+/// the service has ended.
+pub async fn migrate_legacy_settings(db: &SqlitePool) -> Result<(), anyhow::Error> {
+    let legacy = [
+        ("slack_webhook_url", "Slack", "slack", "webhook_url", true),
+        ("line_notify_token", "LINE Notify", "line_notify", "token", false),
+    ];
+
+    for (key, name, kind, field, enabled) in legacy {
+        let Some(stored) = Setting::get(db, key).await? else {
+            continue;
+        };
+        if !stored.is_empty() {
+            let value = secrets::decrypt(key, &stored)?;
+            let config = ChannelConfig::parse(kind, serde_json::json!({ field: value }))?;
+            NotificationChannel::create(db, name, kind, &encrypt_config(&config)?, enabled).await?;
+            tracing::info!("Moved setting {} to notification channel '{}'", key, name);
+        }
+        Setting::delete(db, key).await?;
     }
+    Ok(())
 }
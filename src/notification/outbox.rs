@@ -0,0 +1,171 @@
+use sqlx::SqlitePool;
+
+use super::{send_to_channel, Notification, NotificationKind};
+use crate::api::line_client::new_retry_key;
+use crate::db::models::{NotificationChannel, NotificationLog};
+
+// Every notification is written to `notification_logs` before it is sent,
+// so a chat service being down delays alerts instead of losing them. The
+// first attempt is made right away in the background, so a slow service
+// never holds up the caller (e.g. the webhook handler); failed ones are
+// retried by the scheduler's outbox job with exponential backoff, and given
+// up on ("dead") after MAX_ATTEMPTS, where they can be resent by hand.
+
+/// Attempts before a notification is dead (about an hour of retries)
+pub const MAX_ATTEMPTS: i64 = 8;
+const BASE_DELAY_SECONDS: i64 = 30;
+const MAX_DELAY_SECONDS: i64 = 60 * 60;
+/// How long an attempt in progress keeps others from taking the notification
+const CLAIM_SECONDS: i64 = 5 * 60;
+/// Notifications retried per run of the outbox job
+const BATCH_SIZE: i64 = 50;
+
+/// Delay before the next attempt after `attempts` failed ones: 30s, 1m, 2m, ... up to an hour
+pub fn backoff_seconds(attempts: i64) -> i64 {
+    let exponent = (attempts - 1).clamp(0, 16) as u32;
+    (BASE_DELAY_SECONDS * 2_i64.pow(exponent)).min(MAX_DELAY_SECONDS)
+}
+
+/// Queue a notification for a channel and start the first attempt, without
+/// waiting for it
+pub async fn send(db: &SqlitePool, channel: &NotificationChannel, notification: &Notification) {
+    let message = format!("{}\n{}", notification.title, notification.text);
+    let retry_key = new_retry_key();
+    let queued = NotificationLog::enqueue(
+        db,
+        channel,
+        notification.kind.map(NotificationKind::as_str),
+        &notification.title,
+        &notification.text,
+        notification.line_user_id.as_deref(),
+        &message,
+        &retry_key,
+        CLAIM_SECONDS,
+    )
+    .await;
+
+    let (db, channel) = (db.clone(), channel.clone());
+    let notification = Notification {
+        retry_key: Some(retry_key),
+        ..notification.clone()
+    };
+    tokio::spawn(async move {
+        match queued {
+            Ok(id) => attempt(&db, id, &channel, &notification).await,
+            Err(e) => {
+                // Still try to get the alert out, without a way to retry it
+                tracing::error!("Failed to queue notification for {}: {}", channel.name, e);
+                if let Err(e) = send_to_channel(&db, &channel, &notification).await {
+                    tracing::error!("Failed to send notification to {} ({}): {}", channel.name, channel.kind, e);
+                }
+            }
+        }
+    });
+}
+
+/// Send a claimed notification and record the outcome
+async fn attempt(db: &SqlitePool, id: i64, channel: &NotificationChannel, notification: &Notification) {
+    let result = match send_to_channel(db, channel, notification).await {
+        Ok(()) => {
+            tracing::info!("Notification {} sent to {} ({})", id, channel.name, channel.kind);
+            NotificationLog::mark_sent(db, id).await
+        }
+        Err(e) => {
+            let error = e.to_string();
+            let attempts = match NotificationLog::find_by_id(db, id).await {
+                Ok(log) => log.map(|log| log.attempts).unwrap_or_default() + 1,
+                Err(e) => {
+                    tracing::error!("Failed to load notification {}: {}", id, e);
+                    return;
+                }
+            };
+
+            if attempts >= MAX_ATTEMPTS {
+                tracing::error!(
+                    "Giving up on notification {} to {} after {} attempts: {}",
+                    id,
+                    channel.name,
+                    attempts,
+                    error
+                );
+                NotificationLog::mark_dead(db, id, &error).await
+            } else {
+                let delay = backoff_seconds(attempts);
+                tracing::warn!(
+                    "Failed to send notification {} to {} ({}), retrying in {}s: {}",
+                    id,
+                    channel.name,
+                    channel.kind,
+                    delay,
+                    error
+                );
+                NotificationLog::mark_retry(db, id, &error, delay).await
+            }
+        }
+    };
+
+    if let Err(e) = result {
+        tracing::error!("Failed to record delivery of notification {}: {}", id, e);
+    }
+}
+
+/// The notification a log row was queued for
+fn notification_of(log: &NotificationLog) -> Notification {
+    Notification {
+        kind: log.event_kind.as_deref().and_then(NotificationKind::parse),
+        line_user_id: log.line_user_id.clone(),
+        retry_key: log.retry_key.clone(),
+        ..Notification::new(log.title.clone().unwrap_or_default(), log.text.clone().unwrap_or_default())
+    }
+}
+
+/// Retry a queued notification now. Notifications whose channel is gone or
+/// disabled are dead right away.
+async fn retry(db: &SqlitePool, log: &NotificationLog) -> Result<(), anyhow::Error> {
+    if !NotificationLog::claim(db, log.id, CLAIM_SECONDS).await? {
+        return Ok(());
+    }
+
+    let Some(channel_id) = log.channel_id else {
+        NotificationLog::mark_dead(db, log.id, "Logged before the outbox; the channel is unknown").await?;
+        return Ok(());
+    };
+    match NotificationChannel::find_by_id(db, channel_id).await? {
+        Some(channel) if channel.enabled => attempt(db, log.id, &channel, &notification_of(log)).await,
+        Some(_) => NotificationLog::mark_dead(db, log.id, "The notification channel is disabled").await?,
+        None => NotificationLog::mark_dead(db, log.id, "The notification channel was deleted").await?,
+    }
+    Ok(())
+}
+
+/// Retry the notifications that are due. Returns how many were tried.
+pub async fn process_due(db: &SqlitePool) -> Result<usize, anyhow::Error> {
+    let due = NotificationLog::list_due(db, BATCH_SIZE).await?;
+    for log in &due {
+        if let Err(e) = retry(db, log).await {
+            tracing::error!("Failed to retry notification {}: {}", log.id, e);
+        }
+    }
+    Ok(due.len())
+}
+
+/// Send a notification again, e.g. a dead one after fixing its channel.
+/// Returns the notification after the attempt.
+pub async fn resend(db: &SqlitePool, id: i64) -> Result<NotificationLog, anyhow::Error> {
+    let log = NotificationLog::find_by_id(db, id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Notification {} not found", id))?;
+    if log.status == "pending" && log.attempts == 0 {
+        anyhow::bail!("Notification {} is still being sent", id);
+    }
+    if log.title.is_none() {
+        anyhow::bail!("Notification {} was logged before the outbox and can't be resent", id);
+    }
+
+    NotificationLog::requeue(db, id).await?;
+    retry(db, &log).await?;
+
+    NotificationLog::find_by_id(db, id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Notification {} not found", id))
+}
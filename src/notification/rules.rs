@@ -0,0 +1,314 @@
+use chrono::{Local, NaiveDateTime, NaiveTime, Utc};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+
+use super::{deliver, truncate, Notification, NotificationKind};
+use crate::db::models::{NotificationChannel, NotificationEvent, NotificationRule};
+
+/// Digest interval of rules that hold events only for quiet hours
+const DEFAULT_DIGEST_MINUTES: i64 = 15;
+/// Events listed in a digest; the rest are only counted
+const DIGEST_ITEMS: usize = 10;
+/// How long delivered events are kept for rate limiting
+const EVENT_RETENTION_DAYS: i64 = 7;
+
+/// A routing rule to create or save, as given by a command
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewNotificationRule {
+    pub name: String,
+    /// One of `NotificationKind::ALL`, e.g. `new_message`
+    pub event_kind: String,
+    pub channel_id: i64,
+    pub keywords: Option<Vec<String>>,
+    /// HH:MM local time
+    pub quiet_start: Option<String>,
+    pub quiet_end: Option<String>,
+    pub rate_limit: Option<i64>,
+    pub rate_window_minutes: Option<i64>,
+    pub digest_minutes: Option<i64>,
+    pub enabled: bool,
+}
+
+fn parse_time(field: &str, value: &str) -> Result<NaiveTime, anyhow::Error> {
+    NaiveTime::parse_from_str(value, "%H:%M").map_err(|_| anyhow::anyhow!("{} must be HH:MM, got '{}'", field, value))
+}
+
+/// Check a rule before it is saved. Returns its keywords as stored.
+async fn validate_rule(db: &SqlitePool, rule: &NewNotificationRule) -> Result<Option<String>, anyhow::Error> {
+    if rule.name.trim().is_empty() {
+        anyhow::bail!("A notification rule needs a name");
+    }
+    if NotificationKind::parse(&rule.event_kind).is_none() {
+        let kinds: Vec<&str> = NotificationKind::ALL.iter().map(|kind| kind.as_str()).collect();
+        anyhow::bail!("Unknown event kind '{}' (expected one of {:?})", rule.event_kind, kinds);
+    }
+    if NotificationChannel::find_by_id(db, rule.channel_id).await?.is_none() {
+        anyhow::bail!("Notification channel {} not found", rule.channel_id);
+    }
+
+    match (&rule.quiet_start, &rule.quiet_end) {
+        (Some(start), Some(end)) => {
+            if parse_time("quiet_start", start)? == parse_time("quiet_end", end)? {
+                anyhow::bail!("quiet_start and quiet_end must differ");
+            }
+        }
+        (None, None) => {}
+        _ => anyhow::bail!("Set both quiet_start and quiet_end, or neither"),
+    }
+
+    match (rule.rate_limit, rule.rate_window_minutes) {
+        (Some(limit), Some(minutes)) if limit < 1 || minutes < 1 => {
+            anyhow::bail!("rate_limit and rate_window_minutes must be at least 1")
+        }
+        (Some(_), Some(_)) | (None, None) => {}
+        _ => anyhow::bail!("Set both rate_limit and rate_window_minutes, or neither"),
+    }
+
+    if rule.digest_minutes.is_some_and(|minutes| minutes < 1) {
+        anyhow::bail!("digest_minutes must be at least 1");
+    }
+
+    let keywords: Vec<&str> = rule
+        .keywords
+        .iter()
+        .flatten()
+        .map(|keyword| keyword.trim())
+        .filter(|keyword| !keyword.is_empty())
+        .collect();
+    Ok(if keywords.is_empty() {
+        None
+    } else {
+        Some(serde_json::to_string(&keywords)?)
+    })
+}
+
+pub async fn create_rule(db: &SqlitePool, rule: NewNotificationRule) -> Result<i64, anyhow::Error> {
+    let keywords = validate_rule(db, &rule).await?;
+
+    Ok(NotificationRule::create(
+        db,
+        rule.name.trim(),
+        &rule.event_kind,
+        rule.channel_id,
+        keywords.as_deref(),
+        rule.quiet_start.as_deref(),
+        rule.quiet_end.as_deref(),
+        rule.rate_limit,
+        rule.rate_window_minutes,
+        rule.digest_minutes,
+        rule.enabled,
+    )
+    .await?)
+}
+
+pub async fn update_rule(db: &SqlitePool, id: i64, rule: NewNotificationRule) -> Result<(), anyhow::Error> {
+    let keywords = validate_rule(db, &rule).await?;
+
+    NotificationRule::update(
+        db,
+        id,
+        rule.name.trim(),
+        &rule.event_kind,
+        rule.channel_id,
+        keywords.as_deref(),
+        rule.quiet_start.as_deref(),
+        rule.quiet_end.as_deref(),
+        rule.rate_limit,
+        rule.rate_window_minutes,
+        rule.digest_minutes,
+        rule.enabled,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Whether `now` falls in the rule's quiet hours (never without them)
+fn in_quiet_hours(rule: &NotificationRule, now: NaiveTime) -> bool {
+    let (Some(start), Some(end)) = (&rule.quiet_start, &rule.quiet_end) else {
+        return false;
+    };
+    let (Ok(start), Ok(end)) = (parse_time("quiet_start", start), parse_time("quiet_end", end)) else {
+        return false;
+    };
+
+    if start <= end {
+        start <= now && now < end
+    } else {
+        // e.g. 22:00-07:00 covers the night
+        now >= start || now < end
+    }
+}
+
+/// Whether the notification text contains one of the rule's keywords (always
+/// true without keywords)
+fn matches_keywords(rule: &NotificationRule, notification: &Notification) -> bool {
+    let Some(keywords) = &rule.keywords else {
+        return true;
+    };
+    let keywords: Vec<String> = match serde_json::from_str(keywords) {
+        Ok(keywords) => keywords,
+        Err(e) => {
+            tracing::warn!("Notification rule {} has invalid keywords: {}", rule.id, e);
+            return false;
+        }
+    };
+
+    let text = notification.text.to_lowercase();
+    keywords.iter().any(|keyword| text.contains(&keyword.to_lowercase()))
+}
+
+/// Whether the rule already sent its limit of notifications about this user
+async fn rate_limited(
+    db: &SqlitePool,
+    rule: &NotificationRule,
+    notification: &Notification,
+) -> Result<bool, sqlx::Error> {
+    let (Some(limit), Some(minutes), Some(user_id)) =
+        (rule.rate_limit, rule.rate_window_minutes, notification.line_user_id.as_deref())
+    else {
+        return Ok(false);
+    };
+
+    Ok(NotificationEvent::count_sent_since(db, rule.id, user_id, minutes).await? >= limit)
+}
+
+/// Apply the routing rules of `kind`: send right away to each matching
+/// rule's channel, or hold the event for the rule's digest. Returns false
+/// when the kind has no enabled rules, so it should go to every channel.
+pub async fn route(
+    db: &SqlitePool,
+    kind: NotificationKind,
+    notification: &Notification,
+) -> Result<bool, anyhow::Error> {
+    let rules = NotificationRule::list_for_kind(db, kind.as_str()).await?;
+    if !rules.iter().any(|rule| rule.enabled) {
+        return Ok(false);
+    }
+
+    let now = Local::now().time();
+    let mut sent_to = Vec::new();
+
+    for rule in rules.iter().filter(|rule| rule.enabled) {
+        if !matches_keywords(rule, notification) {
+            continue;
+        }
+
+        let hold = rule.digest_minutes.is_some()
+            || in_quiet_hours(rule, now)
+            || rate_limited(db, rule, notification).await?;
+        let status = if hold { "held" } else { "sent" };
+        NotificationEvent::create(
+            db,
+            rule.id,
+            kind.as_str(),
+            notification.line_user_id.as_deref(),
+            &notification.title,
+            &notification.text,
+            status,
+        )
+        .await?;
+
+        // Several rules may route the same event to one channel
+        if hold || sent_to.contains(&rule.channel_id) {
+            continue;
+        }
+        sent_to.push(rule.channel_id);
+
+        match NotificationChannel::find_by_id(db, rule.channel_id).await? {
+            Some(channel) if channel.enabled => deliver(db, &channel, notification).await,
+            _ => tracing::debug!("Notification rule {} routes to a disabled channel", rule.id),
+        }
+    }
+
+    Ok(true)
+}
+
+/// Send the held events of every rule whose digest is due, outside its
+/// quiet hours, as one notification per rule. Returns the digests sent.
+pub async fn send_digests(db: &SqlitePool) -> Result<usize, anyhow::Error> {
+    NotificationEvent::delete_delivered_before(db, EVENT_RETENTION_DAYS).await?;
+
+    let now = Local::now().time();
+    let mut sent = 0;
+
+    for rule in NotificationRule::list_digest_due(db, DEFAULT_DIGEST_MINUTES).await? {
+        if in_quiet_hours(&rule, now) {
+            continue;
+        }
+        let events = NotificationEvent::list_held(db, rule.id).await?;
+        let Some(last) = events.last() else {
+            continue;
+        };
+
+        // Held events wait for the channel to be enabled again
+        let channel = match NotificationChannel::find_by_id(db, rule.channel_id).await? {
+            Some(channel) if channel.enabled => channel,
+            _ => continue,
+        };
+
+        deliver(db, &channel, &digest(&events)).await;
+        NotificationEvent::mark_digested(db, rule.id, last.id).await?;
+        NotificationRule::set_last_digest(db, rule.id).await?;
+        sent += 1;
+    }
+
+    Ok(sent)
+}
+
+/// One notification summing up held events, e.g. "12 new messages in the
+/// last 15 minutes", listing the first few
+fn digest(events: &[NotificationEvent]) -> Notification {
+    let counts: Vec<String> = NotificationKind::ALL
+        .iter()
+        .filter_map(|kind| {
+            let count = events.iter().filter(|event| event.event_kind == kind.as_str()).count();
+            (count > 0).then(|| counted(*kind, count))
+        })
+        .collect();
+
+    let oldest = events
+        .first()
+        .and_then(|event| NaiveDateTime::parse_from_str(&event.created_at, "%Y-%m-%d %H:%M:%S").ok())
+        .map(|created_at| created_at.and_utc());
+    let minutes = oldest
+        .map(|created_at| ((Utc::now() - created_at).num_seconds() + 59) / 60)
+        .map(|minutes| minutes.max(1))
+        .unwrap_or(1);
+
+    let mut lines: Vec<String> = events
+        .iter()
+        .take(DIGEST_ITEMS)
+        .map(|event| format!("• {}: {}", event.title, truncate(&event.text, 100)))
+        .collect();
+    if events.len() > DIGEST_ITEMS {
+        lines.push(format!("…and {} more", events.len() - DIGEST_ITEMS));
+    }
+
+    let kind = events
+        .first()
+        .and_then(|first| NotificationKind::parse(&first.event_kind))
+        .filter(|kind| events.iter().all(|event| event.event_kind == kind.as_str()));
+    let title = format!(
+        "{} in the last {} minute{}",
+        counts.join(", "),
+        minutes,
+        if minutes == 1 { "" } else { "s" }
+    );
+
+    Notification {
+        kind,
+        ..Notification::new(title, lines.join("\n"))
+    }
+}
+
+fn counted(kind: NotificationKind, count: usize) -> String {
+    let (one, many) = match kind {
+        NotificationKind::NewMessage => ("new message", "new messages"),
+        NotificationKind::NewFollower => ("new follower", "new followers"),
+        NotificationKind::Unfollow => ("unfollow", "unfollows"),
+        NotificationKind::BotMembership => ("group join/leave", "group joins/leaves"),
+        NotificationKind::ScheduledSendFailed => ("failed scheduled send", "failed scheduled sends"),
+        NotificationKind::ReminderFailed => ("failed reminder", "failed reminders"),
+    };
+    format!("{} {}", count, if count == 1 { one } else { many })
+}
@@ -0,0 +1,62 @@
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{http_client, send_request, truncate, Notification, Notifier};
+
+/// Slack's limits for a header block and a section's text
+const HEADER_MAX_CHARS: usize = 150;
+const SECTION_MAX_CHARS: usize = 3000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlackConfig {
+    /// Incoming webhook URL (https://hooks.slack.com/services/...)
+    pub webhook_url: String,
+    /// Format as Block Kit blocks rather than a single line of text
+    #[serde(default)]
+    pub blocks: bool,
+}
+
+pub struct SlackNotifier {
+    client: Client,
+    config: SlackConfig,
+}
+
+impl SlackNotifier {
+    pub fn new(config: SlackConfig) -> Self {
+        Self {
+            client: http_client(),
+            config,
+        }
+    }
+
+    fn payload(&self, notification: &Notification) -> serde_json::Value {
+        // `text` is also the fallback shown in push notifications
+        let text = format!("{}: {}", notification.title, notification.text);
+        if !self.config.blocks {
+            return json!({ "text": text });
+        }
+
+        json!({
+            "text": text,
+            "blocks": [
+                {
+                    "type": "header",
+                    "text": { "type": "plain_text", "text": truncate(&notification.title, HEADER_MAX_CHARS) }
+                },
+                {
+                    "type": "section",
+                    "text": { "type": "mrkdwn", "text": truncate(&notification.text, SECTION_MAX_CHARS) }
+                }
+            ]
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for SlackNotifier {
+    async fn send(&self, notification: &Notification) -> Result<(), anyhow::Error> {
+        let request = self.client.post(&self.config.webhook_url).json(&self.payload(notification));
+        send_request("Slack", request).await
+    }
+}
@@ -0,0 +1,51 @@
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{http_client, send_request, Notification, Notifier};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TeamsConfig {
+    /// Workflows ("Post to a channel when a webhook request is received") or
+    /// incoming webhook URL
+    pub webhook_url: String,
+}
+
+pub struct TeamsNotifier {
+    client: Client,
+    config: TeamsConfig,
+}
+
+impl TeamsNotifier {
+    pub fn new(config: TeamsConfig) -> Self {
+        Self {
+            client: http_client(),
+            config,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for TeamsNotifier {
+    async fn send(&self, notification: &Notification) -> Result<(), anyhow::Error> {
+        // An Adaptive Card, which both Workflows and incoming webhooks accept
+        let payload = json!({
+            "type": "message",
+            "attachments": [{
+                "contentType": "application/vnd.microsoft.card.adaptive",
+                "content": {
+                    "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                    "type": "AdaptiveCard",
+                    "version": "1.4",
+                    "body": [
+                        { "type": "TextBlock", "text": notification.title, "weight": "Bolder", "size": "Medium", "wrap": true },
+                        { "type": "TextBlock", "text": notification.text, "wrap": true }
+                    ]
+                }
+            }]
+        });
+
+        let request = self.client.post(&self.config.webhook_url).json(&payload);
+        send_request("Teams", request).await
+    }
+}
@@ -0,0 +1,74 @@
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use super::{http_client, send_request, Notification, NotificationKind, Notifier};
+
+/// Header carrying `sha256=<hex HMAC of the body>` when a secret is set
+const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Signs each request so the receiver can check it came from this app
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+/// POSTs `{"kind", "title", "text", "sent_at"}` as JSON to any URL
+pub struct WebhookNotifier {
+    client: Client,
+    config: WebhookConfig,
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    /// The notification kind, null for tests and mixed digests
+    kind: Option<NotificationKind>,
+    title: &'a str,
+    text: &'a str,
+    /// RFC 3339, so receivers can reject replayed requests
+    sent_at: String,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: WebhookConfig) -> Self {
+        Self {
+            client: http_client(),
+            config,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for WebhookNotifier {
+    async fn send(&self, notification: &Notification) -> Result<(), anyhow::Error> {
+        let body = serde_json::to_vec(&WebhookPayload {
+            kind: notification.kind,
+            title: &notification.title,
+            text: &notification.text,
+            sent_at: chrono::Utc::now().to_rfc3339(),
+        })?;
+
+        let mut request = self
+            .client
+            .post(&self.config.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        if let Some(secret) = self.config.secret.as_deref().filter(|s| !s.is_empty()) {
+            request = request.header(SIGNATURE_HEADER, format!("sha256={}", sign(secret, &body)));
+        }
+
+        send_request("Webhook", request.body(body)).await
+    }
+}
+
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
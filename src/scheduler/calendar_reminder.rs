@@ -1,12 +1,25 @@
-use chrono::Utc;
 use sqlx::SqlitePool;
-use crate::db::models::{Calendar, Setting};
-use crate::api::line_client::{LineClient, Message};
+use crate::db::models::{Calendar, User};
+use crate::api::line_client::{new_retry_key, LineApiError, LineClient};
+use crate::messaging::{self, Sender};
+use crate::notification::{self, Notification, NotificationKind};
+use crate::templates;
 
 /// Check for upcoming calendar events and send reminders
 pub async fn check_and_send_reminders(db: &SqlitePool) -> Result<(), anyhow::Error> {
     tracing::debug!("Checking for calendar reminders...");
 
+    // Reminders that kept failing transiently are given up once their event starts
+    for event in Calendar::list_expired_reminders(db).await? {
+        let error = format!(
+            "Gave up after the event started: {}",
+            event.reminder_error.as_deref().unwrap_or("LINE did not accept the reminder")
+        );
+        Calendar::skip_reminder(db, event.id, &error).await?;
+        tracing::error!("Gave up on reminder for event {}: {}", event.id, error);
+        notify_reminder_failed(db, &event, &error).await;
+    }
+
     // Get all upcoming events in the next 24 hours that haven't had reminders sent
     let events = sqlx::query_as::<_, Calendar>(
         "SELECT * FROM calendars
@@ -24,19 +37,33 @@ pub async fn check_and_send_reminders(db: &SqlitePool) -> Result<(), anyhow::Err
 
     tracing::info!("Found {} calendar events needing reminders", events.len());
 
-    // Get LINE access token
-    let access_token = match Setting::get(db, "line_channel_access_token").await? {
-        Some(token) if !token.is_empty() => token,
-        _ => {
-            tracing::warn!("LINE channel access token not configured, skipping reminders");
+    let line_client = match LineClient::from_settings(db).await {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::warn!("{}, skipping reminders", e);
             return Ok(());
         }
     };
 
-    let line_client = LineClient::new(access_token);
-
     for event in events {
-        match send_reminder(&line_client, db, &event).await {
+        // Pushes to users who blocked the account are never delivered
+        if !User::is_following(db, &event.line_user_id).await? {
+            Calendar::skip_reminder(db, event.id, "User has unfollowed the account").await?;
+            tracing::info!("Skipped reminder for event {}: {} has unfollowed", event.id, event.line_user_id);
+            continue;
+        }
+
+        // Reuse the retry key across checks so LINE drops duplicate deliveries
+        let retry_key = match &event.reminder_retry_key {
+            Some(key) => key.clone(),
+            None => {
+                let key = new_retry_key();
+                Calendar::set_reminder_retry_key(db, event.id, &key).await?;
+                key
+            }
+        };
+
+        match send_reminder(&line_client, db, &event, &retry_key).await {
             Ok(_) => {
                 // Mark reminder as sent
                 sqlx::query(
@@ -49,7 +76,18 @@ pub async fn check_and_send_reminders(db: &SqlitePool) -> Result<(), anyhow::Err
                 tracing::info!("Sent reminder for event: {} to user {}", event.event_title, event.line_user_id);
             }
             Err(e) => {
+                // Transient errors are retried on the next check until the event
+                // starts; anything else won't succeed on a retry
+                let transient = e.downcast_ref::<LineApiError>().map(|le| le.is_transient()).unwrap_or(false);
+                if transient {
+                    tracing::warn!("Failed to send reminder for event {}, will retry: {}", event.id, e);
+                    Calendar::mark_reminder_retrying(db, event.id, &e.to_string()).await?;
+                    continue;
+                }
+
                 tracing::error!("Failed to send reminder for event {}: {}", event.id, e);
+                Calendar::skip_reminder(db, event.id, &e.to_string()).await?;
+                notify_reminder_failed(db, &event, &e.to_string()).await;
             }
         }
     }
@@ -57,33 +95,26 @@ pub async fn check_and_send_reminders(db: &SqlitePool) -> Result<(), anyhow::Err
     Ok(())
 }
 
+async fn notify_reminder_failed(db: &SqlitePool, event: &Calendar, error: &str) {
+    let notification = Notification::event(
+        NotificationKind::ReminderFailed,
+        format!("Reminder for \"{}\" failed", event.event_title),
+        error.to_string(),
+    )
+    .about_user(&event.line_user_id);
+    notification::send_notifications(db, &notification).await;
+}
+
 async fn send_reminder(
     line_client: &LineClient,
-    _db: &SqlitePool,
+    db: &SqlitePool,
     event: &Calendar,
+    retry_key: &str,
 ) -> Result<(), anyhow::Error> {
-    let event_time = chrono::DateTime::parse_from_rfc3339(&event.event_time)
-        .map_err(|e| anyhow::anyhow!("Invalid event time: {}", e))?;
-
-    let now = Utc::now();
-    let time_until = event_time.signed_duration_since(now);
-
-    let time_str = if time_until.num_hours() > 0 {
-        format!("{}時間後", time_until.num_hours())
-    } else {
-        format!("{}分後", time_until.num_minutes())
-    };
-
-    let reminder_text = format!(
-        "📅 イベントリマインダー\n\n「{}」が{}に開始されます。\n\n{}",
-        event.event_title,
-        time_str,
-        event.event_description.as_deref().unwrap_or("詳細なし")
-    );
-
-    let messages = vec![Message::Text { text: reminder_text }];
+    // Rendered from the editable calendar_reminder template
+    let messages = templates::reminder_messages(db, event).await?;
 
-    line_client.push_message(&event.line_user_id, messages).await?;
+    messaging::push(db, line_client, Sender::Scheduler, &event.line_user_id, messages, Some(retry_key)).await?;
 
     Ok(())
 }
@@ -0,0 +1,103 @@
+use sqlx::SqlitePool;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio_cron_scheduler::JobScheduler;
+
+use crate::db::models::SchedulerLease;
+
+/// How long a lease lasts without being renewed
+const LEASE_TTL_SECONDS: i64 = 60;
+/// How often the owner renews its lease, and others check whether it expired
+const RENEW_INTERVAL: Duration = Duration::from_secs(20);
+
+/// Runs the scheduler while this process holds the scheduler lease, so a
+/// desktop app and a headless server sharing one database never both send
+/// scheduled messages. Whoever starts first owns the scheduler; the other
+/// takes over once the owner stops or fails to renew for `LEASE_TTL_SECONDS`.
+pub struct SchedulerOwner {
+    stop: watch::Sender<bool>,
+    task: JoinHandle<()>,
+}
+
+impl SchedulerOwner {
+    /// Start competing for the lease. `description` names this process in
+    /// the logs of the others, e.g. "headless".
+    pub fn spawn(db: SqlitePool, description: &str) -> Self {
+        let owner = format!("{}-{}", std::process::id(), uuid::Uuid::new_v4());
+        let description = format!("{} (pid {})", description, std::process::id());
+        let (stop, stopped) = watch::channel(false);
+
+        let task = tokio::spawn(hold_lease(db, owner, description, stopped));
+        Self { stop, task }
+    }
+
+    /// Stop the scheduler, if this process runs it, and release the lease
+    pub async fn shutdown(self) {
+        let _ = self.stop.send(true);
+        if let Err(e) = self.task.await {
+            tracing::error!("Scheduler lease task failed: {}", e);
+        }
+    }
+}
+
+async fn hold_lease(db: SqlitePool, owner: String, description: String, mut stopped: watch::Receiver<bool>) {
+    let mut scheduler: Option<JobScheduler> = None;
+    let mut waiting_logged = false;
+
+    loop {
+        match SchedulerLease::acquire(&db, &owner, &description, LEASE_TTL_SECONDS).await {
+            Ok(true) if scheduler.is_none() => match super::init_scheduler(db.clone()).await {
+                Ok(started) => {
+                    tracing::info!("Acquired the scheduler lease as {}", description);
+                    scheduler = Some(started);
+                }
+                Err(e) => {
+                    tracing::error!("Failed to start the scheduler: {}", e);
+                    release(&db, &owner).await;
+                }
+            },
+            Ok(true) => {}
+            Ok(false) => {
+                if let Some(running) = scheduler.take() {
+                    // Only happens if renewing failed for longer than the TTL
+                    tracing::warn!("Lost the scheduler lease to another process, stopping the scheduler");
+                    stop_scheduler(running).await;
+                    waiting_logged = false;
+                }
+                if !waiting_logged {
+                    let holder = SchedulerLease::current(&db).await.ok().flatten();
+                    tracing::info!(
+                        "Scheduler is run by {}, waiting for its lease to expire",
+                        holder.and_then(|l| l.description).unwrap_or_else(|| "another process".to_string())
+                    );
+                    waiting_logged = true;
+                }
+            }
+            Err(e) => tracing::error!("Failed to renew the scheduler lease: {}", e),
+        }
+
+        tokio::select! {
+            _ = stopped.changed() => break,
+            _ = tokio::time::sleep(RENEW_INTERVAL) => {}
+        }
+    }
+
+    if let Some(running) = scheduler {
+        stop_scheduler(running).await;
+        tracing::info!("Scheduler stopped");
+    }
+    release(&db, &owner).await;
+}
+
+async fn stop_scheduler(mut scheduler: JobScheduler) {
+    if let Err(e) = scheduler.shutdown().await {
+        tracing::error!("Failed to stop the scheduler: {}", e);
+    }
+}
+
+async fn release(db: &SqlitePool, owner: &str) {
+    if let Err(e) = SchedulerLease::release(db, owner).await {
+        tracing::error!("Failed to release the scheduler lease: {}", e);
+    }
+}
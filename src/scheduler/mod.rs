@@ -1,10 +1,20 @@
 pub mod calendar_reminder;
+pub mod lease;
 
-use chrono::Utc;
+use chrono::{DateTime, Local, Utc};
+use croner::Cron;
+use serde::Deserialize;
+use utoipa::ToSchema;
 use sqlx::SqlitePool;
 use tokio_cron_scheduler::{Job, JobScheduler};
 
-use crate::db::models::{ScheduledMessage, Setting};
+use crate::api::line_client::{new_retry_key, Audience, LineApiError, LineClient, Message as LineMessage};
+use crate::api::line_message::validate_messages;
+use crate::db::models::{MessageTemplate, ScheduledMessage, ScheduledMessageRun, User};
+use crate::followers;
+use crate::messaging::{self, Sender};
+use crate::notification::{self, truncate, Notification, NotificationKind};
+use crate::templates;
 
 /// Initialize and start the scheduler
 pub async fn init_scheduler(db: SqlitePool) -> Result<JobScheduler, anyhow::Error> {
@@ -32,11 +42,54 @@ pub async fn init_scheduler(db: SqlitePool) -> Result<JobScheduler, anyhow::Erro
         })
     })?;
 
+    // Job to refresh stale user profiles every hour
+    let db_clone3 = db.clone();
+    let profile_job = Job::new_async("0 30 * * * *", move |_uuid, _lock| {
+        let db = db_clone3.clone();
+        Box::pin(async move {
+            match followers::refresh_stale_profiles(&db).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Refreshed {} user profiles", count),
+                Err(e) => tracing::error!("Failed to refresh user profiles: {}", e),
+            }
+        })
+    })?;
+
+    // Job to send due notification digests every minute
+    let db_clone4 = db.clone();
+    let digest_job = Job::new_async("30 * * * * *", move |_uuid, _lock| {
+        let db = db_clone4.clone();
+        Box::pin(async move {
+            match notification::rules::send_digests(&db).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Sent {} notification digests", count),
+                Err(e) => tracing::error!("Failed to send notification digests: {}", e),
+            }
+        })
+    })?;
+
+    // Job to retry failed notifications every 15 seconds
+    let db_clone5 = db.clone();
+    let outbox_job = Job::new_async("*/15 * * * * *", move |_uuid, _lock| {
+        let db = db_clone5.clone();
+        Box::pin(async move {
+            if let Err(e) = notification::outbox::process_due(&db).await {
+                tracing::error!("Failed to retry notifications: {}", e);
+            }
+        })
+    })?;
+
     scheduler.add(scheduled_job).await?;
     scheduler.add(reminder_job).await?;
+    scheduler.add(profile_job).await?;
+    scheduler.add(digest_job).await?;
+    scheduler.add(outbox_job).await?;
     scheduler.start().await?;
 
-    tracing::info!("Scheduler started with scheduled messages and calendar reminders");
+    tracing::info!("Scheduler started with scheduled messages, calendar reminders, profile refresh and notifications");
+
+    // Pick up narrowcasts still in progress when the last scheduler stopped
+    tokio::spawn(resume_narrowcast_tracking(db));
 
     Ok(scheduler)
 }
@@ -47,86 +100,464 @@ async fn check_and_send_scheduled_messages(db: &SqlitePool) -> Result<(), anyhow
     let now = Utc::now();
 
     for message in pending_messages {
-        // Parse schedule time
-        let schedule_time = match chrono::DateTime::parse_from_rfc3339(&message.schedule_time) {
+        // Parse the time the message is due (next run for recurring messages)
+        let due_time = match chrono::DateTime::parse_from_rfc3339(message.due_time()) {
             Ok(dt) => dt.with_timezone(&Utc),
             Err(_) => {
-                tracing::warn!("Invalid schedule time format for message {}: {}", message.id, message.schedule_time);
+                tracing::warn!("Invalid schedule time format for message {}: {}", message.id, message.due_time());
                 continue;
             }
         };
 
         // Check if it's time to send
-        if schedule_time <= now {
-            if let Err(e) = send_scheduled_message(db, &message).await {
-                tracing::error!("Failed to send scheduled message {}: {}", message.id, e);
-                ScheduledMessage::update_status(db, message.id, "failed", Some(&e.to_string())).await?;
-            } else {
-                ScheduledMessage::update_status(db, message.id, "sent", None).await?;
+        if due_time > now {
+            continue;
+        }
+
+        // Reuse the retry key across ticks so LINE drops duplicate deliveries
+        let (retry_key, retry_started_at) = match (&message.retry_key, message.retry_started_at.as_deref()) {
+            (Some(key), Some(started)) => (
+                key.clone(),
+                chrono::DateTime::parse_from_rfc3339(started)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .unwrap_or(now),
+            ),
+            _ => {
+                let key = new_retry_key();
+                ScheduledMessage::set_retry_key(db, message.id, &key, &now.to_rfc3339()).await?;
+                (key, now)
+            }
+        };
+
+        let mut line_request_id = None;
+        let mut skipped = false;
+        let error = match send_scheduled_message(db, &message, &retry_key).await {
+            Ok(request_id) => {
+                line_request_id = request_id;
+                None
+            }
+            Err(e) if e.is::<SendSkipped>() => {
+                tracing::info!("Skipped scheduled message {}: {}", message.id, e);
+                skipped = true;
+                Some(e.to_string())
+            }
+            Err(e) => {
+                let line_error = e.downcast_ref::<LineApiError>();
+                tracing::error!(
+                    "Failed to send scheduled message {}: {} (request id: {})",
+                    message.id,
+                    e,
+                    line_error.and_then(|le| le.request_id()).unwrap_or("-")
+                );
+
+                // Transient errors stay pending and are retried on the next tick,
+                // as long as the retry key is still valid
+                let transient = line_error.map(|le| le.is_transient()).unwrap_or(false);
+                if transient && now - retry_started_at < retry_window() {
+                    ScheduledMessage::mark_retrying(db, message.id, &e.to_string()).await?;
+                    continue;
+                }
+
+                Some(e.to_string())
+            }
+        };
+
+        let run_status = match (&error, skipped) {
+            (_, true) => "skipped",
+            (Some(_), false) => "failed",
+            (None, false) => "sent",
+        };
+        let run_id =
+            ScheduledMessageRun::create(db, message.id, run_status, error.as_deref(), line_request_id.as_deref()).await?;
+        if let (Some(error), "failed") = (&error, run_status) {
+            notify_send_failed(db, &message, error).await;
+        }
+        // LINE accepting a narrowcast doesn't mean it was delivered; follow it
+        // once the message's own status is settled
+        let narrowcast = line_request_id.map(|request_id| (run_id, request_id));
+
+        match message.cron_expression.as_deref().filter(|e| !e.trim().is_empty()) {
+            Some(expression) => {
+                let next_run_at = match next_recurrence(&message, expression, now) {
+                    Ok(next) => next.map(|dt| dt.to_rfc3339()),
+                    Err(e) => {
+                        tracing::error!("Stopping recurring message {}: {}", message.id, e);
+                        notify_send_failed(db, &message, &e.to_string()).await;
+                        ScheduledMessage::update_status(db, message.id, "failed", Some(&e.to_string())).await?;
+                        if let Some((run_id, request_id)) = narrowcast {
+                            tokio::spawn(track_narrowcast(db.clone(), run_id, message, request_id));
+                        }
+                        continue;
+                    }
+                };
+
+                match &next_run_at {
+                    Some(next) => tracing::info!("Recurring message {} next runs at {}", message.id, next),
+                    None => tracing::info!("Recurring message {} completed", message.id),
+                }
+
+                ScheduledMessage::advance(db, message.id, next_run_at.as_deref(), error.as_deref()).await?;
+            }
+            None => {
+                ScheduledMessage::update_status(db, message.id, run_status, error.as_deref()).await?;
             }
         }
+
+        if let Some((run_id, request_id)) = narrowcast {
+            tokio::spawn(track_narrowcast(db.clone(), run_id, message, request_id));
+        }
     }
 
     Ok(())
 }
 
-/// Send a scheduled message via LINE Messaging API
-async fn send_scheduled_message(db: &SqlitePool, message: &ScheduledMessage) -> Result<(), anyhow::Error> {
-    // Get LINE channel access token from settings
-    let access_token = match Setting::get(db, "line_channel_access_token").await? {
-        Some(token) => token,
+/// Wait for a scheduled narrowcast to finish and record its final phase on
+/// the run. A failed narrowcast fails the run (and a one-off message) and
+/// sends a ScheduledSendFailed notification.
+async fn track_narrowcast(db: SqlitePool, run_id: i64, message: ScheduledMessage, request_id: String) {
+    let progress = match LineClient::from_settings(&db).await {
+        Ok(client) => client
+            .wait_for_narrowcast(&request_id, NARROWCAST_POLL_INTERVAL, NARROWCAST_POLL_TIMEOUT)
+            .await
+            .map_err(anyhow::Error::from),
+        Err(e) => Err(e),
+    };
+    let progress = match progress {
+        Ok(progress) => progress,
+        Err(e) => {
+            tracing::error!("Failed to get progress of narrowcast {}: {}", request_id, e);
+            return;
+        }
+    };
+
+    let error = (progress.phase == "failed").then(|| {
+        format!(
+            "Narrowcast failed: {}",
+            progress
+                .failed_description
+                .clone()
+                .or(progress.error_code.map(|code| format!("error code {}", code)))
+                .unwrap_or_else(|| "no reason given".to_string())
+        )
+    });
+    if let Err(e) = ScheduledMessageRun::set_narrowcast_phase(&db, run_id, &progress.phase, error.as_deref()).await {
+        tracing::error!("Failed to record progress of narrowcast {}: {}", request_id, e);
+        return;
+    }
+
+    match &error {
+        Some(error) => {
+            tracing::error!("Scheduled message {} narrowcast {} failed: {}", message.id, request_id, error);
+            if message.cron_expression.as_deref().filter(|e| !e.trim().is_empty()).is_none() {
+                if let Err(e) = ScheduledMessage::update_status(&db, message.id, "failed", Some(error)).await {
+                    tracing::error!("Failed to mark scheduled message {} failed: {}", message.id, e);
+                }
+            }
+            notify_send_failed(&db, &message, error).await;
+        }
+        None => tracing::info!(
+            "Scheduled message {} narrowcast {} is {} ({} of {} delivered)",
+            message.id,
+            request_id,
+            progress.phase,
+            progress.success_count.unwrap_or(0),
+            progress.target_count.unwrap_or(0)
+        ),
+    }
+}
+
+async fn resume_narrowcast_tracking(db: SqlitePool) {
+    let runs = match ScheduledMessageRun::list_unfinished_narrowcasts(&db).await {
+        Ok(runs) => runs,
+        Err(e) => {
+            tracing::error!("Failed to list narrowcasts in progress: {}", e);
+            return;
+        }
+    };
+
+    for run in runs {
+        let Some(request_id) = run.line_request_id else { continue };
+        match ScheduledMessage::find_by_id(&db, run.scheduled_message_id).await {
+            Ok(Some(message)) => {
+                tokio::spawn(track_narrowcast(db.clone(), run.id, message, request_id));
+            }
+            Ok(None) => {}
+            Err(e) => tracing::error!("Failed to load scheduled message {}: {}", run.scheduled_message_id, e),
+        }
+    }
+}
+
+async fn notify_send_failed(db: &SqlitePool, message: &ScheduledMessage, error: &str) {
+    let mut notification = Notification::event(
+        NotificationKind::ScheduledSendFailed,
+        format!("Scheduled message {} failed", message.id),
+        format!("{}\n{}", truncate(&message.message_text, 100), error),
+    );
+    if let Some(user_id) = &message.line_user_id {
+        notification = notification.about_user(user_id);
+    }
+    notification::send_notifications(db, &notification).await;
+}
+
+/// A scheduled message to create, as given by a command or the REST API
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct NewScheduledMessage {
+    /// Recipient of a push; leave out (with no audience) to broadcast
+    pub line_user_id: Option<String>,
+    /// Text to send, or a readable summary when `messages` is given. Text
+    /// messages are templates, rendered for each recipient when sent.
+    pub message_text: Option<String>,
+    /// Stored template to send instead of `message_text`; later edits to it
+    /// apply to sends that haven't happened yet
+    pub template_id: Option<i64>,
+    /// LINE message objects, up to 5
+    #[schema(value_type = Option<Vec<Object>>)]
+    pub messages: Option<Vec<LineMessage>>,
+    /// RFC 3339
+    pub schedule_time: String,
+    pub cron_expression: Option<String>,
+    pub end_at: Option<String>,
+    pub max_occurrences: Option<i64>,
+    /// `{"type": "multicast", "user_ids": [...]}` or a narrowcast audience
+    #[schema(value_type = Option<Object>)]
+    pub audience: Option<Audience>,
+}
+
+/// Validate and store a scheduled message. Returns its ID.
+pub async fn create_scheduled_message(db: &SqlitePool, new: NewScheduledMessage) -> Result<i64, String> {
+    // Broadcasts and narrowcasts can't use recipient variables
+    let to_everyone = new.line_user_id.is_none() && !matches!(new.audience, Some(Audience::Multicast { .. }));
+
+    // A template's text is kept in message_text, in case it is deleted
+    let message_text = match new.template_id {
+        Some(_) if new.messages.is_some() => {
+            return Err("Specify either messages or template_id, not both".to_string());
+        }
+        Some(template_id) => Some(
+            MessageTemplate::find_by_id(db, template_id)
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("Template {} not found", template_id))?
+                .body,
+        ),
+        None => new.message_text,
+    };
+
+    // Rich messages are stored as a JSON array; message_text keeps a readable summary
+    let (message_text, messages) = match new.messages {
+        Some(messages) => {
+            validate_messages(&messages)?;
+            templates::check_messages(&messages, to_everyone)?;
+            let summary = message_text.unwrap_or_else(|| {
+                messages.iter().map(LineMessage::preview_text).collect::<Vec<_>>().join(" / ")
+            });
+            let json = serde_json::to_string(&messages).map_err(|e| e.to_string())?;
+            (summary, Some(json))
+        }
         None => {
-            return Err(anyhow::anyhow!("LINE channel access token not configured"));
+            let text = message_text.ok_or_else(|| "No message to send".to_string())?;
+            validate_messages(&[LineMessage::text(text.clone())])?;
+            templates::check_messages(&[LineMessage::text(text.clone())], to_everyone)?;
+            (text, None)
         }
     };
 
-    if access_token.is_empty() {
-        return Err(anyhow::anyhow!("LINE channel access token is empty"));
+    let start = chrono::DateTime::parse_from_rfc3339(&new.schedule_time)
+        .map_err(|e| format!("Invalid schedule time: {}", e))?
+        .with_timezone(&Utc);
+
+    if let Some(end_at) = &new.end_at {
+        chrono::DateTime::parse_from_rfc3339(end_at)
+            .map_err(|e| format!("Invalid end time: {}", e))?;
     }
 
-    let client = reqwest::Client::new();
-
-    // Prepare message payload
-    let payload = if let Some(user_id) = &message.line_user_id {
-        // Send to specific user (push message)
-        serde_json::json!({
-            "to": user_id,
-            "messages": [{
-                "type": "text",
-                "text": message.message_text
-            }]
-        })
-    } else {
-        // Broadcast message to all followers
-        serde_json::json!({
-            "messages": [{
-                "type": "text",
-                "text": message.message_text
-            }]
-        })
+    if matches!(new.max_occurrences, Some(max) if max < 1) {
+        return Err("max_occurrences must be at least 1".to_string());
+    }
+
+    if new.audience.is_some() && new.line_user_id.is_some() {
+        return Err("Specify either line_user_id or audience, not both".to_string());
+    }
+    if matches!(&new.audience, Some(Audience::Multicast { user_ids }) if user_ids.is_empty()) {
+        return Err("Multicast audience has no users".to_string());
+    }
+    let audience = new.audience
+        .map(|a| serde_json::to_string(&a))
+        .transpose()
+        .map_err(|e| e.to_string())?;
+
+    // Recurring messages first fire at the first cron match at or after the start time
+    let cron_expression = new.cron_expression.filter(|e| !e.trim().is_empty());
+    let next_run_at = match &cron_expression {
+        Some(expression) => Some(
+            next_cron_occurrence(expression, start, true)
+                .map_err(|e| e.to_string())?
+                .to_rfc3339(),
+        ),
+        None => None,
     };
 
-    let endpoint = if message.line_user_id.is_some() {
-        "https://api.line.me/v2/bot/message/push"
-    } else {
-        "https://api.line.me/v2/bot/message/broadcast"
+    ScheduledMessage::create(
+        db,
+        new.line_user_id.as_deref(),
+        &message_text,
+        messages.as_deref(),
+        &new.schedule_time,
+        cron_expression.as_deref(),
+        next_run_at.as_deref(),
+        new.end_at.as_deref(),
+        new.max_occurrences,
+        audience.as_deref(),
+        new.template_id,
+    )
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Next fire time of a cron expression after (or at, if `inclusive`) `from`.
+/// Expressions take 5 or 6 fields and are evaluated in the machine's local time.
+pub fn next_cron_occurrence(
+    expression: &str,
+    from: DateTime<Utc>,
+    inclusive: bool,
+) -> Result<DateTime<Utc>, anyhow::Error> {
+    let cron = Cron::new(expression)
+        .with_seconds_optional()
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid cron expression '{}': {}", expression, e))?;
+
+    let next = cron
+        .find_next_occurrence(&from.with_timezone(&Local), inclusive)
+        .map_err(|e| anyhow::anyhow!("No next occurrence for '{}': {}", expression, e))?;
+
+    Ok(next.with_timezone(&Utc))
+}
+
+/// Work out when a recurring message fires next, or `None` once it has hit
+/// its occurrence limit or end date
+fn next_recurrence(
+    message: &ScheduledMessage,
+    expression: &str,
+    now: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>, anyhow::Error> {
+    if let Some(max) = message.max_occurrences {
+        if message.run_count + 1 >= max {
+            return Ok(None);
+        }
+    }
+
+    // Missed occurrences (e.g. while the app was closed) are skipped, not replayed
+    let next = next_cron_occurrence(expression, now, false)?;
+
+    if let Some(end_at) = &message.end_at {
+        let end_at = chrono::DateTime::parse_from_rfc3339(end_at)
+            .map_err(|e| anyhow::anyhow!("Invalid end time '{}': {}", end_at, e))?;
+        if next > end_at {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(next))
+}
+
+/// How often, and for how long, a scheduled narrowcast's progress is polled
+const NARROWCAST_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+const NARROWCAST_POLL_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// How long a transiently failing message keeps being retried. LINE only
+/// honours an `X-Line-Retry-Key` for 24 hours.
+fn retry_window() -> chrono::Duration {
+    chrono::Duration::hours(24)
+}
+
+/// A send that was deliberately not made because nobody it was addressed to
+/// still follows the account
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+struct SendSkipped(String);
+
+/// The text of a scheduled message: its template's current text, or the
+/// text it was scheduled with if the template was deleted
+async fn scheduled_text(db: &SqlitePool, message: &ScheduledMessage) -> Result<String, sqlx::Error> {
+    let template = match message.template_id {
+        Some(template_id) => MessageTemplate::find_by_id(db, template_id).await?,
+        None => None,
+    };
+
+    Ok(template
+        .map(|template| template.body)
+        .unwrap_or_else(|| message.message_text.clone()))
+}
+
+/// Send a scheduled message via LINE Messaging API. Returns the request ID
+/// of a narrowcast so its progress can be looked up later.
+async fn send_scheduled_message(
+    db: &SqlitePool,
+    message: &ScheduledMessage,
+    retry_key: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let client = LineClient::from_settings(db).await?;
+    let messages = match &message.messages {
+        Some(json) => serde_json::from_str::<Vec<LineMessage>>(json)
+            .map_err(|e| anyhow::anyhow!("Invalid stored messages: {}", e))?,
+        None => vec![LineMessage::text(scheduled_text(db, message).await?)],
+    };
+
+    let audience = match &message.audience {
+        Some(json) => Some(
+            serde_json::from_str::<Audience>(json)
+                .map_err(|e| anyhow::anyhow!("Invalid audience: {}", e))?,
+        ),
+        None => None,
     };
 
-    let response = client
-        .post(endpoint)
-        .header("Authorization", format!("Bearer {}", access_token))
-        .header("Content-Type", "application/json")
-        .json(&payload)
-        .send()
-        .await?;
-
-    if response.status().is_success() {
-        tracing::info!("Scheduled message {} sent successfully", message.id);
-    } else {
-        let error_text = response.text().await?;
-        tracing::error!("Failed to send scheduled message {}: {}", message.id, error_text);
-        return Err(anyhow::anyhow!("LINE API error: {}", error_text));
+    let mut request_id = None;
+    match (audience, &message.line_user_id) {
+        (Some(Audience::Multicast { user_ids }), _) => {
+            let followers = User::filter_following(db, &user_ids).await?;
+            if followers.is_empty() {
+                return Err(SendSkipped("All recipients have unfollowed the account".to_string()).into());
+            }
+            if followers.len() < user_ids.len() {
+                tracing::info!(
+                    "Scheduled message {} skips {} recipients who unfollowed",
+                    message.id,
+                    user_ids.len() - followers.len()
+                );
+            }
+            templates::multicast(db, &client, Sender::Scheduler, &followers, &messages, Some(retry_key)).await?;
+        }
+        (Some(Audience::Narrowcast { recipient, filter, limit }), _) => {
+            let id = messaging::narrowcast(
+                db,
+                &client,
+                Sender::Scheduler,
+                templates::for_everyone(db, &messages).await?,
+                recipient,
+                filter,
+                limit,
+                Some(retry_key),
+            )
+            .await?;
+            tracing::info!("Scheduled message {} started narrowcast {}", message.id, id);
+            request_id = Some(id);
+        }
+        (None, Some(user_id)) => {
+            if !User::is_following(db, user_id).await? {
+                return Err(SendSkipped(format!("{} has unfollowed the account", user_id)).into());
+            }
+            // Send to specific user (push message)
+            let messages = templates::personalize(db, &messages, user_id).await?;
+            messaging::push(db, &client, Sender::Scheduler, user_id, messages, Some(retry_key)).await?;
+        }
+        (None, None) => {
+            // Broadcast message to all followers
+            let messages = templates::for_everyone(db, &messages).await?;
+            messaging::broadcast(db, &client, Sender::Scheduler, messages, Some(retry_key)).await?;
+        }
     }
 
-    Ok(())
+    tracing::info!("Scheduled message {} sent successfully", message.id);
+
+    Ok(request_id)
 }
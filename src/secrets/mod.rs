@@ -0,0 +1,403 @@
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::db::models::{NotificationChannel, SecretsMasterKey, Setting};
+use crate::settings;
+
+/// Shown instead of a secret's value. Saving it back leaves the secret as is.
+pub const MASK: &str = "********";
+
+/// Stored secrets look like `enc:v1:<key id>:<base64 of nonce and ciphertext>`
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const KEY_FILE_NAME: &str = "master.key";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SecretsError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Failed to read or write master key file {path}: {source}")]
+    KeyFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Master key file {0} is missing, but the secret settings are encrypted with it. Restore the file, or set SECRETS_PASSPHRASE if they were encrypted with a passphrase")]
+    MissingKeyFile(PathBuf),
+    #[error("Master key file {0} doesn't contain a base64-encoded 32-byte key")]
+    InvalidKeyFile(PathBuf),
+    #[error("The master key ({found}) isn't the one the secret settings are encrypted with ({expected}). Check SECRETS_PASSPHRASE or MASTER_KEY_FILE")]
+    WrongKey { expected: String, found: String },
+    #[error("Secret settings are encrypted with a key file; unset SECRETS_PASSPHRASE or rotate to a passphrase with --rotate-master-key")]
+    NotPassphraseKey,
+    #[error("Failed to derive a key from the passphrase: {0}")]
+    Kdf(String),
+    #[error("Failed to decrypt setting {0}; it may be corrupted or encrypted with another key")]
+    Decrypt(String),
+    #[error("Secret settings are not available before the master key is loaded")]
+    NotInitialized,
+}
+
+/// A 256-bit key for XChaCha20-Poly1305, with a short id derived from it so
+/// a stored value tells which key encrypted it
+pub struct MasterKey {
+    cipher: XChaCha20Poly1305,
+    id: String,
+    /// Set when the key was derived from a passphrase
+    kdf_salt: Option<String>,
+}
+
+impl MasterKey {
+    fn from_bytes(bytes: &[u8; 32], kdf_salt: Option<String>) -> Self {
+        let id = Sha256::digest(bytes)
+            .iter()
+            .take(4)
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        Self {
+            cipher: XChaCha20Poly1305::new(Key::from_slice(bytes)),
+            id,
+            kdf_salt,
+        }
+    }
+
+    /// Derive a key from a passphrase with Argon2id
+    fn from_passphrase(passphrase: &str, salt: &str) -> Result<Self, SecretsError> {
+        let mut bytes = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt.as_bytes(), &mut bytes)
+            .map_err(|e| SecretsError::Kdf(e.to_string()))?;
+
+        Ok(Self::from_bytes(&bytes, Some(salt.to_string())))
+    }
+
+    /// A new random key, with the key file contents that hold it
+    fn generate() -> (Self, String) {
+        let bytes: [u8; 32] = XChaCha20Poly1305::generate_key(&mut OsRng).into();
+        (Self::from_bytes(&bytes, None), STANDARD.encode(bytes))
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .expect("XChaCha20-Poly1305 encryption doesn't fail for in-memory data");
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&ciphertext);
+        format!("{}{}:{}", ENCRYPTED_PREFIX, self.id, STANDARD.encode(payload))
+    }
+
+    /// Decrypt a stored value. Values that aren't encrypted, e.g. written to
+    /// the database by hand, are returned as they are.
+    pub fn decrypt(&self, key: &str, stored: &str) -> Result<String, SecretsError> {
+        let Some(encrypted) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let decrypt_error = || SecretsError::Decrypt(key.to_string());
+
+        let (_key_id, payload) = encrypted.split_once(':').ok_or_else(decrypt_error)?;
+        let payload = STANDARD.decode(payload).map_err(|_| decrypt_error())?;
+        if payload.len() < NONCE_LEN {
+            return Err(decrypt_error());
+        }
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+
+        let plaintext = self
+            .cipher
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| decrypt_error())?;
+        String::from_utf8(plaintext).map_err(|_| decrypt_error())
+    }
+}
+
+/// Where the master key comes from: the `SECRETS_PASSPHRASE` environment
+/// variable if set, otherwise a key file (`MASTER_KEY_FILE`, by default
+/// `master.key` next to the database)
+enum KeySource {
+    Passphrase(String),
+    File(PathBuf),
+}
+
+impl KeySource {
+    fn from_env(db_path: &str) -> Self {
+        match std::env::var("SECRETS_PASSPHRASE").ok().filter(|p| !p.is_empty()) {
+            Some(passphrase) => KeySource::Passphrase(passphrase),
+            None => KeySource::File(key_file_path(db_path)),
+        }
+    }
+}
+
+fn key_file_path(db_path: &str) -> PathBuf {
+    std::env::var("MASTER_KEY_FILE")
+        .ok()
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            Path::new(db_path)
+                .parent()
+                .unwrap_or_else(|| Path::new("."))
+                .join(KEY_FILE_NAME)
+        })
+}
+
+static MASTER_KEY: OnceLock<MasterKey> = OnceLock::new();
+
+fn master_key() -> Result<&'static MasterKey, SecretsError> {
+    MASTER_KEY.get().ok_or(SecretsError::NotInitialized)
+}
+
+/// Load the master key, creating one on first run, and encrypt any secret
+/// settings that are still stored in plain text. Fails if the key doesn't
+/// match the one the secrets were encrypted with.
+pub async fn init(db: &SqlitePool, db_path: &str) -> Result<(), SecretsError> {
+    let key = load_key(db, &KeySource::from_env(db_path)).await?;
+    encrypt_plaintext_settings(db, &key).await?;
+
+    let _ = MASTER_KEY.set(key);
+    Ok(())
+}
+
+async fn load_key(db: &SqlitePool, source: &KeySource) -> Result<MasterKey, SecretsError> {
+    let recorded = SecretsMasterKey::get(db).await?;
+
+    let key = match (source, &recorded) {
+        (KeySource::Passphrase(passphrase), Some(recorded)) => {
+            let salt = recorded.kdf_salt.as_deref().ok_or(SecretsError::NotPassphraseKey)?;
+            MasterKey::from_passphrase(passphrase, salt)?
+        }
+        (KeySource::Passphrase(passphrase), None) => MasterKey::from_passphrase(passphrase, &generate_salt())?,
+        (KeySource::File(path), Some(_)) => match read_key_file(path)? {
+            Some(key) => key,
+            None => return Err(SecretsError::MissingKeyFile(path.clone())),
+        },
+        (KeySource::File(path), None) => match read_key_file(path)? {
+            Some(key) => key,
+            None => {
+                let (key, contents) = MasterKey::generate();
+                write_key_file(path, &contents)?;
+                tracing::info!("Created master key file {}", path.display());
+                key
+            }
+        },
+    };
+
+    match recorded {
+        Some(recorded) if recorded.key_id != key.id => Err(SecretsError::WrongKey {
+            expected: recorded.key_id,
+            found: key.id.clone(),
+        }),
+        Some(_) => Ok(key),
+        None => {
+            SecretsMasterKey::create(db, &key.id, key.kdf_salt.as_deref()).await?;
+            Ok(key)
+        }
+    }
+}
+
+/// Encrypt secret settings saved before encryption existed, or written to
+/// the database directly
+async fn encrypt_plaintext_settings(db: &SqlitePool, key: &MasterKey) -> Result<(), SecretsError> {
+    for setting in Setting::list_all(db).await? {
+        if is_secret(&setting.key) && !setting.value.is_empty() && !setting.value.starts_with(ENCRYPTED_PREFIX) {
+            Setting::set(db, &setting.key, &key.encrypt(&setting.value), None).await?;
+            tracing::info!("Encrypted setting {}", setting.key);
+        }
+    }
+    Ok(())
+}
+
+/// Whether a setting is encrypted at rest: the ones marked secret in the
+/// settings registry. They are only decrypted where they are used, and
+/// masked wherever settings are listed.
+pub fn is_secret(key: &str) -> bool {
+    settings::find(key).is_some_and(|def| def.secret)
+}
+
+/// Encrypt a value with the master key, for secrets stored outside settings
+pub fn encrypt(plaintext: &str) -> Result<String, SecretsError> {
+    Ok(master_key()?.encrypt(plaintext))
+}
+
+/// Decrypt a value stored with `encrypt`. `label` names it in errors.
+pub fn decrypt(label: &str, stored: &str) -> Result<String, SecretsError> {
+    master_key()?.decrypt(label, stored)
+}
+
+/// A setting's value, decrypted if it's a secret. Use this where the value
+/// is actually needed, not for display.
+pub async fn get_setting(db: &SqlitePool, key: &str) -> Result<Option<String>, SecretsError> {
+    match Setting::get(db, key).await? {
+        Some(value) if is_secret(key) && !value.is_empty() => Ok(Some(master_key()?.decrypt(key, &value)?)),
+        value => Ok(value),
+    }
+}
+
+/// Save a setting, encrypting it if it's a secret. Saving `MASK` over a
+/// secret keeps its current value, so masked values can be sent back as is.
+pub async fn set_setting(
+    db: &SqlitePool,
+    key: &str,
+    value: &str,
+    description: Option<&str>,
+) -> Result<(), SecretsError> {
+    if !is_secret(key) || value.is_empty() {
+        return Ok(Setting::set(db, key, value, description).await?);
+    }
+    if value == MASK {
+        return Ok(());
+    }
+
+    Ok(Setting::set(db, key, &master_key()?.encrypt(value), description).await?)
+}
+
+/// A setting's value for display: secrets that are set become `MASK`
+pub fn masked(key: &str, value: String) -> String {
+    if is_secret(key) && !value.is_empty() {
+        MASK.to_string()
+    } else {
+        value
+    }
+}
+
+/// Settings for display, with secrets masked
+pub async fn list_masked_settings(db: &SqlitePool) -> Result<Vec<Setting>, sqlx::Error> {
+    let mut settings = Setting::list_all(db).await?;
+    for setting in &mut settings {
+        setting.value = masked(&setting.key, std::mem::take(&mut setting.value));
+    }
+    Ok(settings)
+}
+
+/// Re-encrypt every secret setting under a new master key and switch to it.
+/// The new key is derived from `NEW_SECRETS_PASSPHRASE` if that is set, and
+/// is a new random key file otherwise; the previous key file is kept as
+/// `<file>.old`. Returns a note on how to use the new key.
+pub async fn rotate_master_key(db: &SqlitePool, db_path: &str) -> Result<String, SecretsError> {
+    let source = KeySource::from_env(db_path);
+    let old_key = load_key(db, &source).await?;
+
+    // A new passphrase, or a new key file in place of the current one
+    let (new_key, key_file) = match std::env::var("NEW_SECRETS_PASSPHRASE").ok().filter(|p| !p.is_empty()) {
+        Some(passphrase) => (MasterKey::from_passphrase(&passphrase, &generate_salt())?, None),
+        None => {
+            let (key, contents) = MasterKey::generate();
+            (key, Some((key_file_path(db_path), contents)))
+        }
+    };
+
+    let mut values = Vec::new();
+    for setting in Setting::list_all(db).await? {
+        if is_secret(&setting.key) && !setting.value.is_empty() {
+            let plaintext = old_key.decrypt(&setting.key, &setting.value)?;
+            values.push((setting.key, new_key.encrypt(&plaintext)));
+        }
+    }
+
+    let mut channel_configs = Vec::new();
+    for channel in NotificationChannel::list_all(db).await? {
+        let plaintext = old_key.decrypt(&format!("notification channel {}", channel.id), &channel.config)?;
+        channel_configs.push((channel.id, new_key.encrypt(&plaintext)));
+    }
+
+    // Write the new key file before switching, so the database never refers
+    // to a key that isn't on disk
+    let staged = match &key_file {
+        Some((path, contents)) => {
+            let staged = path.with_extension("key.new");
+            write_key_file(&staged, contents)?;
+            Some(staged)
+        }
+        None => None,
+    };
+
+    SecretsMasterKey::rotate(db, &new_key.id, new_key.kdf_salt.as_deref(), &values, &channel_configs).await?;
+
+    let note = match (key_file, staged) {
+        (Some((path, _)), Some(staged)) => {
+            if path.exists() {
+                let old = path.with_extension("key.old");
+                std::fs::rename(&path, &old).map_err(|source| SecretsError::KeyFile { path: old, source })?;
+            }
+            std::fs::rename(&staged, &path).map_err(|source| SecretsError::KeyFile {
+                path: path.clone(),
+                source,
+            })?;
+
+            let mut note = format!("Secret settings are now encrypted with the key in {}", path.display());
+            if matches!(source, KeySource::Passphrase(_)) {
+                note.push_str("; unset SECRETS_PASSPHRASE from now on");
+            }
+            note
+        }
+        _ => "Secret settings are now encrypted with the new passphrase; set SECRETS_PASSPHRASE to it from now on"
+            .to_string(),
+    };
+
+    tracing::info!(
+        "Rotated the master key from {} to {} ({} secret settings and {} notification channels re-encrypted)",
+        old_key.id,
+        new_key.id,
+        values.len(),
+        channel_configs.len()
+    );
+    Ok(note)
+}
+
+fn generate_salt() -> String {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    STANDARD.encode(salt)
+}
+
+fn read_key_file(path: &Path) -> Result<Option<MasterKey>, SecretsError> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(source) => {
+            return Err(SecretsError::KeyFile {
+                path: path.to_path_buf(),
+                source,
+            })
+        }
+    };
+
+    let bytes: [u8; 32] = STANDARD
+        .decode(contents.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| SecretsError::InvalidKeyFile(path.to_path_buf()))?;
+    Ok(Some(MasterKey::from_bytes(&bytes, None)))
+}
+
+/// Write a key file readable only by the current user
+fn write_key_file(path: &Path, contents: &str) -> Result<(), SecretsError> {
+    let key_file_error = |source| SecretsError::KeyFile {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(key_file_error)?;
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path).map_err(key_file_error)?;
+    std::io::Write::write_all(&mut file, format!("{}\n", contents).as_bytes()).map_err(key_file_error)
+}
@@ -0,0 +1,38 @@
+use sqlx::SqlitePool;
+
+use crate::api::line_client::{BotInfo, LineClient};
+use crate::api::slack_client::{AuthInfo, SlackClient};
+use crate::integrations::airtable::AirtableClient;
+use crate::integrations::google_sheets::GoogleSheetsClient;
+use crate::integrations::notion::NotionClient;
+use crate::integrations::ExternalIntegration;
+
+// Live checks of the configured credentials, for the "test connection"
+// buttons in settings. Unlike normal sends, a missing setting is an error.
+// Notification channels are tested with `notification::test_channel`.
+
+pub const INTEGRATIONS: &[&str] = &["notion", "airtable", "google_sheets"];
+
+/// Check the LINE access token (and API host) by fetching the bot's info
+pub async fn test_line(db: &SqlitePool) -> Result<BotInfo, anyhow::Error> {
+    let client = LineClient::from_settings(db).await?;
+    Ok(client.get_bot_info().await?)
+}
+
+/// Check the Slack bridge's bot token (and API host) with `auth.test`
+pub async fn test_slack_bridge(db: &SqlitePool) -> Result<AuthInfo, anyhow::Error> {
+    let client = SlackClient::from_settings(db).await?;
+    Ok(client.auth_test().await?)
+}
+
+/// Check one of the `INTEGRATIONS` with its settings
+pub async fn test_integration(db: &SqlitePool, integration: &str) -> Result<(), anyhow::Error> {
+    let client: Box<dyn ExternalIntegration + Send + Sync> = match integration {
+        "notion" => Box::new(NotionClient::from_settings(db).await?),
+        "airtable" => Box::new(AirtableClient::from_settings(db).await?),
+        "google_sheets" => Box::new(GoogleSheetsClient::from_settings(db).await?),
+        other => anyhow::bail!("Unknown integration '{}' (expected one of {:?})", other, INTEGRATIONS),
+    };
+
+    client.connect().await
+}
@@ -0,0 +1,272 @@
+pub mod connection;
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+use crate::db::models::Setting;
+use crate::secrets::{self, SecretsError};
+
+// Keys of the known settings. Code reads settings through these rather than
+// string literals, so a typo fails to compile instead of reading nothing.
+pub const LINE_CHANNEL_ACCESS_TOKEN: &str = "line_channel_access_token";
+pub const LINE_CHANNEL_SECRET: &str = "line_channel_secret";
+pub const LINE_API_BASE_URL: &str = "line_api_base_url";
+pub const WELCOME_MESSAGES: &str = "welcome_messages";
+pub const NOTION_API_KEY: &str = "notion_api_key";
+pub const NOTION_DATABASE_ID: &str = "notion_database_id";
+pub const AIRTABLE_API_KEY: &str = "airtable_api_key";
+pub const AIRTABLE_BASE_ID: &str = "airtable_base_id";
+pub const AIRTABLE_TABLE_NAME: &str = "airtable_table_name";
+pub const GOOGLE_SHEETS_API_KEY: &str = "google_sheets_api_key";
+pub const GOOGLE_SHEETS_SPREADSHEET_ID: &str = "google_sheets_spreadsheet_id";
+pub const SLACK_BOT_TOKEN: &str = "slack_bot_token";
+pub const SLACK_SIGNING_SECRET: &str = "slack_signing_secret";
+pub const SLACK_BRIDGE_CHANNEL: &str = "slack_bridge_channel";
+pub const SLACK_API_BASE_URL: &str = "slack_api_base_url";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingKind {
+    Text,
+    /// An absolute http(s) URL
+    Url,
+    Json,
+}
+
+/// Checks a non-empty value, describing what's wrong with it
+pub type Validator = fn(&str) -> Result<(), String>;
+
+/// A setting the app knows about
+#[derive(Debug, Serialize)]
+pub struct SettingDef {
+    pub key: &'static str,
+    pub kind: SettingKind,
+    pub description: &'static str,
+    /// Used when neither the database nor the environment has a value
+    pub default: Option<&'static str>,
+    /// Encrypted at rest and masked when listed (see `secrets`)
+    pub secret: bool,
+    /// Environment variable read when the setting isn't saved
+    pub env: Option<&'static str>,
+    /// Extra check on top of the kind's
+    #[serde(skip)]
+    pub validate: Option<Validator>,
+}
+
+impl SettingDef {
+    const fn new(key: &'static str, kind: SettingKind, description: &'static str) -> Self {
+        Self {
+            key,
+            kind,
+            description,
+            default: None,
+            secret: false,
+            env: None,
+            validate: None,
+        }
+    }
+
+    const fn secret(mut self) -> Self {
+        self.secret = true;
+        self
+    }
+
+    const fn env(mut self, env: &'static str) -> Self {
+        self.env = Some(env);
+        self
+    }
+
+    const fn validate(mut self, validate: Validator) -> Self {
+        self.validate = Some(validate);
+        self
+    }
+
+    /// Check a value before saving it. An empty value clears the setting.
+    pub fn check(&self, value: &str) -> Result<(), String> {
+        if value.is_empty() {
+            return Ok(());
+        }
+
+        match self.kind {
+            SettingKind::Text => {}
+            SettingKind::Url => {
+                let url = reqwest::Url::parse(value).map_err(|e| format!("{} must be a URL: {}", self.key, e))?;
+                if !matches!(url.scheme(), "http" | "https") {
+                    return Err(format!("{} must be an http or https URL", self.key));
+                }
+            }
+            SettingKind::Json => {
+                serde_json::from_str::<serde_json::Value>(value)
+                    .map_err(|e| format!("{} must be JSON: {}", self.key, e))?;
+            }
+        }
+
+        match self.validate {
+            Some(validate) => validate(value).map_err(|e| format!("{}: {}", self.key, e)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Every setting the app reads. Saving any other key is rejected.
+pub static REGISTRY: &[SettingDef] = &[
+    SettingDef::new(LINE_CHANNEL_ACCESS_TOKEN, SettingKind::Text, "LINE Messaging API channel access token")
+        .secret()
+        .env("LINE_CHANNEL_ACCESS_TOKEN")
+        .validate(no_whitespace),
+    SettingDef::new(LINE_CHANNEL_SECRET, SettingKind::Text, "LINE channel secret, to verify webhook signatures")
+        .secret()
+        .env("LINE_CHANNEL_SECRET")
+        .validate(channel_secret),
+    SettingDef::new(LINE_API_BASE_URL, SettingKind::Url, "LINE API host, e.g. a mock API (default: https://api.line.me)")
+        .env("LINE_API_BASE_URL"),
+    SettingDef::new(WELCOME_MESSAGES, SettingKind::Json, "Messages replied to new followers (JSON array)")
+        .validate(json_array),
+    SettingDef::new(NOTION_API_KEY, SettingKind::Text, "Notion integration token")
+        .secret()
+        .env("NOTION_API_KEY")
+        .validate(no_whitespace),
+    SettingDef::new(NOTION_DATABASE_ID, SettingKind::Text, "Notion database to sync to").env("NOTION_DATABASE_ID"),
+    SettingDef::new(AIRTABLE_API_KEY, SettingKind::Text, "Airtable personal access token")
+        .secret()
+        .env("AIRTABLE_API_KEY")
+        .validate(no_whitespace),
+    SettingDef::new(AIRTABLE_BASE_ID, SettingKind::Text, "Airtable base to sync to (app...)").env("AIRTABLE_BASE_ID"),
+    SettingDef::new(AIRTABLE_TABLE_NAME, SettingKind::Text, "Airtable table to sync to").env("AIRTABLE_TABLE_NAME"),
+    SettingDef::new(GOOGLE_SHEETS_API_KEY, SettingKind::Text, "Google API key with the Sheets API enabled")
+        .secret()
+        .env("GOOGLE_SHEETS_API_KEY")
+        .validate(no_whitespace),
+    SettingDef::new(GOOGLE_SHEETS_SPREADSHEET_ID, SettingKind::Text, "Google Sheets spreadsheet to sync to")
+        .env("GOOGLE_SHEETS_SPREADSHEET_ID"),
+    SettingDef::new(SLACK_BOT_TOKEN, SettingKind::Text, "Slack bot token for the Slack bridge (xoxb-...)")
+        .secret()
+        .env("SLACK_BOT_TOKEN")
+        .validate(no_whitespace),
+    SettingDef::new(SLACK_SIGNING_SECRET, SettingKind::Text, "Slack signing secret, to verify Events API requests")
+        .secret()
+        .env("SLACK_SIGNING_SECRET")
+        .validate(no_whitespace),
+    SettingDef::new(SLACK_BRIDGE_CHANNEL, SettingKind::Text, "Slack channel ID the bridge posts conversations to (C...)")
+        .env("SLACK_BRIDGE_CHANNEL")
+        .validate(no_whitespace),
+    SettingDef::new(SLACK_API_BASE_URL, SettingKind::Url, "Slack Web API host, e.g. a local stand-in (default: https://slack.com/api)")
+        .env("SLACK_API_BASE_URL"),
+];
+
+fn no_whitespace(value: &str) -> Result<(), String> {
+    if value.chars().any(char::is_whitespace) {
+        Err("must not contain spaces or line breaks".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+fn channel_secret(value: &str) -> Result<(), String> {
+    if value.len() == 32 && value.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err("must be the 32-character hexadecimal channel secret".to_string())
+    }
+}
+
+fn json_array(value: &str) -> Result<(), String> {
+    match serde_json::from_str::<serde_json::Value>(value) {
+        Ok(serde_json::Value::Array(_)) => Ok(()),
+        _ => Err("must be a JSON array".to_string()),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SettingsError {
+    #[error("Unknown setting '{0}'")]
+    Unknown(String),
+    #[error("{0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Secrets(#[from] SecretsError),
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+pub fn find(key: &str) -> Option<&'static SettingDef> {
+    REGISTRY.iter().find(|def| def.key == key)
+}
+
+fn definition(key: &str) -> Result<&'static SettingDef, SettingsError> {
+    find(key).ok_or_else(|| SettingsError::Unknown(key.to_string()))
+}
+
+/// Where a setting's effective value comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueSource {
+    Database,
+    Env,
+    Default,
+    Unset,
+}
+
+/// A setting's effective value: the saved value (decrypted if secret), else
+/// its environment variable, else its default. Empty values count as unset.
+pub async fn get(db: &SqlitePool, key: &str) -> Result<Option<String>, SettingsError> {
+    let def = definition(key)?;
+    let saved = secrets::get_setting(db, key).await?;
+    Ok(resolve(def, saved).0)
+}
+
+fn resolve(def: &SettingDef, saved: Option<String>) -> (Option<String>, ValueSource) {
+    if let Some(value) = saved.filter(|v| !v.is_empty()) {
+        return (Some(value), ValueSource::Database);
+    }
+    if let Some(value) = def.env.and_then(|env| std::env::var(env).ok()).filter(|v| !v.is_empty()) {
+        return (Some(value), ValueSource::Env);
+    }
+    match def.default {
+        Some(value) => (Some(value.to_string()), ValueSource::Default),
+        None => (None, ValueSource::Unset),
+    }
+}
+
+/// Validate and save a setting. Secrets are encrypted, and saving the mask
+/// over one keeps its value.
+pub async fn set(db: &SqlitePool, key: &str, value: &str, description: Option<&str>) -> Result<(), SettingsError> {
+    let def = definition(key)?;
+    if !(def.secret && value == secrets::MASK) {
+        def.check(value).map_err(SettingsError::Invalid)?;
+    }
+
+    secrets::set_setting(db, key, value, description.or(Some(def.description))).await?;
+    Ok(())
+}
+
+/// A known setting with its effective value, for the settings screen
+#[derive(Debug, Serialize)]
+pub struct SettingInfo {
+    #[serde(flatten)]
+    pub def: &'static SettingDef,
+    /// Masked for secrets
+    pub value: Option<String>,
+    pub source: ValueSource,
+    pub updated_at: Option<String>,
+}
+
+/// All known settings with their effective values, secrets masked
+pub async fn list(db: &SqlitePool) -> Result<Vec<SettingInfo>, SettingsError> {
+    let saved = Setting::list_all(db).await?;
+
+    Ok(REGISTRY
+        .iter()
+        .map(|def| {
+            let row = saved.iter().find(|s| s.key == def.key);
+            // Resolve from the stored value: a secret is only masked, never decrypted
+            let (value, source) = resolve(def, row.map(|s| s.value.clone()));
+            SettingInfo {
+                def,
+                value: value.map(|v| secrets::masked(def.key, v)),
+                source,
+                updated_at: row.filter(|_| source == ValueSource::Database).map(|s| s.updated_at.clone()),
+            }
+        })
+        .collect())
+}
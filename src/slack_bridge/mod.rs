@@ -0,0 +1,287 @@
+use sqlx::SqlitePool;
+use std::sync::LazyLock;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+use crate::api::line_client::{LineClient, Message as LineMessage};
+use crate::api::slack_client::SlackClient;
+use crate::db::models::{Conversation, Message, SlackBridgePost};
+use crate::messaging::{self, Sender};
+use crate::notification::outbox;
+use crate::settings;
+
+// Support staff answer LINE users from Slack: every 1:1 conversation gets a
+// thread in the bridge channel, inbound messages are posted into it, and
+// replies in the thread (received by the Slack Events endpoint) are pushed
+// to the user. The bridge is off until the bot token and channel are set.
+//
+// Inbound messages are queued in `slack_bridge_posts` when they are stored
+// and posted by a worker next to the webhook server, so a Slack outage
+// delays posts instead of losing them. Failed posts are retried with the
+// notification outbox's backoff and given up on ("dead") after MAX_ATTEMPTS.
+
+/// Attempts before a post is dead (about 13 hours of retries)
+const MAX_ATTEMPTS: i64 = 20;
+/// How long an attempt in progress keeps others from taking the post
+const CLAIM_SECONDS: i64 = 5 * 60;
+/// Posts tried per pass of the worker
+const BATCH_SIZE: i64 = 50;
+/// How often the worker looks for retries that came due
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Wakes the worker when a post is queued
+static QUEUED: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+/// The configured Slack side of the bridge
+struct Bridge {
+    client: SlackClient,
+    channel: String,
+}
+
+async fn bridge(db: &SqlitePool) -> Result<Option<Bridge>, anyhow::Error> {
+    let Some(channel) = settings::get(db, settings::SLACK_BRIDGE_CHANNEL).await? else {
+        return Ok(None);
+    };
+    if settings::get(db, settings::SLACK_BOT_TOKEN).await?.is_none() {
+        return Ok(None);
+    }
+
+    Ok(Some(Bridge {
+        client: SlackClient::from_settings(db).await?,
+        channel,
+    }))
+}
+
+/// Queue a stored inbound message for its conversation's thread, if the
+/// bridge is set up
+pub async fn enqueue(db: &SqlitePool, message: &Message) -> Result<(), anyhow::Error> {
+    let Some(line_user_id) = &message.line_user_id else {
+        return Ok(());
+    };
+    if bridge(db).await?.is_none() {
+        return Ok(());
+    }
+
+    SlackBridgePost::enqueue(db, message.id, line_user_id).await?;
+    QUEUED.notify_one();
+    Ok(())
+}
+
+/// Post queued messages to Slack, for as long as the process runs. Runs
+/// next to the webhook server, which queues them.
+pub fn spawn(db: SqlitePool) {
+    tokio::spawn(async move {
+        loop {
+            // Posting one message can make the next of its conversation due
+            loop {
+                match process_due(&db).await {
+                    Ok(0) => break,
+                    Ok(_) => continue,
+                    Err(e) => {
+                        tracing::error!("Failed to post queued messages to Slack: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            tokio::select! {
+                _ = QUEUED.notified() => {}
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            }
+        }
+    });
+}
+
+/// Post the queued messages that are due. Returns how many were tried.
+async fn process_due(db: &SqlitePool) -> Result<usize, anyhow::Error> {
+    let due = SlackBridgePost::list_due(db, BATCH_SIZE).await?;
+    for post in &due {
+        if let Err(e) = attempt(db, post).await {
+            tracing::error!("Failed to post message {} to Slack: {}", post.message_id, e);
+        }
+    }
+    Ok(due.len())
+}
+
+/// Post a queued message and record the outcome
+async fn attempt(db: &SqlitePool, post: &SlackBridgePost) -> Result<(), anyhow::Error> {
+    if !SlackBridgePost::claim(db, post.id, CLAIM_SECONDS).await? {
+        return Ok(());
+    }
+
+    let Some(message) = Message::find_by_id(db, post.message_id).await? else {
+        SlackBridgePost::mark_dead(db, post.id, "The message was deleted").await?;
+        return Ok(());
+    };
+    let Some(bridge) = bridge(db).await? else {
+        SlackBridgePost::mark_dead(db, post.id, "The Slack bridge is not configured").await?;
+        return Ok(());
+    };
+
+    match forward_inbound(db, &bridge, &post.line_user_id, &message).await {
+        Ok(()) => SlackBridgePost::mark_sent(db, post.id).await?,
+        Err(e) => {
+            let error = e.to_string();
+            let attempts = post.attempts + 1;
+            if attempts >= MAX_ATTEMPTS {
+                tracing::error!(
+                    "Giving up on posting message {} to Slack after {} attempts: {}",
+                    message.id,
+                    attempts,
+                    error
+                );
+                SlackBridgePost::mark_dead(db, post.id, &error).await?;
+            } else {
+                let delay = outbox::backoff_seconds(attempts);
+                tracing::warn!("Failed to post message {} to Slack, retrying in {}s: {}", message.id, delay, error);
+                SlackBridgePost::mark_retry(db, post.id, &error, delay).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Post an inbound message into its conversation's thread, starting the
+/// thread on the first message
+async fn forward_inbound(
+    db: &SqlitePool,
+    bridge: &Bridge,
+    line_user_id: &str,
+    message: &Message,
+) -> Result<(), anyhow::Error> {
+    // Read now rather than when the message was queued: an earlier message
+    // may have started the thread since
+    let Some(conversation) = Conversation::find_by_user(db, line_user_id).await? else {
+        return Ok(());
+    };
+
+    // A thread in a channel the bridge no longer uses is left behind
+    let thread_ts = match (&conversation.slack_channel, &conversation.slack_thread_ts) {
+        (Some(channel), Some(thread_ts)) if *channel == bridge.channel => thread_ts.clone(),
+        _ => start_thread(db, bridge, &conversation).await?,
+    };
+
+    bridge
+        .client
+        .post_message(&bridge.channel, &slack_text(message), Some(&thread_ts))
+        .await?;
+    Ok(())
+}
+
+async fn start_thread(db: &SqlitePool, bridge: &Bridge, conversation: &Conversation) -> Result<String, anyhow::Error> {
+    let name = conversation.display_name.as_deref().unwrap_or("LINEユーザー");
+    let text = format!(
+        ":speech_balloon: *{}* （`{}`）とのLINEの会話です。このスレッドに返信すると、LINEでユーザーに送信されます。",
+        escape(name),
+        conversation.line_user_id
+    );
+
+    let posted = bridge.client.post_message(&bridge.channel, &text, None).await?;
+    Conversation::set_slack_thread(db, &conversation.line_user_id, &posted.channel, &posted.ts).await?;
+    tracing::info!("Started Slack thread {} for {}", posted.ts, conversation.line_user_id);
+    Ok(posted.ts)
+}
+
+/// A stored message as Slack text
+fn slack_text(message: &Message) -> String {
+    match (message.message_type.as_str(), &message.message_text) {
+        ("text", Some(text)) => escape(text),
+        (message_type, _) => format!("_[{}]_", message_type),
+    }
+}
+
+/// Escape the characters Slack treats as markup
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Slack message text as plain text for LINE: links become their label and
+/// URL, mentions and channel links their name, and escapes are undone
+pub fn plain_text(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('<') {
+        plain.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            plain.push_str(&rest[start..]);
+            rest = "";
+            break;
+        };
+
+        let inner = &rest[start + 1..start + end];
+        let (target, label) = match inner.split_once('|') {
+            Some((target, label)) => (target, Some(label)),
+            None => (inner, None),
+        };
+        match (target.chars().next(), label) {
+            // <@U123> user mentions and <!here>-style broadcasts mean nothing to the LINE user
+            (Some('@'), _) | (Some('!'), _) => {}
+            (Some('#'), Some(label)) => {
+                plain.push('#');
+                plain.push_str(label);
+            }
+            (_, Some(label)) if label != target => {
+                plain.push_str(label);
+                plain.push_str(" (");
+                plain.push_str(target);
+                plain.push(')');
+            }
+            _ => plain.push_str(target),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    plain.push_str(rest);
+
+    plain
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+/// Push a reply from a conversation's Slack thread to the LINE user, stored
+/// as an outbound message from `slack`. A failure is reported in the thread.
+/// Messages in threads the bridge didn't start are ignored.
+pub async fn relay_reply(db: &SqlitePool, channel: &str, thread_ts: &str, text: &str) -> Result<(), anyhow::Error> {
+    let Some(conversation) = Conversation::find_by_slack_thread(db, channel, thread_ts).await? else {
+        tracing::debug!("Ignoring Slack reply in unknown thread {} of {}", thread_ts, channel);
+        return Ok(());
+    };
+    let text = plain_text(text);
+    if text.is_empty() {
+        return Ok(());
+    }
+
+    let result = async {
+        let client = LineClient::from_settings(db).await?;
+        messaging::push(
+            db,
+            &client,
+            Sender::Slack,
+            &conversation.line_user_id,
+            vec![LineMessage::text(text)],
+            None,
+        )
+        .await?;
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+
+    match result {
+        Ok(()) => {
+            tracing::info!("Relayed Slack reply to {}", conversation.line_user_id);
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!("Failed to relay Slack reply to {}: {}", conversation.line_user_id, e);
+            let notice = format!(":warning: LINEへの送信に失敗しました: {}", escape(&e.to_string()));
+            SlackClient::from_settings(db)
+                .await?
+                .post_message(channel, &notice, Some(thread_ts))
+                .await?;
+            Ok(())
+        }
+    }
+}
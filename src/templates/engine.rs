@@ -0,0 +1,539 @@
+use std::collections::HashMap;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+
+// The template language:
+//
+//   {{display_name}}                       a variable; missing ones render empty
+//   {{event.time | date:"%m/%d %H:%M"}}    filters, applied left to right
+//   {{attr.rank | default:"一般"}}          a fallback for missing or empty values
+//   {{#if attr.rank}}...{{else}}...{{/if}} sections, also `{{#if attr.rank == "gold"}}` and `!=`
+//
+// Filters are `date:"<strftime>"` (in local time), `default:"<text>"`,
+// `upper` and `lower`. Everything outside `{{ }}` is sent as is.
+
+/// How times render without a `date` filter
+const DEFAULT_DATE_FORMAT: &str = "%Y/%m/%d %H:%M";
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum TemplateError {
+    #[error("Unclosed {{{{ at character {0}")]
+    Unclosed(usize),
+    #[error("Empty {{{{ }}}} at character {0}")]
+    EmptyTag(usize),
+    #[error("Invalid variable name '{0}'")]
+    InvalidVariable(String),
+    #[error("Unknown filter '{0}' (expected date, default, upper or lower)")]
+    UnknownFilter(String),
+    #[error("Filter '{0}' needs a quoted argument, e.g. {0}:\"...\"")]
+    MissingArgument(String),
+    #[error("Invalid date format '{0}'")]
+    InvalidDateFormat(String),
+    #[error("Expected a quoted value after '{0}'")]
+    InvalidComparison(String),
+    #[error("{{{{{0}}}}} without a matching {{{{#if}}}}")]
+    Unmatched(String),
+    #[error("{{{{#if}}}} without a closing {{{{/if}}}}")]
+    UnclosedSection,
+}
+
+/// A variable's value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Time(DateTime<Local>),
+}
+
+impl Value {
+    fn is_empty(&self) -> bool {
+        matches!(self, Value::Text(text) if text.is_empty())
+    }
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Self {
+        Value::Text(text)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Self {
+        Value::Text(text.to_string())
+    }
+}
+
+/// Variables by name, e.g. `event.title`
+pub type Context = HashMap<String, Value>;
+
+#[derive(Debug, Clone)]
+enum Filter {
+    Date(String),
+    Default(String),
+    Upper,
+    Lower,
+}
+
+#[derive(Debug, Clone)]
+struct Expr {
+    variable: String,
+    filters: Vec<Filter>,
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Present(Expr),
+    Equals(Expr, String),
+    NotEquals(Expr, String),
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Output(Expr),
+    If {
+        condition: Condition,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+/// A parsed template
+#[derive(Debug, Clone)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+/// An open `{{#if}}` while parsing
+struct Section {
+    condition: Condition,
+    then: Vec<Node>,
+    otherwise: Vec<Node>,
+    in_else: bool,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, TemplateError> {
+        let mut nodes = Vec::new();
+        let mut sections: Vec<Section> = Vec::new();
+        let mut rest = source;
+        let mut offset = 0;
+
+        while let Some(start) = rest.find("{{") {
+            push_text(current(&mut nodes, &mut sections), &rest[..start]);
+
+            let position = source[..offset + start].chars().count();
+            let end = find_unquoted(&rest[start + 2..], "}}").ok_or(TemplateError::Unclosed(position))?;
+            let tag = rest[start + 2..start + 2 + end].trim();
+            if tag.is_empty() {
+                return Err(TemplateError::EmptyTag(position));
+            }
+
+            if let Some(condition) = tag.strip_prefix("#if ") {
+                sections.push(Section {
+                    condition: parse_condition(condition)?,
+                    then: Vec::new(),
+                    otherwise: Vec::new(),
+                    in_else: false,
+                });
+            } else if tag == "else" {
+                match sections.last_mut() {
+                    Some(section) if !section.in_else => section.in_else = true,
+                    _ => return Err(TemplateError::Unmatched(tag.to_string())),
+                }
+            } else if tag == "/if" {
+                let section = sections.pop().ok_or_else(|| TemplateError::Unmatched(tag.to_string()))?;
+                current(&mut nodes, &mut sections).push(Node::If {
+                    condition: section.condition,
+                    then: section.then,
+                    otherwise: section.otherwise,
+                });
+            } else {
+                let expr = parse_expr(tag)?;
+                current(&mut nodes, &mut sections).push(Node::Output(expr));
+            }
+
+            let consumed = start + 2 + end + 2;
+            offset += consumed;
+            rest = &rest[consumed..];
+        }
+        push_text(current(&mut nodes, &mut sections), rest);
+
+        if !sections.is_empty() {
+            return Err(TemplateError::UnclosedSection);
+        }
+        Ok(Self { nodes })
+    }
+
+    /// Render with `context`. Missing variables render empty (or their default).
+    pub fn render(&self, context: &Context) -> String {
+        let mut out = String::new();
+        render_nodes(&self.nodes, context, &mut out);
+        out
+    }
+
+    /// Names of the variables the template uses, in order of first use
+    pub fn variables(&self) -> Vec<String> {
+        let mut variables = Vec::new();
+        collect_variables(&self.nodes, &mut variables);
+        variables
+    }
+}
+
+/// Where parsed nodes go: the innermost open section, or the top level
+fn current<'a>(nodes: &'a mut Vec<Node>, sections: &'a mut [Section]) -> &'a mut Vec<Node> {
+    match sections.last_mut() {
+        Some(section) if section.in_else => &mut section.otherwise,
+        Some(section) => &mut section.then,
+        None => nodes,
+    }
+}
+
+fn push_text(nodes: &mut Vec<Node>, text: &str) {
+    if !text.is_empty() {
+        nodes.push(Node::Text(text.to_string()));
+    }
+}
+
+/// Byte offset of the first `pattern` outside quoted strings
+fn find_unquoted(text: &str, pattern: &str) -> Option<usize> {
+    let mut in_quotes = false;
+    for (i, c) in text.char_indices() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        } else if !in_quotes && text[i..].starts_with(pattern) {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// Split on `separator` outside quoted strings
+fn split_unquoted<'a>(mut text: &'a str, separator: &str) -> Vec<&'a str> {
+    let mut parts = Vec::new();
+    while let Some(i) = find_unquoted(text, separator) {
+        parts.push(&text[..i]);
+        text = &text[i + separator.len()..];
+    }
+    parts.push(text);
+    parts
+}
+
+/// The text of a `"quoted"` string
+fn unquote(text: &str) -> Option<&str> {
+    let text = text.trim();
+    text.strip_prefix('"')?.strip_suffix('"').filter(|inner| !inner.contains('"'))
+}
+
+fn parse_expr(text: &str) -> Result<Expr, TemplateError> {
+    let mut parts = split_unquoted(text, "|").into_iter();
+    let variable = parts.next().unwrap_or_default().trim();
+    let valid = !variable.is_empty()
+        && variable.split('.').all(|segment| {
+            !segment.is_empty() && segment.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        });
+    if !valid {
+        return Err(TemplateError::InvalidVariable(variable.to_string()));
+    }
+
+    let filters = parts.map(parse_filter).collect::<Result<_, _>>()?;
+    Ok(Expr {
+        variable: variable.to_string(),
+        filters,
+    })
+}
+
+fn parse_filter(text: &str) -> Result<Filter, TemplateError> {
+    let (name, argument) = match text.split_once(':') {
+        Some((name, argument)) => (name.trim(), Some(argument)),
+        None => (text.trim(), None),
+    };
+    let quoted = |name: &str| {
+        argument
+            .and_then(unquote)
+            .map(str::to_string)
+            .ok_or_else(|| TemplateError::MissingArgument(name.to_string()))
+    };
+
+    match name {
+        "date" => {
+            let format = quoted(name)?;
+            if StrftimeItems::new(&format).any(|item| matches!(item, Item::Error)) {
+                return Err(TemplateError::InvalidDateFormat(format));
+            }
+            Ok(Filter::Date(format))
+        }
+        "default" => Ok(Filter::Default(quoted(name)?)),
+        "upper" => Ok(Filter::Upper),
+        "lower" => Ok(Filter::Lower),
+        other => Err(TemplateError::UnknownFilter(other.to_string())),
+    }
+}
+
+fn parse_condition(text: &str) -> Result<Condition, TemplateError> {
+    for operator in ["==", "!="] {
+        let Some(i) = find_unquoted(text, operator) else {
+            continue;
+        };
+        let expr = parse_expr(&text[..i])?;
+        let value = unquote(&text[i + operator.len()..])
+            .ok_or_else(|| TemplateError::InvalidComparison(operator.to_string()))?
+            .to_string();
+        return Ok(if operator == "==" {
+            Condition::Equals(expr, value)
+        } else {
+            Condition::NotEquals(expr, value)
+        });
+    }
+    Ok(Condition::Present(parse_expr(text)?))
+}
+
+/// Parse a stored time: RFC 3339, SQLite's `YYYY-MM-DD HH:MM:SS` (UTC) or a date
+pub fn parse_time(text: &str) -> Option<DateTime<Local>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.with_timezone(&Local));
+    }
+    if let Ok(time) = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S") {
+        return Some(time.and_utc().with_timezone(&Local));
+    }
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .and_then(|time| time.and_local_timezone(Local).single())
+}
+
+impl Expr {
+    /// The value with filters applied; `None` when missing and not defaulted
+    fn evaluate(&self, context: &Context) -> Option<Value> {
+        let mut value = context.get(&self.variable).cloned();
+
+        for filter in &self.filters {
+            value = match (filter, value) {
+                (Filter::Default(fallback), value) => match value {
+                    Some(value) if !value.is_empty() => Some(value),
+                    _ => Some(Value::Text(fallback.clone())),
+                },
+                (_, None) => None,
+                (Filter::Date(format), Some(Value::Time(time))) => Some(Value::Text(time.format(format).to_string())),
+                (Filter::Date(format), Some(Value::Text(text))) => Some(Value::Text(match parse_time(&text) {
+                    Some(time) => time.format(format).to_string(),
+                    // Not a time; shown as is rather than dropped
+                    None => text,
+                })),
+                (Filter::Upper, Some(value)) => Some(Value::Text(display(&value).to_uppercase())),
+                (Filter::Lower, Some(value)) => Some(Value::Text(display(&value).to_lowercase())),
+            };
+        }
+
+        value
+    }
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::Text(text) => text.clone(),
+        Value::Time(time) => time.format(DEFAULT_DATE_FORMAT).to_string(),
+    }
+}
+
+impl Condition {
+    fn holds(&self, context: &Context) -> bool {
+        match self {
+            Condition::Present(expr) => expr.evaluate(context).is_some_and(|value| !value.is_empty()),
+            Condition::Equals(expr, expected) => expr.evaluate(context).is_some_and(|value| display(&value) == *expected),
+            Condition::NotEquals(expr, expected) => {
+                expr.evaluate(context).is_none_or(|value| display(&value) != *expected)
+            }
+        }
+    }
+
+    fn expr(&self) -> &Expr {
+        match self {
+            Condition::Present(expr) | Condition::Equals(expr, _) | Condition::NotEquals(expr, _) => expr,
+        }
+    }
+}
+
+fn render_nodes(nodes: &[Node], context: &Context, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Output(expr) => {
+                if let Some(value) = expr.evaluate(context) {
+                    out.push_str(&display(&value));
+                }
+            }
+            Node::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                let branch = if condition.holds(context) { then } else { otherwise };
+                render_nodes(branch, context, out);
+            }
+        }
+    }
+}
+
+fn collect_variables(nodes: &[Node], variables: &mut Vec<String>) {
+    for node in nodes {
+        match node {
+            Node::Text(_) => {}
+            Node::Output(expr) => add_variable(variables, &expr.variable),
+            Node::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                add_variable(variables, &condition.expr().variable);
+                collect_variables(then, variables);
+                collect_variables(otherwise, variables);
+            }
+        }
+    }
+}
+
+fn add_variable(variables: &mut Vec<String>, variable: &str) {
+    if !variables.iter().any(|v| v == variable) {
+        variables.push(variable.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn event_time() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 10, 17, 9, 5, 0).unwrap()
+    }
+
+    fn context() -> Context {
+        let mut context = Context::new();
+        context.insert("display_name".to_string(), "山田".into());
+        context.insert("attr.rank".to_string(), "gold".into());
+        context.insert("attr.empty".to_string(), "".into());
+        context.insert("event.time".to_string(), Value::Time(event_time()));
+        context
+    }
+
+    fn render(source: &str) -> String {
+        Template::parse(source).unwrap().render(&context())
+    }
+
+    fn parse_error(source: &str) -> TemplateError {
+        Template::parse(source).unwrap_err()
+    }
+
+    #[test]
+    fn renders_text_and_variables() {
+        assert_eq!(render("こんにちは"), "こんにちは");
+        assert_eq!(render("{{display_name}}さん、{{ attr.rank }}会員です"), "山田さん、gold会員です");
+        assert_eq!(render("[{{attr.missing}}]"), "[]");
+    }
+
+    #[test]
+    fn renders_times() {
+        assert_eq!(render("{{event.time}}"), "2026/10/17 09:05");
+        assert_eq!(render(r#"{{event.time | date:"%m/%d %H:%M"}}"#), "10/17 09:05");
+    }
+
+    #[test]
+    fn date_filter_parses_text_times() {
+        let mut context = Context::new();
+        context.insert("rfc3339".to_string(), event_time().to_rfc3339().into());
+        context.insert("date".to_string(), "2026-10-17".into());
+        context.insert("text".to_string(), "未定".into());
+        let template = Template::parse(r#"{{rfc3339 | date:"%H:%M"}} {{date | date:"%m/%d"}} {{text | date:"%m/%d"}}"#);
+
+        assert_eq!(template.unwrap().render(&context), "09:05 10/17 未定");
+    }
+
+    #[test]
+    fn default_filter_covers_missing_and_empty_values() {
+        assert_eq!(render(r#"{{attr.missing | default:"一般"}}"#), "一般");
+        assert_eq!(render(r#"{{attr.empty | default:"一般"}}"#), "一般");
+        assert_eq!(render(r#"{{attr.rank | default:"一般"}}"#), "gold");
+    }
+
+    #[test]
+    fn filters_apply_left_to_right() {
+        assert_eq!(render("{{attr.rank | upper}}"), "GOLD");
+        assert_eq!(render(r#"{{attr.missing | default:"Guest" | lower}}"#), "guest");
+        assert_eq!(render(r#"{{attr.missing | upper | default:"x"}}"#), "x");
+    }
+
+    #[test]
+    fn quoted_arguments_may_contain_delimiters() {
+        assert_eq!(render(r#"{{attr.missing | default:"a | b }} c"}}"#), "a | b }} c");
+        assert_eq!(render(r#"{{#if attr.rank == "a}}b"}}yes{{else}}no{{/if}}"#), "no");
+    }
+
+    #[test]
+    fn if_sections() {
+        assert_eq!(render("{{#if display_name}}{{display_name}}さん{{/if}}こんにちは"), "山田さんこんにちは");
+        assert_eq!(render("{{#if attr.empty}}x{{else}}y{{/if}}"), "y");
+        assert_eq!(render("{{#if attr.missing}}x{{/if}}"), "");
+        assert_eq!(render(r#"{{#if attr.rank == "gold"}}特典{{else}}通常{{/if}}"#), "特典");
+        assert_eq!(render(r#"{{#if attr.rank != "gold"}}特典{{else}}通常{{/if}}"#), "通常");
+        assert_eq!(render(r#"{{#if attr.missing != "gold"}}x{{/if}}"#), "x");
+        assert_eq!(render(r#"{{#if attr.missing | default:"gold" == "gold"}}x{{/if}}"#), "x");
+    }
+
+    #[test]
+    fn nested_sections() {
+        let source = r#"{{#if display_name}}A{{#if attr.rank == "silver"}}B{{else}}C{{#if attr.missing}}D{{/if}}{{/if}}E{{else}}F{{/if}}"#;
+        assert_eq!(render(source), "ACE");
+        assert_eq!(Template::parse(source).unwrap().render(&Context::new()), "F");
+    }
+
+    #[test]
+    fn lists_variables_once_in_order() {
+        let source = r#"{{#if attr.rank == "gold"}}{{display_name}}{{else}}{{attr.rank}}{{/if}}{{display_name}}{{now}}"#;
+        let template = Template::parse(source).unwrap();
+
+        assert_eq!(template.variables(), ["attr.rank", "display_name", "now"]);
+    }
+
+    #[test]
+    fn rejects_malformed_tags() {
+        assert_eq!(parse_error("日本{{display_name"), TemplateError::Unclosed(2));
+        assert_eq!(parse_error(r#"{{x | default:"}}"#), TemplateError::Unclosed(0));
+        assert_eq!(parse_error("a{{ }}"), TemplateError::EmptyTag(1));
+        assert_eq!(parse_error("{{first name}}"), TemplateError::InvalidVariable("first name".to_string()));
+        assert_eq!(parse_error("{{attr..rank}}"), TemplateError::InvalidVariable("attr..rank".to_string()));
+        assert_eq!(parse_error("{{| upper}}"), TemplateError::InvalidVariable(String::new()));
+    }
+
+    #[test]
+    fn rejects_bad_filters() {
+        assert_eq!(parse_error("{{x | capitalize}}"), TemplateError::UnknownFilter("capitalize".to_string()));
+        assert_eq!(parse_error("{{x | default}}"), TemplateError::MissingArgument("default".to_string()));
+        assert_eq!(parse_error("{{x | default:一般}}"), TemplateError::MissingArgument("default".to_string()));
+        assert_eq!(parse_error("{{x | date}}"), TemplateError::MissingArgument("date".to_string()));
+        assert_eq!(parse_error(r#"{{x | date:"%Y-%"}}"#), TemplateError::InvalidDateFormat("%Y-%".to_string()));
+    }
+
+    #[test]
+    fn rejects_unbalanced_sections() {
+        assert_eq!(parse_error("{{#if x}}a"), TemplateError::UnclosedSection);
+        assert_eq!(parse_error("{{#if x}}{{#if y}}a{{/if}}"), TemplateError::UnclosedSection);
+        assert_eq!(parse_error("a{{/if}}"), TemplateError::Unmatched("/if".to_string()));
+        assert_eq!(parse_error("a{{else}}"), TemplateError::Unmatched("else".to_string()));
+        assert_eq!(parse_error("{{#if x}}a{{else}}b{{else}}c{{/if}}"), TemplateError::Unmatched("else".to_string()));
+        assert_eq!(parse_error("{{#if x == gold}}a{{/if}}"), TemplateError::InvalidComparison("==".to_string()));
+        assert_eq!(parse_error("{{#if x != }}a{{/if}}"), TemplateError::InvalidComparison("!=".to_string()));
+    }
+
+    #[test]
+    fn parses_stored_times() {
+        let utc = chrono::Utc.with_ymd_and_hms(2026, 10, 17, 3, 30, 0).unwrap();
+        assert_eq!(parse_time("2026-10-17T03:30:00Z"), Some(utc.with_timezone(&Local)));
+        assert_eq!(parse_time("2026-10-17 03:30:00"), Some(utc.with_timezone(&Local)));
+        assert_eq!(
+            parse_time("2026-10-17"),
+            Local.with_ymd_and_hms(2026, 10, 17, 0, 0, 0).single()
+        );
+        assert_eq!(parse_time("明日"), None);
+    }
+}
@@ -0,0 +1,339 @@
+pub mod engine;
+
+use chrono::{Local, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+pub use engine::{Context, Template, TemplateError, Value};
+
+use crate::api::line_client::{part_retry_key, LineClient, Message as LineMessage};
+use crate::api::line_message::validate_messages;
+use crate::db::models::{Calendar, MessageTemplate, ScheduledMessage, User, UserAttribute};
+use crate::messaging::{self, Sender};
+
+// Text messages sent to users are templates, rendered for each recipient
+// when they are sent: scheduled messages, calendar reminders and the
+// broadcast tab's sends. Recipients' variables are
+//
+//   line_user_id, display_name   from the users table
+//   attr.<name>                  custom attributes (user_attributes)
+//   event.title, event.description, event.time, event.starts_in   reminders only
+//   now                          the send time
+//
+// Broadcasts and narrowcasts have no single recipient: templates using the
+// recipient's variables are rejected for them, rather than sent with those
+// left empty.
+
+/// The stored template calendar reminders are rendered from
+pub const REMINDER_TEMPLATE: &str = "calendar_reminder";
+
+/// Used when the reminder template has been deleted or renamed
+const DEFAULT_REMINDER_BODY: &str =
+    "📅 イベントリマインダー\n\n「{{event.title}}」が{{event.starts_in}}に開始されます。\n\n{{event.description | default:\"詳細なし\"}}";
+
+/// A template to create or save, as given by a command
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewMessageTemplate {
+    pub name: String,
+    pub body: String,
+    pub description: Option<String>,
+}
+
+/// A template rendered for one recipient
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplatePreview {
+    pub text: String,
+    /// Variables the template uses that have no value for this recipient
+    pub missing: Vec<String>,
+}
+
+fn validate_template(template: &NewMessageTemplate) -> Result<(), anyhow::Error> {
+    if template.name.trim().is_empty() {
+        anyhow::bail!("A template needs a name");
+    }
+    Template::parse(&template.body)?;
+    validate_messages(&[LineMessage::text(template.body.clone())]).map_err(anyhow::Error::msg)
+}
+
+pub async fn create_template(db: &SqlitePool, template: NewMessageTemplate) -> Result<i64, anyhow::Error> {
+    validate_template(&template)?;
+
+    Ok(MessageTemplate::create(db, template.name.trim(), &template.body, template.description.as_deref()).await?)
+}
+
+pub async fn update_template(db: &SqlitePool, id: i64, template: NewMessageTemplate) -> Result<(), anyhow::Error> {
+    validate_template(&template)?;
+
+    // Pending broadcasts and narrowcasts render the template when they're
+    // sent, and every run would fail
+    let to_everyone = ScheduledMessage::list_pending_to_everyone_by_template(db, id).await?;
+    if !to_everyone.is_empty() {
+        let templates = parse_messages(&[LineMessage::text(template.body.clone())])?;
+        if let Err(e) = check_for_everyone(&templates) {
+            anyhow::bail!(
+                "{} (used by pending scheduled messages {})",
+                e,
+                to_everyone.iter().map(i64::to_string).collect::<Vec<_>>().join(", ")
+            );
+        }
+    }
+
+    let updated =
+        MessageTemplate::update(db, id, template.name.trim(), &template.body, template.description.as_deref()).await?;
+    if updated == 0 {
+        anyhow::bail!("Template {} not found", id);
+    }
+    Ok(())
+}
+
+/// A template that can't be sent as asked: invalid, rendering to an invalid
+/// message, or using recipient variables without a recipient
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct InvalidTemplate(String);
+
+/// Whether a variable differs between recipients
+fn is_recipient_variable(variable: &str) -> bool {
+    matches!(variable, "line_user_id" | "display_name") || variable.starts_with("attr.")
+}
+
+/// The variables of a recipient (or of nobody in particular, for broadcasts)
+pub async fn context_for(db: &SqlitePool, line_user_id: Option<&str>) -> Result<Context, sqlx::Error> {
+    let mut context = Context::new();
+    context.insert("now".to_string(), Value::Time(Local::now()));
+
+    let Some(line_user_id) = line_user_id else {
+        return Ok(context);
+    };
+    context.insert("line_user_id".to_string(), line_user_id.into());
+    if let Some(display_name) = User::find_by_line_id(db, line_user_id).await?.and_then(|user| user.display_name) {
+        context.insert("display_name".to_string(), display_name.into());
+    }
+    for attribute in UserAttribute::list_by_user(db, line_user_id).await? {
+        context.insert(format!("attr.{}", attribute.name), attribute.value.into());
+    }
+
+    Ok(context)
+}
+
+/// Add a calendar event's variables to a context
+fn add_event(context: &mut Context, event: &Calendar) {
+    context.insert("event.title".to_string(), event.event_title.clone().into());
+    if let Some(description) = &event.event_description {
+        context.insert("event.description".to_string(), description.clone().into());
+    }
+    if let Some(time) = engine::parse_time(&event.event_time) {
+        context.insert("event.time".to_string(), Value::Time(time));
+
+        let until = time.with_timezone(&Utc).signed_duration_since(Utc::now());
+        let starts_in = if until.num_hours() > 0 {
+            format!("{}時間後", until.num_hours())
+        } else {
+            format!("{}分後", until.num_minutes().max(0))
+        };
+        context.insert("event.starts_in".to_string(), starts_in.into());
+    }
+}
+
+/// Parsed templates of the text messages, by position
+fn parse_messages(messages: &[LineMessage]) -> Result<Vec<Option<Template>>, InvalidTemplate> {
+    messages
+        .iter()
+        .map(|message| match message {
+            LineMessage::Text { text, .. } => Template::parse(text).map(Some),
+            _ => Ok(None),
+        })
+        .collect::<Result<_, TemplateError>>()
+        .map_err(|e| InvalidTemplate(format!("Invalid template: {}", e)))
+}
+
+/// Reject templates that need a recipient, for sends without one
+fn check_for_everyone(templates: &[Option<Template>]) -> Result<(), InvalidTemplate> {
+    let mut variables: Vec<String> = templates
+        .iter()
+        .flatten()
+        .flat_map(Template::variables)
+        .filter(|variable| is_recipient_variable(variable))
+        .collect();
+    if variables.is_empty() {
+        return Ok(());
+    }
+
+    variables.sort();
+    variables.dedup();
+    Err(InvalidTemplate(format!(
+        "Broadcasts and narrowcasts have no single recipient, so they can't use {}; send a multicast instead",
+        variables.iter().map(|v| format!("{{{{{}}}}}", v)).collect::<Vec<_>>().join(", ")
+    )))
+}
+
+/// Check that the text messages are valid templates, before they are stored.
+/// `to_everyone` is for broadcasts and narrowcasts.
+pub fn check_messages(messages: &[LineMessage], to_everyone: bool) -> Result<(), String> {
+    let templates = parse_messages(messages).map_err(|e| e.to_string())?;
+    if to_everyone {
+        check_for_everyone(&templates).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// The messages with their text rendered for `context`
+fn render_messages(
+    messages: &[LineMessage],
+    templates: &[Option<Template>],
+    context: &Context,
+) -> Result<Vec<LineMessage>, anyhow::Error> {
+    let rendered: Vec<LineMessage> = messages
+        .iter()
+        .zip(templates)
+        .map(|(message, template)| match (message, template) {
+            (LineMessage::Text { options, .. }, Some(template)) => LineMessage::Text {
+                text: template.render(context),
+                options: options.clone(),
+            },
+            _ => message.clone(),
+        })
+        .collect();
+
+    // A template can render empty or past LINE's length limit
+    validate_messages(&rendered).map_err(|e| InvalidTemplate(format!("Rendered message is invalid: {}", e)))?;
+    Ok(rendered)
+}
+
+/// Render messages for one recipient
+pub async fn personalize(
+    db: &SqlitePool,
+    messages: &[LineMessage],
+    line_user_id: &str,
+) -> Result<Vec<LineMessage>, anyhow::Error> {
+    let templates = parse_messages(messages)?;
+    let context = context_for(db, Some(line_user_id)).await?;
+    render_messages(messages, &templates, &context)
+}
+
+/// Render messages for a broadcast or narrowcast
+pub async fn for_everyone(db: &SqlitePool, messages: &[LineMessage]) -> Result<Vec<LineMessage>, anyhow::Error> {
+    let templates = parse_messages(messages)?;
+    check_for_everyone(&templates)?;
+    let context = context_for(db, None).await?;
+    render_messages(messages, &templates, &context)
+}
+
+/// Multicast messages rendered for each recipient. Recipients whose messages
+/// render the same are sent one multicast; a retry key is derived for each.
+pub async fn multicast(
+    db: &SqlitePool,
+    client: &LineClient,
+    sender: Sender,
+    line_user_ids: &[String],
+    messages: &[LineMessage],
+    retry_key: Option<&str>,
+) -> Result<Vec<Option<String>>, anyhow::Error> {
+    let templates = parse_messages(messages)?;
+    let per_recipient = templates
+        .iter()
+        .flatten()
+        .flat_map(Template::variables)
+        .any(|variable| is_recipient_variable(&variable));
+
+    // Only the text differs between renderings
+    let texts = |rendered: &[LineMessage]| -> Vec<String> {
+        rendered
+            .iter()
+            .filter_map(|message| match message {
+                LineMessage::Text { text, .. } => Some(text.clone()),
+                _ => None,
+            })
+            .collect()
+    };
+
+    let mut groups: Vec<(Vec<String>, Vec<LineMessage>, Vec<String>)> = Vec::new();
+    if per_recipient {
+        for line_user_id in line_user_ids {
+            let context = context_for(db, Some(line_user_id)).await?;
+            let rendered = render_messages(messages, &templates, &context)?;
+            let key = texts(&rendered);
+            match groups.iter_mut().find(|(group_key, _, _)| *group_key == key) {
+                Some((_, _, recipients)) => recipients.push(line_user_id.clone()),
+                None => groups.push((key, rendered, vec![line_user_id.clone()])),
+            }
+        }
+    } else {
+        let context = context_for(db, None).await?;
+        let rendered = render_messages(messages, &templates, &context)?;
+        groups.push((Vec::new(), rendered, line_user_ids.to_vec()));
+    }
+
+    let mut request_ids = Vec::new();
+    for (index, (_, rendered, recipients)) in groups.into_iter().enumerate() {
+        let key = retry_key.map(|key| part_retry_key(key, index));
+        request_ids.extend(messaging::multicast(db, client, sender, &recipients, rendered, key.as_deref()).await?);
+    }
+    Ok(request_ids)
+}
+
+/// The reminder for a calendar event, from the `calendar_reminder` template
+pub async fn reminder_messages(db: &SqlitePool, event: &Calendar) -> Result<Vec<LineMessage>, anyhow::Error> {
+    let body = MessageTemplate::find_by_name(db, REMINDER_TEMPLATE)
+        .await?
+        .map(|template| template.body)
+        .unwrap_or_else(|| DEFAULT_REMINDER_BODY.to_string());
+
+    let messages = [LineMessage::text(body)];
+    let templates = parse_messages(&messages)?;
+    let mut context = context_for(db, Some(&event.line_user_id)).await?;
+    add_event(&mut context, event);
+    render_messages(&messages, &templates, &context)
+}
+
+/// Render template text for a user (and calendar event, for reminder
+/// variables), without sending it
+pub async fn preview(
+    db: &SqlitePool,
+    body: &str,
+    line_user_id: Option<&str>,
+    calendar_id: Option<i64>,
+) -> Result<TemplatePreview, anyhow::Error> {
+    let template = Template::parse(body)?;
+    let mut context = context_for(db, line_user_id).await?;
+    if let Some(calendar_id) = calendar_id {
+        let event = Calendar::find_by_id(db, calendar_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Calendar event {} not found", calendar_id))?;
+        add_event(&mut context, &event);
+    }
+
+    let missing = template
+        .variables()
+        .into_iter()
+        .filter(|variable| !context.contains_key(variable))
+        .collect();
+    Ok(TemplatePreview {
+        text: template.render(&context),
+        missing,
+    })
+}
+
+/// Set a custom attribute of a user, or remove it with an empty value
+pub async fn set_user_attribute(
+    db: &SqlitePool,
+    line_user_id: &str,
+    name: &str,
+    value: &str,
+) -> Result<(), anyhow::Error> {
+    let name = name.trim();
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        anyhow::bail!("Attribute names may only contain letters, digits, '_' and '-', got '{}'", name);
+    }
+    if User::find_by_line_id(db, line_user_id).await?.is_none() {
+        anyhow::bail!("User {} not found", line_user_id);
+    }
+
+    if value.is_empty() {
+        UserAttribute::delete(db, line_user_id, name).await?;
+    } else {
+        UserAttribute::set(db, line_user_id, name, value).await?;
+    }
+    Ok(())
+}
@@ -34,6 +34,9 @@ tabButtons.forEach(button => {
             case 'dashboard':
                 loadDashboard();
                 break;
+            case 'inbox':
+                loadConversations();
+                break;
             case 'users':
                 loadUsers();
                 break;
@@ -43,6 +46,16 @@ tabButtons.forEach(button => {
             case 'scheduled':
                 loadScheduledMessages();
                 loadUsersForSelect();
+                loadMessageTemplates();
+                break;
+            case 'richmenus':
+                loadRichMenus();
+                break;
+            case 'autoreply':
+                loadAutoReplyRules();
+                break;
+            case 'integrations':
+                loadSettingInputs();
                 break;
             case 'settings':
                 loadSettings();
@@ -51,13 +64,159 @@ tabButtons.forEach(button => {
     });
 });
 
+// Inbox
+const conversationStatusLabels = {
+    'open': '未対応',
+    'pending': '返信待ち',
+    'resolved': '解決済み'
+};
+
+let selectedConversation = null;
+
+async function loadConversations() {
+    const container = document.getElementById('conversation-list');
+
+    try {
+        const assignedTo = document.getElementById('inbox-assignee-filter').value.trim();
+        const conversations = await invoke('get_conversations', {
+            status: document.getElementById('inbox-status-filter').value || null,
+            assignedTo: assignedTo || null,
+            unreadOnly: document.getElementById('inbox-unread-only').checked,
+            limit: 200
+        });
+
+        container.innerHTML = conversations.length === 0
+            ? '<p class="loading">会話がありません</p>'
+            : conversations.map(c => `
+                <div class="data-item user-item conversation-item ${c.line_user_id === selectedConversation ? 'selected' : ''}"
+                    onclick="openConversation('${c.line_user_id}')">
+                    ${c.picture_url
+                        ? `<img class="user-avatar" src="${c.picture_url}" alt="">`
+                        : '<div class="user-avatar"></div>'}
+                    <div>
+                        <p><strong>${c.display_name || c.line_user_id}</strong>
+                            ${c.unread_count > 0 ? `<span class="unread-badge">${c.unread_count}</span>` : ''}</p>
+                        <p>${c.last_message_preview || ''}</p>
+                        <p>${conversationStatusLabels[c.status] || c.status}${c.assigned_to ? ` / 担当: ${c.assigned_to}` : ''}
+                            / 受信: ${formatDate(c.last_inbound_at)}</p>
+                    </div>
+                </div>
+            `).join('');
+    } catch (error) {
+        container.innerHTML = `<p class="error">エラー: ${error}</p>`;
+    }
+
+    updateUnreadBadge();
+}
+
+async function updateUnreadBadge() {
+    try {
+        const unread = await invoke('get_conversations', { unreadOnly: true, limit: 1000 });
+        const total = unread.reduce((sum, c) => sum + c.unread_count, 0);
+        const badge = document.getElementById('inbox-unread-badge');
+        badge.textContent = total;
+        badge.hidden = total === 0;
+    } catch (error) {
+        console.error('Failed to count unread messages:', error);
+    }
+}
+
+async function openConversation(lineUserId) {
+    selectedConversation = lineUserId;
+    const container = document.getElementById('conversation-detail');
+
+    try {
+        const [conversation, messages] = await Promise.all([
+            invoke('mark_conversation_read', { lineUserId }),
+            invoke('get_messages_by_user', { lineUserId, limit: 100 })
+        ]);
+
+        container.innerHTML = `
+            <div class="form-card">
+                <h3>${conversation.display_name || conversation.line_user_id}</h3>
+                <select onchange="setConversationStatus('${lineUserId}', this.value)">
+                    ${Object.entries(conversationStatusLabels).map(([value, label]) => `
+                        <option value="${value}" ${conversation.status === value ? 'selected' : ''}>${label}</option>
+                    `).join('')}
+                </select>
+                <input type="text" id="conversation-assignee" placeholder="担当者" value="${conversation.assigned_to || ''}">
+                <button class="btn btn-secondary" onclick="assignConversation('${lineUserId}')">担当者を設定</button>
+                <div class="conversation-thread">
+                    ${messages.map(msg => msg.direction === 'outbound' ? renderOutboundMessage(msg) : `
+                        <div class="data-item">
+                            <p>${msg.message_text || `[${msg.message_type}]`}</p>
+                            ${renderMediaControls(msg)}
+                            <p>${formatDate(msg.timestamp)}</p>
+                        </div>
+                    `).join('')}
+                </div>
+                <form onsubmit="replyToConversation(event, '${lineUserId}')">
+                    <div class="form-group">
+                        <textarea id="conversation-reply" rows="3" required></textarea>
+                    </div>
+                    <button type="submit" class="btn btn-primary">返信</button>
+                </form>
+            </div>
+        `;
+    } catch (error) {
+        container.innerHTML = `<p class="error">エラー: ${error}</p>`;
+    }
+
+    loadConversations();
+}
+
+async function setConversationStatus(lineUserId, status) {
+    try {
+        await invoke('set_conversation_status', { lineUserId, status });
+        loadConversations();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function assignConversation(lineUserId) {
+    try {
+        const assignedTo = document.getElementById('conversation-assignee').value;
+        await invoke('assign_conversation', { lineUserId, assignedTo });
+        loadConversations();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function replyToConversation(event, lineUserId) {
+    event.preventDefault();
+
+    try {
+        const messageText = document.getElementById('conversation-reply').value;
+        await invoke('reply_to_conversation', { lineUserId, messageText });
+        openConversation(lineUserId);
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+// New messages and inbox changes arrive as events from the backend
+if (_isTauri) {
+    window.__TAURI__.event.listen('inbox-event', ({ payload }) => {
+        if (!document.getElementById('inbox-tab').classList.contains('active')) {
+            updateUnreadBadge();
+        } else if (payload.type === 'message' && payload.conversation.line_user_id === selectedConversation) {
+            openConversation(selectedConversation);
+        } else {
+            loadConversations();
+        }
+    });
+}
+
 // Load users
 async function loadUsers() {
     const container = document.getElementById('users-list');
     container.innerHTML = '<p class="loading">データを読み込み中...</p>';
 
     try {
-        const users = await invoke('get_users');
+        const includeUnfollowed = document.getElementById('users-include-unfollowed').checked;
+        const users = await invoke('get_users', { includeUnfollowed });
 
         if (!users || users.length === 0) {
             container.innerHTML = '<p class="loading">登録ユーザーがいません</p>';
@@ -65,10 +224,24 @@ async function loadUsers() {
         }
 
         container.innerHTML = users.map(user => `
-            <div class="data-item">
-                <p><strong>LINE ID:</strong> ${user.line_user_id}</p>
-                <p><strong>表示名:</strong> ${user.display_name || '未設定'}</p>
-                <p><strong>登録日:</strong> ${formatDate(user.created_at)}</p>
+            <div class="data-item user-item">
+                ${user.picture_url
+                    ? `<img class="user-avatar" src="${user.picture_url}" alt="">`
+                    : '<div class="user-avatar"></div>'}
+                <div>
+                    <p><strong>表示名:</strong> ${user.display_name || '未取得'}</p>
+                    ${user.status_message ? `<p><strong>ステータス:</strong> ${user.status_message}</p>` : ''}
+                    <p><strong>LINE ID:</strong> ${user.line_user_id}</p>
+                    <p><strong>状態:</strong> ${user.follow_status === 'unfollowed'
+                        ? `<span class="status-failed">ブロック中（${formatDate(user.unfollowed_at)}）</span>`
+                        : user.follow_status === 'unknown' ? '不明（友だち追加の記録なし）' : '友だち'}</p>
+                    <p><strong>友だち追加:</strong> ${user.followed_at ? formatDate(user.followed_at) : '不明'}
+                        ${user.refollowed_at ? `（再追加: ${formatDate(user.refollowed_at)}）` : ''}
+                        <button class="btn btn-secondary" onclick="showFollowHistory('${user.line_user_id}')">履歴</button></p>
+                    <div id="follow-history-${user.line_user_id}"></div>
+                    <p><strong>プロフィール取得:</strong> ${user.profile_refreshed_at ? formatDate(user.profile_refreshed_at) : '未取得'}
+                        <button class="btn btn-secondary" onclick="refreshUserProfile('${user.line_user_id}')">再取得</button></p>
+                </div>
             </div>
         `).join('');
     } catch (error) {
@@ -76,6 +249,42 @@ async function loadUsers() {
     }
 }
 
+async function showFollowHistory(lineUserId) {
+    const container = document.getElementById(`follow-history-${lineUserId}`);
+
+    try {
+        const history = await invoke('get_follow_history', { lineUserId });
+        container.innerHTML = history.length === 0
+            ? '<p>履歴がありません</p>'
+            : history.map(entry => `
+                <p>${formatDate(entry.occurred_at)} ${entry.event_type === 'follow'
+                    ? (entry.is_unblocked ? 'ブロック解除' : '友だち追加')
+                    : 'ブロック'}</p>
+            `).join('');
+    } catch (error) {
+        container.innerHTML = `<p class="error">エラー: ${error}</p>`;
+    }
+}
+
+async function refreshUserProfile(lineUserId) {
+    try {
+        await invoke('refresh_user_profile', { lineUserId });
+        loadUsers();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function refreshStaleProfiles() {
+    try {
+        const count = await invoke('refresh_stale_profiles');
+        alert(`${count}件のプロフィールを更新しました`);
+        loadUsers();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
 // Load messages
 async function loadMessages() {
     const container = document.getElementById('messages-list');
@@ -90,11 +299,14 @@ async function loadMessages() {
             return;
         }
 
-        container.innerHTML = messages.map(msg => `
+        container.innerHTML = messages.map(msg => msg.direction === 'outbound' ? renderOutboundMessage(msg) : `
             <div class="data-item">
                 <p><strong>ユーザーID:</strong> ${msg.line_user_id}</p>
+                ${msg.source_id ? `<p><strong>${msg.source_type === 'group' ? 'グループ' : 'トークルーム'}:</strong> ${msg.source_id}</p>` : ''}
                 <p><strong>タイプ:</strong> <span class="message-type">${msg.message_type}</span></p>
                 ${msg.message_text ? `<p><strong>内容:</strong> ${msg.message_text}</p>` : ''}
+                ${msg.retracted_at ? `<p><strong>送信取消:</strong> ${formatDate(msg.retracted_at)}</p>` : ''}
+                ${renderMediaControls(msg)}
                 <p><strong>受信日時:</strong> ${formatDate(msg.timestamp)}</p>
             </div>
         `).join('');
@@ -103,6 +315,95 @@ async function loadMessages() {
     }
 }
 
+const senderLabels = {
+    'admin': '管理者',
+    'bot': 'Bot',
+    'scheduler': 'スケジュール配信',
+    'rule': '自動応答'
+};
+
+const sendMethodLabels = {
+    'push': '個別',
+    'reply': '応答',
+    'multicast': 'マルチキャスト',
+    'broadcast': 'ブロードキャスト',
+    'narrowcast': 'ナローキャスト'
+};
+
+// Messages we sent, shown alongside received ones
+function renderOutboundMessage(msg) {
+    return `
+        <div class="data-item outbound-message">
+            <p><strong>送信先:</strong> ${msg.line_user_id || '全員'}（${sendMethodLabels[msg.send_method] || msg.send_method}）</p>
+            <p><strong>送信者:</strong> ${senderLabels[msg.sender] || msg.sender}</p>
+            <p><strong>内容:</strong> ${msg.message_text || `[${msg.message_type}]`}</p>
+            <p><strong>結果:</strong> ${msg.delivery_status === 'sent'
+                ? '<span class="status-sent">送信済み</span>'
+                : `<span class="status-failed">送信失敗: ${msg.error_message || ''}</span>`}
+                ${msg.line_request_id ? `（リクエストID: ${msg.line_request_id}）` : ''}</p>
+            <p><strong>送信日時:</strong> ${formatDate(msg.timestamp)}</p>
+        </div>
+    `;
+}
+
+// Media controls for image/video/audio/file messages
+function renderMediaControls(msg) {
+    if (msg.direction === 'outbound') return '';
+    if (!['image', 'video', 'audio', 'file'].includes(msg.message_type) || !msg.message_data) return '';
+
+    const data = JSON.parse(msg.message_data);
+    const details = [
+        data.fileName,
+        data.mimeType,
+        data.size ? `${Math.round(data.size / 1024)}KB` : null,
+        data.duration ? `${Math.round(data.duration / 1000)}秒` : null
+    ].filter(Boolean).join(' / ');
+
+    if (data.status === 'downloaded') {
+        return `
+            <p><strong>メディア:</strong> ${details}
+            <button class="btn btn-secondary" onclick="showMessageMedia(${msg.id}, '${msg.message_type}')">表示</button></p>
+            <div id="message-media-${msg.id}"></div>
+        `;
+    }
+    if (data.status === 'failed') {
+        return `
+            <p><strong>メディア:</strong> 取得失敗（${data.error || ''}）
+            <button class="btn btn-secondary" onclick="retryMessageMedia(${msg.id})">再取得</button></p>
+        `;
+    }
+    return '<p><strong>メディア:</strong> 取得中...</p>';
+}
+
+async function showMessageMedia(messageId, messageType) {
+    const container = document.getElementById(`message-media-${messageId}`);
+
+    try {
+        const path = await invoke('get_message_media', { messageId });
+        const url = window.__TAURI__.core.convertFileSrc(path);
+        if (messageType === 'image') {
+            container.innerHTML = `<img src="${url}" style="max-width: 320px;">`;
+        } else if (messageType === 'video') {
+            container.innerHTML = `<video src="${url}" controls style="max-width: 320px;"></video>`;
+        } else if (messageType === 'audio') {
+            container.innerHTML = `<audio src="${url}" controls></audio>`;
+        } else {
+            container.innerHTML = `<a href="${url}" download>ダウンロード</a>`;
+        }
+    } catch (error) {
+        container.innerHTML = `<p class="error">エラー: ${error}</p>`;
+    }
+}
+
+async function retryMessageMedia(messageId) {
+    try {
+        await invoke('download_message_media', { messageId });
+        loadMessages();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
 // Load scheduled messages
 async function loadScheduledMessages() {
     const container = document.getElementById('scheduled-list');
@@ -118,9 +419,10 @@ async function loadScheduledMessages() {
 
         container.innerHTML = messages.map(msg => `
             <div class="data-item">
-                <p><strong>配信先:</strong> ${msg.line_user_id || '全ユーザー'}</p>
+                <p><strong>配信先:</strong> ${formatScheduledTarget(msg)}</p>
                 <p><strong>メッセージ:</strong> ${msg.message_text}</p>
-                <p><strong>配信予定:</strong> ${formatDate(msg.schedule_time)}</p>
+                <p><strong>配信予定:</strong> ${formatDate(msg.next_run_at || msg.schedule_time)}</p>
+                ${msg.cron_expression ? `<p><strong>繰り返し:</strong> ${msg.cron_expression}（${msg.run_count}回配信済み${msg.max_occurrences ? ` / 最大${msg.max_occurrences}回` : ''}）</p>` : ''}
                 <p><strong>ステータス:</strong> <span class="status-${msg.status}">${getStatusText(msg.status)}</span></p>
             </div>
         `).join('');
@@ -141,29 +443,90 @@ async function loadUsersForSelect() {
             `<option value="${user.line_user_id}">${user.display_name || user.line_user_id}</option>`
         ).join('');
 
-        select.innerHTML = '<option value="">全ユーザー（ブロードキャスト）</option>' + userOptions;
+        select.innerHTML = '<option value="">全ユーザー（ブロードキャスト）</option>' +
+            '<option value="__multicast__">選択したユーザー（マルチキャスト）</option>' +
+            '<option value="__narrowcast__">属性で絞り込み（ナローキャスト）</option>' +
+            userOptions;
+        document.getElementById('scheduled-multicast-users').innerHTML = userOptions;
+        document.getElementById('template-preview-user').innerHTML = '<option value="">ユーザーなし（ブロードキャスト）</option>' + userOptions;
+        document.getElementById('attribute-user').innerHTML = userOptions;
+        updateScheduledTargetFields();
+        loadUserAttributes();
     } catch (error) {
         console.error('Failed to load users:', error);
     }
 }
 
+// Show the extra fields for multicast / narrowcast targets
+function updateScheduledTargetFields() {
+    const target = document.getElementById('scheduled-user-id').value;
+    document.getElementById('scheduled-multicast-group').style.display = target === '__multicast__' ? '' : 'none';
+    document.getElementById('scheduled-narrowcast-group').style.display = target === '__narrowcast__' ? '' : 'none';
+}
+
+// Build the audience for multicast / narrowcast targets
+function buildScheduledAudience(target) {
+    if (target === '__multicast__') {
+        const selected = document.getElementById('scheduled-multicast-users').selectedOptions;
+        return { type: 'multicast', user_ids: Array.from(selected).map(option => option.value) };
+    }
+
+    const filters = [];
+    const gender = document.getElementById('narrowcast-gender').value;
+    const ageGte = document.getElementById('narrowcast-age-gte').value;
+    const ageLt = document.getElementById('narrowcast-age-lt').value;
+    if (gender) filters.push({ type: 'gender', oneOf: [gender] });
+    if (ageGte || ageLt) filters.push({ type: 'age', gte: ageGte || undefined, lt: ageLt || undefined });
+
+    let demographic = null;
+    if (filters.length === 1) demographic = filters[0];
+    if (filters.length > 1) demographic = { type: 'operator', and: filters };
+
+    return { type: 'narrowcast', filter: demographic ? { demographic } : null };
+}
+
 // Create scheduled message
 async function createScheduledMessage(event) {
     event.preventDefault();
 
-    const userId = document.getElementById('scheduled-user-id').value || null;
-    const text = document.getElementById('scheduled-text').value;
+    const target = document.getElementById('scheduled-user-id').value;
+    const isGroupTarget = target === '__multicast__' || target === '__narrowcast__';
+    const userId = isGroupTarget ? null : (target || null);
+    const audience = isGroupTarget ? buildScheduledAudience(target) : null;
+    const templateId = document.getElementById('scheduled-template').value;
+    const text = document.getElementById('scheduled-text').value || null;
+    const messagesJson = document.getElementById('scheduled-messages-json').value.trim();
     const time = document.getElementById('scheduled-time').value;
+    const cron = document.getElementById('scheduled-cron').value.trim() || null;
+    const endTime = document.getElementById('scheduled-end-time').value;
+    const maxOccurrences = document.getElementById('scheduled-max-occurrences').value;
 
     // Convert to ISO 8601 format
     const scheduleTime = new Date(time).toISOString();
+    const endAt = endTime ? new Date(endTime).toISOString() : null;
+
+    // Stickers, templates, quick replies etc. are entered as LINE message objects
+    let messages = null;
+    if (messagesJson) {
+        try {
+            messages = JSON.parse(messagesJson);
+        } catch (error) {
+            alert(`メッセージオブジェクトのJSONが不正です: ${error}`);
+            return;
+        }
+    }
 
     try {
         await invoke('create_scheduled_message', {
             lineUserId: userId,
             messageText: text,
+            messages: messages,
             scheduleTime: scheduleTime,
-            cronExpression: null
+            cronExpression: cron,
+            endAt: endAt,
+            maxOccurrences: maxOccurrences ? parseInt(maxOccurrences, 10) : null,
+            audience: audience,
+            templateId: templateId ? parseInt(templateId, 10) : null
         });
 
         alert('スケジュールを登録しました');
@@ -174,19 +537,449 @@ async function createScheduledMessage(event) {
     }
 }
 
+// Message templates, rendered for each recipient when sent
+let messageTemplates = [];
+
+async function loadMessageTemplates() {
+    const container = document.getElementById('template-list');
+
+    try {
+        messageTemplates = await invoke('get_message_templates');
+        container.innerHTML = messageTemplates.map(template => `
+            <div class="data-item">
+                <p><strong>${template.name}</strong>${template.description ? ` - ${template.description}` : ''}</p>
+                <p style="white-space: pre-wrap;">${template.body}</p>
+                <button class="btn btn-secondary" onclick="editMessageTemplate(${template.id})">編集</button>
+                <button class="btn btn-secondary" onclick="deleteMessageTemplate(${template.id})">削除</button>
+            </div>
+        `).join('');
+        document.getElementById('scheduled-template').innerHTML =
+            '<option value="">使わない（メッセージ内容を送信）</option>' +
+            messageTemplates.map(template => `<option value="${template.id}">${template.name}</option>`).join('');
+    } catch (error) {
+        container.innerHTML = `<p class="error">エラー: ${error}</p>`;
+    }
+}
+
+function editMessageTemplate(id) {
+    const template = messageTemplates.find(t => t.id === id);
+    document.getElementById('template-id').value = template.id;
+    document.getElementById('template-name').value = template.name;
+    document.getElementById('template-description').value = template.description || '';
+    document.getElementById('template-body').value = template.body;
+}
+
+function resetMessageTemplateForm() {
+    document.getElementById('template-form').reset();
+    document.getElementById('template-id').value = '';
+}
+
+async function saveMessageTemplate(event) {
+    event.preventDefault();
+
+    const id = document.getElementById('template-id').value;
+    const template = {
+        name: document.getElementById('template-name').value,
+        description: document.getElementById('template-description').value || null,
+        body: document.getElementById('template-body').value
+    };
+
+    try {
+        if (id) {
+            await invoke('update_message_template', { id: parseInt(id, 10), template });
+        } else {
+            await invoke('create_message_template', { template });
+        }
+        resetMessageTemplateForm();
+        loadMessageTemplates();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function deleteMessageTemplate(id) {
+    if (!confirm('このテンプレートを削除しますか？')) return;
+
+    try {
+        await invoke('delete_message_template', { id });
+        loadMessageTemplates();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+// Render the template being edited for the chosen user
+async function previewMessageTemplate() {
+    const result = document.getElementById('template-preview-result');
+    const calendarId = document.getElementById('template-preview-calendar').value;
+
+    try {
+        const preview = await invoke('preview_message_template', {
+            body: document.getElementById('template-body').value,
+            lineUserId: document.getElementById('template-preview-user').value || null,
+            calendarId: calendarId ? parseInt(calendarId, 10) : null
+        });
+        result.textContent = preview.text +
+            (preview.missing.length > 0 ? `\n\n（値のない変数: ${preview.missing.join(', ')}）` : '');
+    } catch (error) {
+        result.textContent = `エラー: ${error}`;
+    }
+}
+
+async function loadUserAttributes() {
+    const container = document.getElementById('attribute-list');
+    const lineUserId = document.getElementById('attribute-user').value;
+    if (!lineUserId) {
+        container.innerHTML = '';
+        return;
+    }
+
+    try {
+        const attributes = await invoke('get_user_attributes', { lineUserId });
+        container.innerHTML = attributes.map(attribute => `
+            <div class="data-item">
+                <p><strong>${attribute.name}</strong>: ${attribute.value}</p>
+            </div>
+        `).join('');
+    } catch (error) {
+        container.innerHTML = `<p class="error">エラー: ${error}</p>`;
+    }
+}
+
+async function saveUserAttribute(event) {
+    event.preventDefault();
+
+    try {
+        await invoke('set_user_attribute', {
+            lineUserId: document.getElementById('attribute-user').value,
+            name: document.getElementById('attribute-name').value,
+            value: document.getElementById('attribute-value').value.trim()
+        });
+        document.getElementById('attribute-form').reset();
+        loadUserAttributes();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+// Load rich menus, their aliases and the selects that reference them
+async function loadRichMenus() {
+    const container = document.getElementById('richmenu-list');
+    container.innerHTML = '<p class="loading">データを読み込み中...</p>';
+
+    try {
+        const [menus, aliases, users] = await Promise.all([
+            invoke('get_rich_menus'),
+            invoke('get_rich_menu_aliases'),
+            invoke('get_users')
+        ]);
+
+        const menuOptions = menus.map(menu => `<option value="${menu.id}">${menu.name}</option>`).join('');
+        document.getElementById('richmenu-link-menu').innerHTML = menuOptions;
+        document.getElementById('richmenu-alias-menu').innerHTML = menuOptions;
+        document.getElementById('richmenu-link-users').innerHTML = users.map(user =>
+            `<option value="${user.line_user_id}">${user.display_name || user.line_user_id}</option>`
+        ).join('');
+
+        const menuNames = Object.fromEntries(menus.map(menu => [menu.id, menu.name]));
+        document.getElementById('richmenu-alias-list').innerHTML = aliases.map(alias => `
+            <div class="data-item">
+                <p><strong>${alias.alias_id}</strong> → ${menuNames[alias.rich_menu_id] || alias.rich_menu_id}
+                <button class="btn btn-secondary" onclick="deleteRichMenuAlias('${alias.alias_id}')">削除</button></p>
+            </div>
+        `).join('');
+
+        if (!menus || menus.length === 0) {
+            container.innerHTML = '<p class="loading">リッチメニューがありません</p>';
+            return;
+        }
+
+        container.innerHTML = menus.map(menu => `
+            <div class="data-item">
+                <p><strong>名前:</strong> ${menu.name}${menu.is_default ? '（デフォルト）' : ''}</p>
+                <p><strong>LINE ID:</strong> ${menu.line_rich_menu_id}</p>
+                <p><strong>画像:</strong> ${menu.image_path || '未アップロード'}</p>
+                ${menu.image_path ? '' : `<button class="btn btn-secondary" onclick="uploadRichMenuImage(${menu.id})">画像アップロード</button>`}
+                <button class="btn btn-secondary" onclick="setDefaultRichMenu(${menu.id})">デフォルトに設定</button>
+                <button class="btn btn-secondary" onclick="deleteRichMenu(${menu.id})">削除</button>
+            </div>
+        `).join('');
+    } catch (error) {
+        container.innerHTML = `<p class="error">エラー: ${error}</p>`;
+    }
+}
+
+// Create rich menu
+async function createRichMenu(event) {
+    event.preventDefault();
+
+    let definition;
+    try {
+        definition = JSON.parse(document.getElementById('richmenu-definition').value);
+    } catch (error) {
+        alert(`定義のJSONが不正です: ${error}`);
+        return;
+    }
+    const imagePath = document.getElementById('richmenu-image-path').value.trim() || null;
+
+    try {
+        await invoke('create_rich_menu', { definition, imagePath });
+        alert('リッチメニューを作成しました');
+        document.getElementById('richmenu-form').reset();
+        loadRichMenus();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function uploadRichMenuImage(richMenuId) {
+    const imagePath = prompt('画像ファイルのパス（JPEG/PNG・1MB以下）');
+    if (!imagePath) return;
+
+    try {
+        await invoke('upload_rich_menu_image', { richMenuId, imagePath });
+        loadRichMenus();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function setDefaultRichMenu(richMenuId) {
+    try {
+        await invoke('set_default_rich_menu', { richMenuId });
+        loadRichMenus();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function clearDefaultRichMenu() {
+    try {
+        await invoke('clear_default_rich_menu');
+        loadRichMenus();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function deleteRichMenu(richMenuId) {
+    if (!confirm('このリッチメニューを削除しますか？')) return;
+
+    try {
+        await invoke('delete_rich_menu', { richMenuId });
+        loadRichMenus();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+function selectedRichMenuUsers() {
+    return Array.from(document.getElementById('richmenu-link-users').selectedOptions).map(option => option.value);
+}
+
+async function linkRichMenu() {
+    const richMenuId = parseInt(document.getElementById('richmenu-link-menu').value, 10);
+    const lineUserIds = selectedRichMenuUsers();
+
+    try {
+        if (lineUserIds.length === 1) {
+            await invoke('link_rich_menu_to_user', { richMenuId, lineUserId: lineUserIds[0] });
+        } else {
+            await invoke('link_rich_menu_to_users', { richMenuId, lineUserIds });
+        }
+        alert('リッチメニューを割り当てました');
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function unlinkRichMenu() {
+    const lineUserIds = selectedRichMenuUsers();
+
+    try {
+        if (lineUserIds.length === 1) {
+            await invoke('unlink_rich_menu_from_user', { lineUserId: lineUserIds[0] });
+        } else {
+            await invoke('unlink_rich_menu_from_users', { lineUserIds });
+        }
+        alert('割り当てを解除しました');
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function setRichMenuAlias(event) {
+    event.preventDefault();
+
+    const aliasId = document.getElementById('richmenu-alias-id').value.trim();
+    const richMenuId = parseInt(document.getElementById('richmenu-alias-menu').value, 10);
+
+    try {
+        await invoke('set_rich_menu_alias', { aliasId, richMenuId });
+        document.getElementById('richmenu-alias-form').reset();
+        loadRichMenus();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function deleteRichMenuAlias(aliasId) {
+    try {
+        await invoke('delete_rich_menu_alias', { aliasId });
+        loadRichMenus();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+const matchTypeLabels = {
+    'exact': '完全一致',
+    'contains': '含む',
+    'regex': '正規表現',
+    'any': 'すべて'
+};
+
+function formatAutoReplyCondition(rule) {
+    const condition = rule.match_type === 'any'
+        ? 'すべてのメッセージ'
+        : `${matchTypeLabels[rule.match_type] || rule.match_type}「${rule.pattern}」`;
+    const window = rule.start_time ? `（${rule.start_time}〜${rule.end_time}）` : '';
+    return condition + window;
+}
+
+// Load auto-reply rules and recent matches
+async function loadAutoReplyRules() {
+    const container = document.getElementById('autoreply-list');
+    container.innerHTML = '<p class="loading">データを読み込み中...</p>';
+
+    try {
+        const [rules, logs] = await Promise.all([
+            invoke('get_auto_reply_rules'),
+            invoke('get_auto_reply_logs', { limit: 50 })
+        ]);
+
+        container.innerHTML = rules.length === 0
+            ? '<p class="loading">ルールがありません</p>'
+            : rules.map(rule => `
+                <div class="data-item">
+                    <p><strong>${rule.name}</strong>（優先度 ${rule.priority}）${rule.enabled ? '' : ' - 無効'}</p>
+                    <p><strong>条件:</strong> ${formatAutoReplyCondition(rule)}</p>
+                    <p><strong>返信:</strong> ${JSON.parse(rule.messages).map(m => m.text || `[${m.type}]`).join(' / ')}</p>
+                    <button class="btn btn-secondary" onclick='toggleAutoReplyRule(${JSON.stringify(rule)})'>${rule.enabled ? '無効にする' : '有効にする'}</button>
+                    <button class="btn btn-secondary" onclick="deleteAutoReplyRule(${rule.id})">削除</button>
+                </div>
+            `).join('');
+
+        const ruleNames = Object.fromEntries(rules.map(rule => [rule.id, rule.name]));
+        document.getElementById('autoreply-log-list').innerHTML = logs.map(log => `
+            <div class="data-item">
+                <p>${formatDate(log.matched_at)} ${log.line_user_id}「${log.message_text}」→ ${ruleNames[log.rule_id] || log.rule_id}
+                ${log.status === 'sent' ? '' : `<span class="status-failed">失敗: ${log.error_message || ''}</span>`}</p>
+            </div>
+        `).join('');
+    } catch (error) {
+        container.innerHTML = `<p class="error">エラー: ${error}</p>`;
+    }
+}
+
+// Create auto-reply rule
+async function createAutoReplyRule(event) {
+    event.preventDefault();
+
+    const text = document.getElementById('autoreply-text').value.trim();
+    const messagesJson = document.getElementById('autoreply-messages-json').value.trim();
+    let messages;
+    try {
+        messages = messagesJson ? JSON.parse(messagesJson) : [{ type: 'text', text }];
+    } catch (error) {
+        alert(`メッセージオブジェクトのJSONが不正です: ${error}`);
+        return;
+    }
+
+    const matchType = document.getElementById('autoreply-match-type').value;
+    try {
+        await invoke('create_auto_reply_rule', {
+            name: document.getElementById('autoreply-name').value,
+            matchType,
+            pattern: matchType === 'any' ? null : document.getElementById('autoreply-pattern').value,
+            caseSensitive: document.getElementById('autoreply-case-sensitive').checked,
+            startTime: document.getElementById('autoreply-start-time').value || null,
+            endTime: document.getElementById('autoreply-end-time').value || null,
+            messages,
+            priority: parseInt(document.getElementById('autoreply-priority').value, 10) || 0,
+            enabled: true
+        });
+        document.getElementById('autoreply-form').reset();
+        loadAutoReplyRules();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function toggleAutoReplyRule(rule) {
+    try {
+        await invoke('update_auto_reply_rule', {
+            ruleId: rule.id,
+            name: rule.name,
+            matchType: rule.match_type,
+            pattern: rule.pattern,
+            caseSensitive: rule.case_sensitive,
+            startTime: rule.start_time,
+            endTime: rule.end_time,
+            messages: JSON.parse(rule.messages),
+            priority: rule.priority,
+            enabled: !rule.enabled
+        });
+        loadAutoReplyRules();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function deleteAutoReplyRule(ruleId) {
+    if (!confirm('このルールを削除しますか？')) return;
+
+    try {
+        await invoke('delete_auto_reply_rule', { ruleId });
+        loadAutoReplyRules();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function testAutoReply() {
+    const container = document.getElementById('autoreply-test-result');
+    const text = document.getElementById('autoreply-test-text').value;
+    const time = document.getElementById('autoreply-test-time').value || null;
+
+    try {
+        const rule = await invoke('test_auto_reply', { text, time });
+        container.innerHTML = rule
+            ? `<p>「${rule.name}」が応答します: ${JSON.parse(rule.messages).map(m => m.text || `[${m.type}]`).join(' / ')}</p>`
+            : '<p>一致するルールはありません</p>';
+    } catch (error) {
+        container.innerHTML = `<p class="error">エラー: ${error}</p>`;
+    }
+}
+
 // Load settings
 async function loadSettings() {
     try {
         // Load LINE settings
         const lineToken = await invoke('get_setting', { key: 'line_channel_access_token' });
         const lineSecret = await invoke('get_setting', { key: 'line_channel_secret' });
-        const lineNotifyToken = await invoke('get_setting', { key: 'line_notify_token' });
-        const slackWebhook = await invoke('get_setting', { key: 'slack_webhook_url' });
 
         if (lineToken) document.getElementById('line-channel-token').value = lineToken;
         if (lineSecret) document.getElementById('line-channel-secret').value = lineSecret;
-        if (lineNotifyToken) document.getElementById('line-notify-token').value = lineNotifyToken;
-        if (slackWebhook) document.getElementById('slack-webhook-url').value = slackWebhook;
+
+        const welcomeMessages = await invoke('get_welcome_messages');
+        document.getElementById('welcome-messages-json').value =
+            welcomeMessages.length > 0 ? JSON.stringify(welcomeMessages, null, 2) : '';
+
+        await loadNotificationChannels();
+        loadNotificationRules();
+        loadNotificationLogs();
+        loadApiKeys();
     } catch (error) {
         console.error('Failed to load settings:', error);
     }
@@ -218,33 +1011,344 @@ async function saveLineSetting(event) {
     }
 }
 
-// Save notification settings
-async function saveNotificationSettings(event) {
+// Save welcome messages
+async function loadApiKeys() {
+    const container = document.getElementById('api-key-list');
+
+    try {
+        const keys = await invoke('get_api_keys');
+        container.innerHTML = keys.map(key => `
+            <div class="data-item">
+                <p><strong>${key.name}</strong>（${key.key_prefix}…）${key.revoked_at ? ' - <span class="status-failed">無効</span>' : ''}</p>
+                <p><strong>権限:</strong> ${key.scopes}</p>
+                <p><strong>作成:</strong> ${formatDate(key.created_at)} / <strong>最終使用:</strong> ${formatDate(key.last_used_at)}</p>
+                ${key.revoked_at ? '' : `<button class="btn btn-secondary" onclick="revokeApiKey(${key.id})">無効にする</button>`}
+            </div>
+        `).join('');
+    } catch (error) {
+        container.innerHTML = `<p class="error">エラー: ${error}</p>`;
+    }
+}
+
+async function createApiKey(event) {
     event.preventDefault();
 
-    const lineNotifyToken = document.getElementById('line-notify-token').value;
-    const slackWebhook = document.getElementById('slack-webhook-url').value;
+    const name = document.getElementById('api-key-name').value;
+    const scopes = [...document.querySelectorAll('#api-key-scopes input:checked')].map(input => input.value);
 
     try {
-        await invoke('set_setting', {
-            key: 'line_notify_token',
-            value: lineNotifyToken,
-            description: 'LINE Notify Token'
+        const created = await invoke('create_api_key', { name, scopes });
+        prompt('APIキーを作成しました。この画面を閉じると再表示できません。', created.key);
+        document.getElementById('api-key-form').reset();
+        loadApiKeys();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function revokeApiKey(id) {
+    if (!confirm('このAPIキーを無効にしますか？')) return;
+
+    try {
+        await invoke('revoke_api_key', { id });
+        loadApiKeys();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function saveWelcomeMessages(event) {
+    event.preventDefault();
+
+    const json = document.getElementById('welcome-messages-json').value.trim();
+    let messages;
+    try {
+        messages = json ? JSON.parse(json) : [];
+    } catch (error) {
+        alert(`JSONが不正です: ${error}`);
+        return;
+    }
+
+    try {
+        await invoke('set_welcome_messages', { messages });
+        alert('あいさつメッセージを保存しました');
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+// Notification channels
+const NOTIFICATION_CHANNEL_EXAMPLES = {
+    slack: { webhook_url: 'https://hooks.slack.com/services/...', blocks: true },
+    discord: { webhook_url: 'https://discord.com/api/webhooks/...' },
+    teams: { webhook_url: 'https://....logic.azure.com/workflows/...' },
+    email: {
+        host: 'smtp.example.com',
+        port: 587,
+        security: 'starttls',
+        username: 'user@example.com',
+        password: '',
+        from: 'LINE管理 <noreply@example.com>',
+        to: ['admin@example.com']
+    },
+    webhook: { url: 'https://example.com/hooks/line-admin', secret: '' },
+    line_push: { line_user_ids: ['U...'] }
+};
+
+let notificationChannels = [];
+
+function fillNotificationChannelConfig() {
+    const kind = document.getElementById('notification-channel-kind').value;
+    document.getElementById('notification-channel-config').value =
+        JSON.stringify(NOTIFICATION_CHANNEL_EXAMPLES[kind], null, 2);
+}
+
+async function loadNotificationChannels() {
+    const container = document.getElementById('notification-channel-list');
+    if (!document.getElementById('notification-channel-config').value) fillNotificationChannelConfig();
+
+    try {
+        notificationChannels = await invoke('get_notification_channels');
+        document.getElementById('notification-rule-channel').innerHTML = notificationChannels
+            .map(channel => `<option value="${channel.id}">${channel.name}（${channel.kind}）</option>`)
+            .join('');
+        container.innerHTML = notificationChannels.length === 0
+            ? '<p>通知チャネルはまだありません</p>'
+            : notificationChannels.map(channel => `
+            <div class="data-item">
+                <p><strong>${channel.name}</strong>（${channel.kind}）${channel.enabled ? '' : ' - <span class="status-failed">無効</span>'}</p>
+                <pre>${JSON.stringify(channel.config, null, 2)}</pre>
+                <button class="btn btn-secondary" onclick="toggleNotificationChannel(${channel.id})">${channel.enabled ? '無効にする' : '有効にする'}</button>
+                <button class="btn btn-secondary" onclick="testConnection('test_notification_channel', this, { id: ${channel.id} })">テスト送信</button>
+                <button class="btn btn-secondary" onclick="deleteNotificationChannel(${channel.id})">削除</button>
+                <span class="connection-result"></span>
+            </div>
+        `).join('');
+    } catch (error) {
+        container.innerHTML = `<p class="error">エラー: ${error}</p>`;
+    }
+}
+
+async function createNotificationChannel(event) {
+    event.preventDefault();
+
+    let config;
+    try {
+        config = JSON.parse(document.getElementById('notification-channel-config').value);
+    } catch (error) {
+        alert(`JSONが不正です: ${error}`);
+        return;
+    }
+
+    try {
+        await invoke('create_notification_channel', {
+            name: document.getElementById('notification-channel-name').value,
+            kind: document.getElementById('notification-channel-kind').value,
+            config,
+            enabled: document.getElementById('notification-channel-enabled').checked
         });
+        document.getElementById('notification-channel-form').reset();
+        fillNotificationChannelConfig();
+        loadNotificationChannels();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
 
-        await invoke('set_setting', {
-            key: 'slack_webhook_url',
-            value: slackWebhook,
-            description: 'Slack Webhook URL'
+// Masked credentials are sent back as-is and keep their saved values
+async function toggleNotificationChannel(id) {
+    const channel = notificationChannels.find(c => c.id === id);
+
+    try {
+        await invoke('update_notification_channel', {
+            id,
+            name: channel.name,
+            config: channel.config,
+            enabled: !channel.enabled
+        });
+        loadNotificationChannels();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function deleteNotificationChannel(id) {
+    if (!confirm('この通知チャネルを削除しますか？（このチャネルへの通知ルールも削除されます）')) return;
+
+    try {
+        await invoke('delete_notification_channel', { id });
+        await loadNotificationChannels();
+        loadNotificationRules();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+// Notification outbox
+const NOTIFICATION_LOG_STATUS = {
+    pending: ['送信待ち', 'status-pending'],
+    sent: ['送信済み', 'status-sent'],
+    dead: ['送信不可', 'status-failed']
+};
+
+async function loadNotificationLogs() {
+    const container = document.getElementById('notification-log-list');
+    const status = document.getElementById('notification-log-status').value || null;
+
+    try {
+        const counts = await invoke('get_notification_log_counts');
+        document.getElementById('notification-log-counts').textContent = counts
+            .map(([status, count]) => `${(NOTIFICATION_LOG_STATUS[status] || [status])[0]}: ${count}`)
+            .join(' / ');
+
+        const logs = await invoke('get_notification_logs', { status, limit: 50 });
+        container.innerHTML = logs.length === 0 ? '<p>通知はありません</p>' : logs.map(log => {
+            const [label, className] = NOTIFICATION_LOG_STATUS[log.status] || [log.status, ''];
+            const retrying = log.status === 'pending' && log.attempts > 0;
+            return `
+            <div class="data-item">
+                <p><strong>${log.recipient}</strong>（${log.notification_type}）- <span class="${className}">${retrying ? '再試行待ち' : label}</span>
+                   ${log.event_kind ? `[${NOTIFICATION_KIND_LABELS[log.event_kind] || log.event_kind}]` : ''}</p>
+                <p>${log.message}</p>
+                <p><strong>作成:</strong> ${formatDate(log.created_at)} / <strong>試行:</strong> ${log.attempts}回
+                   ${log.delivered_at ? ` / <strong>送信:</strong> ${formatDate(log.delivered_at)}` : ''}
+                   ${retrying ? ` / <strong>次回:</strong> ${formatDate(log.next_attempt_at)}` : ''}</p>
+                ${log.error_message ? `<p class="error">${log.error_message}</p>` : ''}
+                ${log.status !== 'pending' && log.title !== null ? `<button class="btn btn-secondary" onclick="resendNotification(${log.id})">再送</button>` : ''}
+            </div>
+        `;
+        }).join('');
+    } catch (error) {
+        container.innerHTML = `<p class="error">エラー: ${error}</p>`;
+    }
+}
+
+async function resendNotification(id) {
+    try {
+        const log = await invoke('resend_notification', { id });
+        if (log.status !== 'sent') alert(`再送に失敗しました（自動で再試行します）: ${log.error_message}`);
+        loadNotificationLogs();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+// Notification rules
+const NOTIFICATION_KIND_LABELS = {
+    new_message: '新しいメッセージ',
+    new_follower: '友だち追加',
+    unfollow: 'ブロック',
+    bot_membership: 'グループへの参加・退出',
+    scheduled_send_failed: 'スケジュール配信の失敗',
+    reminder_failed: 'リマインダーの失敗'
+};
+
+let notificationRules = [];
+
+function describeNotificationRule(rule) {
+    const conditions = [];
+    if (rule.keywords) conditions.push(`キーワード: ${JSON.parse(rule.keywords).join(', ')}`);
+    if (rule.quiet_start) conditions.push(`通知しない時間帯: ${rule.quiet_start}〜${rule.quiet_end}`);
+    if (rule.rate_limit) conditions.push(`上限: ${rule.rate_window_minutes}分間に${rule.rate_limit}件`);
+    if (rule.digest_minutes) conditions.push(`${rule.digest_minutes}分ごとにまとめて送信`);
+    return conditions.length > 0 ? conditions.join(' / ') : 'すべて都度送信';
+}
+
+async function loadNotificationRules() {
+    const container = document.getElementById('notification-rule-list');
+
+    try {
+        notificationRules = await invoke('get_notification_rules');
+        container.innerHTML = notificationRules.map(rule => {
+            const channel = notificationChannels.find(c => c.id === rule.channel_id);
+            return `
+            <div class="data-item">
+                <p><strong>${rule.name}</strong>${rule.enabled ? '' : ' - <span class="status-failed">無効</span>'}</p>
+                <p>${NOTIFICATION_KIND_LABELS[rule.event_kind] || rule.event_kind} → ${channel ? channel.name : rule.channel_id}</p>
+                <p>${describeNotificationRule(rule)}</p>
+                <button class="btn btn-secondary" onclick="toggleNotificationRule(${rule.id})">${rule.enabled ? '無効にする' : '有効にする'}</button>
+                <button class="btn btn-secondary" onclick="deleteNotificationRule(${rule.id})">削除</button>
+            </div>
+        `;
+        }).join('');
+    } catch (error) {
+        container.innerHTML = `<p class="error">エラー: ${error}</p>`;
+    }
+}
+
+function optionalNumber(id) {
+    const value = document.getElementById(id).value;
+    return value ? parseInt(value, 10) : null;
+}
+
+async function createNotificationRule(event) {
+    event.preventDefault();
+
+    const keywords = document.getElementById('notification-rule-keywords').value
+        .split(/[,、]/)
+        .map(keyword => keyword.trim())
+        .filter(keyword => keyword);
+    const rule = {
+        name: document.getElementById('notification-rule-name').value,
+        event_kind: document.getElementById('notification-rule-kind').value,
+        channel_id: parseInt(document.getElementById('notification-rule-channel').value, 10),
+        keywords: keywords.length > 0 ? keywords : null,
+        quiet_start: document.getElementById('notification-rule-quiet-start').value || null,
+        quiet_end: document.getElementById('notification-rule-quiet-end').value || null,
+        rate_limit: optionalNumber('notification-rule-rate-limit'),
+        rate_window_minutes: optionalNumber('notification-rule-rate-window'),
+        digest_minutes: optionalNumber('notification-rule-digest-minutes'),
+        enabled: document.getElementById('notification-rule-enabled').checked
+    };
+
+    try {
+        await invoke('create_notification_rule', { rule });
+        document.getElementById('notification-rule-form').reset();
+        loadNotificationRules();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function toggleNotificationRule(id) {
+    const rule = notificationRules.find(r => r.id === id);
+
+    try {
+        await invoke('update_notification_rule', {
+            id,
+            rule: {
+                ...rule,
+                keywords: rule.keywords ? JSON.parse(rule.keywords) : null,
+                enabled: !rule.enabled
+            }
         });
+        loadNotificationRules();
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+async function deleteNotificationRule(id) {
+    if (!confirm('この通知ルールを削除しますか？（まとめ送信待ちの通知は破棄されます）')) return;
 
-        alert('通知設定を保存しました');
+    try {
+        await invoke('delete_notification_rule', { id });
+        loadNotificationRules();
     } catch (error) {
         alert(`エラー: ${error}`);
     }
 }
 
 // Utility functions
+function formatScheduledTarget(msg) {
+    if (msg.audience) {
+        const audience = JSON.parse(msg.audience);
+        if (audience.type === 'multicast') return `${audience.user_ids.length}人のユーザー（マルチキャスト）`;
+        return '属性で絞り込み（ナローキャスト）';
+    }
+    return msg.line_user_id || '全ユーザー';
+}
+
 function formatDate(dateString) {
     if (!dateString) return '-';
     const date = new Date(dateString);
@@ -256,11 +1360,81 @@ function getStatusText(status) {
         'pending': '配信待ち',
         'sent': '配信完了',
         'failed': '配信失敗',
-        'cancelled': 'キャンセル'
+        'cancelled': 'キャンセル',
+        'completed': '繰り返し終了',
+        'skipped': '送信対象なし'
     };
     return statusMap[status] || status;
 }
 
+// Inputs bound to a setting through data-setting="<key>". Secrets come back
+// masked; values from environment variables are shown as placeholders.
+async function loadSettingInputs() {
+    try {
+        const settings = await invoke('get_setting_definitions');
+        document.querySelectorAll('[data-setting]').forEach(input => {
+            const setting = settings.find(s => s.key === input.dataset.setting);
+            if (!setting) return;
+            input.title = setting.description;
+            if (setting.source === 'database') {
+                input.value = setting.value;
+            } else if (setting.source === 'env') {
+                input.value = '';
+                input.placeholder = `環境変数 ${setting.env} の値を使用中`;
+            }
+        });
+    } catch (error) {
+        console.error('Failed to load settings:', error);
+    }
+}
+
+async function saveSettingInputs(event) {
+    event.preventDefault();
+
+    try {
+        for (const input of event.target.querySelectorAll('[data-setting]')) {
+            await invoke('set_setting', { key: input.dataset.setting, value: input.value.trim() });
+        }
+        alert('設定を保存しました');
+    } catch (error) {
+        alert(`エラー: ${error}`);
+    }
+}
+
+// Connection tests. The result is shown next to the clicked button.
+async function testConnection(command, button, args = {}) {
+    const result = button.parentElement.querySelector('.connection-result');
+    result.className = 'connection-result';
+    result.textContent = 'テスト中...';
+
+    try {
+        const info = await invoke(command, args);
+        result.classList.add('status-sent');
+        if (info && info.displayName) {
+            result.textContent = `接続OK（${info.displayName} ${info.basicId}）`;
+        } else if (info && info.team) {
+            result.textContent = `接続OK（${info.team} / ${info.user}）`;
+        } else {
+            result.textContent = '接続OK';
+        }
+    } catch (error) {
+        result.classList.add('status-failed');
+        result.textContent = `失敗: ${error}`;
+    }
+}
+
+function testLineConnection(button) {
+    return testConnection('test_line_connection', button);
+}
+
+function testSlackBridgeConnection(button) {
+    return testConnection('test_slack_bridge_connection', button);
+}
+
+function testIntegration(integration, button) {
+    return testConnection('test_integration_connection', button, { integration });
+}
+
 // External integrations
 async function syncToNotion() {
     try {
@@ -296,6 +1470,7 @@ async function loadDashboard() {
 
         // Update stat cards
         document.getElementById('total-users').textContent = stats.total_users.toLocaleString();
+        document.getElementById('unfollowed-users').textContent = `ブロック: ${stats.unfollowed_users.toLocaleString()}人`;
         document.getElementById('total-messages').textContent = stats.total_messages.toLocaleString();
         document.getElementById('messages-today').textContent = stats.messages_today.toLocaleString();
         document.getElementById('new-users-week').textContent = stats.new_users_this_week.toLocaleString();
@@ -384,3 +1559,4 @@ function renderHourlyActivityChart(hourlyActivity) {
 // Initialize: Load dashboard on startup
 // Since the script is at the end of body, DOM is already ready
 loadDashboard();
+updateUnreadBadge();